@@ -0,0 +1,2717 @@
+use crate::helpers::{
+    attr_arg_span, boxed_future_output, check_cached_flag_output, check_generic_params,
+    check_hashable_inputs, check_not_method, enabled_switch, find_lifetime, first_type_arg,
+    inner_fn_ident, inner_generics, literal_or_fn, outer_inputs, parse_arg, prime_fn_ident, respan,
+    static_lifetimes, type_args, type_display, type_name, LitOrExpr,
+};
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, parse_str, AttributeArgs, Block, FnArg, GenericArgument, Ident,
+    ItemFn, NestedMeta, Path, PathArguments, ReturnType, Signature, Type, Visibility,
+};
+
+#[derive(FromMeta)]
+struct MacroArgs {
+    #[darling(default)]
+    name: Option<String>,
+    #[darling(default)]
+    cache_visibility: Option<String>,
+    #[darling(default)]
+    prime_name: Option<String>,
+    #[darling(default)]
+    unbound: bool,
+    #[darling(default)]
+    size: Option<LitOrExpr>,
+    #[darling(default)]
+    size_fn: Option<String>,
+    #[darling(default)]
+    low_watermark: Option<usize>,
+    #[darling(default)]
+    time: Option<u64>,
+    #[darling(default)]
+    time_fn: Option<String>,
+    #[darling(default)]
+    time_refresh: bool,
+    #[darling(default)]
+    time_to_idle: Option<u64>,
+    #[darling(default)]
+    key: Option<String>,
+    #[darling(default)]
+    convert: Option<String>,
+    #[darling(default)]
+    result: bool,
+    #[darling(default)]
+    option: bool,
+    #[darling(default)]
+    result_option: bool,
+    #[darling(default)]
+    sync_writes: bool,
+    #[darling(default)]
+    with_cached_flag: bool,
+    #[darling(default, rename = "type")]
+    cache_type: Option<String>,
+    #[darling(default, rename = "create")]
+    cache_create: Option<String>,
+    #[darling(default)]
+    create_try: Option<String>,
+    #[darling(default)]
+    on_create_error: Option<String>,
+    #[darling(default)]
+    invalidation_group: Option<String>,
+    #[darling(default)]
+    version_fn: Option<String>,
+    #[darling(default)]
+    key_transform: Option<String>,
+    #[darling(default)]
+    on_miss: Option<String>,
+    #[darling(default)]
+    placeholder: Option<String>,
+    #[darling(default)]
+    warn_at: Option<usize>,
+    #[darling(default)]
+    trace_caller: bool,
+    #[darling(default)]
+    error_on_miss: bool,
+    #[darling(default)]
+    config: Option<String>,
+    #[darling(default)]
+    prime_batched: bool,
+    #[darling(default)]
+    prime_batch_size: Option<usize>,
+    #[darling(default)]
+    with_key: bool,
+    #[darling(default)]
+    at_most_once: bool,
+    #[darling(default)]
+    feature: Option<String>,
+    #[darling(default)]
+    cold_start_timeout: Option<u64>,
+    #[darling(default)]
+    compute_timeout_ms: Option<u64>,
+    #[darling(default)]
+    evict_after_set: Option<u64>,
+    #[darling(default)]
+    convert_prepared: Option<String>,
+    #[darling(default)]
+    prepared_arg: Option<String>,
+    #[darling(default)]
+    max_inflight: Option<usize>,
+    #[darling(default)]
+    ignore_types: Option<String>,
+    #[darling(default)]
+    extractor: bool,
+    #[darling(default)]
+    hash_only_key: bool,
+    #[darling(default)]
+    key_hasher: Option<String>,
+    #[darling(default)]
+    key_fn_async: Option<String>,
+    #[darling(default)]
+    invalidate_sibling: Option<String>,
+    #[darling(default)]
+    inline: bool,
+    #[darling(default)]
+    shared: bool,
+    #[darling(default)]
+    lock: Option<String>,
+}
+
+pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    let args = match MacroArgs::from_list(&attr_args) {
+        Ok(v) => v,
+        Err(e) => {
+            return TokenStream::from(e.write_errors());
+        }
+    };
+    let mut input = parse_macro_input!(input as ItemFn);
+    if let Err(e) = check_not_method(&input.sig, "cached") {
+        return e.to_compile_error().into();
+    }
+    // a function returning a manually boxed future, e.g. `Pin<Box<dyn Future<Output = T> + Send>>`,
+    // is cached like an `async fn` returning `T` awaiting it, and the generated function boxes its
+    // future the same way
+    let boxed_output = match &input.sig.output {
+        ReturnType::Type(_, ty) if input.sig.asyncness.is_none() => {
+            let mut boxed = (**ty).clone();
+            boxed_future_output(&mut boxed)
+                .map(|output| output.clone())
+                .map(|output| (boxed, output))
+        }
+        _ => None,
+    };
+    let boxed_output = match boxed_output {
+        Some((boxed, output)) => {
+            let body = &input.block;
+            input.sig.asyncness = Some(parse_quote! { async });
+            input.sig.output = parse_quote! { -> #output };
+            input.block = parse_quote! { { (move || #body)().await } };
+            Some(boxed)
+        }
+        None => None,
+    };
+    if args.trace_caller && input.sig.asyncness.is_some() {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "trace_caller"),
+            "`trace_caller` is not supported for async functions, `#[track_caller]` can't be used on them",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.error_on_miss
+        && (args.result
+            || args.option
+            || args.result_option
+            || args.with_cached_flag
+            || args.sync_writes)
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "error_on_miss"),
+            "`error_on_miss` can't be combined with `result`, `option`, `result_option`, \
+            `with_cached_flag` or `sync_writes`, misses return `Err(cached::CacheMiss)` without calling the function",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.cold_start_timeout.is_some()
+        && (!args.sync_writes || args.placeholder.is_some() || args.with_key)
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "cold_start_timeout"),
+            "`cold_start_timeout` requires `sync_writes` and can't be combined with `placeholder` \
+            or `with_key`, it bounds how long callers wait for the lock held while computing",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.compute_timeout_ms.is_some()
+        && (input.sig.asyncness.is_none()
+            || !(args.result || args.option || args.result_option)
+            || args.placeholder.is_some()
+            || args.cold_start_timeout.is_some()
+            || args.at_most_once)
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "compute_timeout_ms"),
+            "`compute_timeout_ms` requires an async function with `result`, `option` or `result_option`, \
+            returning `Err(cached::ComputeTimeout.into())` or `None` on timeout, and can't be combined \
+            with `placeholder`, `cold_start_timeout` or `at_most_once`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    // with `lock = "rwlock"`, the store is kept behind a `RwLock` and looked up under its read
+    // lock with `CachedRead::cache_read`, so lookups don't wait for each other. With
+    // `lock = "none"`, the store is a `ConcurrentCached` store shared without any lock.
+    let (rwlock, unlocked) = match args.lock.as_deref() {
+        None | Some("mutex") => (false, false),
+        Some("rwlock") => (true, false),
+        Some("none") => (false, true),
+        Some(lock) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "lock"),
+                format!(
+                    "unknown `lock` `{}`, expected `mutex`, `rwlock` or `none`",
+                    lock
+                ),
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+    if rwlock && (input.sig.asyncness.is_some() || args.cold_start_timeout.is_some()) {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "lock"),
+            "`lock = \"rwlock\"` only applies to sync functions and can't be combined with \
+            `cold_start_timeout`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.config.is_some()
+        && (args.key.is_some()
+            || args.convert.is_some()
+            || args.cache_type.is_some()
+            || args.cache_create.is_some()
+            || args.create_try.is_some()
+            || args.unbound
+            || args.size.is_some()
+            || args.size_fn.is_some()
+            || args.time.is_some()
+            || args.time_fn.is_some()
+            || args.version_fn.is_some()
+            || args.warn_at.is_some()
+            || args.trace_caller)
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "config"),
+            "`config` can't be combined with `key`, `convert`, `type`, `create`, `create_try`, `unbound`, `size`, \
+            `size_fn`, `time`, `time_fn`, `version_fn`, `warn_at` or `trace_caller`, the cache store \
+            and its keys are defined by the `cached::CacheConfig` implementation",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if (args.convert_prepared.is_some() || args.prepared_arg.is_some())
+        && (args.convert_prepared.is_none()
+            || args.prepared_arg.is_none()
+            || args.key.is_none()
+            || args.convert.is_some()
+            || args.config.is_some())
+    {
+        let arg = if args.convert_prepared.is_some() {
+            "convert_prepared"
+        } else {
+            "prepared_arg"
+        };
+        return syn::Error::new(
+            attr_arg_span(&attr_args, arg),
+            "`convert_prepared` and `prepared_arg` must be set together along with `key`, and \
+            replace `convert`: the block returns the key and the argument of the function body, \
+            e.g. `key = \"String\", convert_prepared = r#\"{ let s = s.trim().to_lowercase(); (s.clone(), s) }\"#, \
+            prepared_arg = \"s: String\"`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.max_inflight.is_some()
+        && (input.sig.asyncness.is_some() || args.max_inflight == Some(0) || args.at_most_once)
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "max_inflight"),
+            "`max_inflight` must be greater than zero, and is only supported for sync functions \
+            without `at_most_once`: it limits how many threads run the function at once",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.result_option && (args.result || args.option) {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "result_option"),
+            "`result_option` can't be combined with `result` or `option`, it caches the `T` of \
+            `Option<Result<T, E>>` return values",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.create_try.is_some() && (args.cache_create.is_some() || args.cache_type.is_none()) {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "create_try"),
+            "`create_try` requires `type` to be set, and replaces `create`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    // whether a failure of `create_try` falls back to a `NullCache` instead of panicking
+    let null_on_create_error = match args.on_create_error.as_deref() {
+        Some(_) if args.create_try.is_none() => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "on_create_error"),
+                "`on_create_error` requires `create_try` to be set",
+            )
+            .to_compile_error()
+            .into();
+        }
+        None | Some("panic") => false,
+        Some("null") => true,
+        Some(policy) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "on_create_error"),
+                format!(
+                    "unknown `on_create_error` policy `{}`, expected `panic` or `null`",
+                    policy
+                ),
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+    if args.placeholder.is_some() && !args.sync_writes {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "placeholder"),
+            "`placeholder` requires `sync_writes = true`: it's returned to the callers missing \
+            a key while its value is computed by another caller",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.prime_batch_size.is_some() && !args.prime_batched {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "prime_batch_size"),
+            "`prime_batch_size` requires `prime_batched = true`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.result && args.option {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "option"),
+            "`result` and `option` are mutually exclusive",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // pull out the parts of the input
+    let mut attributes = input.attrs;
+    let visibility = input.vis;
+    let signature = input.sig;
+    let body = input.block;
+
+    // with `inline`, the cached function is inlined into its callers along with the cache lookup,
+    // the body computing the values stays in its own function. The function's other attributes, e.g. `#[must_use]`, are kept as is.
+    if args.inline {
+        if let Some(attr) = attributes.iter().find(|attr| attr.path.is_ident("inline")) {
+            return syn::Error::new_spanned(
+                attr,
+                "`inline = true` already marks the cached function `#[inline]`, remove this attribute",
+            )
+            .to_compile_error()
+            .into();
+        }
+        attributes.push(parse_quote! { #[inline] });
+    }
+
+    // the visibility of the cache static, the function's unless set with `cache_visibility`
+    let cache_visibility = match args
+        .cache_visibility
+        .as_deref()
+        .map(parse_str::<Visibility>)
+    {
+        None => visibility.clone(),
+        Some(Ok(cache_visibility)) => cache_visibility,
+        Some(Err(e)) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "cache_visibility"),
+                format!(
+                    "unable to parse `cache_visibility`, expected e.g. `pub(crate)`, \
+                    `pub(in crate::path)`, or `\"\"` for a private cache: {}",
+                    e
+                ),
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    // pull out the parts of the function signature
+    let fn_ident = signature.ident.clone();
+    let inner_fn_ident = inner_fn_ident(&fn_ident);
+    let inputs = signature.inputs.clone();
+    let output = signature.output.clone();
+    let asyncness = signature.asyncness;
+    let (inner_generics, inner_turbofish, inner_where) = inner_generics(&signature.generics);
+
+    // pull out the names and types of the function inputs
+    let input_tys = inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
+            FnArg::Typed(pat_type) => pat_type.ty.clone(),
+        })
+        .collect::<Vec<Box<Type>>>();
+
+    // the arguments of the generated functions, and the names passing them on to `inner`
+    let outer_inputs = outer_inputs(&inputs);
+    let input_names = &outer_inputs.names;
+
+    // pull out the output type
+    let output_ty = match &output {
+        ReturnType::Default => quote! {()},
+        ReturnType::Type(_, ty) => quote! {#ty},
+    };
+
+    let output_span = output_ty.span();
+    let output_ts = TokenStream::from(output_ty.clone());
+    let output_type_display = output_ts.to_string().replace(' ', "");
+
+    // with `with_cached_flag = true`, the values are wrapped in `cached::Return<T>`
+    if args.with_cached_flag {
+        let (layers, expected, mode) = match (args.result, args.option) {
+            _ if args.result_option => (
+                2,
+                "Option<Result<cached::Return<T>, E>>",
+                Some("`result_option = true`"),
+            ),
+            (true, _) => (1, "Result<cached::Return<T>, E>", Some("`result = true`")),
+            (_, true) => (1, "Option<cached::Return<T>>", Some("`option = true`")),
+            _ => (0, "cached::Return<T>", None),
+        };
+        if let Err(e) = check_cached_flag_output(&output, layers, expected, mode) {
+            return e.to_compile_error().into();
+        }
+    }
+
+    // Find the type of the value to store.
+    // Normally it's the same as the return type of the functions, but
+    // for Options and Results it's the (first) inner type. So for
+    // Option<u32>, store u32, for Result<i32, String>, store i32, etc.
+    // With `result_option`, store the `T` of an `Option<Result<T, E>>`.
+    let cache_value_ty = match (&args.result, &args.option) {
+        _ if args.result_option => match return_ty(&output)
+            .and_then(first_type_arg)
+            .and_then(first_type_arg)
+        {
+            Some(value_ty) => quote! {#value_ty},
+            None => {
+                return syn::Error::new(
+                    output_span,
+                    format!(
+                        "`result_option` requires the function to return an `Option<Result<T, E>>`, found `{}`",
+                        output_type_display
+                    ),
+                )
+                .to_compile_error()
+                .into();
+            }
+        },
+        (false, false) => output_ty.clone(),
+        _ => {
+            let attr = if args.result { "result" } else { "option" };
+            match output.clone() {
+                ReturnType::Default => {
+                    return syn::Error::new(
+                        attr_arg_span(&attr_args, attr),
+                        format!("`{}` requires the function to return something", attr),
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                ReturnType::Type(_, ty) => {
+                    let inner_ty = match &*ty {
+                        Type::Path(typepath) => {
+                            match &typepath.path.segments.last().unwrap().arguments {
+                                PathArguments::AngleBracketed(brackets) => brackets.args.first(),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    match inner_ty {
+                        Some(inner_ty) => quote! {#inner_ty},
+                        None => {
+                            return syn::Error::new(
+                                ty.span(),
+                                format!(
+                                    "`{}` requires the function to return a type with an inner type, found `{}`",
+                                    attr, output_type_display
+                                ),
+                            )
+                            .to_compile_error()
+                            .into();
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    // the cache is a `static`, so it can't hold values borrowed for a lifetime of the function
+    let fn_lifetimes = signature
+        .generics
+        .lifetimes()
+        .map(|param| param.lifetime.ident.clone())
+        .collect::<Vec<_>>();
+    if let Some((lifetime, span)) = find_lifetime(cache_value_ty.clone(), &fn_lifetimes) {
+        return syn::Error::new(
+            span,
+            format!(
+                "the cached value type `{}` borrows for `'{}`, but the cache of `{}` is a `static` \
+                holding values across calls, so it can only hold `'static` values. Return an owned \
+                type instead, e.g. a `String` rather than a `&'{} str`, or an `Arc`",
+                type_display(&cache_value_ty),
+                lifetime,
+                fn_ident,
+                lifetime
+            ),
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // make the cache identifier
+    let cache_ident = match args.name.as_ref() {
+        Some(name) => Ident::new(name, fn_ident.span()),
+        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
+    };
+    // with `shared`, the function uses the cache static of an earlier declaration with the same
+    // `name` rather than defining it, and names its own items (e.g. its enabled flag) after itself
+    if args.shared && args.name.is_none() {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "shared"),
+            "`shared` uses the cache of another `#[cached]` function, set it with `name`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let items_ident = if args.shared {
+        Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span())
+    } else {
+        cache_ident.clone()
+    };
+
+    // with `max_inflight`, the body runs holding a permit of a semaphore shared by the callers
+    let inflight_ident = Ident::new(
+        &format!("__{}_INFLIGHT_LIMIT", items_ident),
+        items_ident.span(),
+    );
+    let (body, inflight_items) = match args.max_inflight {
+        Some(max_inflight) => (
+            quote! {
+                {
+                    let _permit = #inflight_ident.acquire();
+                    #body
+                }
+            },
+            quote! {
+                #[doc(hidden)]
+                static #inflight_ident: ::cached::once_cell::sync::Lazy<::cached::proc_macro::InflightLimit> = ::cached::once_cell::sync::Lazy::new(|| ::cached::proc_macro::InflightLimit::new(#max_inflight));
+            },
+        ),
+        None => (quote! { #body }, quote! {}),
+    };
+
+    // with `config`, the cache store, its key and what gets cached are defined by a
+    // `cached::CacheConfig` implementation for the arguments of the function
+    let config = match &args.config {
+        Some(config) => match parse_arg::<Path>(&attr_args, "config", "a path", config) {
+            Ok(config) => Some(quote! { <#config as ::cached::CacheConfig<(#(#input_tys),*)>> }),
+            Err(e) => return e.to_compile_error().into(),
+        },
+        None => None,
+    };
+
+    // with `key_fn_async`, an async function computes the key from references to the arguments
+    if args.key_fn_async.is_some() {
+        let error = if asyncness.is_none() {
+            Some("`key_fn_async` only applies to async functions")
+        } else if args.convert.is_some() || config.is_some() {
+            Some("`key_fn_async` can't be combined with `convert` or `config`, it computes the key itself")
+        } else if args.key.is_none() && args.cache_type.is_none() {
+            Some("`key_fn_async` needs `key` (or `type`) to set the type of the keys it returns")
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return syn::Error::new(attr_arg_span(&attr_args, "key_fn_async"), error)
+                .to_compile_error()
+                .into();
+        }
+    }
+    let keyed = args.key.is_some()
+        || args.convert.is_some()
+        || config.is_some()
+        || args.key_fn_async.is_some();
+    // with `convert_prepared`, the body of the function takes `prepared_arg` instead of the
+    // arguments, returned by the block along with the key
+    let prepared_arg = match args.prepared_arg.as_deref().map(parse_str::<FnArg>) {
+        None => None,
+        Some(Ok(prepared_arg)) => Some(prepared_arg),
+        Some(Err(e)) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "prepared_arg"),
+                format!(
+                    "unable to parse `prepared_arg`, expected an argument such as `s: String`: {}",
+                    e
+                ),
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+    if let Err(e) = check_generic_params(&signature, keyed, "cached") {
+        return e.to_compile_error().into();
+    }
+    if !keyed {
+        if let Err(e) = check_hashable_inputs(&signature, "cached") {
+            return e.to_compile_error().into();
+        }
+    }
+
+    // without `key` and `convert`, the arguments making up the key: all of them but those whose type
+    // is named in `ignore_types`, e.g. the shared state of a web handler. With `extractor`, `State` and
+    // `Extension` arguments are ignored by default, and the others key the cache by `KeyExtract::Key`.
+    let ignored_types = match &args.ignore_types {
+        Some(ignore_types) => ignore_types
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>(),
+        None if args.extractor => vec!["State".to_string(), "Extension".to_string()],
+        None => vec![],
+    };
+    if (args.ignore_types.is_some() || args.extractor || args.hash_only_key) && keyed {
+        let arg = if args.hash_only_key {
+            "hash_only_key"
+        } else if args.extractor {
+            "extractor"
+        } else {
+            "ignore_types"
+        };
+        return syn::Error::new(
+            attr_arg_span(&attr_args, arg),
+            format!(
+                "`{}` applies to the default key, it can't be combined with `key`, `convert` or `config`",
+                arg
+            ),
+        )
+        .to_compile_error()
+        .into();
+    }
+    let is_ignored = |ty: &Type| type_name(ty).is_some_and(|name| ignored_types.contains(&name));
+    if args.ignore_types.is_some() {
+        if let Some(unused) = ignored_types.iter().find(|ignored| {
+            !input_tys
+                .iter()
+                .any(|ty| type_name(ty).as_deref() == Some(ignored.as_str()))
+        }) {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "ignore_types"),
+                format!(
+                    "no argument of `{}` has a `{}` type to ignore",
+                    fn_ident, unused
+                ),
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+    if args.key_hasher.is_some() && !args.hash_only_key {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "key_hasher"),
+            "`key_hasher` only applies to functions with `hash_only_key = true`",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let extractor = args.extractor;
+    let hash_only_key = args.hash_only_key;
+    let (key_tys, key_values): (Vec<_>, Vec<_>) = input_tys
+        .iter()
+        .zip(input_names)
+        .filter(|(ty, _)| !is_ignored(ty))
+        .map(|(ty, name)| {
+            if hash_only_key && !extractor {
+                // hashed by reference, the arguments are never stored
+                (quote! { #ty }, quote! { &#name })
+            } else if extractor {
+                // the key part is owned, whatever the lifetime of a borrowed argument
+                let ty = static_lifetimes(quote! { #ty });
+                (
+                    quote! { <#ty as ::cached::proc_macro::KeyExtract>::Key },
+                    quote! { ::cached::proc_macro::KeyExtract::extract_key(&#name) },
+                )
+            } else {
+                (
+                    quote! { #ty },
+                    quote! { ::std::clone::Clone::clone(&#name) },
+                )
+            }
+        })
+        .unzip();
+    // with `hash_only_key`, the key is the hash of its parts
+    let (key_tys, key_values) = if hash_only_key {
+        let hasher = match &args.key_hasher {
+            Some(hasher) => {
+                let hasher = match parse_arg::<Type>(&attr_args, "key_hasher", "a type", hasher) {
+                    Ok(hasher) => hasher,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+                respan(quote! { #hasher }, attr_arg_span(&attr_args, "key_hasher"))
+            }
+            None => quote! { ::std::collections::hash_map::DefaultHasher },
+        };
+        (
+            vec![quote! { u64 }],
+            vec![quote! { ::cached::keys::hash_key::<#hasher, _>(&(#(#key_values),*)) }],
+        )
+    } else {
+        (key_tys, key_values)
+    };
+
+    // make the cache key type and block that converts the inputs into the key type
+    let (cache_key_ty, key_convert_block) = match (&args.key, &args.convert, &args.cache_type) {
+        _ if config.is_some() => (
+            quote! { #config::Key },
+            quote! { #config::key((#(::std::clone::Clone::clone(&#input_names)),*)) },
+        ),
+        _ if args.key_fn_async.is_some() => {
+            let key_fn_async = match parse_arg::<Path>(
+                &attr_args,
+                "key_fn_async",
+                "a path",
+                args.key_fn_async.as_ref().unwrap(),
+            ) {
+                Ok(key_fn_async) => key_fn_async,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let cache_key_ty = match &args.key {
+                Some(key_str) => {
+                    let cache_key_ty = match parse_arg::<Type>(&attr_args, "key", "a type", key_str)
+                    {
+                        Ok(cache_key_ty) => cache_key_ty,
+                        Err(e) => return e.to_compile_error().into(),
+                    };
+                    quote! {#cache_key_ty}
+                }
+                None => quote! {},
+            };
+            (
+                cache_key_ty,
+                quote! { #key_fn_async(#(&#input_names),*).await },
+            )
+        }
+        (Some(key_str), None, _) if prepared_arg.is_some() => {
+            let cache_key_ty = match parse_arg::<Type>(&attr_args, "key", "a type", key_str) {
+                Ok(cache_key_ty) => cache_key_ty,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            (quote! {#cache_key_ty}, quote! { __cached_key })
+        }
+        (Some(key_str), Some(convert_str), _) => {
+            let cache_key_ty = match parse_arg::<Type>(&attr_args, "key", "a type", key_str) {
+                Ok(cache_key_ty) => cache_key_ty,
+                Err(e) => return e.to_compile_error().into(),
+            };
+
+            let key_convert_block =
+                match parse_arg::<Block>(&attr_args, "convert", "a block", convert_str) {
+                    Ok(key_convert_block) => key_convert_block,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+
+            (quote! {#cache_key_ty}, quote! {#key_convert_block})
+        }
+        (None, Some(convert_str), Some(_)) => {
+            let key_convert_block =
+                match parse_arg::<Block>(&attr_args, "convert", "a block", convert_str) {
+                    Ok(key_convert_block) => key_convert_block,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+
+            (quote! {}, quote! {#key_convert_block})
+        }
+        (None, None, _) => (quote! {(#(#key_tys),*)}, quote! {(#(#key_values),*)}),
+        (Some(_), None, _) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "key"),
+                "`key` requires `convert` to be set",
+            )
+            .to_compile_error()
+            .into();
+        }
+        (None, Some(_), None) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "convert"),
+                "`convert` requires `key` or `type` to be set",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    // `convert` blocks may use the names bound by arguments destructured with a pattern
+    let key_convert_block = if args.convert.is_some() && !outer_inputs.rebind.is_empty() {
+        let rebind = &outer_inputs.rebind;
+        quote! {
+            {
+                #rebind
+                #key_convert_block
+            }
+        }
+    } else {
+        key_convert_block
+    };
+
+    // post-process the key, e.g. to normalize the case of string keys. The helper gives the
+    // closure the key type, so it doesn't need to be annotated.
+    let key_convert_block = match &args.key_transform {
+        Some(key_transform) => {
+            let key_transform =
+                match parse_arg::<Block>(&attr_args, "key_transform", "a block", key_transform) {
+                    Ok(key_transform) => key_transform,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+            quote! {
+                {
+                    fn __cached_key_transform<K, F: ::std::ops::FnOnce(K) -> K>(key: K, transform: F) -> K {
+                        transform(key)
+                    }
+                    __cached_key_transform(#key_convert_block, #key_transform)
+                }
+            }
+        }
+        None => key_convert_block,
+    };
+
+    // prepend the current version to the key so bumping the version makes
+    // every existing entry unreachable
+    let (cache_key_ty, key_convert_block) = match &args.version_fn {
+        Some(version_fn) => {
+            let version_fn = match parse_arg::<Path>(&attr_args, "version_fn", "a path", version_fn)
+            {
+                Ok(version_fn) => version_fn,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let cache_key_ty = if cache_key_ty.is_empty() {
+                cache_key_ty
+            } else {
+                quote! {(::std::string::String, #cache_key_ty)}
+            };
+            (
+                cache_key_ty,
+                quote! {(::std::string::ToString::to_string(&#version_fn()), #key_convert_block)},
+            )
+        }
+        None => (cache_key_ty, key_convert_block),
+    };
+
+    // how the generated functions compute the key, and the arguments of the function body: with
+    // `convert_prepared`, the block computes both at once
+    let (prepare, inner_inputs, inner_args) = match (&args.convert_prepared, &prepared_arg) {
+        (Some(convert_prepared), Some(prepared_arg)) => {
+            let convert_prepared = match parse_arg::<Block>(
+                &attr_args,
+                "convert_prepared",
+                "a block",
+                convert_prepared,
+            ) {
+                Ok(convert_prepared) => convert_prepared,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let rebind = &outer_inputs.rebind;
+            (
+                quote! { let (__cached_key, __cached_prepared) = { #rebind #convert_prepared }; },
+                quote! { #prepared_arg },
+                quote! { __cached_prepared },
+            )
+        }
+        _ => (quote! {}, quote! { #inputs }, quote! { #(#input_names),* }),
+    };
+    let let_key = quote! {
+        #prepare
+        let key = #key_convert_block;
+    };
+
+    // with `at_most_once`, the function runs once for the whole program: the first result is kept
+    // in a `OnceCell` along with its key, and no other cache (or helper function) is involved
+    if args.at_most_once {
+        const ALLOWED: &[&str] = &[
+            "at_most_once",
+            "name",
+            "key",
+            "convert",
+            "key_transform",
+            "result",
+            "option",
+        ];
+        let conflicting = attr_args.iter().find_map(|arg| match arg {
+            NestedMeta::Meta(meta) if !ALLOWED.iter().any(|name| meta.path().is_ident(name)) => {
+                Some(meta)
+            }
+            _ => None,
+        });
+        if let Some(meta) = conflicting {
+            return syn::Error::new(
+                meta.span(),
+                "`at_most_once` can only be combined with `name`, `key`, `convert`, \
+                `key_transform`, `result` and `option`, the function runs once and its result is \
+                kept for the whole program",
+            )
+            .to_compile_error()
+            .into();
+        }
+        let stored_ty = quote! { (#cache_key_ty, #cache_value_ty) };
+        let await_call = if asyncness.is_some() {
+            quote! {.await}
+        } else {
+            quote! {}
+        };
+        let call_inner = quote! { #inner_fn_ident #inner_turbofish(#inner_args) #await_call };
+        // compute the stored key and value, as a `Result` for `result`/`option` functions whose
+        // failures aren't stored
+        let init = if args.result {
+            quote! { #call_inner.map(|value| (stored_key, value)) }
+        } else if args.option {
+            quote! { #call_inner.map(|value| (stored_key, value)).ok_or(()) }
+        } else {
+            quote! { (stored_key, #call_inner) }
+        };
+        let (cell_ty, cell_create, get_or_init) = match (
+            asyncness.is_some(),
+            args.result || args.option,
+        ) {
+            (false, false) => (
+                quote! { ::cached::once_cell::sync::OnceCell<#stored_ty> },
+                quote! { ::cached::once_cell::sync::OnceCell::new() },
+                quote! { #cache_ident.get_or_init(move || #init) },
+            ),
+            (false, true) => (
+                quote! { ::cached::once_cell::sync::OnceCell<#stored_ty> },
+                quote! { ::cached::once_cell::sync::OnceCell::new() },
+                quote! { #cache_ident.get_or_try_init(move || #init) },
+            ),
+            (true, false) => (
+                quote! { ::cached::once_cell::sync::Lazy<::cached::async_sync::OnceCell<#stored_ty>> },
+                quote! { ::cached::once_cell::sync::Lazy::new(::cached::async_sync::OnceCell::new) },
+                quote! { #cache_ident.get_or_init(move || async move { #init }).await },
+            ),
+            (true, true) => (
+                quote! { ::cached::once_cell::sync::Lazy<::cached::async_sync::OnceCell<#stored_ty>> },
+                quote! { ::cached::once_cell::sync::Lazy::new(::cached::async_sync::OnceCell::new) },
+                quote! { #cache_ident.get_or_try_init(move || async move { #init }).await },
+            ),
+        };
+        let ran_with_other_args = format!(
+            "`{}` is `at_most_once` and already ran with other arguments",
+            fn_ident
+        );
+        let check_key = quote! {
+            if *cached_key != key {
+                ::std::panic!(#ran_with_other_args);
+            }
+        };
+        let return_block = if args.result {
+            quote! {
+                match #get_or_init {
+                    ::std::result::Result::Ok((cached_key, value)) => {
+                        #check_key
+                        ::std::result::Result::Ok(::std::clone::Clone::clone(value))
+                    }
+                    ::std::result::Result::Err(e) => ::std::result::Result::Err(e),
+                }
+            }
+        } else if args.option {
+            quote! {
+                match #get_or_init {
+                    ::std::result::Result::Ok((cached_key, value)) => {
+                        #check_key
+                        ::std::option::Option::Some(::std::clone::Clone::clone(value))
+                    }
+                    ::std::result::Result::Err(()) => ::std::option::Option::None,
+                }
+            }
+        } else {
+            quote! {
+                let (cached_key, value) = #get_or_init;
+                #check_key
+                ::std::clone::Clone::clone(value)
+            }
+        };
+
+        let cache_ident_doc = format!(
+            "Cached static for the [`{}`] function, holding the key and result of its only run.",
+            fn_ident
+        );
+        let cache_fn_doc_extra = format!(
+            "This is an `at_most_once` cached function: it runs at most once for the whole \
+            program, later calls with the same arguments return its result from the [`{}`] \
+            cached static, and calls with other arguments panic.",
+            cache_ident
+        );
+        if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
+            attributes.push(parse_quote! { #[doc = ""] });
+            attributes.push(parse_quote! { #[doc = "# Caching"] });
+        }
+        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+
+        let mut signature_no_muts = signature;
+        signature_no_muts.inputs = outer_inputs.inputs.clone();
+        let inner_fn = if asyncness.is_some() {
+            quote! { async fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body; }
+        } else {
+            quote! { fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body; }
+        };
+        let (signature_no_muts, cached_body) = rebox_future(
+            boxed_output.as_ref(),
+            signature_no_muts,
+            quote! {
+                #inner_fn
+                #let_key
+                let stored_key = ::std::clone::Clone::clone(&key);
+                #return_block
+            },
+        );
+        return quote! {
+            #[doc = #cache_ident_doc]
+            #cache_visibility static #cache_ident: #cell_ty = #cell_create;
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #cached_body
+            }
+        }
+        .into();
+    }
+
+    // the size and time of the default stores, either literals or functions called
+    // when the cache is first used
+    let size = match literal_or_fn(&attr_args, ("size", &args.size), ("size_fn", &args.size_fn)) {
+        Ok(size) => size,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let time = match literal_or_fn(&attr_args, ("time", &args.time), ("time_fn", &args.time_fn)) {
+        Ok(time) => time,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    if args.time_to_idle.is_some() && (time.is_none() || args.time_refresh) {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "time_to_idle"),
+            "`time_to_idle` requires `time` (or `time_fn`) and can't be combined with \
+            `time_refresh`, which already expires results a lifespan after they were last retrieved",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    if args.evict_after_set.is_some()
+        && (time.is_some()
+            || args.evict_after_set == Some(0)
+            || args.at_most_once
+            || (args.key.is_none() && args.convert.is_some() && args.cache_type.is_some()))
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "evict_after_set"),
+            "`evict_after_set` must be greater than zero and can't be combined with `time` (or \
+            `time_fn`) or `at_most_once`, it removes each result a fixed number of seconds after \
+            it was cached, whether it's retrieved or not. With `type` and `convert`, the `key` \
+            type must be set too",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    if args.low_watermark.is_some()
+        && (size.is_none() || time.is_some() || args.cache_type.is_some())
+    {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "low_watermark"),
+            "`low_watermark` only applies to the default `SizedCache`, it requires `size` (or \
+            `size_fn`) and can't be combined with `time` or `type`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // the default store is unbounded, optionally warning when it grows past `warn_at` entries
+    let unbound_create = match args.warn_at {
+        Some(warn_at) => {
+            if size.is_some() || time.is_some() || args.cache_type.is_some() {
+                return syn::Error::new(
+                    attr_arg_span(&attr_args, "warn_at"),
+                    "`warn_at` only applies to the default unbounded cache, it can't be combined \
+                    with `size`, `time` or `type`",
+                )
+                .to_compile_error()
+                .into();
+            }
+            let cache_name = cache_ident.to_string();
+            quote! {::cached::UnboundCache::with_warn_threshold(#cache_name, #warn_at)}
+        }
+        None => quote! {::cached::UnboundCache::new()},
+    };
+
+    // with `trace_caller`, the location of the call that cached a value is stored alongside it
+    let returned_value_ty = cache_value_ty.clone();
+    let cache_value_ty = if args.trace_caller {
+        quote! {(::std::panic::Location<'static>, #cache_value_ty)}
+    } else {
+        cache_value_ty
+    };
+    let store_value = if args.trace_caller {
+        quote! { (*::std::panic::Location::caller(), ::std::clone::Clone::clone(result)) }
+    } else {
+        quote! { ::std::clone::Clone::clone(result) }
+    };
+
+    // make the cache type and create statement
+    let (cache_ty, cache_create) = match (
+        &args.unbound,
+        &size,
+        &time,
+        &args.cache_type,
+        args.cache_create.as_ref().or(args.create_try.as_ref()),
+        &args.time_refresh,
+    ) {
+        _ if config.is_some() => (quote! { #config::Cache }, quote! { #config::create() }),
+        (true, None, None, None, None, _) => {
+            let cache_ty = quote! {::cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create = unbound_create;
+            (cache_ty, cache_create)
+        }
+        (false, Some(size), None, None, None, _) => {
+            let cache_ty = quote! {::cached::SizedCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create = match args.low_watermark {
+                Some(low_watermark) => {
+                    quote! {::cached::SizedCache::with_size_and_watermark(#size, #low_watermark)}
+                }
+                None => quote! {::cached::SizedCache::with_size(#size)},
+            };
+            (cache_ty, cache_create)
+        }
+        (false, None, Some(time), None, None, time_refresh) => {
+            let cache_ty = quote! {::cached::TimedCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create = match args.time_to_idle {
+                Some(tti) => quote! {::cached::TimedCache::with_lifespan_and_tti(#time, #tti)},
+                None => {
+                    quote! {::cached::TimedCache::with_lifespan_and_refresh(#time, #time_refresh)}
+                }
+            };
+            (cache_ty, cache_create)
+        }
+        (false, Some(size), Some(time), None, None, time_refresh) => {
+            let cache_ty = quote! {::cached::TimedSizedCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create = match args.time_to_idle {
+                Some(tti) => {
+                    quote! {::cached::TimedSizedCache::with_size_and_lifespan_and_tti(#size, #time, #tti)}
+                }
+                None => {
+                    quote! {::cached::TimedSizedCache::with_size_and_lifespan_and_refresh(#size, #time, #time_refresh)}
+                }
+            };
+            (cache_ty, cache_create)
+        }
+        (false, None, None, None, None, _) => {
+            let cache_ty = quote! {::cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
+            let cache_create = unbound_create;
+            (cache_ty, cache_create)
+        }
+        (false, None, None, Some(type_str), Some(create_str), _) => {
+            let cache_type = match parse_arg::<Type>(&attr_args, "type", "a type", type_str) {
+                Ok(cache_type) => cache_type,
+                Err(e) => return e.to_compile_error().into(),
+            };
+
+            let cache_create = match parse_arg::<Block>(&attr_args, "create", "a block", create_str)
+            {
+                Ok(cache_create) => cache_create,
+                Err(e) => return e.to_compile_error().into(),
+            };
+
+            (quote! { #cache_type }, quote! { #cache_create })
+        }
+        (false, None, None, Some(_), None, _) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "type"),
+                "`type` requires `create` to also be set",
+            )
+            .to_compile_error()
+            .into();
+        }
+        (false, None, None, None, Some(_), _) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "create"),
+                "`create` requires `type` to also be set",
+            )
+            .to_compile_error()
+            .into();
+        }
+        _ => {
+            // report the second of the conflicting attributes
+            const CACHE_TYPES: &[&str] =
+                &["unbound", "size", "time", "type", "create", "create_try"];
+            let span = attr_args
+                .iter()
+                .filter_map(|arg| match arg {
+                    NestedMeta::Meta(meta)
+                        if CACHE_TYPES.iter().any(|name| meta.path().is_ident(name)) =>
+                    {
+                        Some(meta.span())
+                    }
+                    _ => None,
+                })
+                .nth(1)
+                .unwrap_or_else(proc_macro2::Span::call_site);
+            return syn::Error::new(
+                span,
+                "cache types (`unbound`, `size` and/or `time`, or `type` and `create`) are mutually exclusive",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+    // with a custom `type`, assert it's a store for the keys and values of the function, so a type
+    // that isn't is reported at the `type` attribute rather than at the calls in the generated code
+    let store_assertion = match &args.cache_type {
+        Some(type_str) if config.is_none() => {
+            let store_ty = match parse_arg::<Type>(&attr_args, "type", "a type", type_str) {
+                Ok(store_ty) => store_ty,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            // the key type is inferred from the store when only given by `type`
+            let key_ty = if cache_key_ty.is_empty() {
+                quote! {_}
+            } else {
+                cache_key_ty.clone()
+            };
+            let assertion = respan(
+                quote! {
+                    __cached_store_must_implement_cached::<#store_ty, #key_ty, #cache_value_ty>();
+                },
+                attr_arg_span(&attr_args, "type"),
+            );
+            quote! {
+                {
+                    fn __cached_store_must_implement_cached<C: ::cached::Cached<K, V>, K, V>() {}
+                    #assertion
+                }
+            }
+        }
+        _ => quote! {},
+    };
+    // likewise with `key`, assert the key type can key the default stores (the sized ones clone
+    // their keys), e.g. a newtype missing a derive is reported at the `key` attribute
+    let key_assertion = match &args.key {
+        Some(key_str) if config.is_none() && args.cache_type.is_none() => {
+            let key_ty = match parse_arg::<Type>(&attr_args, "key", "a type", key_str) {
+                Ok(key_ty) => key_ty,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let (assert_fn, clone_bound) = if size.is_some() {
+                (
+                    quote! { __cached_key_must_implement_hash_eq_and_clone },
+                    quote! { + ::std::clone::Clone },
+                )
+            } else {
+                (
+                    quote! { __cached_key_must_implement_hash_and_eq },
+                    quote! {},
+                )
+            };
+            let assertion = respan(
+                quote! { #assert_fn::<#key_ty>(); },
+                attr_arg_span(&attr_args, "key"),
+            );
+            quote! {
+                {
+                    fn #assert_fn<K: ::std::hash::Hash + ::std::cmp::Eq #clone_bound>() {}
+                    #assertion
+                }
+            }
+        }
+        _ => quote! {},
+    };
+
+    // with `create_try`, the create block returns a `Result`, and creating the store either
+    // panics or falls back to a `NullCache` caching nothing when it fails
+    let (cache_ty, cache_create) = match &args.create_try {
+        Some(_) => {
+            let cache_name = cache_ident.to_string();
+            if null_on_create_error {
+                (
+                    quote! { ::cached::FallibleCache<#cache_ty, #cache_key_ty, #cache_value_ty> },
+                    quote! { ::cached::FallibleCache::from_result(#cache_name, #cache_create) },
+                )
+            } else {
+                (
+                    cache_ty,
+                    quote! {
+                        match #cache_create {
+                            ::std::result::Result::Ok(cache) => cache,
+                            ::std::result::Result::Err(e) => {
+                                ::std::panic!("unable to create cache `{}`: {:?}", #cache_name, e)
+                            }
+                        }
+                    },
+                )
+            }
+        }
+        None => (cache_ty, cache_create),
+    };
+    // with `invalidation_group`, the store is emptied by `cached::invalidate_group`
+    let (cache_ty, cache_create) = match &args.invalidation_group {
+        Some(group) => (
+            quote! { ::cached::GroupedCache<#cache_ty> },
+            quote! { ::cached::GroupedCache::new(#group, #cache_create) },
+        ),
+        None => (cache_ty, cache_create),
+    };
+    // with `evict_after_set`, each value is removed a fixed number of seconds after it's set
+    let (cache_ty, cache_create) = match args.evict_after_set {
+        Some(seconds) => (
+            quote! { ::cached::EvictAfterSetCache<#cache_ty, #cache_key_ty> },
+            quote! { ::cached::EvictAfterSetCache::new(#seconds, #cache_create) },
+        ),
+        None => (cache_ty, cache_create),
+    };
+
+    // store the computed value, if the config wants it cached
+    let store = |store_stmt: proc_macro2::TokenStream| match &config {
+        Some(config) => quote! {
+            if #config::should_cache(&result) {
+                #store_stmt
+            }
+        },
+        None => store_stmt,
+    };
+    let cache_set = store(quote! { ::cached::Cached::cache_set(&mut *cache, key, #store_value); });
+    // the part of a computed result that gets stored by `store_stmt`
+    let (result, option, result_option) = (args.result, args.option, args.result_option);
+    let set_block = |store_stmt: proc_macro2::TokenStream| match (result, option) {
+        _ if result_option => quote! {
+            if let ::std::option::Option::Some(::std::result::Result::Ok(result)) = &result {
+                #store_stmt
+            }
+        },
+        (false, false) => quote! {
+            {
+                let result = &result;
+                #store_stmt
+            }
+        },
+        (true, false) => quote! {
+            if let ::std::result::Result::Ok(result) = &result {
+                #store_stmt
+            }
+        },
+        _ => quote! {
+            if let ::std::option::Option::Some(result) = &result {
+                #store_stmt
+            }
+        },
+    };
+
+    // with `lock = "none"`, the store is kept in a plain static and shared through its
+    // `ConcurrentCached` methods, returning clones of the cached values. Only the function, its
+    // priming function and `{fn}_cache_flush` are generated, the other helpers lock the store.
+    if unlocked {
+        const ALLOWED: &[&str] = &[
+            "lock",
+            "name",
+            "type",
+            "create",
+            "key",
+            "convert",
+            "key_transform",
+            "result",
+            "option",
+            "prime_name",
+        ];
+        let conflicting = attr_args.iter().find_map(|arg| match arg {
+            NestedMeta::Meta(meta) if !ALLOWED.iter().any(|name| meta.path().is_ident(name)) => {
+                Some(meta)
+            }
+            _ => None,
+        });
+        if let Some(meta) = conflicting {
+            return syn::Error::new(
+                meta.span(),
+                "`lock = \"none\"` can only be combined with `name`, `type`, `create`, `key`, \
+                `convert`, `key_transform`, `result`, `option` and `prime_name`, the store is \
+                shared without a lock",
+            )
+            .to_compile_error()
+            .into();
+        }
+        if args.cache_type.is_none() {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "lock"),
+                "`lock = \"none\"` requires `type` and `create`, a store implementing \
+                `cached::ConcurrentCached`",
+            )
+            .to_compile_error()
+            .into();
+        }
+
+        // a store that doesn't implement `ConcurrentCached` is reported at `lock`
+        let cache_get = respan(
+            quote! { ::cached::ConcurrentCached::cache_get(&*#cache_ident, &key) },
+            attr_arg_span(&attr_args, "lock"),
+        );
+        let set_cache_block = set_block(quote! {
+            ::cached::ConcurrentCached::cache_set(
+                &*#cache_ident,
+                key,
+                ::std::clone::Clone::clone(result),
+            );
+        });
+        let return_cached = if args.result {
+            quote! { ::std::result::Result::Ok(result) }
+        } else if args.option {
+            quote! { ::std::option::Option::Some(result) }
+        } else {
+            quote! { result }
+        };
+        let await_call = if asyncness.is_some() {
+            quote! {.await}
+        } else {
+            quote! {}
+        };
+        let inner_fn = if asyncness.is_some() {
+            quote! { async fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body; }
+        } else {
+            quote! { fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body; }
+        };
+
+        let mut signature_no_muts = signature;
+        signature_no_muts.inputs = outer_inputs.inputs.clone();
+        let mut prime_sig = signature_no_muts.clone();
+        prime_sig.ident = match prime_fn_ident(&fn_ident, args.prime_name.as_deref(), &attr_args) {
+            Ok(ident) => ident,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let (signature_no_muts, cached_body) = rebox_future(
+            boxed_output.as_ref(),
+            signature_no_muts,
+            quote! {
+                #inner_fn
+                #let_key
+                if let ::std::option::Option::Some(result) = #cache_get {
+                    return #return_cached;
+                }
+                let result = #inner_fn_ident #inner_turbofish(#inner_args) #await_call;
+                #set_cache_block
+                result
+            },
+        );
+        let (prime_sig, prime_body) = rebox_future(
+            boxed_output.as_ref(),
+            prime_sig,
+            quote! {
+                #inner_fn
+                #let_key
+                let result = #inner_fn_ident #inner_turbofish(#inner_args) #await_call;
+                #set_cache_block
+                result
+            },
+        );
+
+        let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
+        let prime_fn_doc = format!("Primes the cached function [`{}`].", fn_ident);
+        let flush_fn_ident = Ident::new(&format!("{}_cache_flush", &fn_ident), fn_ident.span());
+        let flush_fn_doc = format!("Empties the cache of [`{}`].", fn_ident);
+        let cache_fn_doc_extra = format!(
+            "This is a cached function that uses the [`{}`] cached static, shared without a lock.",
+            cache_ident
+        );
+        if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
+            attributes.push(parse_quote! { #[doc = ""] });
+            attributes.push(parse_quote! { #[doc = "# Caching"] });
+        }
+        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+        return quote! {
+            #[doc = #cache_ident_doc]
+            #cache_visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> = ::cached::once_cell::sync::Lazy::new(|| #cache_create);
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #cached_body
+            }
+            #[doc = #prime_fn_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                #prime_body
+            }
+            #[doc = #flush_fn_doc]
+            #[allow(dead_code)]
+            #visibility #asyncness fn #flush_fn_ident() {
+                ::cached::ConcurrentCached::cache_clear(&*#cache_ident);
+            }
+        }
+        .into();
+    }
+
+    // make the set cache and return cache blocks
+    let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
+        _ if args.result_option => {
+            let set_cache_block = set_block(cache_set);
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; r.expires_in = expires_in; return ::std::option::Option::Some(::std::result::Result::Ok(r)) }
+            } else {
+                quote! { return ::std::option::Option::Some(::std::result::Result::Ok(::std::clone::Clone::clone(result))) }
+            };
+            (set_cache_block, return_cache_block)
+        }
+        (false, false) => {
+            let set_cache_block = set_block(cache_set);
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; r.expires_in = expires_in; return r }
+            } else if args.error_on_miss {
+                quote! { return ::std::result::Result::Ok(::std::clone::Clone::clone(result)) }
+            } else {
+                quote! { return ::std::clone::Clone::clone(result) }
+            };
+            (set_cache_block, return_cache_block)
+        }
+        (true, false) => {
+            let set_cache_block = set_block(cache_set);
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; r.expires_in = expires_in; return ::std::result::Result::Ok(r) }
+            } else {
+                quote! { return ::std::result::Result::Ok(::std::clone::Clone::clone(result)) }
+            };
+            (set_cache_block, return_cache_block)
+        }
+        (_, true) => {
+            let set_cache_block = set_block(cache_set);
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; r.expires_in = expires_in; return ::std::option::Option::Some(r) }
+            } else {
+                quote! { return ::std::option::Option::Some(::std::clone::Clone::clone(result)) }
+            };
+            (set_cache_block, return_cache_block)
+        }
+    };
+    // with `with_cached_flag`, look up how long until the cached value expires too
+    let (cache_get_pattern, cache_get, on_hit_expires_in) = if args.with_cached_flag {
+        (
+            quote! { ::std::option::Option::Some((result, expires_in)) },
+            quote! { ::cached::Cached::cache_get_with_expiry(&mut *cache, &key) },
+            quote! { expires_in },
+        )
+    } else {
+        (
+            quote! { ::std::option::Option::Some(result) },
+            quote! { ::cached::Cached::cache_get(&mut *cache, &key) },
+            quote! { _ },
+        )
+    };
+    // with `lock = "rwlock"`, sync functions look values up under the read lock
+    // (reporting a store that doesn't implement `CachedRead` at `lock`)
+    let cache_read = match (rwlock, args.with_cached_flag) {
+        (true, true) => quote! { ::cached::CachedRead::cache_read_with_expiry(&*cache, &key) },
+        (true, false) => quote! { ::cached::CachedRead::cache_read(&*cache, &key) },
+        (false, _) => cache_get.clone(),
+    };
+    let cache_read = if rwlock {
+        respan(cache_read, attr_arg_span(&attr_args, "lock"))
+    } else {
+        cache_read
+    };
+    let return_cache_block = if args.trace_caller {
+        quote! { let result = &result.1; #return_cache_block }
+    } else {
+        return_cache_block
+    };
+    // with `cold_start_timeout`, sync functions return `Ok` of what the block returns (async
+    // functions return it from a closure of `get_or_set_async_with_timeout`)
+    let return_cache_block = match (args.cold_start_timeout, &asyncness) {
+        (Some(_), None) => quote! {
+            return ::std::result::Result::Ok((|| -> #output_ty { #return_cache_block })())
+        },
+        _ => return_cache_block,
+    };
+
+    // the part of a computed result that gets cached
+    let cache_result_block = match (&args.result, &args.option) {
+        _ if args.result_option => quote! {
+            match result {
+                ::std::option::Option::Some(::std::result::Result::Ok(result)) => {
+                    ::std::option::Option::Some(::std::clone::Clone::clone(result))
+                }
+                _ => ::std::option::Option::None,
+            }
+        },
+        (false, false) => {
+            quote! { ::std::option::Option::Some(::std::clone::Clone::clone(result)) }
+        }
+        (true, false) => quote! {
+            match result {
+                ::std::result::Result::Ok(result) => {
+                    ::std::option::Option::Some(::std::clone::Clone::clone(result))
+                }
+                ::std::result::Result::Err(_) => ::std::option::Option::None,
+            }
+        },
+        (_, true) => quote! {
+            match result {
+                ::std::option::Option::Some(result) => {
+                    ::std::option::Option::Some(::std::clone::Clone::clone(result))
+                }
+                ::std::option::Option::None => ::std::option::Option::None,
+            }
+        },
+    };
+    let cache_result_block = match &config {
+        Some(config) => quote! {
+            (#cache_result_block).filter(|result| #config::should_cache(result))
+        },
+        None => cache_result_block,
+    };
+
+    // The cache of an async function is shared by every task calling it, so its keys and values
+    // must be `Send`. Assert it with the user's types, so a non-`Send` type (e.g. an `Rc`) is
+    // reported where it's written instead of as errors about the generated static and future.
+    // The `Mutex` of a sync function's `static` also needs `Send` values, though not `Sync` ones.
+    let send_assertions = if asyncness.is_some() {
+        let key_tys = match (&args.key, &args.convert) {
+            _ if config.is_some() => vec![quote! { #config::Key }],
+            (Some(key_str), _) if args.convert.is_some() || args.key_fn_async.is_some() => {
+                let key_ty = match parse_arg::<Type>(&attr_args, "key", "a type", key_str) {
+                    Ok(key_ty) => key_ty,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+                vec![respan(quote! {#key_ty}, attr_arg_span(&attr_args, "key"))]
+            }
+            (None, None) if args.key_fn_async.is_none() => key_tys.clone(),
+            // the key type is given by a custom `type`
+            _ => vec![],
+        };
+        let key_assertions = key_tys.iter().map(|ty| {
+            quote_spanned! {ty.span()=>
+                __cached_async_key_must_be_send::<#ty>();
+            }
+        });
+        let value_assertion = quote_spanned! {cache_value_ty.span()=>
+            __cached_async_value_must_be_send::<#cache_value_ty>();
+        };
+        quote! {
+            {
+                fn __cached_async_key_must_be_send<T: ::std::marker::Send + ?::std::marker::Sized>() {}
+                fn __cached_async_value_must_be_send<T: ::std::marker::Send + ?::std::marker::Sized>() {}
+                #(#key_assertions)*
+                #value_assertion
+            }
+        }
+    } else {
+        let value_assertion = quote_spanned! {cache_value_ty.span()=>
+            __cached_value_must_be_send_for_static::<#cache_value_ty>();
+        };
+        quote! {
+            {
+                fn __cached_value_must_be_send_for_static<T: ::std::marker::Send + ?::std::marker::Sized>() {}
+                #value_assertion
+            }
+        }
+    };
+
+    // the hook called with the key when a miss triggers a computation, without the cache lock held
+    let on_miss = match &args.on_miss {
+        Some(on_miss) => {
+            let on_miss = match parse_arg::<Block>(&attr_args, "on_miss", "a block", on_miss) {
+                Ok(on_miss) => on_miss,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            quote! {#on_miss}
+        }
+        None => quote! {|_| {}},
+    };
+
+    let (bypass_condition, enabled_items) = enabled_switch(&fn_ident, &items_ident, &visibility);
+    // with `feature`, the cache is bypassed unless the feature is enabled in the caller's crate
+    let bypass_condition = match &args.feature {
+        Some(feature) => quote! { ::std::cfg!(not(feature = #feature)) || #bypass_condition },
+        None => bypass_condition,
+    };
+    // with `cold_start_timeout`, the function returns `Ok` of the results it computes
+    let cold_start_timeout = args.cold_start_timeout;
+    let ok = |result: proc_macro2::TokenStream| match cold_start_timeout {
+        Some(_) => quote! { ::std::result::Result::Ok(#result) },
+        None => result,
+    };
+    let computed_result = ok(quote! { result });
+
+    // with `invalidate_sibling`, computing a result to cache also empties the caches of the sibling
+    // functions, through their `{fn}_cache_flush` functions, without the cache lock held
+    let siblings = match &args.invalidate_sibling {
+        Some(siblings) => siblings
+            .split(',')
+            .map(|sibling| sibling.trim())
+            .filter(|sibling| !sibling.is_empty())
+            .map(|sibling| match parse_str::<Path>(sibling) {
+                Ok(path) if path.is_ident(&fn_ident) => Err(format!(
+                    "`{}` can't invalidate its own cache, it would empty it on every miss",
+                    fn_ident
+                )),
+                Ok(path) => Ok(path),
+                Err(_) => Err(format!("`{}` is not a function path", sibling)),
+            })
+            .collect::<Result<Vec<_>, _>>(),
+        None => Ok(vec![]),
+    };
+    let siblings = match siblings {
+        Ok(siblings) => siblings,
+        Err(message) => {
+            return syn::Error::new(attr_arg_span(&attr_args, "invalidate_sibling"), message)
+                .to_compile_error()
+                .into();
+        }
+    };
+    let await_flush_sibling = if asyncness.is_some() {
+        quote! {.await}
+    } else {
+        quote! {}
+    };
+    let flush_siblings = siblings.into_iter().map(|mut sibling| {
+        let last = sibling.segments.last_mut().expect("a path has segments");
+        last.ident = Ident::new(&format!("{}_cache_flush", last.ident), last.ident.span());
+        // a sibling of the other asyncness is an error rather than an unused future
+        quote! { let () = #sibling()#await_flush_sibling; }
+    });
+    let flush_siblings = quote! { #(#flush_siblings)* };
+    // the computed result, and for async functions the future computing it
+    let (compute, compute_future) = match (args.invalidate_sibling.is_some(), &asyncness) {
+        (false, Some(_)) => (
+            quote! { #inner_fn_ident #inner_turbofish(#inner_args).await },
+            quote! { #inner_fn_ident #inner_turbofish(#inner_args) },
+        ),
+        (false, None) => (
+            quote! { #inner_fn_ident #inner_turbofish(#inner_args) },
+            quote! {},
+        ),
+        (true, _) => {
+            let flush_block = set_block(store(flush_siblings));
+            let compute = quote! {
+                {
+                    let result = #inner_fn_ident #inner_turbofish(#inner_args)#await_flush_sibling;
+                    #flush_block
+                    result
+                }
+            };
+            let compute_future = quote! { async move #compute };
+            (compute, compute_future)
+        }
+    };
+    // the function body, and the block calling it without touching the cache while caching is disabled
+    let (inner_fn, bypass_block) = if args.error_on_miss {
+        (
+            quote! {},
+            quote! {
+                if #bypass_condition {
+                    return ::std::result::Result::Err(::cached::CacheMiss);
+                }
+            },
+        )
+    } else if asyncness.is_some() {
+        let computed = ok(quote! { #inner_fn_ident #inner_turbofish(#inner_args).await });
+        (
+            quote! { async fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body; },
+            quote! {
+                if #bypass_condition {
+                    #prepare
+                    return #computed;
+                }
+            },
+        )
+    } else {
+        let computed = ok(quote! { #inner_fn_ident #inner_turbofish(#inner_args) });
+        (
+            quote! { fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body; },
+            quote! {
+                if #bypass_condition {
+                    #prepare
+                    return #computed;
+                }
+            },
+        )
+    };
+
+    // with `placeholder`, the keys being computed, so concurrent callers return the placeholder
+    let in_flight_ident = Ident::new(&format!("__{}_IN_FLIGHT", items_ident), items_ident.span());
+    let (placeholder, in_flight_items) = match &args.placeholder {
+        Some(placeholder) => {
+            let placeholder =
+                match parse_arg::<Block>(&attr_args, "placeholder", "a block", placeholder) {
+                    Ok(placeholder) => placeholder,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+            (
+                Some(placeholder),
+                quote! {
+                    #[doc(hidden)]
+                    static #in_flight_ident: ::cached::once_cell::sync::Lazy<::cached::proc_macro::InFlight<#cache_key_ty>> = ::cached::once_cell::sync::Lazy::new(::cached::proc_macro::InFlight::new);
+                },
+            )
+        }
+        None => (None, quote! {}),
+    };
+
+    // the store the generated functions use: the static, or with the `testing` feature, the
+    // store of the innermost `{fn}_cache_scope` of the current thread (functions with `shared`
+    // have no scopes of their own and always use the shared static)
+    let store_ty = if asyncness.is_some() {
+        quote! { ::cached::async_sync::Mutex<#cache_ty> }
+    } else if rwlock {
+        quote! { ::std::sync::RwLock<#cache_ty> }
+    } else {
+        quote! { ::std::sync::Mutex<#cache_ty> }
+    };
+    // how sync functions lock the store to change it
+    let lock = if rwlock {
+        quote! { write }
+    } else {
+        quote! { lock }
+    };
+    // with `evict_after_set`, a thread removes the values of the cached static once they're due
+    let spawn_evict = match args.evict_after_set {
+        Some(seconds) => {
+            let lock = if asyncness.is_some() {
+                quote! { #cache_ident.blocking_lock() }
+            } else {
+                quote! { #cache_ident.#lock().unwrap() }
+            };
+            quote! { ::cached::proc_macro::spawn_evict_after_set(#seconds, || #lock.evict_due()); }
+        }
+        None => quote! {},
+    };
+    let (store_ref, scope_items) = if cfg!(feature = "testing") && !args.shared {
+        let scopes_ident = Ident::new(&format!("__{}_SCOPES", items_ident), items_ident.span());
+        let scope_fn_ident = Ident::new(&format!("{}_cache_scope", fn_ident), fn_ident.span());
+        let scope_fn_doc = format!(
+            "Swaps in a fresh cache for [`{}`] on the current thread until the returned scope \
+            is dropped, e.g. to give a test cached values of its own.",
+            fn_ident
+        );
+        let scope_ty = static_lifetimes(store_ty.clone());
+        let items = quote! {
+            ::std::thread_local! {
+                #[doc(hidden)]
+                static #scopes_ident: ::cached::testing::Scopes<#scope_ty> = ::std::cell::RefCell::new(::std::vec::Vec::new());
+            }
+            #[doc = #scope_fn_doc]
+            #[allow(dead_code)]
+            #visibility fn #scope_fn_ident() -> ::cached::testing::CacheScope<#scope_ty> {
+                ::cached::testing::CacheScope::enter(&#scopes_ident, <#store_ty>::new(#cache_create))
+            }
+        };
+        let store_ref =
+            quote! { (*::cached::testing::current_store(&#scopes_ident, &#cache_ident)) };
+        (store_ref, items)
+    } else {
+        (quote! { #cache_ident }, quote! {})
+    };
+
+    // how sync functions lock the cache, giving up with `ColdStartTimeout` after `cold_start_timeout`
+    let lock_cache = match args.cold_start_timeout {
+        Some(timeout) => quote! {
+            ::cached::proc_macro::lock_with_timeout(
+                &#store_ref,
+                ::std::time::Duration::from_secs(#timeout),
+            )?
+        },
+        None => quote! { #store_ref.#lock().unwrap() },
+    };
+
+    let read_cache = if rwlock {
+        quote! { let cache = #store_ref.read().unwrap(); }
+    } else {
+        quote! { let mut cache = #lock_cache; }
+    };
+
+    let sync_writes = args.sync_writes;
+    let do_set_return_block = if args.error_on_miss {
+        // cache-only reads: entries are only added by priming the cache
+        let lookup_block = if asyncness.is_some() {
+            quote! {
+                let mut cache = #store_ref.lock().await;
+                if let #cache_get_pattern = #cache_get {
+                    #return_cache_block
+                }
+            }
+        } else {
+            quote! {}
+        };
+        quote! {
+            #lookup_block
+            ::std::result::Result::Err(::cached::CacheMiss)
+        }
+    } else if let (Some(placeholder), Some(_)) = (&placeholder, &asyncness) {
+        quote! {
+            ::cached::async_sync::get_or_set_async_or_placeholder(
+                &#store_ref,
+                &#in_flight_ident,
+                key,
+                |result: &#cache_value_ty, #on_hit_expires_in| { #return_cache_block },
+                #on_miss,
+                || #compute_future,
+                |result: &#output_ty| #cache_result_block,
+                || #placeholder,
+            )
+            .await
+        }
+    } else if let Some(placeholder) = &placeholder {
+        quote! {
+            // another caller is computing the value, return the placeholder instead of waiting
+            let _computing = match #in_flight_ident.start(&key) {
+                ::std::option::Option::Some(computing) => computing,
+                ::std::option::Option::None => return #placeholder,
+            };
+            {
+                // the value may have been cached since the lookup
+                let mut cache = #store_ref.#lock().unwrap();
+                if let #cache_get_pattern = #cache_get {
+                    #return_cache_block
+                }
+            }
+
+            // run the function and cache the result
+            (#on_miss)(&key);
+            let result = #compute;
+            let mut cache = #store_ref.#lock().unwrap();
+            #set_cache_block
+            result
+        }
+    } else if let (Some(timeout), Some(_)) = (args.cold_start_timeout, &asyncness) {
+        quote! {
+            ::cached::async_sync::get_or_set_async_with_timeout(
+                &#store_ref,
+                key,
+                ::std::time::Duration::from_secs(#timeout),
+                |result: &#cache_value_ty, #on_hit_expires_in| { #return_cache_block },
+                #on_miss,
+                || #compute_future,
+                |result: &#output_ty| #cache_result_block,
+            )
+            .await
+        }
+    } else if let (Some(timeout_ms), Some(_)) = (args.compute_timeout_ms, &asyncness) {
+        // on timeout without a stale value, `option` functions return `None` and the others an error
+        let on_timeout = if args.option {
+            quote! { ::std::option::Option::None }
+        } else {
+            quote! { ::std::result::Result::Err(::std::convert::From::from(::cached::ComputeTimeout)) }
+        };
+        quote! {
+            ::cached::async_sync::get_or_set_async_with_deadline(
+                &#store_ref,
+                key,
+                #sync_writes,
+                ::std::time::Duration::from_millis(#timeout_ms),
+                |result: &#cache_value_ty, #on_hit_expires_in| { #return_cache_block },
+                #on_miss,
+                || #compute_future,
+                |result: &#output_ty| #cache_result_block,
+                || #on_timeout,
+            )
+            .await
+        }
+    } else if asyncness.is_some() {
+        quote! {
+            ::cached::async_sync::get_or_set_async(
+                &#store_ref,
+                key,
+                #sync_writes,
+                |result: &#cache_value_ty, #on_hit_expires_in| { #return_cache_block },
+                #on_miss,
+                || #compute_future,
+                |result: &#output_ty| #cache_result_block,
+            )
+            .await
+        }
+    } else if args.sync_writes {
+        quote! {
+            // try to get a write lock first
+            let mut cache = #lock_cache;
+            if let #cache_get_pattern = #cache_get {
+                #return_cache_block
+            }
+
+            // run the function and cache the result
+            (#on_miss)(&key);
+            let result = #compute;
+            #set_cache_block
+            #computed_result
+        }
+    } else {
+        quote! {
+            // run the function and cache the result
+            (#on_miss)(&key);
+            let result = #compute;
+            let mut cache = #store_ref.#lock().unwrap();
+            #set_cache_block
+            result
+        }
+    };
+
+    // the generated functions take the arguments without their binding modes, e.g. a
+    // `mut a: i32` argument is only `mut` in the `inner` function that mutates it
+    let mut signature_no_muts = signature;
+    signature_no_muts.inputs = outer_inputs.inputs.clone();
+
+    // create a signature for the cache-priming function
+    let prime_fn_ident = match prime_fn_ident(&fn_ident, args.prime_name.as_deref(), &attr_args) {
+        Ok(ident) => ident,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let mut prime_sig = signature_no_muts.clone();
+    prime_sig.ident = prime_fn_ident;
+
+    // with `prime_batched`, priming buffers the values, stored under a single acquisition of
+    // the cache lock by `{fn}_prime_flush`, or once `prime_batch_size` values are buffered
+    let batch_ident = Ident::new(
+        &format!("__{}_PRIME_BATCH", items_ident),
+        items_ident.span(),
+    );
+    let flush_fn_ident = Ident::new(&format!("{}_prime_flush", fn_ident), fn_ident.span());
+    let (await_flush, flush_lock) = if asyncness.is_some() {
+        (quote! {.await}, quote! { #store_ref.lock().await })
+    } else {
+        (quote! {}, quote! { #store_ref.#lock().unwrap() })
+    };
+    let prime_batch_items = if args.prime_batched {
+        let flush_fn_doc = format!(
+            "Stores the values buffered by [`{}`] in the cache of [`{}`], returning how many \
+            values were stored. Buffered values aren't returned by [`{}`] until they're flushed.",
+            prime_sig.ident, fn_ident, fn_ident
+        );
+        quote! {
+            #[doc(hidden)]
+            static #batch_ident: ::cached::once_cell::sync::Lazy<::std::sync::Mutex<::std::vec::Vec<(#cache_key_ty, #cache_value_ty)>>> = ::cached::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new(::std::vec::Vec::new()));
+            #[doc = #flush_fn_doc]
+            #[allow(dead_code)]
+            #visibility #asyncness fn #flush_fn_ident() -> usize {
+                let batch = ::std::mem::take(&mut *#batch_ident.lock().unwrap());
+                let flushed = batch.len();
+                if flushed > 0 {
+                    let mut cache = #flush_lock;
+                    ::cached::Cached::cache_extend(&mut *cache, batch);
+                }
+                flushed
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let prime_store_block = if args.prime_batched {
+        let buffer_block = set_block(store(quote! { batch.push((key, #store_value)); }));
+        let buffer_block = quote! {
+            let mut batch = #batch_ident.lock().unwrap();
+            #buffer_block
+        };
+        match args.prime_batch_size {
+            Some(batch_size) => quote! {
+                let flush = {
+                    #buffer_block
+                    batch.len() >= #batch_size
+                };
+                if flush {
+                    #flush_fn_ident()#await_flush;
+                }
+            },
+            None => quote! {
+                {
+                    #buffer_block
+                }
+            },
+        }
+    } else {
+        quote! {
+            let mut cache = #flush_lock;
+            #set_cache_block
+        }
+    };
+
+    let prime_do_set_return_block = if asyncness.is_some() {
+        quote! {
+            // run the function and cache the result
+            async fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body;
+            let result = #compute;
+            #prime_store_block
+            result
+        }
+    } else {
+        quote! {
+            // run the function and cache the result
+            fn #inner_fn_ident #inner_generics(#inner_inputs) #output #inner_where #body;
+            let result = #compute;
+            #prime_store_block
+            result
+        }
+    };
+
+    // for `result` and `option` functions, make a priming function reporting whether
+    // the value was cached so callers warming many keys can retry the failures
+    let try_prime_fn = if args.result || args.option || args.result_option {
+        let prime_fn_ident = &prime_sig.ident;
+        let try_prime_fn_ident =
+            Ident::new(&format!("{}_try_prime_cache", &fn_ident), fn_ident.span());
+        let mut try_prime_sig = signature_no_muts.clone();
+        try_prime_sig.ident = try_prime_fn_ident;
+        let await_prime = if asyncness.is_some() {
+            quote! {.await}
+        } else {
+            quote! {}
+        };
+        let call_prime =
+            quote! { #prime_fn_ident #inner_turbofish(#(#input_names),*) #await_prime };
+        let (try_prime_output, try_prime_body) = if args.result_option {
+            // `None`s aren't errors, they're reported as not cached
+            let result_ty = return_ty(&output)
+                .and_then(first_type_arg)
+                .expect("checked with the cached value type");
+            let error_ty = result_error_ty(result_ty).unwrap_or_else(
+                || quote! { <#result_ty as ::cached::proc_macro::ResultError>::Error },
+            );
+            (
+                quote! { -> ::std::result::Result<bool, #error_ty> },
+                quote! {
+                    match #call_prime {
+                        ::std::option::Option::Some(::std::result::Result::Ok(_)) => {
+                            ::std::result::Result::Ok(true)
+                        }
+                        ::std::option::Option::Some(::std::result::Result::Err(e)) => {
+                            ::std::result::Result::Err(e)
+                        }
+                        ::std::option::Option::None => ::std::result::Result::Ok(false),
+                    }
+                },
+            )
+        } else if args.result {
+            let error_ty = return_ty(&output).and_then(result_error_ty).unwrap_or_else(
+                || quote! { <#output_ty as ::cached::proc_macro::ResultError>::Error },
+            );
+            (
+                quote! { -> ::std::result::Result<bool, #error_ty> },
+                quote! {
+                    match #call_prime {
+                        ::std::result::Result::Ok(_) => ::std::result::Result::Ok(true),
+                        ::std::result::Result::Err(e) => ::std::result::Result::Err(e),
+                    }
+                },
+            )
+        } else {
+            (quote! { -> bool }, quote! { #call_prime.is_some() })
+        };
+        try_prime_sig.output = parse_quote! { #try_prime_output };
+        let try_prime_fn_doc = format!(
+            "Primes the cached function [`{}`], returning whether the result was cached.",
+            fn_ident
+        );
+        quote! {
+            #[doc = #try_prime_fn_doc]
+            #[allow(dead_code)]
+            #visibility #try_prime_sig {
+                #try_prime_body
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // make cached static, cached function and prime cached function doc comments
+    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
+    // with `shared`, the static is defined by another declaration, which must have the same store
+    // type. Assert it, so a mismatch is reported at `shared` rather than at each use of the store.
+    let (cache_static, shared_assertion) = if args.shared {
+        let assertion = respan(
+            quote! {
+                let _: &::cached::once_cell::sync::Lazy<#store_ty> = &#cache_ident;
+            },
+            attr_arg_span(&attr_args, "shared"),
+        );
+        (quote! {}, quote! { { #assertion } })
+    } else {
+        (
+            quote! {
+                #[doc = #cache_ident_doc]
+                #cache_visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#store_ty> = ::cached::once_cell::sync::Lazy::new(|| { #spawn_evict <#store_ty>::new(#cache_create) });
+            },
+            quote! {},
+        )
+    };
+    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
+    let cache_fn_doc_extra = format!(
+        "This is a cached function that uses the [`{}`] cached static.",
+        cache_ident
+    );
+    let (size_fn, time_fn) = (&args.size_fn, &args.time_fn);
+    let cache_fn_doc_store = cache_store_doc(
+        &cache_ty,
+        &cache_key_ty,
+        if args.key.is_none() && args.convert.is_none() && key_tys.len() == 1 {
+            key_tys[0].clone()
+        } else {
+            cache_key_ty.clone()
+        },
+        args.size
+            .map(|size| size.to_string())
+            .or_else(|| size_fn.as_ref().map(|f| format!("`{}()`", f))),
+        args.time
+            .map(|time| time.to_string())
+            .or_else(|| time_fn.as_ref().map(|f| format!("`{}()`", f))),
+        args.time_refresh,
+        args.time_to_idle,
+    );
+    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
+        attributes.push(parse_quote! { #[doc = ""] });
+        attributes.push(parse_quote! { #[doc = "# Caching"] });
+    }
+    attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+    attributes.push(parse_quote! { #[doc = ""] });
+    attributes.push(parse_quote! { #[doc = #cache_fn_doc_store] });
+
+    // a function returning the cache key of a call, e.g. to remove the value cached for it. The key
+    // type isn't known when it's only given by a custom `type`, and a `config` derives keys itself.
+    let key_fn = if cache_key_ty.is_empty() || config.is_some() {
+        quote! {}
+    } else {
+        let mut key_sig = signature_no_muts.clone();
+        // the key is only computed synchronously, unless it's awaited from `key_fn_async`
+        if args.key_fn_async.is_none() {
+            key_sig.asyncness = None;
+        }
+        key_sig.ident = Ident::new(&format!("{}_key", &fn_ident), fn_ident.span());
+        key_sig.output = parse_quote! { -> #cache_key_ty };
+        let key_fn_doc = format!(
+            "Returns the `{}` key caching the result of [`{}`] for these arguments.",
+            type_display(&cache_key_ty),
+            fn_ident
+        );
+        quote! {
+            #[doc = #key_fn_doc]
+            #[allow(dead_code, unused_variables)]
+            #visibility #key_sig {
+                #let_key
+                key
+            }
+        }
+    };
+
+    // with `with_key`, a function returning the result of a call along with its cache key, in the
+    // success variant for `result`/`option` functions
+    let with_key_fn = if args.with_key {
+        if config.is_some() {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "with_key"),
+                "`with_key` can't be combined with `config`, which derives the keys itself",
+            )
+            .to_compile_error()
+            .into();
+        }
+        if cache_key_ty.is_empty() {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "with_key"),
+                "`with_key` needs to know the key type, set it with `key` along with `convert`",
+            )
+            .to_compile_error()
+            .into();
+        }
+        let keyed_ty = quote! { (#cache_key_ty, #returned_value_ty) };
+        let error_ty = |result_ty: &Type| {
+            result_error_ty(result_ty).unwrap_or_else(
+                || quote! { <#result_ty as ::cached::proc_macro::ResultError>::Error },
+            )
+        };
+        let (with_key_output, with_key_result) = if args.error_on_miss {
+            (
+                quote! { ::std::result::Result<#keyed_ty, ::cached::CacheMiss> },
+                quote! { result.map(|value| (key, value)) },
+            )
+        } else if args.result_option {
+            let result_ty = return_ty(&output)
+                .and_then(first_type_arg)
+                .expect("checked with the cached value type");
+            let error_ty = error_ty(result_ty);
+            (
+                quote! { ::std::option::Option<::std::result::Result<#keyed_ty, #error_ty>> },
+                quote! { result.map(|result| result.map(|value| (key, value))) },
+            )
+        } else if args.result {
+            let error_ty =
+                error_ty(return_ty(&output).expect("checked with the cached value type"));
+            (
+                quote! { ::std::result::Result<#keyed_ty, #error_ty> },
+                quote! { result.map(|value| (key, value)) },
+            )
+        } else if args.option {
+            (
+                quote! { ::std::option::Option<#keyed_ty> },
+                quote! { result.map(|value| (key, value)) },
+            )
+        } else {
+            (keyed_ty, quote! { (key, result) })
+        };
+        let mut with_key_sig = signature_no_muts.clone();
+        with_key_sig.ident = Ident::new(&format!("{}_with_key", &fn_ident), fn_ident.span());
+        with_key_sig.output = parse_quote! { -> #with_key_output };
+        let await_call = if asyncness.is_some() {
+            quote! {.await}
+        } else {
+            quote! {}
+        };
+        let with_key_fn_doc = format!(
+            "Calls [`{}`], returning its result along with the `{}` key caching it.",
+            fn_ident,
+            type_display(&cache_key_ty)
+        );
+        quote! {
+            #[doc = #with_key_fn_doc]
+            #[allow(dead_code)]
+            #visibility #with_key_sig {
+                #let_key
+                let result = #fn_ident #inner_turbofish(#(#input_names),*) #await_call;
+                #with_key_result
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // functions removing a cached value, by the arguments of a call or by its key. The key type
+    // isn't known when it's only given by a custom `type`, so only the former is generated then.
+    let remove_fns = {
+        let lock = if asyncness.is_some() {
+            quote! { #store_ref.lock().await }
+        } else {
+            quote! { #store_ref.#lock().unwrap() }
+        };
+        let removed_value = if args.trace_caller {
+            quote! { .map(|(_, value)| value) }
+        } else {
+            quote! {}
+        };
+        let key_doc = if cache_key_ty.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " Values are cached by `{}` key.",
+                type_display(&cache_key_ty)
+            )
+        };
+
+        let mut remove_sig = signature_no_muts.clone();
+        remove_sig.ident = Ident::new(&format!("{}_cache_remove", &fn_ident), fn_ident.span());
+        remove_sig.output = parse_quote! { -> ::std::option::Option<#returned_value_ty> };
+        let remove_fn_doc = format!(
+            "Removes the value cached by [`{}`] for these arguments, returning it.{}",
+            fn_ident, key_doc
+        );
+        let remove_key_fn = if cache_key_ty.is_empty() {
+            quote! {}
+        } else {
+            let remove_key_fn_ident =
+                Ident::new(&format!("{}_cache_remove_key", &fn_ident), fn_ident.span());
+            let remove_key_fn_doc = format!(
+                "Removes the value cached by [`{}`] for a `{}` key, returning it.",
+                fn_ident,
+                type_display(&cache_key_ty)
+            );
+            quote! {
+                #[doc = #remove_key_fn_doc]
+                #[allow(dead_code)]
+                #visibility #asyncness fn #remove_key_fn_ident(key: &#cache_key_ty) -> ::std::option::Option<#returned_value_ty> {
+                    let mut cache = #lock;
+                    ::cached::Cached::cache_remove(&mut *cache, key)#removed_value
+                }
+            }
+        };
+        quote! {
+            #[doc = #remove_fn_doc]
+            #[allow(dead_code, unused_variables)]
+            #visibility #remove_sig {
+                #let_key
+                let mut cache = #lock;
+                ::cached::Cached::cache_remove(&mut *cache, &key)#removed_value
+            }
+            #remove_key_fn
+        }
+    };
+
+    // a function telling whether a value is cached for the arguments of a call, without cloning it
+    let contains_key_fn = {
+        let lock = if asyncness.is_some() {
+            quote! { #store_ref.lock().await }
+        } else {
+            quote! { #store_ref.#lock().unwrap() }
+        };
+        let mut contains_sig = signature_no_muts.clone();
+        contains_sig.ident = Ident::new(
+            &format!("{}_cache_contains_key", &fn_ident),
+            fn_ident.span(),
+        );
+        contains_sig.output = parse_quote! { -> bool };
+        let contains_fn_doc = format!(
+            "Returns whether [`{}`] has a value cached for these arguments, without cloning it. The lookup counts as a cache hit or miss.",
+            fn_ident
+        );
+        quote! {
+            #[doc = #contains_fn_doc]
+            #[allow(dead_code, unused_variables)]
+            #visibility #contains_sig {
+                #let_key
+                let mut cache = #lock;
+                ::cached::Cached::cache_get(&mut *cache, &key).is_some()
+            }
+        }
+    };
+
+    // a function emptying the cache, called by the functions naming this one in `invalidate_sibling`
+    let cache_flush_fn = {
+        let lock = if asyncness.is_some() {
+            quote! { #store_ref.lock().await }
+        } else {
+            // a cache poisoned by a panicking caller can still be emptied
+            quote! { #store_ref.#lock().unwrap_or_else(::std::sync::PoisonError::into_inner) }
+        };
+        let flush_fn_ident = Ident::new(&format!("{}_cache_flush", &fn_ident), fn_ident.span());
+        let flush_fn_doc = format!(
+            "Empties the cache of [`{}`] and resets its hit and miss counts.",
+            fn_ident
+        );
+        quote! {
+            #[doc = #flush_fn_doc]
+            #[allow(dead_code)]
+            #visibility #asyncness fn #flush_fn_ident() {
+                let mut cache = #lock;
+                ::cached::Cached::cache_clear(&mut *cache);
+                ::cached::Cached::cache_reset_metrics(&mut *cache);
+            }
+        }
+    };
+
+    // with `trace_caller`, a function returning a cached value along with the location that cached it
+    let get_with_location_fn = if args.trace_caller {
+        let mut get_sig = signature_no_muts.clone();
+        get_sig.ident = Ident::new(
+            &format!("{}_cache_get_with_location", &fn_ident),
+            fn_ident.span(),
+        );
+        get_sig.output = parse_quote! {
+            -> ::std::option::Option<(::std::panic::Location<'static>, #returned_value_ty)>
+        };
+        let get_fn_doc = format!(
+            "Returns the cached result of [`{}`] for these arguments, along with the location of the call that cached it.",
+            fn_ident
+        );
+        attributes.push(parse_quote! { #[track_caller] });
+        quote! {
+            #[doc = #get_fn_doc]
+            #[allow(dead_code)]
+            #visibility #get_sig {
+                #let_key
+                let mut cache = #store_ref.#lock().unwrap();
+                ::cached::Cached::cache_get(&mut *cache, &key).cloned()
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // functions pinning entries of the default timed sized cache, exempting them from expiry and eviction
+    let pin_fns = if size.is_some() && time.is_some() && args.cache_type.is_none() {
+        let lock = if asyncness.is_some() {
+            quote! { #store_ref.lock().await }
+        } else {
+            quote! { #store_ref.#lock().unwrap() }
+        };
+        let timed_sized_cache = if args.invalidation_group.is_some() {
+            quote! { cache.get_mut() }
+        } else {
+            quote! { cache }
+        };
+        let pin_fns = [("pin", "Pins"), ("unpin", "Unpins")].iter().map(|(action, verb)| {
+            let mut pin_sig = signature_no_muts.clone();
+            pin_sig.ident = Ident::new(
+                &format!("{}_cache_{}", &fn_ident, action),
+                fn_ident.span(),
+            );
+            pin_sig.output = parse_quote! { -> bool };
+            let action = Ident::new(action, fn_ident.span());
+            let pin_fn_doc = format!(
+                "{} the cached result of [`{}`] for these arguments, see [`cached::TimedSizedCache::{}`].",
+                verb, fn_ident, action
+            );
+            quote! {
+                #[doc = #pin_fn_doc]
+                #[allow(dead_code)]
+                #visibility #pin_sig {
+                    #let_key
+                    let mut cache = #lock;
+                    #timed_sized_cache.#action(&key)
+                }
+            }
+        });
+        quote! { #(#pin_fns)* }
+    } else {
+        quote! {}
+    };
+
+    let prime_track_caller = if args.trace_caller {
+        quote! { #[track_caller] }
+    } else {
+        quote! {}
+    };
+
+    // with `error_on_miss`, the cached function returns `Err(CacheMiss)` instead of computing,
+    // and with `cold_start_timeout`, `Err(ColdStartTimeout)` when the cache stays locked
+    let signature_no_muts = if args.error_on_miss {
+        let mut cached_sig = signature_no_muts;
+        cached_sig.output =
+            parse_quote! { -> ::std::result::Result<#output_ty, ::cached::CacheMiss> };
+        cached_sig
+    } else if args.cold_start_timeout.is_some() {
+        let mut cached_sig = signature_no_muts;
+        cached_sig.output =
+            parse_quote! { -> ::std::result::Result<#output_ty, ::cached::ColdStartTimeout> };
+        cached_sig
+    } else {
+        signature_no_muts
+    };
+
+    // put it all together
+    let expanded = if asyncness.is_some() {
+        let (signature_no_muts, cached_body) = rebox_future(
+            boxed_output.as_ref(),
+            signature_no_muts,
+            quote! {
+                #send_assertions
+                #store_assertion
+                #shared_assertion
+                #key_assertion
+                #inner_fn
+                #bypass_block
+                #let_key
+                #do_set_return_block
+            },
+        );
+        quote! {
+            // Cached static
+            #cache_static
+            #enabled_items
+            #scope_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #cached_body
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                #let_key
+                #prime_do_set_return_block
+            }
+            #prime_batch_items
+            #in_flight_items
+            #inflight_items
+            #try_prime_fn
+            #key_fn
+            #with_key_fn
+            #remove_fns
+            #contains_key_fn
+            #cache_flush_fn
+            #pin_fns
+        }
+    } else {
+        quote! {
+            // Cached static
+            #cache_static
+            #enabled_items
+            #scope_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #send_assertions
+                #store_assertion
+                #shared_assertion
+                #key_assertion
+                #inner_fn
+                #bypass_block
+                #let_key
+                {
+                    // check if the result is cached
+                    #read_cache
+                    if let #cache_get_pattern = #cache_read {
+                        #return_cache_block
+                    }
+                }
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #prime_track_caller
+            #visibility #prime_sig {
+                #let_key
+                #prime_do_set_return_block
+            }
+            #prime_batch_items
+            #in_flight_items
+            #inflight_items
+            #try_prime_fn
+            #key_fn
+            #with_key_fn
+            #remove_fns
+            #contains_key_fn
+            #cache_flush_fn
+            #pin_fns
+            #get_with_location_fn
+        }
+    };
+
+    expanded.into()
+}
+
+/// The return type of a function, if it returns something.
+fn return_ty(output: &ReturnType) -> Option<&Type> {
+    match output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(ty),
+    }
+}
+
+/// With `boxed`, the return type of a function returning a manually boxed future, turn the
+/// `async` signature of the cached function back into one returning its output boxed the same
+/// way, with `body` running in the boxed future
+fn rebox_future(
+    boxed: Option<&Type>,
+    mut sig: Signature,
+    body: proc_macro2::TokenStream,
+) -> (Signature, proc_macro2::TokenStream) {
+    let mut boxed = match boxed {
+        Some(boxed) => boxed.clone(),
+        None => return (sig, body),
+    };
+    if let (Some(output), Some(ty)) = (boxed_future_output(&mut boxed), return_ty(&sig.output)) {
+        *output = ty.clone();
+    }
+    sig.asyncness = None;
+    sig.output = parse_quote! { -> #boxed };
+    (sig, quote! { ::std::boxed::Box::pin(async move { #body }) })
+}
+
+/// The error type `E` of a `Result<T, E>` type, if it's spelled out.
+fn result_error_ty(ty: &Type) -> Option<proc_macro2::TokenStream> {
+    match type_args(ty)?.nth(1) {
+        Some(GenericArgument::Type(error_ty)) => Some(quote! {#error_ty}),
+        _ => None,
+    }
+}
+
+/// Describe the cache of a function for its documentation, e.g. "Results are cached in a
+/// `cached::SizedCache<u32, String>` by `u32` key, keeping at most 100 results."
+fn cache_store_doc(
+    cache_ty: &proc_macro2::TokenStream,
+    cache_key_ty: &proc_macro2::TokenStream,
+    key_display_ty: proc_macro2::TokenStream,
+    size: Option<String>,
+    time: Option<String>,
+    time_refresh: bool,
+    time_to_idle: Option<u64>,
+) -> String {
+    let key_display = type_display(&key_display_ty);
+    // show single argument keys as `u32` rather than `(u32)`, and `cached::SizedCache` rather
+    // than the fully qualified `::cached::SizedCache` of the generated code
+    let cache_ty_display = type_display(cache_ty);
+    let cache_ty_display = cache_ty_display.trim_start_matches("::").replacen(
+        &format!("<{},", type_display(cache_key_ty)),
+        &format!("<{},", key_display),
+        1,
+    );
+    let mut doc = format!("Results are cached in a `{}`", cache_ty_display);
+    if !key_display.is_empty() && key_display != "()" {
+        doc.push_str(&format!(" by `{}` key", key_display));
+    }
+    match (&size, &time) {
+        (Some(size), Some(time)) => doc.push_str(&format!(
+            ", keeping at most {} results for {} seconds",
+            size, time
+        )),
+        (Some(size), None) => doc.push_str(&format!(", keeping at most {} results", size)),
+        (None, Some(time)) => doc.push_str(&format!(", keeping results for {} seconds", time)),
+        (None, None) => {}
+    }
+    if time.is_some() && time_refresh {
+        doc.push_str(" (refreshed when retrieved)");
+    }
+    if let (Some(_), Some(tti)) = (&time, time_to_idle) {
+        doc.push_str(&format!(
+            " or {} seconds without being retrieved, whichever comes first",
+            tti
+        ));
+    }
+    doc.push('.');
+    doc
+}
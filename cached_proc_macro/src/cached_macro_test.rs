@@ -0,0 +1,109 @@
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, AttributeArgs, Ident, Item, ItemFn, Stmt};
+
+#[derive(FromMeta)]
+struct CachedMacroTestArgs {}
+
+/// A `#[cached]` function declared in the body of the test function.
+struct InnerCache {
+    flush_fn: Ident,
+    is_async: bool,
+}
+
+/// Find the `{fn}_cache_flush` function generated for a `#[cached]` function declared inside the
+/// test body. The `cached` attribute hasn't been expanded yet, so the name is derived the same way
+/// the `cached` macro derives it. Flushing through it empties the store however it's locked.
+fn inner_cache(item_fn: &syn::ItemFn) -> Option<InnerCache> {
+    item_fn.attrs.iter().find(|attr| {
+        attr.path
+            .segments
+            .last()
+            .map(|seg| seg.ident == "cached")
+            .unwrap_or(false)
+    })?;
+
+    let fn_ident = &item_fn.sig.ident;
+    Some(InnerCache {
+        flush_fn: Ident::new(&format!("{}_cache_flush", fn_ident), fn_ident.span()),
+        is_async: item_fn.sig.asyncness.is_some(),
+    })
+}
+
+pub fn cached_macro_test(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    if let Err(e) = CachedMacroTestArgs::from_list(&attr_args) {
+        return TokenStream::from(e.write_errors());
+    }
+    let input = parse_macro_input!(input as ItemFn);
+
+    let attributes = input.attrs;
+    let visibility = input.vis;
+    let signature = input.sig;
+    let body = input.block;
+    let asyncness = signature.asyncness;
+
+    let caches = body
+        .stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Item(Item::Fn(item_fn)) => inner_cache(item_fn),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if caches.is_empty() {
+        return syn::Error::new(
+            signature.ident.span(),
+            "`cached_macro_test` expects at least one `#[cached]` function declared in the body",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // Flush every cache before the test body runs, and again once it returns or unwinds. The
+    // drop guard can't wait for the caches of async functions, they're only emptied when they
+    // aren't locked; the next run will flush the cache before starting anyway.
+    let flush_before = caches
+        .iter()
+        .map(|InnerCache { flush_fn, is_async }| {
+            if *is_async && asyncness.is_some() {
+                quote! { #flush_fn().await; }
+            } else if *is_async {
+                quote! { ::cached::proc_macro::flush_now(#flush_fn()); }
+            } else {
+                quote! { #flush_fn(); }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let flush_after = caches
+        .iter()
+        .map(|InnerCache { flush_fn, is_async }| {
+            if *is_async {
+                quote! { ::cached::proc_macro::flush_now(#flush_fn()); }
+            } else {
+                quote! { #flush_fn(); }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let stmts = body.stmts;
+    let expanded = quote! {
+        #(#attributes)*
+        #visibility #signature {
+            struct CachedMacroTestGuard;
+            impl ::std::ops::Drop for CachedMacroTestGuard {
+                fn drop(&mut self) {
+                    #(#flush_after)*
+                }
+            }
+            #(#flush_before)*
+            let _cached_macro_test_guard = CachedMacroTestGuard;
+            #(#stmts)*
+        }
+    };
+
+    expanded.into()
+}
@@ -0,0 +1,744 @@
+use proc_macro2::{Group, Ident, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::parse::Parse;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::token::Comma;
+use syn::{
+    parse_quote, parse_str, FnArg, GenericArgument, GenericParam, Generics, NestedMeta, Pat, Path,
+    PathArguments, ReturnType, Signature, Type, TypeParamBound,
+};
+
+/// Returns the span of the first `Self` token found in `tokens`, if any.
+fn find_self_type(tokens: TokenStream) -> Option<proc_macro2::Span> {
+    tokens.into_iter().find_map(|tt| match tt {
+        TokenTree::Ident(ident) if ident == "Self" => Some(ident.span()),
+        TokenTree::Group(group) => find_self_type(group.stream()),
+        _ => None,
+    })
+}
+
+/// Caches are stored in a `static` generated next to the function, so methods can't be
+/// cached: a `static` can't be declared inside an `impl` block, and it can't name `Self`
+/// or the generic parameters of a generic `impl`. Produce an error explaining this instead
+/// of generating code that fails to compile somewhere confusing.
+pub(super) fn check_not_method(sig: &Signature, macro_name: &str) -> Result<(), syn::Error> {
+    for input in &sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new(
+                    receiver.self_token.span,
+                    format!(
+                        "`#[{macro_name}]` does not support methods (functions taking `self`). \
+                        The cache is a `static`, which can't be declared in an `impl` block or depend \
+                        on the `impl`'s generic parameters. Cache a free function instead and call it \
+                        from the method with the parts of `self` that identify the result, e.g. \
+                        `fn {fn_name}(&self, ..) {{ {fn_name}_cached(self.id, ..) }}`",
+                        macro_name = macro_name,
+                        fn_name = sig.ident,
+                    ),
+                ));
+            }
+            FnArg::Typed(pat_type) => {
+                if let Some(span) = find_self_type(pat_type.ty.to_token_stream()) {
+                    return Err(self_type_error(span, macro_name));
+                }
+            }
+        }
+    }
+    if let ReturnType::Type(_, ty) = &sig.output {
+        if let Some(span) = find_self_type(ty.to_token_stream()) {
+            return Err(self_type_error(span, macro_name));
+        }
+    }
+    Ok(())
+}
+
+fn self_type_error(span: proc_macro2::Span, macro_name: &str) -> syn::Error {
+    syn::Error::new(
+        span,
+        format!(
+            "`#[{}]` functions can't use `Self` in their signature. The cache is a `static` \
+            declared outside of the `impl` block, so it can't name `Self`. Spell out the \
+            concrete type, or cache a free function and call it from the associated function",
+            macro_name
+        ),
+    )
+}
+
+/// The generic parameters, turbofish and `where` clause used to declare and call the
+/// inner function with the same generics as the cached function, so function bodies
+/// referring to type or const parameters still compile.
+pub(super) fn inner_generics(generics: &Generics) -> (TokenStream, TokenStream, TokenStream) {
+    let params = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(ty) => Some(&ty.ident),
+            GenericParam::Const(c) => Some(&c.ident),
+            GenericParam::Lifetime(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let turbofish = if params.is_empty() {
+        quote! {}
+    } else {
+        quote! {::<#(#params),*>}
+    };
+    let where_clause = &generics.where_clause;
+    (quote! {#generics}, turbofish, quote! {#where_clause})
+}
+
+/// The name of the function holding the body of the cached function, prefixed so it doesn't
+/// shadow the items the body calls, e.g. a function of the module named `inner`.
+pub(super) fn inner_fn_ident(fn_ident: &Ident) -> Ident {
+    Ident::new(&format!("__cached_inner_{}", fn_ident), fn_ident.span())
+}
+
+/// The name of the cache-priming function: `{fn}_prime_cache`, or the `prime_name` macro
+/// argument, e.g. when another item of the module already uses the default name. Such a
+/// clash can't be detected from the macro, only a `prime_name` that isn't a valid name or
+/// names the cached function itself.
+pub(super) fn prime_fn_ident(
+    fn_ident: &Ident,
+    prime_name: Option<&str>,
+    args: &[NestedMeta],
+) -> Result<Ident, syn::Error> {
+    let prime_name = match prime_name {
+        Some(prime_name) => prime_name,
+        None => {
+            return Ok(Ident::new(
+                &format!("{}_prime_cache", fn_ident),
+                fn_ident.span(),
+            ))
+        }
+    };
+    let span = attr_arg_span(args, "prime_name");
+    match syn::parse_str::<Ident>(prime_name) {
+        Ok(ident) if ident == *fn_ident => Err(syn::Error::new(
+            span,
+            format!(
+                "`prime_name = \"{}\"` is the name of the cached function, choose another name \
+                for its cache-priming function",
+                prime_name
+            ),
+        )),
+        Ok(ident) => Ok(Ident::new(&ident.to_string(), fn_ident.span())),
+        Err(_) => Err(syn::Error::new(
+            span,
+            format!(
+                "`prime_name = \"{}\"` isn't a valid function name",
+                prime_name
+            ),
+        )),
+    }
+}
+
+/// The span of the `name = ...` macro argument, falling back to the macro call site.
+pub(super) fn attr_arg_span(args: &[NestedMeta], name: &str) -> Span {
+    args.iter()
+        .find_map(|arg| match arg {
+            NestedMeta::Meta(meta) if meta.path().is_ident(name) => Some(meta.span()),
+            _ => None,
+        })
+        .unwrap_or_else(Span::call_site)
+}
+
+/// Parse the string value of the macro argument `name`, e.g. the type given by `key = "..."`,
+/// reporting a failure as an error at the argument, along with what failed to parse.
+pub(super) fn parse_arg<T: Parse>(
+    args: &[NestedMeta],
+    name: &str,
+    kind: &str,
+    value: &str,
+) -> Result<T, syn::Error> {
+    parse_str::<T>(value).map_err(|e| {
+        syn::Error::new(
+            attr_arg_span(args, name),
+            format!("unable to parse `{}` as {}: {}", name, kind, e),
+        )
+    })
+}
+
+/// A numeric macro argument, given either as a literal (`size = 100`) or as a string-quoted
+/// expression, e.g. of constants (`size = "MAX_USERS * 2"`).
+pub(super) enum LitOrExpr {
+    Lit(syn::LitInt),
+    // the expression, and its source for documentation
+    Expr(Box<syn::Expr>, String),
+}
+
+impl darling::FromMeta for LitOrExpr {
+    fn from_value(value: &syn::Lit) -> darling::Result<Self> {
+        match value {
+            syn::Lit::Int(lit) => Ok(LitOrExpr::Lit(lit.clone())),
+            syn::Lit::Str(s) => s
+                .parse::<syn::Expr>()
+                .map(|expr| LitOrExpr::Expr(Box::new(expr), s.value()))
+                .map_err(|e| darling::Error::custom(format!("unable to parse expression: {}", e))),
+            _ => Err(darling::Error::unexpected_lit_type(value)),
+        }
+        .map_err(|e| e.with_span(value))
+    }
+}
+
+impl ToTokens for LitOrExpr {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            LitOrExpr::Lit(lit) => lit.to_tokens(tokens),
+            LitOrExpr::Expr(expr, _) => tokens.extend(quote! { (#expr) }),
+        }
+    }
+}
+
+impl std::fmt::Display for LitOrExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LitOrExpr::Lit(lit) => write!(f, "{}", lit),
+            LitOrExpr::Expr(_, source) => write!(f, "`{}`", source.trim()),
+        }
+    }
+}
+
+/// Resolve a numeric cache parameter given either as a literal (`size = 10`) or as
+/// a function called when the cache is created (`size_fn = "config::cache_size"`).
+pub(super) fn literal_or_fn<T: ToTokens>(
+    args: &[NestedMeta],
+    (name, literal): (&str, &Option<T>),
+    (fn_name, function): (&str, &Option<String>),
+) -> Result<Option<TokenStream>, syn::Error> {
+    match (literal, function) {
+        (Some(literal), None) => Ok(Some(quote! {#literal})),
+        (None, Some(function)) => {
+            let function = parse_str::<Path>(function).map_err(|e| {
+                syn::Error::new(
+                    attr_arg_span(args, fn_name),
+                    format!("unable to parse `{}` path: {}", fn_name, e),
+                )
+            })?;
+            Ok(Some(quote! {#function()}))
+        }
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err(syn::Error::new(
+            attr_arg_span(args, fn_name),
+            format!("`{}` and `{}` are mutually exclusive", name, fn_name),
+        )),
+    }
+}
+
+/// Set the span of every token in `tokens`, so errors about types parsed from
+/// string arguments point at the argument rather than the whole attribute.
+pub(super) fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut tt| {
+            if let TokenTree::Group(group) = &tt {
+                let mut respanned = Group::new(group.delimiter(), respan(group.stream(), span));
+                respanned.set_span(span);
+                tt = TokenTree::Group(respanned);
+            } else {
+                tt.set_span(span);
+            }
+            tt
+        })
+        .collect()
+}
+
+/// Give the elided (`&T`) and anonymous (`'_`) lifetimes of type `tokens` the `'static`
+/// lifetime they have in the type of a static, so the type can also be written in a signature.
+pub(super) fn static_lifetimes(tokens: TokenStream) -> TokenStream {
+    let mut tokens = tokens.into_iter().peekable();
+    let mut out = TokenStream::new();
+    while let Some(tt) = tokens.next() {
+        match tt {
+            TokenTree::Group(group) => {
+                let mut group_static =
+                    Group::new(group.delimiter(), static_lifetimes(group.stream()));
+                group_static.set_span(group.span());
+                out.extend(Some(TokenTree::Group(group_static)));
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '&' => {
+                let span = punct.span();
+                out.extend(Some(TokenTree::Punct(punct)));
+                if !matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '\'') {
+                    out.extend(quote_spanned! {span=> 'static});
+                }
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                if matches!(tokens.peek(), Some(TokenTree::Ident(ident)) if ident == "_") {
+                    tokens.next();
+                    out.extend(quote_spanned! {punct.span()=> 'static});
+                } else {
+                    out.extend(Some(TokenTree::Punct(punct)));
+                }
+            }
+            tt => out.extend(Some(tt)),
+        }
+    }
+    out
+}
+
+/// Find a lifetime of `lifetimes`, e.g. the lifetime parameters of a function, in type `tokens`,
+/// returning the lifetime and its span.
+pub(super) fn find_lifetime(tokens: TokenStream, lifetimes: &[Ident]) -> Option<(Ident, Span)> {
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        match tt {
+            TokenTree::Group(group) => {
+                if let Some(found) = find_lifetime(group.stream(), lifetimes) {
+                    return Some(found);
+                }
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                if let Some(TokenTree::Ident(ident)) = tokens.peek() {
+                    if lifetimes.contains(ident) {
+                        return Some((
+                            ident.clone(),
+                            punct
+                                .span()
+                                .join(ident.span())
+                                .unwrap_or_else(|| ident.span()),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the name of `ty`, the last segment of its path, seeing through references: `State` for
+/// `axum::extract::State<AppState>` or `&State<AppState>`.
+pub(super) fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        Type::Reference(reference) => type_name(&reference.elem),
+        Type::Paren(paren) => type_name(&paren.elem),
+        Type::Group(group) => type_name(&group.elem),
+        _ => None,
+    }
+}
+
+/// Returns the first `f32`/`f64` found in `ty`, if any.
+fn find_float(ty: &Type) -> Option<&Ident> {
+    match ty {
+        Type::Path(type_path) => type_path.path.segments.iter().find_map(|segment| {
+            if segment.ident == "f32" || segment.ident == "f64" {
+                return Some(&segment.ident);
+            }
+            match &segment.arguments {
+                PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+                    GenericArgument::Type(ty) => find_float(ty),
+                    _ => None,
+                }),
+                _ => None,
+            }
+        }),
+        Type::Reference(reference) => find_float(&reference.elem),
+        Type::Slice(slice) => find_float(&slice.elem),
+        Type::Array(array) => find_float(&array.elem),
+        Type::Paren(paren) => find_float(&paren.elem),
+        Type::Group(group) => find_float(&group.elem),
+        Type::Tuple(tuple) => tuple.elems.iter().find_map(find_float),
+        _ => None,
+    }
+}
+
+/// Without `key` and `convert`, the function arguments make up the cache key, so they must be
+/// `Hash + Eq`. Floats are neither, so report them with a suggested `convert` instead of an
+/// error about the bounds of the generated cache.
+pub(super) fn check_hashable_inputs(sig: &Signature, macro_name: &str) -> Result<(), syn::Error> {
+    for input in &sig.inputs {
+        if let FnArg::Typed(pat_type) = input {
+            if let Some(float) = find_float(&pat_type.ty) {
+                let arg = match pat_type.pat.as_ref() {
+                    Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                    _ => "arg".to_string(),
+                };
+                let is_float = |ty: &Type| matches!(ty, Type::Path(p) if p.path.is_ident(float));
+                let suggestion = match pat_type.ty.as_ref() {
+                    ty if is_float(ty) => format!(
+                        "key = \"cached::keys::F64Key\", convert = r#\"{{ cached::keys::F64Key::from({}) }}\"#",
+                        arg
+                    ),
+                    Type::Reference(r) if is_float(&r.elem) => format!(
+                        "key = \"cached::keys::F64Key\", convert = r#\"{{ cached::keys::F64Key::from(*{}) }}\"#",
+                        arg
+                    ),
+                    _ => format!(
+                        "key = \"cached::keys::BytesKey\", convert = r#\"{{ cached::keys::BytesKey::from(&{}[..]) }}\"#",
+                        arg
+                    ),
+                };
+                return Err(syn::Error::new(
+                    float.span(),
+                    format!(
+                        "`#[{macro_name}]` can't use `{arg}` in the cache key: `{float}` doesn't implement \
+                        `Hash` or `Eq`. Convert the arguments into a key instead, e.g. `{suggestion}` \
+                        (see the `cached::keys` module), or round the value to an integer",
+                        macro_name = macro_name,
+                        arg = arg,
+                        float = float,
+                        suggestion = suggestion,
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the first of the `names` found in `tokens`, if any.
+fn find_ident(tokens: TokenStream, names: &[&Ident]) -> Option<Ident> {
+    tokens.into_iter().find_map(|tt| match tt {
+        TokenTree::Ident(ident) if names.contains(&&ident) => Some(ident),
+        TokenTree::Group(group) => find_ident(group.stream(), names),
+        _ => None,
+    })
+}
+
+/// The cache of a function is a single `static` shared by every instantiation of the function,
+/// so its key and values can't depend on the generic parameters. Without `key` and `convert`
+/// (`keyed`), the arguments make up the key and the instantiations would share values, so
+/// report the parameters with a suggested key including the const ones instead of errors about
+/// generic statics.
+pub(super) fn check_generic_params(
+    sig: &Signature,
+    keyed: bool,
+    macro_name: &str,
+) -> Result<(), syn::Error> {
+    let params = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(ty) => Some((&ty.ident, None)),
+            GenericParam::Const(c) => Some((&c.ident, Some(&c.ty))),
+            GenericParam::Lifetime(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let names = params.iter().map(|(ident, _)| *ident).collect::<Vec<_>>();
+    if let ReturnType::Type(_, ty) = &sig.output {
+        if let Some(param) = find_ident(ty.to_token_stream(), &names) {
+            return Err(syn::Error::new(
+                param.span(),
+                format!(
+                    "the return type of a `#[{macro_name}]` function can't depend on its generic \
+                    parameter `{param}`: every instantiation of the function shares a single cache",
+                    macro_name = macro_name,
+                    param = param,
+                ),
+            ));
+        }
+    }
+    let (ident, const_ty) = match params.first() {
+        Some(param) if !keyed => *param,
+        _ => return Ok(()),
+    };
+    let help = match const_ty {
+        Some(const_ty) => format!(
+            "Set `key` and `convert` to a key including `{ident}` instead, e.g. for an array \
+            argument `data: [u8; {ident}]`: `key = \"({ty}, Vec<u8>)\", convert = r#\"{{ ({ident}, data.to_vec()) }}\"#`",
+            ident = ident,
+            ty = const_ty.to_token_stream().to_string().replace(' ', ""),
+        ),
+        None => format!(
+            "Set `key` and `convert` to a key of a concrete type instead, e.g. \
+            `key = \"String\", convert = r#\"{{ arg.to_string() }}\"#` for an argument of a `{ident}: Display`, \
+            including what tells the instantiations of `{ident}` apart if their results differ",
+            ident = ident,
+        ),
+    };
+    Err(syn::Error::new(
+        ident.span(),
+        format!(
+            "`#[{macro_name}]` can't key its cache on the arguments of a function generic over \
+            `{ident}`: every instantiation of the function shares a single cache. {help}",
+            macro_name = macro_name,
+            ident = ident,
+            help = help,
+        ),
+    ))
+}
+
+/// The generic arguments of the last segment of a type path.
+pub(super) fn type_args(ty: &Type) -> Option<impl Iterator<Item = &GenericArgument>> {
+    if let Type::Path(typepath) = ty {
+        if let PathArguments::AngleBracketed(brackets) = &typepath.path.segments.last()?.arguments {
+            return Some(brackets.args.iter());
+        }
+    }
+    None
+}
+
+/// The first type argument of a type, e.g. `T` for `Option<T>`.
+pub(super) fn first_type_arg(ty: &Type) -> Option<&Type> {
+    match type_args(ty)?.next() {
+        Some(GenericArgument::Type(arg_ty)) => Some(arg_ty),
+        _ => None,
+    }
+}
+
+/// The only type argument of `ty` if it's named `name`, e.g. `T` for `Box<T>`.
+fn only_type_arg_mut<'a>(ty: &'a mut Type, name: &str) -> Option<&'a mut Type> {
+    let segment = match ty {
+        Type::Path(typepath) if typepath.qself.is_none() => typepath.path.segments.last_mut()?,
+        _ => return None,
+    };
+    if segment.ident != name {
+        return None;
+    }
+    match &mut segment.arguments {
+        PathArguments::AngleBracketed(brackets) if brackets.args.len() == 1 => {
+            match brackets.args.first_mut()? {
+                GenericArgument::Type(arg_ty) => Some(arg_ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The `Output` of a manually boxed future, e.g. `T` for `Pin<Box<dyn Future<Output = T> + Send>>`,
+/// returned mutably so it can be replaced by the output of the generated function.
+pub(super) fn boxed_future_output(ty: &mut Type) -> Option<&mut Type> {
+    let boxed = only_type_arg_mut(ty, "Pin")?;
+    let bounds = match only_type_arg_mut(boxed, "Box")? {
+        Type::TraitObject(object) => &mut object.bounds,
+        _ => return None,
+    };
+    bounds.iter_mut().find_map(|bound| {
+        let segment = match bound {
+            TypeParamBound::Trait(bound) => bound.path.segments.last_mut()?,
+            _ => return None,
+        };
+        match &mut segment.arguments {
+            PathArguments::AngleBracketed(brackets) if segment.ident == "Future" => {
+                brackets.args.iter_mut().find_map(|arg| match arg {
+                    GenericArgument::Binding(binding) if binding.ident == "Output" => {
+                        Some(&mut binding.ty)
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Whether `ty` is the `cached::Return<T>` wrapper of `with_cached_flag`, either imported
+/// (`Return<T>`) or through a path ending in `cached::Return<T>`. `MyReturn<T>` or the
+/// `Return` of another module aren't.
+pub(super) fn is_return_ty(ty: &Type) -> bool {
+    let path = match ty {
+        Type::Group(group) => return is_return_ty(&group.elem),
+        Type::Path(typepath) if typepath.qself.is_none() => &typepath.path,
+        _ => return false,
+    };
+    let mut segments = path.segments.iter().rev();
+    match segments.next() {
+        Some(last)
+            if last.ident == "Return"
+                && matches!(last.arguments, PathArguments::AngleBracketed(_)) => {}
+        _ => return false,
+    }
+    match segments.next() {
+        Some(module) => module.ident == "cached",
+        None => true,
+    }
+}
+
+/// With `with_cached_flag`, check that the function returns `cached::Return<T>` wrapped in
+/// `layers` types, e.g. one for `Result<cached::Return<T>, E>`, walking their first type
+/// argument. `expected` spells out the required return type and `mode` the macro arguments
+/// requiring it, if any, for the error.
+pub(super) fn check_cached_flag_output(
+    output: &ReturnType,
+    layers: usize,
+    expected: &str,
+    mode: Option<&str>,
+) -> Result<(), syn::Error> {
+    let ty = match output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(ty.as_ref()),
+    };
+    let flag_ty = (0..layers).fold(ty, |ty, _| ty.and_then(first_type_arg));
+    if matches!(flag_ty, Some(ty) if is_return_ty(ty)) {
+        return Ok(());
+    }
+    let found = match ty {
+        Some(ty) => format!("`{}`", type_display(&ty.to_token_stream())),
+        None => "no return type".to_string(),
+    };
+    let mode = mode.map_or(String::new(), |mode| format!(" with {}", mode));
+    let span = ty.map_or_else(|| output.span(), |ty| ty.span());
+    Err(syn::Error::new(
+        span,
+        format!(
+            "`with_cached_flag = true`{} requires the function to return `{}`, found {}. \
+            The `cached::Return` wrapper tells whether the value came from the cache",
+            mode, expected, found
+        ),
+    ))
+}
+
+/// Render type tokens for documentation, e.g. `SizedCache<(String, u32), User>`, only
+/// spacing apart consecutive words and items of lists.
+pub(super) fn type_display(tokens: &TokenStream) -> String {
+    fn render(tokens: TokenStream, out: &mut String) {
+        let mut prev_word = false;
+        for tt in tokens {
+            match tt {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        proc_macro2::Delimiter::Parenthesis => ("(", ")"),
+                        proc_macro2::Delimiter::Bracket => ("[", "]"),
+                        proc_macro2::Delimiter::Brace => ("{ ", " }"),
+                        proc_macro2::Delimiter::None => ("", ""),
+                    };
+                    out.push_str(open);
+                    render(group.stream(), out);
+                    out.push_str(close);
+                    prev_word = false;
+                }
+                TokenTree::Punct(punct) => {
+                    out.push(punct.as_char());
+                    if punct.as_char() == ',' || punct.as_char() == ';' {
+                        out.push(' ');
+                    }
+                    prev_word = false;
+                }
+                TokenTree::Ident(_) | TokenTree::Literal(_) => {
+                    if prev_word {
+                        out.push(' ');
+                    }
+                    out.push_str(&tt.to_string());
+                    prev_word = true;
+                }
+            }
+        }
+    }
+    let mut out = String::new();
+    render(tokens.clone(), &mut out);
+    out
+}
+
+/// The runtime switch of a single cached function: the condition under which the function
+/// must bypass its cache (caching disabled globally or for this function), and the items
+/// declaring the function's flag and its `{fn}_set_enabled` function.
+pub(super) fn enabled_switch(
+    fn_ident: &Ident,
+    cache_ident: &Ident,
+    visibility: &syn::Visibility,
+) -> (TokenStream, TokenStream) {
+    let flag_ident = Ident::new(&format!("__{}_ENABLED", cache_ident), cache_ident.span());
+    let set_enabled_ident = Ident::new(&format!("{}_set_enabled", fn_ident), fn_ident.span());
+    let set_enabled_doc = format!(
+        "Enables or disables the cache of [`{}`], returning whether it was enabled. While disabled, \
+        the function computes its result without reading or writing the cache.",
+        fn_ident
+    );
+    let bypass_condition = quote! {
+        !::cached::caching_enabled()
+            || !#flag_ident.load(::std::sync::atomic::Ordering::Relaxed)
+    };
+    let items = quote! {
+        #[doc(hidden)]
+        static #flag_ident: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(true);
+        #[doc = #set_enabled_doc]
+        #[allow(dead_code)]
+        #visibility fn #set_enabled_ident(enabled: bool) -> bool {
+            #flag_ident.swap(enabled, ::std::sync::atomic::Ordering::Relaxed)
+        }
+    };
+    (bypass_condition, items)
+}
+
+/// The arguments of the functions generated for a cached function, the names passing them on
+/// to the `inner` function (which keeps the original patterns), and the statements re-binding
+/// the names of destructuring patterns for `convert` blocks.
+pub(super) struct OuterInputs {
+    pub(super) inputs: Punctuated<FnArg, Comma>,
+    pub(super) names: Vec<Ident>,
+    pub(super) rebind: TokenStream,
+}
+
+/// Strip the `ref` and `mut` binding modes from simple arguments, which would only cause
+/// warnings about unused `mut`s in the generated functions. Other patterns (tuples, structs,
+/// `name @ pattern`, ...) can't be passed on by name, so the generated functions take the
+/// argument as `__cached_arg_{index}` and re-bind the names of the pattern by reference.
+pub(super) fn outer_inputs(inputs: &Punctuated<FnArg, Comma>) -> OuterInputs {
+    let mut outer = OuterInputs {
+        inputs: Punctuated::new(),
+        names: vec![],
+        rebind: quote! {},
+    };
+    for (index, input) in inputs.iter().enumerate() {
+        let pat_type = match input {
+            FnArg::Receiver(_) => {
+                outer.inputs.push(input.clone());
+                continue;
+            }
+            FnArg::Typed(pat_type) => pat_type,
+        };
+        let mut pat_type = pat_type.clone();
+        match pat_type.pat.as_mut() {
+            Pat::Ident(pat_ident) if pat_ident.subpat.is_none() => {
+                pat_ident.by_ref = None;
+                pat_ident.mutability = None;
+                outer.names.push(pat_ident.ident.clone());
+            }
+            pat => {
+                let name = Ident::new(&format!("__cached_arg_{}", index), pat.span());
+                let mut rebind_pat = pat.clone();
+                strip_binding_modes(&mut rebind_pat);
+                outer.rebind.extend(quote! {
+                    #[allow(unused_variables)]
+                    let #rebind_pat = &#name;
+                });
+                *pat = parse_quote! { #name };
+                outer.names.push(name);
+            }
+        }
+        outer.inputs.push(FnArg::Typed(pat_type));
+    }
+    outer
+}
+
+/// Remove `ref`, `mut` and reference patterns from `pat`, so it binds by reference when
+/// matched against a reference.
+fn strip_binding_modes(pat: &mut Pat) {
+    match pat {
+        Pat::Ident(pat_ident) => {
+            pat_ident.by_ref = None;
+            pat_ident.mutability = None;
+            if let Some((_, subpat)) = &mut pat_ident.subpat {
+                strip_binding_modes(subpat);
+            }
+        }
+        // default binding modes see through the references of the argument
+        Pat::Reference(pat_ref) => {
+            let mut inner = pat_ref.pat.as_ref().clone();
+            strip_binding_modes(&mut inner);
+            *pat = inner;
+        }
+        Pat::Or(pat_or) => pat_or.cases.iter_mut().for_each(strip_binding_modes),
+        Pat::Slice(pat_slice) => pat_slice.elems.iter_mut().for_each(strip_binding_modes),
+        Pat::Tuple(pat_tuple) => pat_tuple.elems.iter_mut().for_each(strip_binding_modes),
+        Pat::TupleStruct(pat_tuple_struct) => pat_tuple_struct
+            .pat
+            .elems
+            .iter_mut()
+            .for_each(strip_binding_modes),
+        Pat::Struct(pat_struct) => pat_struct
+            .fields
+            .iter_mut()
+            .for_each(|field| strip_binding_modes(&mut field.pat)),
+        Pat::Type(pat_type) => strip_binding_modes(&mut pat_type.pat),
+        _ => {}
+    }
+}
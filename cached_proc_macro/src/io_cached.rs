@@ -0,0 +1,549 @@
+use crate::helpers::{
+    check_cached_flag_output, check_not_method, enabled_switch, inner_fn_ident, inner_generics,
+    is_return_ty, outer_inputs, parse_arg, prime_fn_ident,
+};
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, AttributeArgs, Block, Expr, ExprClosure, FnArg,
+    GenericArgument, Ident, ItemFn, PathArguments, ReturnType, Type,
+};
+
+#[derive(FromMeta)]
+struct IOMacroArgs {
+    map_error: String,
+    #[darling(default)]
+    redis: bool,
+    #[darling(default)]
+    cache_prefix_block: Option<String>,
+    #[darling(default)]
+    version: Option<u64>,
+    #[darling(default)]
+    key_encoder: Option<String>,
+    #[darling(default)]
+    name: Option<String>,
+    #[darling(default)]
+    prime_name: Option<String>,
+    #[darling(default)]
+    time: Option<u64>,
+    #[darling(default)]
+    time_refresh: Option<bool>,
+    #[darling(default)]
+    key: Option<String>,
+    #[darling(default)]
+    convert: Option<String>,
+    #[darling(default)]
+    with_cached_flag: bool,
+    #[darling(default, rename = "type")]
+    cache_type: Option<String>,
+    #[darling(default, rename = "create")]
+    cache_create: Option<String>,
+}
+
+pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    let args = match IOMacroArgs::from_list(&attr_args) {
+        Ok(v) => v,
+        Err(e) => {
+            return TokenStream::from(e.write_errors());
+        }
+    };
+    let input = parse_macro_input!(input as ItemFn);
+    if let Err(e) = check_not_method(&input.sig, "io_cached") {
+        return e.to_compile_error().into();
+    }
+
+    // pull out the parts of the input
+    let mut attributes = input.attrs;
+    let visibility = input.vis;
+    let signature = input.sig;
+    let body = input.block;
+
+    // pull out the parts of the function signature
+    let fn_ident = signature.ident.clone();
+    let inner_fn_ident = inner_fn_ident(&fn_ident);
+    let inputs = signature.inputs.clone();
+    let output = signature.output.clone();
+    let asyncness = signature.asyncness;
+    let (inner_generics, inner_turbofish, inner_where) = inner_generics(&signature.generics);
+
+    // pull out the names and types of the function inputs
+    let input_tys = inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
+            FnArg::Typed(pat_type) => pat_type.ty.clone(),
+        })
+        .collect::<Vec<Box<Type>>>();
+
+    // the arguments of the generated functions, and the names passing them on to `inner`
+    let outer_inputs = outer_inputs(&inputs);
+    let input_names = &outer_inputs.names;
+
+    // pull out the output type
+    let output_ty = match &output {
+        ReturnType::Default => quote! {()},
+        ReturnType::Type(_, ty) => quote! {#ty},
+    };
+
+    let output_ts = TokenStream::from(output_ty);
+    let output_type_display = output_ts.to_string().replace(' ', "");
+
+    // with `with_cached_flag = true`, the values are wrapped in `cached::Return<T>`
+    if args.with_cached_flag {
+        if let Err(e) = check_cached_flag_output(&output, 1, "Result<cached::Return<T>, E>", None) {
+            return e.to_compile_error().into();
+        }
+    }
+
+    // Find the type of the value to store.
+    // Return type always needs to be a result, so we want the (first) inner type.
+    // For Result<i32, String>, store i32, etc.
+    let cache_value_ty = match output.clone() {
+        ReturnType::Default => {
+            panic!(
+                "#[io_cached] functions must return `Result`s, found {:?}",
+                output_type_display
+            );
+        }
+        ReturnType::Type(_, ty) => {
+            if let Type::Path(typepath) = *ty {
+                let segments = typepath.path.segments;
+                if let PathArguments::AngleBracketed(brackets) = &segments.last().unwrap().arguments
+                {
+                    let inner_ty = brackets.args.first().unwrap();
+                    if matches!(inner_ty, GenericArgument::Type(ty) if is_return_ty(ty)) {
+                        if let GenericArgument::Type(Type::Path(typepath)) = inner_ty {
+                            let segments = &typepath.path.segments;
+                            if let PathArguments::AngleBracketed(brackets) =
+                                &segments.last().unwrap().arguments
+                            {
+                                let inner_ty = brackets.args.first().unwrap();
+                                quote! {#inner_ty}
+                            } else {
+                                panic!(
+                                    "#[io_cached] unable to determine cache value type, found {:?}",
+                                    output_type_display
+                                );
+                            }
+                        } else {
+                            panic!(
+                                "#[io_cached] unable to determine cache value type, found {:?}",
+                                output_type_display
+                            );
+                        }
+                    } else {
+                        quote! {#inner_ty}
+                    }
+                } else {
+                    panic!("#[io_cached] functions must return `Result`s")
+                }
+            } else {
+                panic!(
+                    "function return type too complex, #[io_cached] functions must return `Result`s"
+                )
+            }
+        }
+    };
+
+    // make the cache identifier
+    let cache_ident = match args.name {
+        Some(name) => Ident::new(&name, fn_ident.span()),
+        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
+    };
+
+    // make the cache key type and block that converts the inputs into the key type
+    let (cache_key_ty, key_convert_block) = match (&args.key, &args.convert, &args.cache_type) {
+        (Some(key_str), Some(convert_str), _) => {
+            let cache_key_ty = match parse_arg::<Type>(&attr_args, "key", "a type", key_str) {
+                Ok(cache_key_ty) => cache_key_ty,
+                Err(e) => return e.to_compile_error().into(),
+            };
+
+            let key_convert_block =
+                match parse_arg::<Block>(&attr_args, "convert", "a block", convert_str) {
+                    Ok(key_convert_block) => key_convert_block,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+
+            (quote! {#cache_key_ty}, quote! {#key_convert_block})
+        }
+        (None, Some(convert_str), Some(_)) => {
+            let key_convert_block =
+                match parse_arg::<Block>(&attr_args, "convert", "a block", convert_str) {
+                    Ok(key_convert_block) => key_convert_block,
+                    Err(e) => return e.to_compile_error().into(),
+                };
+
+            (quote! {}, quote! {#key_convert_block})
+        }
+        (None, None, _) => (
+            quote! {(#(#input_tys),*)},
+            quote! {(#(::std::clone::Clone::clone(&#input_names)),*)},
+        ),
+        (Some(_), None, _) => panic!("key requires convert to be set"),
+        (None, Some(_), None) => panic!("convert requires key or type to be set"),
+    };
+
+    // `convert` blocks may use the names bound by arguments destructured with a pattern
+    let key_convert_block = if args.convert.is_some() && !outer_inputs.rebind.is_empty() {
+        let rebind = &outer_inputs.rebind;
+        quote! {
+            {
+                #rebind
+                #key_convert_block
+            }
+        }
+    } else {
+        key_convert_block
+    };
+
+    // make the cache type and create statement
+    let (cache_ty, cache_create) = match (
+        &args.redis,
+        &args.time,
+        &args.time_refresh,
+        &args.cache_prefix_block,
+        &args.cache_type,
+        &args.cache_create,
+    ) {
+        (true, time, time_refresh, cache_prefix, cache_type, cache_create) => {
+            let cache_ty = match cache_type {
+                Some(cache_type) => {
+                    let cache_type =
+                        match parse_arg::<Type>(&attr_args, "type", "a type", cache_type) {
+                            Ok(cache_type) => cache_type,
+                            Err(e) => return e.to_compile_error().into(),
+                        };
+                    quote! { #cache_type }
+                }
+                None => {
+                    if asyncness.is_some() {
+                        quote! { ::cached::AsyncRedisCache<#cache_key_ty, #cache_value_ty> }
+                    } else {
+                        quote! { ::cached::RedisCache<#cache_key_ty, #cache_value_ty> }
+                    }
+                }
+            };
+            let cache_create = match cache_create {
+                Some(cache_create) => {
+                    if time.is_some()
+                        || time_refresh.is_some()
+                        || cache_prefix.is_some()
+                        || args.version.is_some()
+                        || args.key_encoder.is_some()
+                    {
+                        panic!("cannot specify `time`, `time_refresh`, `cache_prefix`, `version`, or `key_encoder` when passing `create block");
+                    } else {
+                        let cache_create =
+                            match parse_arg::<Block>(&attr_args, "create", "a block", cache_create)
+                            {
+                                Ok(cache_create) => cache_create,
+                                Err(e) => return e.to_compile_error().into(),
+                            };
+                        quote! { #cache_create }
+                    }
+                }
+                None => {
+                    if time.is_none() {
+                        if asyncness.is_some() {
+                            panic!("AsyncRedisCache requires a `time` when `create` block is not specified")
+                        } else {
+                            panic!(
+                                "RedisCache requires a `time` when `create` block is not specified"
+                            )
+                        };
+                    } else {
+                        let cache_prefix = match cache_prefix {
+                            Some(cp) => {
+                                let cp = match parse_arg::<Block>(
+                                    &attr_args,
+                                    "cache_prefix_block",
+                                    "a block",
+                                    cp,
+                                ) {
+                                    Ok(cp) => cp,
+                                    Err(e) => return e.to_compile_error().into(),
+                                };
+                                quote! { #cp }
+                            }
+                            None => {
+                                let fn_path = format!("::{}:", fn_ident);
+                                quote! { ::std::concat!(::std::module_path!(), #fn_path) }
+                            }
+                        };
+                        let set_refresh = time_refresh.map(|time_refresh| {
+                            quote! { .set_refresh(#time_refresh) }
+                        });
+                        let set_version = args.version.map(|version| {
+                            quote! { .set_version(#version) }
+                        });
+                        let set_key_encoder = match &args.key_encoder {
+                            Some(key_encoder) => {
+                                match parse_arg::<Expr>(
+                                    &attr_args,
+                                    "key_encoder",
+                                    "an expression",
+                                    key_encoder,
+                                ) {
+                                    Ok(key_encoder) => {
+                                        Some(quote! { .set_key_encoder(#key_encoder) })
+                                    }
+                                    Err(e) => return e.to_compile_error().into(),
+                                }
+                            }
+                            None => None,
+                        };
+                        if asyncness.is_some() {
+                            quote! { ::cached::AsyncRedisCache::new(#cache_prefix, #time)#set_refresh #set_version #set_key_encoder.build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
+                        } else {
+                            quote! {
+                                ::cached::RedisCache::new(#cache_prefix, #time)#set_refresh #set_version #set_key_encoder.build().expect("error constructing RedisCache in #[io_cached] macro")
+                            }
+                        }
+                    }
+                }
+            };
+            (cache_ty, cache_create)
+        }
+        (_, time, time_refresh, cache_prefix, cache_type, cache_create) => {
+            let cache_ty = match cache_type {
+                Some(cache_type) => {
+                    let cache_type =
+                        match parse_arg::<Type>(&attr_args, "type", "a type", cache_type) {
+                            Ok(cache_type) => cache_type,
+                            Err(e) => return e.to_compile_error().into(),
+                        };
+                    quote! { #cache_type }
+                }
+                None => panic!("#[io_cached] cache `type` must be specified"),
+            };
+            if args.key_encoder.is_some() {
+                panic!("`key_encoder` requires `redis = true`");
+            }
+            let cache_create = match cache_create {
+                Some(cache_create) => {
+                    if time.is_some()
+                        || time_refresh.is_some()
+                        || cache_prefix.is_some()
+                        || args.version.is_some()
+                    {
+                        panic!("cannot specify `time`, `time_refresh`, `cache_prefix`, or `version` when passing `create block");
+                    } else {
+                        let cache_create =
+                            match parse_arg::<Block>(&attr_args, "create", "a block", cache_create)
+                            {
+                                Ok(cache_create) => cache_create,
+                                Err(e) => return e.to_compile_error().into(),
+                            };
+                        quote! { #cache_create }
+                    }
+                }
+                None => {
+                    panic!("#[io_cached] cache `create` block must be specified");
+                }
+            };
+            (cache_ty, cache_create)
+        }
+        #[allow(unreachable_patterns)]
+        _ => panic!("#[io_cached] cache types cache type could not be determined"),
+    };
+
+    let map_error = &args.map_error;
+    let map_error = match parse_arg::<ExprClosure>(&attr_args, "map_error", "a closure", map_error)
+    {
+        Ok(map_error) => map_error,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    // make the set cache and return cache blocks
+    let (set_cache_block, return_cache_block) = {
+        let (set_cache_block, return_cache_block) = if args.with_cached_flag {
+            (
+                if asyncness.is_some() {
+                    quote! {
+                        if let ::std::result::Result::Ok(result) = &result {
+                            ::cached::IOCachedAsync::cache_set(&**cache, key, ::std::clone::Clone::clone(&result.value)).await.map_err(#map_error)?;
+                        }
+                    }
+                } else {
+                    quote! {
+                        if let ::std::result::Result::Ok(result) = &result {
+                            ::cached::IOCached::cache_set(&**cache, key, ::std::clone::Clone::clone(&result.value)).map_err(#map_error)?;
+                        }
+                    }
+                },
+                quote! { let mut r = ::cached::Return::new(result); r.was_cached = true; return ::std::result::Result::Ok(r) },
+            )
+        } else {
+            (
+                if asyncness.is_some() {
+                    quote! {
+                        if let ::std::result::Result::Ok(result) = &result {
+                            ::cached::IOCachedAsync::cache_set(&**cache, key, ::std::clone::Clone::clone(result)).await.map_err(#map_error)?;
+                        }
+                    }
+                } else {
+                    quote! {
+                        if let ::std::result::Result::Ok(result) = &result {
+                            ::cached::IOCached::cache_set(&**cache, key, ::std::clone::Clone::clone(result)).map_err(#map_error)?;
+                        }
+                    }
+                },
+                quote! { return ::std::result::Result::Ok(result) },
+            )
+        };
+        (set_cache_block, return_cache_block)
+    };
+
+    let (bypass_condition, enabled_items) = enabled_switch(&fn_ident, &cache_ident, &visibility);
+    // the function body, and the block calling it without touching the cache while caching is disabled
+    let (inner_fn, call_inner) = if asyncness.is_some() {
+        (
+            quote! { async fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body; },
+            quote! { #inner_fn_ident #inner_turbofish(#(#input_names),*).await },
+        )
+    } else {
+        (
+            quote! { fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body; },
+            quote! { #inner_fn_ident #inner_turbofish(#(#input_names),*) },
+        )
+    };
+    let bypass_block = quote! {
+        if #bypass_condition {
+            return #call_inner;
+        }
+    };
+
+    let do_set_return_block = if asyncness.is_some() {
+        quote! {
+            // run the function and cache the result
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*).await;
+            let cache = &#cache_ident.get().await;
+            #set_cache_block
+            result
+        }
+    } else {
+        quote! {
+            // run the function and cache the result
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*);
+            let cache = &#cache_ident;
+            #set_cache_block
+            result
+        }
+    };
+
+    // the generated functions take the arguments without their binding modes, e.g. a
+    // `mut a: i32` argument is only `mut` in the `inner` function that mutates it
+    let mut signature_no_muts = signature;
+    signature_no_muts.inputs = outer_inputs.inputs.clone();
+
+    // create a signature for the cache-priming function
+    let prime_fn_ident = match prime_fn_ident(&fn_ident, args.prime_name.as_deref(), &attr_args) {
+        Ok(ident) => ident,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let mut prime_sig = signature_no_muts.clone();
+    prime_sig.ident = prime_fn_ident;
+
+    let prime_do_set_return_block = if asyncness.is_some() {
+        quote! {
+            // run the function and cache the result
+            async fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body;
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*).await;
+            let cache = &#cache_ident.get().await;
+            #set_cache_block
+            result
+        }
+    } else {
+        quote! {
+            // run the function and cache the result
+            fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body;
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*);
+            let cache = &#cache_ident;
+            #set_cache_block
+            result
+        }
+    };
+
+    // make cached static, cached function and prime cached function doc comments
+    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
+    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
+    let cache_fn_doc_extra = format!(
+        "This is a cached function that uses the [`{}`] cached static.",
+        cache_ident
+    );
+    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
+        attributes.push(parse_quote! { #[doc = ""] });
+        attributes.push(parse_quote! { #[doc = "# Caching"] });
+        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+    } else {
+        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+    }
+
+    // put it all together
+    let expanded = if asyncness.is_some() {
+        quote! {
+            // Cached static
+            #[doc = #cache_ident_doc]
+            ::cached::lazy_static::lazy_static! {
+                #visibility static ref #cache_ident: ::cached::async_once::AsyncOnce<#cache_ty> = ::cached::async_once::AsyncOnce::new(async move { #cache_create });
+            }
+            #enabled_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #inner_fn
+                #bypass_block
+                let key = #key_convert_block;
+                {
+                    // check if the result is cached
+                    let cache = &#cache_ident.get().await;
+                    if let ::std::option::Option::Some(result) = ::cached::IOCachedAsync::cache_get(&**cache, &key).await.map_err(#map_error)? {
+                        #return_cache_block
+                    }
+                }
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                let key = #key_convert_block;
+                #prime_do_set_return_block
+            }
+        }
+    } else {
+        quote! {
+            // Cached static
+            #[doc = #cache_ident_doc]
+            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> = ::cached::once_cell::sync::Lazy::new(|| #cache_create);
+            #enabled_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #inner_fn
+                #bypass_block
+                let key = #key_convert_block;
+                {
+                    // check if the result is cached
+                    let cache = &#cache_ident;
+                    if let ::std::option::Option::Some(result) = ::cached::IOCached::cache_get(&**cache, &key).map_err(#map_error)? {
+                        #return_cache_block
+                    }
+                }
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                let key = #key_convert_block;
+                #prime_do_set_return_block
+            }
+        }
+    };
+
+    expanded.into()
+}
@@ -1,49 +1,38 @@
-use darling::FromMeta;
-use proc_macro::TokenStream;
-use quote::quote;
-use std::ops::Deref;
-use syn::punctuated::Punctuated;
-use syn::spanned::Spanned;
-use syn::{
-    parse_macro_input, parse_quote, parse_str, AttributeArgs, Block, ExprClosure, FnArg,
-    GenericArgument, Ident, ItemFn, Pat, PathArguments, ReturnType, Type,
-};
+mod cached;
+mod cached_macro_test;
+mod helpers;
+mod io_cached;
+mod once;
 
-#[derive(FromMeta)]
-struct MacroArgs {
-    #[darling(default)]
-    name: Option<String>,
-    #[darling(default)]
-    unbound: bool,
-    #[darling(default)]
-    size: Option<usize>,
-    #[darling(default)]
-    time: Option<u64>,
-    #[darling(default)]
-    time_refresh: bool,
-    #[darling(default)]
-    key: Option<String>,
-    #[darling(default)]
-    convert: Option<String>,
-    #[darling(default)]
-    result: bool,
-    #[darling(default)]
-    option: bool,
-    #[darling(default)]
-    sync_writes: bool,
-    #[darling(default)]
-    with_cached_flag: bool,
-    #[darling(default, rename = "type")]
-    cache_type: Option<String>,
-    #[darling(default, rename = "create")]
-    cache_create: Option<String>,
-}
+use proc_macro::TokenStream;
 
 /// # Attributes
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
+/// - `cache_visibility`: (optional, string) specify the visibility of the generated cache, defaults to the
+///   visibility of the function, e.g. `cache_visibility = "pub(crate)"`, `cache_visibility = "pub(in crate::path)"`,
+///   or `cache_visibility = ""` to keep the cache private to the module of a public function.
+/// - `prime_name`: (optional, string) specify the name of the generated cache-priming function, defaults to
+///   `{fn}_prime_cache`. Use it when another item of the module already has that name.
 /// - `size`: (optional, usize) specify an LRU max size, implies the cache type is a `SizedCache` or `TimedSizedCache`.
+///   Either a literal, or a string-quoted expression such as `size = "MAX_USERS * 2"` to use constants.
+/// - `low_watermark`: (optional, usize) with `size` (and no `time`), once the cache is full, evict the least recently
+///   used values in bulk until it holds `low_watermark` values instead of evicting one value per insert, see
+///   `SizedCache::with_size_and_watermark`.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCache` or `TimedSizedCache`.
-/// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits.
+///   Values expire once exactly `time` seconds have elapsed, so `time = 0` never returns a cached value
+///   (see `cached::stores::is_expired`).
+/// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits. With `time_refresh = true`,
+///   every call returning a cached value restarts its lifespan (sliding expiration), so a value retrieved at least
+///   once every `time` seconds never expires. Defaults to `false`: values expire `time` seconds after they were
+///   cached, however often they're retrieved. Peeking at a value, e.g. through `Cached::cache_get_with_meta`,
+///   doesn't refresh it.
+/// - `time_to_idle`: (optional, u64) with `time` (or `time_fn`), also expire values that weren't retrieved for
+///   `time_to_idle` seconds, whichever comes first, see `TimedCache::with_lifespan_and_tti`. Can't be combined with
+///   `time_refresh`.
+/// - `size_fn`: (optional, string path) specify a function returning the LRU max size (`usize`), e.g.
+///   `size_fn = "config::cache_size"`. It is called once, when the cache is first used. Mutually exclusive with `size`.
+/// - `time_fn`: (optional, string path) specify a function returning the cache TTL in seconds (`u64`). It is called
+///   once, when the cache is first used. Mutually exclusive with `time`.
 /// - `sync_writes`: (optional, bool) specify whether to synchronize the execution of writing of uncached values.
 /// - `type`: (optional, string type) The cache store type to use. Defaults to `UnboundCache`. When `unbound` is
 ///   specified, defaults to `UnboundCache`. When `size` is specified, defaults to `SizedCache`.
@@ -51,965 +40,297 @@ struct MacroArgs {
 ///   When `size` and `time` are specified, defaults to `TimedSizedCache`. When `type` is
 ///   specified, `create` must also be specified.
 /// - `create`: (optional, string expr) specify an expression used to create a new cache store, e.g. `create = r##"{ CacheType::new() }"##`.
+/// - `create_try`: (optional, string expr) instead of `create`, specify an expression returning a
+///   `Result<CacheType, E>` (with `E: Debug`), for stores whose creation may fail. The cache is created the first
+///   time the function is called, and a failure is handled according to `on_create_error`.
+/// - `on_create_error`: (optional, string) with `create_try`, either `"panic"` (the default), panicking with the
+///   error, which poisons the cache so every later call panics too, or `"null"`: the cache type becomes
+///   `cached::FallibleCache<CacheType, K, V>`, which falls back to a `cached::NullCache` caching nothing, so the
+///   function keeps working without its cache. With the `log` feature, the error is logged.
 /// - `key`: (optional, string type) specify what type to use for the cache key, e.g. `key = "u32"`.
-///    When `key` is specified, `convert` must also be specified.
+///   When `key` is specified, `convert` must also be specified. A newtype keeps the keys of a function from
+///   being mixed up with others, e.g. `key = "UserId", convert = r#"{ UserId(id) }"#`. The key type must
+///   implement `Hash` and `Eq`, and `Clone` with `size`.
 /// - `convert`: (optional, string expr) specify an expression used to convert function arguments to a cache
 ///   key, e.g. `convert = r##"{ format!("{}:{}", arg1, arg2) }"##`. When `convert` is specified,
 ///   `key` or `type` must also be set.
+/// - `convert_prepared` and `prepared_arg`: (optional, string expr and string argument) advanced replacement of
+///   `convert` for functions whose key and body share an expensive preparation of the arguments, e.g. normalizing
+///   a name. The block returns the key along with the single argument the function body takes instead of the
+///   function arguments, declared by `prepared_arg`, so the preparation runs once per call:
+///   `key = "String", convert_prepared = r#"{ let name = normalize(name); (name.clone(), name) }"#,
+///   prepared_arg = "name: String"`. `key` must be set too.
+/// - `config`: (optional, string path) specify a type implementing `cached::CacheConfig` for the arguments of the
+///   function, e.g. `config = "UserCacheConfig"`, defining the cache store, its creation, the key made from the
+///   arguments and which values are cached in plain Rust. Replaces `type`, `create`, `key` and `convert`, and can't
+///   be combined with them or with the attributes choosing the default store.
 /// - `result`: (optional, bool) If your function returns a `Result`, only cache `Ok` values returned by the function.
+///   A `{fn}_try_prime_cache` function returning `Result<bool, E>` is generated next to `{fn}_prime_cache`
+///   so callers warming many keys can tell which ones were cached.
 /// - `option`: (optional, bool) If your function returns an `Option`, only cache `Some` values returned by the function.
+///   A `{fn}_try_prime_cache` function returning whether the value was cached (`bool`) is generated.
+/// - `result_option`: (optional, bool) If your function returns an `Option<Result<T, E>>`, only cache the `T` of
+///   `Some(Ok(T))` values returned by the function, `None`s and `Err`s aren't cached. The generated
+///   `{fn}_try_prime_cache` returns `Ok(false)` for a `None` and `Err(E)` for an error. Can't be combined with
+///   `result` or `option`.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
-///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+///   the `cached::Return.was_cached` flag will be updated when a cached value is returned. With a timed cache,
+///   `cached::Return.expires_in` is set to how long until the returned value expires.
+/// - `key_transform`: (optional, string block) specify a block evaluating to a closure applied to every cache
+///   key (the arguments, or the result of `convert`) before it's used, e.g.
+///   `key_transform = r#"{ |k| k.to_lowercase() }"#` to make string keys case-insensitive. It must
+///   return a key of the same type.
+/// - `version_fn`: (optional, string path) specify a function returning the current cache version (any
+///   `ToString` type, e.g. a `u64` or `String`), e.g. `version_fn = "config::schema_version"`. It is called
+///   on every lookup and its value is prepended to the cache key, so entries cached under a previous version
+///   become unreachable as soon as the version changes (they stay in the store until evicted, so prefer a
+///   bounded store). When `type` is specified, its key type must be `(String, K)`.
+/// - `on_miss`: (optional, string block) specify a block evaluating to a closure called with a reference to
+///   the key whenever a cache miss triggers computing the value, e.g. `on_miss = r#"{ |key| log_cold_key(key) }"#`.
+///   It's called right before computing, only by the caller that computes the value (not by `sync_writes`
+///   callers finding it cached once they get the lock, nor by those returning a `placeholder`), so it can be
+///   used to log cold keys or to start prefetching related keys. The cache lock isn't held while calling it,
+///   unless `sync_writes` holds it for computing.
+/// - `warn_at`: (optional, usize) with the default unbounded cache, log a warning naming the cache the first time
+///   it grows past this many entries (requires the `log` feature of `cached`). The cache stays unbounded.
+/// - When both `size` and `time` are specified, `{fn}_cache_pin` and `{fn}_cache_unpin` functions taking the
+///   same arguments are generated to pin the cached result (see `TimedSizedCache::pin`), exempting it from expiry
+///   and eviction. They return whether the result was (un)pinned.
+/// - `trace_caller`: (optional, bool) store the location of the call that cached each value alongside it
+///   (the cache value type becomes `(std::panic::Location<'static>, V)`), and generate a
+///   `{fn}_cache_get_with_location` function with the same arguments returning both. The cached function is
+///   marked `#[track_caller]`, so this isn't supported for async functions.
+/// - `error_on_miss`: (optional, bool) only read from the cache: the function returns
+///   `Result<T, cached::CacheMiss>` and returns `Err(cached::CacheMiss)` instead of calling the function when no
+///   value is cached, e.g. for a replica that must never reach the database. Values are cached by calling
+///   `{fn}_prime_cache`. Can't be combined with `result`, `option`, `with_cached_flag` or `sync_writes`.
+/// - `prime_batched`: (optional, bool) make `{fn}_prime_cache` buffer the values it computes instead of
+///   storing each one, so priming many keys while the function is called doesn't contend for the cache lock
+///   on every value. A `{fn}_prime_flush()` function (async for async functions) is generated to store the
+///   buffered values with `Cached::cache_extend` under a single acquisition of the lock, returning how many
+///   values were stored. Buffered values aren't visible to the cached function until they're flushed: a call
+///   for a buffered key computes its result again, and a flushed value replaces any value cached meanwhile.
+///   The buffer is shared by every thread and task priming the function.
+/// - `prime_batch_size`: (optional, usize) with `prime_batched`, flush the buffer from `{fn}_prime_cache` once
+///   it holds this many values.
+/// - `invalidation_group`: (optional, string) add the cache to a named invalidation group, e.g.
+///   `invalidation_group = "users"` on every function caching user data. `cached::invalidate_group("users")`
+///   then invalidates the caches of all the functions of the group at once. The cache type becomes
+///   `cached::GroupedCache<CacheType>`.
+/// - `evict_after_set`: (optional, u64) remove each cached result this many seconds after it was cached, whether
+///   it's retrieved in the meantime or not, e.g. for values that must be fetched again periodically. A background
+///   thread removes the results once they're due. Can't be combined with `time`, which expires results instead.
+///   The cache type becomes `cached::EvictAfterSetCache<CacheType, KeyType>`.
+/// - `with_key`: (optional, bool) also generate a `{fn}_with_key` function with the same arguments, returning
+///   the result of a call along with its cache key, e.g. `(key, value)`. For `result`, `option` and
+///   `result_option` functions, the key is in the success variant: `Result<(key, value), E>`. It needs the key
+///   type, so it can't be used with `config` or when the key type is only given by `type`. Use `{fn}_key`
+///   instead to compute the key of a call without calling the function.
+/// - `at_most_once`: (optional, bool) run the function at most once for the whole program, e.g. for one-time
+///   initialization that must never be repeated. The first result is kept (in a `OnceCell`, racing first calls
+///   wait for it) along with the key of its arguments. Later calls with the same arguments return it, calls with
+///   other arguments panic. With `result` or `option`, failures aren't kept, so the function runs until it
+///   succeeds once. It can only be combined with `name`, `key`, `convert`, `key_transform`, `result` and
+///   `option`, and none of the functions below are generated. Unlike `#[once]`, the result never expires.
+/// - `placeholder`: (optional, string block) with `sync_writes`, specify a block evaluating to a return value of
+///   the function, returned to the callers missing a key whose value another caller is computing, instead of
+///   waiting for it, e.g. `placeholder = r#"{ Page::loading() }"#` for latency-sensitive reads. **Those callers
+///   get non-final data**: the placeholder isn't cached, and only the value computed by the first caller is. The
+///   cache isn't locked while computing, so other keys can be read meanwhile.
+/// - `feature`: (optional, string) only cache while the given Cargo feature of the crate declaring the function
+///   is enabled, e.g. `feature = "caching"` to disable caching in debug or test builds for determinism. Without
+///   the feature, every call runs the function, as if disabled with `{fn}_set_enabled(false)`. The generated
+///   functions and cache are still there, so code using them compiles either way.
+/// - `cold_start_timeout`: (optional, u64) with `sync_writes`, give up after waiting this many seconds for the
+///   cache lock, e.g. while another caller computes a value on a cold cache. The function then returns
+///   `Result<T, cached::ColdStartTimeout>`: `Err(ColdStartTimeout)` when the wait times out, `Ok` otherwise. Async
+///   functions wait with `tokio::time::timeout`. Can't be combined with `placeholder` or `with_key`.
+/// - `compute_timeout_ms`: (optional, u64) for async functions with `result`, `option` or `result_option`, give
+///   up computing a missing value after this many milliseconds, dropping the computation. The expired value of
+///   the key is returned instead if the cache still holds it (see `Cached::cache_get_stale`), otherwise
+///   `Err(cached::ComputeTimeout.into())`, or `None` with `option`. The error type must implement
+///   `From<cached::ComputeTimeout>`. With `sync_writes`, the lock is released on timeout, so the next caller
+///   computes the value again. Can't be combined with `placeholder` or `cold_start_timeout`.
+/// - `max_inflight`: (optional, usize) for sync functions, limit how many threads run the function at once, e.g.
+///   to protect a backend from a burst of misses. The threads beyond the limit block until another one is done.
+///   A recursive function would wait for itself once the limit is reached.
+/// - `ignore_types`: (optional, string) a comma-separated list of type names, e.g. `ignore_types = "State, Extension"`.
+///   Arguments of these types (matched by the last segment of their path) are left out of the default key, e.g. the
+///   shared state of a web handler. Can't be combined with `key`, `convert` or `config`.
+/// - `extractor`: (optional, bool) key the cache of a web handler by its extractors: each argument of the default key
+///   contributes its `cached::proc_macro::KeyExtract::Key`, which unwraps newtypes such as axum's or actix-web's
+///   `Path<T>`, `Query<T>` and `Json<T>` into the (cloned) `T`. Arguments of `State` and `Extension` types are left
+///   out of the key unless `ignore_types` says otherwise. See `examples/axum.rs`.
+/// - `key_fn_async`: (optional, string path) for async functions, an async function computing the cache key, called
+///   with a reference to each argument and awaited before looking up the cache, e.g. to resolve a short name into a
+///   canonical id. `key` sets the type of the keys it returns. Can't be combined with `convert` or `config`. The
+///   generated `{fn}_key` function is async too.
+/// - `hash_only_key`: (optional, bool) key the cache by the `u64` hash of the arguments (see
+///   `cached::keys::hash_key`), so they only need to implement `Hash`: not `Eq` nor `Clone`, and they may be
+///   borrowed. Arguments with the same hash share a cache entry, so a (rare) hash collision returns the value
+///   cached for other arguments: only use it when that's acceptable. Can't be combined with `key`, `convert`
+///   or `config`.
+/// - `key_hasher`: (optional, string type) with `hash_only_key`, the `Hasher + Default` type hashing the arguments,
+///   defaults to `std::collections::hash_map::DefaultHasher`.
+/// - `invalidate_sibling`: (optional, string) a comma-separated list of paths to other `#[cached]` functions whose
+///   caches are emptied (by calling their `{fn}_cache_flush` function) every time this function computes a result
+///   it caches or primes its cache, e.g. `invalidate_sibling = "list_users"` on `create_user`. The siblings must be
+///   async if this function is, and sync otherwise.
+/// - `inline`: (optional, bool) mark the generated cached function `#[inline]`, e.g. for a tiny function called in
+///   a tight loop, where the call to the wrapper adds up next to the cache lookup. Only the wrapper is marked, not
+///   the body computing the values. Other attributes of the function, such as `#[must_use]`, are kept either way.
+/// - `shared`: (optional, bool) with `name`, use the cache defined by another `#[cached]` function with the same
+///   `name` rather than defining a second one. The other function's cache static must be in scope (in the same
+///   module, or imported with `use`), and both functions must declare the same store (`size`, `time`, `type`, ...)
+///   so that its type matches, which is checked at compile time. The `size`, `time` and `create` values of the
+///   shared declaration aren't used, the store is created by the defining one. The functions then share keys,
+///   hit and miss counts and size, so their keys must not collide unless they compute the same values, and the
+///   `{fn}_cache_flush`, `{fn}_cache_remove` and `{fn}_prime_cache` functions of either act on the shared store.
+///   The `{fn}_set_enabled` switch stays per function. With the `testing` feature, no `{fn}_cache_scope` is
+///   generated for the shared declaration, which always uses the shared static.
+/// - `lock`: (optional, string) how the cache is shared between threads: `"mutex"` (the default) behind a `Mutex`,
+///   or `"rwlock"` behind a `std::sync::RwLock`, looking values up under its read lock with
+///   `cached::CachedRead::cache_read` so that hits on different threads don't wait for each other, e.g. for a hot
+///   function called from many threads. Misses and the other generated functions take the write lock. The store
+///   must implement `CachedRead` (like the default `UnboundCache`), and the cached keys and values must be `Sync`.
+///   Only for sync functions, and not with `cold_start_timeout`. Or `"none"`, keeping a store implementing
+///   `cached::ConcurrentCached` (like `cached::stores::DashMapCache`) in a plain static without a lock, which
+///   returns clones of the cached values. It requires `type` and `create`, can only be combined with `name`, `key`,
+///   `convert`, `key_transform`, `result`, `option` and `prime_name`, and only generates `{fn}_prime_cache` and
+///   `{fn}_cache_flush` next to the function.
+/// - A `{fn}_set_enabled(bool)` function is generated to disable (or re-enable) the cache of this function
+///   at runtime, e.g. to debug a single suspect function. It returns whether the cache was enabled. See also
+///   `cached::set_caching_enabled` to disable every cache at once.
+/// - With the `testing` feature, a `{fn}_cache_scope()` function is generated, swapping in a fresh cache on the
+///   current thread until the returned scope is dropped, so tests running in parallel don't share cached values.
+///   See `cached::testing`.
+/// - A `{fn}_key` function with the same arguments is generated, returning the key caching the result of a
+///   call (after `convert`, `key_transform` and `version_fn`), e.g. to remove a value with
+///   `CACHE.lock().unwrap().cache_remove(&my_fn_key(args))`. It isn't generated with `config`, whose
+///   `CacheConfig::key` derives the keys, or when the key type is only given by `type`.
+/// - `{fn}_cache_remove` (with the same arguments) and `{fn}_cache_remove_key` (taking a reference to the cache
+///   key) functions are generated to remove a cached value, returning it. `{fn}_cache_remove_key` isn't generated
+///   when the key type is only given by `type`.
+/// - A `{fn}_cache_contains_key` function with the same arguments is generated, returning whether a value is cached
+///   for them without cloning it. Like a call, the lookup counts as a hit or miss, and refreshes the TTL with
+///   `time_refresh`.
+/// - A `{fn}_cache_flush()` function is generated, emptying the cache and resetting its hit and miss counts. It's
+///   `async` for async functions, and isn't generated with `at_most_once`.
+/// - Keys can't borrow from the arguments: the cache is a `static` that outlives every call. Use an owned
+///   `key`, or a scope-limited cache such as a local `HashMap<&'a str, V>` (which implements `cached::Cached`)
+///   instead.
 ///
 /// ## Note
 /// The `type`, `create`, `key`, and `convert` attributes must be in a `String`
 /// This is because darling, which is used for parsing the attributes, does not support directly parsing
 /// attributes into `Type`s or `Block`s.
+///
+/// ## Async functions
+/// The cache of an `async` function is shared by every task calling it, so the cache key and the
+/// cached value types must be `Send`. A non-`Send` key or value (e.g. an `Rc<T>`) is reported at
+/// the offending type with a `__cached_async_key_must_be_send` / `__cached_async_value_must_be_send`
+/// bound. The error type of a `result` function is never cached and has no such requirement.
+///
+/// A function returning a manually boxed future, `Pin<Box<dyn Future<Output = T>>>` (optionally
+/// `+ Send` and with a lifetime), e.g. a trait method written before `async fn` in traits, is
+/// cached like an `async` function returning `T`: its future is awaited on misses, and the
+/// generated function returns a boxed future the same way. The other generated functions, such as
+/// `{fn}_prime_cache`, are `async` functions.
+///
+/// ## Cached values
+/// The cache is a `static` shared by every thread, so cached values must be `'static` and `Send`
+/// (but not `Sync`, the cache is behind a `Mutex`). A value type borrowing for a lifetime of the
+/// function is reported at the lifetime, and a non-`Send` value of a sync function at the return
+/// type, with a `__cached_value_must_be_send_for_static` bound.
+///
+/// ## Generic functions
+/// Every instantiation of a generic function shares a single cache, so the cached return type can't
+/// depend on the generic parameters, and the key must be set with `key` and `convert` to a concrete type.
+/// For `const` parameters, include them in the key so the instantiations don't share values, e.g.
+/// `#[cached(key = "(usize, Vec<u8>)", convert = r#"{ (N, data.to_vec()) }"#)]` on
+/// `fn render<const N: usize>(data: [u8; N]) -> String`.
 #[proc_macro_attribute]
 pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
-    let attr_args = parse_macro_input!(args as AttributeArgs);
-    let args = match MacroArgs::from_list(&attr_args) {
-        Ok(v) => v,
-        Err(e) => {
-            return TokenStream::from(e.write_errors());
-        }
-    };
-    let input = parse_macro_input!(input as ItemFn);
-
-    // pull out the parts of the input
-    let mut attributes = input.attrs;
-    let visibility = input.vis;
-    let signature = input.sig;
-    let body = input.block;
-
-    // pull out the parts of the function signature
-    let fn_ident = signature.ident.clone();
-    let inputs = signature.inputs.clone();
-    let output = signature.output.clone();
-    let asyncness = signature.asyncness;
-
-    // pull out the names and types of the function inputs
-    let input_tys = inputs
-        .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => pat_type.ty.clone(),
-        })
-        .collect::<Vec<Box<Type>>>();
-
-    let input_names = inputs
-        .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => {
-                // if you define arguments as mutable, e.g.
-                // #[cached]
-                // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
-                //     a += 1;
-                //     b += 1;
-                //     (a, b)
-                // }
-                // then we need to strip off the `mut` keyword from the
-                // variable identifiers so we can refer to arguments `a` and `b`
-                // instead of `mut a` and `mut b`
-                match &pat_type.pat.deref() {
-                    Pat::Ident(pat_ident) => {
-                        if pat_ident.mutability.is_some() {
-                            let mut p = pat_ident.clone();
-                            p.mutability = None;
-                            Box::new(Pat::Ident(p))
-                        } else {
-                            Box::new(Pat::Ident(pat_ident.clone()))
-                        }
-                    }
-                    _ => pat_type.pat.clone(),
-                }
-            }
-        })
-        .collect::<Vec<Box<Pat>>>();
-
-    // pull out the output type
-    let output_ty = match &output {
-        ReturnType::Default => quote! {()},
-        ReturnType::Type(_, ty) => quote! {#ty},
-    };
-
-    let output_span = output_ty.span();
-    let output_ts = TokenStream::from(output_ty.clone());
-    let output_parts = output_ts
-        .clone()
-        .into_iter()
-        .filter_map(|tt| match tt {
-            proc_macro::TokenTree::Ident(ident) => Some(ident.to_string()),
-            _ => None,
-        })
-        .collect::<Vec<_>>();
-    let output_string = output_parts.join("::");
-    let output_type_display = output_ts.to_string().replace(' ', "");
-
-    // if `with_cached_flag = true`, then enforce that the return type
-    // is something wrapped in `Return`. Either `Return<T>` or the
-    // fully qualified `cached::Return<T>`
-    if args.with_cached_flag
-        && !output_string.contains("Return")
-        && !output_string.contains("cached::Return")
-    {
-        return syn::Error::new(
-            output_span,
-            format!(
-                "\nWhen specifying `with_cached_flag = true`, \
-                    the return type must be wrapped in `cached::Return<T>`. \n\
-                    The following return types are supported: \n\
-                    |    `cached::Return<T>`\n\
-                    |    `std::result::Result<cachedReturn<T>, E>`\n\
-                    |    `std::option::Option<cachedReturn<T>>`\n\
-                    Found type: {t}.",
-                t = output_type_display
-            ),
-        )
-        .to_compile_error()
-        .into();
-    }
-
-    // Find the type of the value to store.
-    // Normally it's the same as the return type of the functions, but
-    // for Options and Results it's the (first) inner type. So for
-    // Option<u32>, store u32, for Result<i32, String>, store i32, etc.
-    let cache_value_ty = match (&args.result, &args.option) {
-        (false, false) => output_ty,
-        (true, true) => panic!("the result and option attributes are mutually exclusive"),
-        _ => match output.clone() {
-            ReturnType::Default => {
-                panic!("function must return something for result or option attributes")
-            }
-            ReturnType::Type(_, ty) => {
-                if let Type::Path(typepath) = *ty {
-                    let segments = typepath.path.segments;
-                    if let PathArguments::AngleBracketed(brackets) =
-                        &segments.last().unwrap().arguments
-                    {
-                        let inner_ty = brackets.args.first().unwrap();
-                        quote! {#inner_ty}
-                    } else {
-                        panic!("function return type has no inner type")
-                    }
-                } else {
-                    panic!("function return type too complex")
-                }
-            }
-        },
-    };
-
-    // make the cache identifier
-    let cache_ident = match args.name {
-        Some(name) => Ident::new(&name, fn_ident.span()),
-        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
-    };
-
-    // make the cache key type and block that converts the inputs into the key type
-    let (cache_key_ty, key_convert_block) = match (&args.key, &args.convert, &args.cache_type) {
-        (Some(key_str), Some(convert_str), _) => {
-            let cache_key_ty = parse_str::<Type>(key_str).expect("unable to parse cache key type");
-
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
-
-            (quote! {#cache_key_ty}, quote! {#key_convert_block})
-        }
-        (None, Some(convert_str), Some(_)) => {
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
-
-            (quote! {}, quote! {#key_convert_block})
-        }
-        (None, None, _) => (
-            quote! {(#(#input_tys),*)},
-            quote! {(#(#input_names.clone()),*)},
-        ),
-        (Some(_), None, _) => panic!("key requires convert to be set"),
-        (None, Some(_), None) => panic!("convert requires key or type to be set"),
-    };
-
-    // make the cache type and create statement
-    let (cache_ty, cache_create) = match (
-        &args.unbound,
-        &args.size,
-        &args.time,
-        &args.cache_type,
-        &args.cache_create,
-        &args.time_refresh,
-    ) {
-        (true, None, None, None, None, _) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
-            (cache_ty, cache_create)
-        }
-        (false, Some(size), None, None, None, _) => {
-            let cache_ty = quote! {cached::SizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::SizedCache::with_size(#size)};
-            (cache_ty, cache_create)
-        }
-        (false, None, Some(time), None, None, time_refresh) => {
-            let cache_ty = quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create =
-                quote! {cached::TimedCache::with_lifespan_and_refresh(#time, #time_refresh)};
-            (cache_ty, cache_create)
-        }
-        (false, Some(size), Some(time), None, None, time_refresh) => {
-            let cache_ty = quote! {cached::TimedSizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::TimedSizedCache::with_size_and_lifespan_and_refresh(#size, #time, #time_refresh)};
-            (cache_ty, cache_create)
-        }
-        (false, None, None, None, None, _) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
-            (cache_ty, cache_create)
-        }
-        (false, None, None, Some(type_str), Some(create_str), _) => {
-            let cache_type = parse_str::<Type>(type_str).expect("unable to parse cache type");
-
-            let cache_create =
-                parse_str::<Block>(create_str).expect("unable to parse cache create block");
-
-            (quote! { #cache_type }, quote! { #cache_create })
-        }
-        (false, None, None, Some(_), None, _) => {
-            panic!("type requires create to also be set")
-        }
-        (false, None, None, None, Some(_), _) => {
-            panic!("create requires type to also be set")
-        }
-        _ => panic!(
-            "cache types (unbound, size and/or time, or type and create) are mutually exclusive"
-        ),
-    };
-
-    // make the set cache and return cache blocks
-    let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
-        (false, false) => {
-            let set_cache_block = quote! { cache.cache_set(key, result.clone()); };
-            let return_cache_block = if args.with_cached_flag {
-                quote! { let mut r = result.clone(); r.was_cached = true; return r }
-            } else {
-                quote! { return result.clone() }
-            };
-            (set_cache_block, return_cache_block)
-        }
-        (true, false) => {
-            let set_cache_block = quote! {
-                if let Ok(result) = &result {
-                    cache.cache_set(key, result.clone());
-                }
-            };
-            let return_cache_block = if args.with_cached_flag {
-                quote! { let mut r = result.clone(); r.was_cached = true; return Ok(r) }
-            } else {
-                quote! { return Ok(result.clone()) }
-            };
-            (set_cache_block, return_cache_block)
-        }
-        (false, true) => {
-            let set_cache_block = quote! {
-                if let Some(result) = &result {
-                    cache.cache_set(key, result.clone());
-                }
-            };
-            let return_cache_block = if args.with_cached_flag {
-                quote! { let mut r = result.clone(); r.was_cached = true; return Some(r) }
-            } else {
-                quote! { return Some(result.clone()) }
-            };
-            (set_cache_block, return_cache_block)
-        }
-        _ => panic!("the result and option attributes are mutually exclusive"),
-    };
-
-    let do_set_return_block = if asyncness.is_some() {
-        if args.sync_writes {
-            quote! {
-                // try to get a write lock first
-                let mut cache = #cache_ident.lock().await;
-                if let Some(result) = cache.cache_get(&key) {
-                    #return_cache_block
-                }
-
-                // run the function and cache the result
-                async fn inner(#inputs) #output #body;
-                let result = inner(#(#input_names),*).await;
-                #set_cache_block
-                result
-            }
-        } else {
-            quote! {
-                // run the function and cache the result
-                async fn inner(#inputs) #output #body;
-                let result = inner(#(#input_names),*).await;
-                let mut cache = #cache_ident.lock().await;
-                #set_cache_block
-                result
-            }
-        }
-    } else if args.sync_writes {
-        quote! {
-            // try to get a write lock first
-            let mut cache = #cache_ident.lock().unwrap();
-            if let Some(result) = cache.cache_get(&key) {
-                #return_cache_block
-            }
-
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let mut cache = #cache_ident.lock().unwrap();
-            #set_cache_block
-            result
-        }
-    };
-
-    // if you define arguments as mutable, e.g.
-    // #[cached]
-    // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
-    //     a += 1;
-    //     b += 1;
-    //     (a, b)
-    // }
-    // then we want the `mut` keywords present on the "inner" function
-    // that wraps your actual block of code.
-    // If the `mut`s are also on the outer method, then you'll
-    // get compiler warnings about your arguments not needing to be `mut`
-    // when they really do need to be.
-    let mut signature_no_muts = signature;
-    let mut sig_inputs = Punctuated::new();
-    for inp in &signature_no_muts.inputs {
-        let item = match inp {
-            FnArg::Receiver(_) => inp.clone(),
-            FnArg::Typed(pat_type) => {
-                let mut pt = pat_type.clone();
-                let pat = match &pat_type.pat.deref() {
-                    Pat::Ident(pat_ident) => {
-                        if pat_ident.mutability.is_some() {
-                            let mut p = pat_ident.clone();
-                            p.mutability = None;
-                            Box::new(Pat::Ident(p))
-                        } else {
-                            Box::new(Pat::Ident(pat_ident.clone()))
-                        }
-                    }
-                    _ => pat_type.pat.clone(),
-                };
-                pt.pat = pat;
-                FnArg::Typed(pt)
-            }
-        };
-        sig_inputs.push(item);
-    }
-    signature_no_muts.inputs = sig_inputs;
-
-    // create a signature for the cache-priming function
-    let prime_fn_ident = Ident::new(&format!("{}_prime_cache", &fn_ident), fn_ident.span());
-    let mut prime_sig = signature_no_muts.clone();
-    prime_sig.ident = prime_fn_ident;
-
-    let prime_do_set_return_block = if asyncness.is_some() {
-        quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
-            let mut cache = #cache_ident.lock().await;
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let mut cache = #cache_ident.lock().unwrap();
-            #set_cache_block
-            result
-        }
-    };
-
-    // make cached static, cached function and prime cached function doc comments
-    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
-    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
-    );
-    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
-        attributes.push(parse_quote! { #[doc = ""] });
-        attributes.push(parse_quote! { #[doc = "# Caching"] });
-        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
-    } else {
-        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
-    }
-
-    // put it all together
-    let expanded = if asyncness.is_some() {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::Mutex::new(#cache_create));
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                use cached::Cached;
-                let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let mut cache = #cache_ident.lock().await;
-                    if let Some(result) = cache.cache_get(&key) {
-                        #return_cache_block
-                    }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                use cached::Cached;
-                let key = #key_convert_block;
-                #prime_do_set_return_block
-            }
-        }
-    } else {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(#cache_create));
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                use cached::Cached;
-                let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let mut cache = #cache_ident.lock().unwrap();
-                    if let Some(result) = cache.cache_get(&key) {
-                        #return_cache_block
-                    }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                use cached::Cached;
-                let key = #key_convert_block;
-                #prime_do_set_return_block
-            }
-        }
-    };
-
-    expanded.into()
-}
-
-#[derive(FromMeta)]
-struct OnceMacroArgs {
-    #[darling(default)]
-    name: Option<String>,
-    #[darling(default)]
-    time: Option<u64>,
-    #[darling(default)]
-    sync_writes: bool,
-    #[darling(default)]
-    result: bool,
-    #[darling(default)]
-    option: bool,
-    #[darling(default)]
-    with_cached_flag: bool,
+    cached::cached(args, input)
 }
 
 /// # Attributes
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
+/// - `prime_name`: (optional, string) specify the name of the generated cache-priming function, defaults to
+///   `{fn}_prime_cache`. Use it when another item of the module already has that name.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCached` or `TimedSizedCache`.
+///   Values expire once exactly `time` seconds have elapsed, so `time = 0` runs the function on every call.
 /// - `sync_writes`: (optional, bool) specify whether to synchronize the execution of writing of uncached values.
 /// - `result`: (optional, bool) If your function returns a `Result`, only cache `Ok` values returned by the function.
+/// - `reset_on_err`: (optional, bool) with `result`, clear the stored value when the function returns `Err`,
+///   so an expired value isn't kept around after a failed refresh and the next call runs the function again.
+/// - `retry_backoff_ms`: (optional, u64) with `result`, after the function returns `Err`, return (a clone of) that
+///   error without running the function again until this many milliseconds have elapsed, e.g. so callers don't
+///   hammer a flaky dependency during startup. The backoff doubles with each consecutive failure, up to
+///   `retry_backoff_max_ms`, and an `Ok` resets it. Requires the error type to implement `Clone`.
+/// - `retry_backoff_max_ms`: (optional, u64) with `retry_backoff_ms`, the maximum backoff in milliseconds,
+///   defaults to `retry_backoff_ms` (a constant backoff).
 /// - `option`: (optional, bool) If your function returns an `Option`, only cache `Some` values returned by the function.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
-///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+///   the `cached::Return.was_cached` flag will be updated when a cached value is returned. With `time`,
+///   `cached::Return.expires_in` is set to how long until the returned value expires.
+/// - `block_on`: (optional, bool) For async functions, also generate a `{fn}_blocking` function with the same
+///   arguments that can be called from synchronous code. It runs the async function to completion with
+///   `cached::async_sync::block_on`.
+/// - `lock`: (optional, string) how the value is stored: `"rwlock"` (the default) behind a `RwLock`, or `"arcswap"`
+///   (requires the `arcswap` feature of `cached`) in an `arc_swap::ArcSwapOption`, so reading a cached value
+///   never locks, e.g. for hot getters called from many threads. With `sync_writes`, misses run the function
+///   under a mutex. Not for run-once guards.
+/// - A `{fn}_set_enabled(bool)` function is generated to disable (or re-enable) the cache of this function
+///   at runtime, returning whether it was enabled (not for run-once guards, see below).
+///
+/// ## Note
+/// Async `#[once]` functions store their value behind a `tokio::sync::RwLock`, which doesn't need a running
+/// runtime by itself, but the body of the function usually does (e.g. when it uses `tokio::time` or tokio io).
+/// Calling the function from synchronous code, such as a plain `#[test]`, then panics with "there is no reactor
+/// running". Use `block_on = true` and call `{fn}_blocking` from those call sites instead.
+///
+/// ## Run-once guards
+/// A `#[once]` function returning `()` runs once (or once per `time` seconds), e.g. for idempotent
+/// initialization routines. Its cache only records whether (and when) it ran, and a `{fn}_has_run()`
+/// function is generated to check it.
 #[proc_macro_attribute]
 pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
-    let attr_args = parse_macro_input!(args as AttributeArgs);
-    let args = match OnceMacroArgs::from_list(&attr_args) {
-        Ok(v) => v,
-        Err(e) => {
-            return TokenStream::from(e.write_errors());
-        }
-    };
-    let input = parse_macro_input!(input as ItemFn);
-
-    // pull out the parts of the input
-    let mut attributes = input.attrs;
-    let visibility = input.vis;
-    let signature = input.sig;
-    let body = input.block;
-
-    // pull out the parts of the function signature
-    let fn_ident = signature.ident.clone();
-    let inputs = signature.inputs.clone();
-    let output = signature.output.clone();
-    let asyncness = signature.asyncness;
-
-    // pull out the names and types of the function inputs
-    let input_names = inputs
-        .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => {
-                // if you define arguments as mutable, e.g.
-                // #[once]
-                // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
-                //     a += 1;
-                //     b += 1;
-                //     (a, b)
-                // }
-                // then we need to strip off the `mut` keyword from the
-                // variable identifiers so we can refer to arguments `a` and `b`
-                // instead of `mut a` and `mut b`
-                match &pat_type.pat.deref() {
-                    Pat::Ident(pat_ident) => {
-                        if pat_ident.mutability.is_some() {
-                            let mut p = pat_ident.clone();
-                            p.mutability = None;
-                            Box::new(Pat::Ident(p))
-                        } else {
-                            Box::new(Pat::Ident(pat_ident.clone()))
-                        }
-                    }
-                    _ => pat_type.pat.clone(),
-                }
-            }
-        })
-        .collect::<Vec<Box<Pat>>>();
-
-    // pull out the output type
-    let output_ty = match &output {
-        ReturnType::Default => quote! {()},
-        ReturnType::Type(_, ty) => quote! {#ty},
-    };
-
-    let output_span = output_ty.span();
-    let output_ts = TokenStream::from(output_ty.clone());
-    let output_parts = output_ts
-        .clone()
-        .into_iter()
-        .filter_map(|tt| match tt {
-            proc_macro::TokenTree::Ident(ident) => Some(ident.to_string()),
-            _ => None,
-        })
-        .collect::<Vec<_>>();
-    let output_string = output_parts.join("::");
-    let output_type_display = output_ts.to_string().replace(' ', "");
-
-    // if `with_cached_flag = true`, then enforce that the return type
-    // is something wrapped in `Return`. Either `Return<T>` or the
-    // fully qualified `cached::Return<T>`
-    if args.with_cached_flag
-        && !output_string.contains("Return")
-        && !output_string.contains("cached::Return")
-    {
-        return syn::Error::new(
-            output_span,
-            format!(
-                "\nWhen specifying `with_cached_flag = true`, \
-                    the return type must be wrapped in `cached::Return<T>`. \n\
-                    The following return types are supported: \n\
-                    |    `cached::Return<T>`\n\
-                    |    `std::result::Result<cachedReturn<T>, E>`\n\
-                    |    `std::option::Option<cachedReturn<T>>`\n\
-                    Found type: {t}.",
-                t = output_type_display
-            ),
-        )
-        .to_compile_error()
-        .into();
-    }
-
-    // Find the type of the value to store.
-    // Normally it's the same as the return type of the functions, but
-    // for Options and Results it's the (first) inner type. So for
-    // Option<u32>, store u32, for Result<i32, String>, store i32, etc.
-    let cache_value_ty = match (&args.result, &args.option) {
-        (false, false) => output_ty,
-        (true, true) => panic!("the result and option attributes are mutually exclusive"),
-        _ => match output.clone() {
-            ReturnType::Default => {
-                panic!("function must return something for result or option attributes")
-            }
-            ReturnType::Type(_, ty) => {
-                if let Type::Path(typepath) = *ty {
-                    let segments = typepath.path.segments;
-                    if let PathArguments::AngleBracketed(brackets) =
-                        &segments.last().unwrap().arguments
-                    {
-                        let inner_ty = brackets.args.first().unwrap();
-                        quote! {#inner_ty}
-                    } else {
-                        panic!("function return type has no inner type")
-                    }
-                } else {
-                    panic!("function return type too complex")
-                }
-            }
-        },
-    };
-
-    // make the cache identifier
-    let cache_ident = match args.name {
-        Some(name) => Ident::new(&name, fn_ident.span()),
-        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
-    };
-
-    // make the cache type and create statement
-    let (cache_ty, cache_create) = match &args.time {
-        None => (quote! { Option<#cache_value_ty> }, quote! { None }),
-        Some(_) => (
-            quote! { Option<(::cached::instant::Instant, #cache_value_ty)> },
-            quote! { None },
-        ),
-    };
-
-    // make the set cache and return cache blocks
-    let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
-        (false, false) => {
-            let set_cache_block = if args.time.is_some() {
-                quote! {
-                    *cached = Some((now, result.clone()));
-                }
-            } else {
-                quote! {
-                    *cached = Some(result.clone());
-                }
-            };
-
-            let return_cache_block = if args.with_cached_flag {
-                quote! { let mut r = result.clone(); r.was_cached = true; return r }
-            } else {
-                quote! { return result.clone() }
-            };
-            let return_cache_block = if let Some(time) = &args.time {
-                quote! {
-                    let (created_sec, result) = result;
-                    if now.duration_since(*created_sec).as_secs() < #time {
-                        #return_cache_block
-                    }
-                }
-            } else {
-                quote! { #return_cache_block }
-            };
-            (set_cache_block, return_cache_block)
-        }
-        (true, false) => {
-            let set_cache_block = if args.time.is_some() {
-                quote! {
-                    if let Ok(result) = &result {
-                        *cached = Some((now, result.clone()));
-                    }
-                }
-            } else {
-                quote! {
-                    if let Ok(result) = &result {
-                        *cached = Some(result.clone());
-                    }
-                }
-            };
-
-            let return_cache_block = if args.with_cached_flag {
-                quote! { let mut r = result.clone(); r.was_cached = true; return Ok(r) }
-            } else {
-                quote! { return Ok(result.clone()) }
-            };
-            let return_cache_block = if let Some(time) = &args.time {
-                quote! {
-                    let (created_sec, result) = result;
-                    if now.duration_since(*created_sec).as_secs() < #time {
-                        #return_cache_block
-                    }
-                }
-            } else {
-                quote! { #return_cache_block }
-            };
-            (set_cache_block, return_cache_block)
-        }
-        (false, true) => {
-            let set_cache_block = if args.time.is_some() {
-                quote! {
-                    if let Some(result) = &result {
-                        *cached = Some((now, result.clone()));
-                    }
-                }
-            } else {
-                quote! {
-                    if let Some(result) = &result {
-                        *cached = Some(result.clone());
-                    }
-                }
-            };
-
-            let return_cache_block = if args.with_cached_flag {
-                quote! { let mut r = result.clone(); r.was_cached = true; return Some(r) }
-            } else {
-                quote! { return Some(result.clone()) }
-            };
-            let return_cache_block = if let Some(time) = &args.time {
-                quote! {
-                    let (created_sec, result) = result;
-                    if now.duration_since(*created_sec).as_secs() < #time {
-                        #return_cache_block
-                    }
-                }
-            } else {
-                quote! { #return_cache_block }
-            };
-            (set_cache_block, return_cache_block)
-        }
-        _ => panic!("the result and option attributes are mutually exclusive"),
-    };
-
-    let do_set_return_block = if asyncness.is_some() {
-        if args.sync_writes {
-            quote! {
-                // try to get a write lock first
-                let mut cached = #cache_ident.write().await;
-                if let Some(result) = &*cached {
-                    #return_cache_block
-                }
-
-                // run the function and cache the result
-                async fn inner(#inputs) #output #body;
-                let result = inner(#(#input_names),*).await;
-                #set_cache_block
-                result
-            }
-        } else {
-            quote! {
-                // run the function and cache the result
-                async fn inner(#inputs) #output #body;
-                let result = inner(#(#input_names),*).await;
-                let mut cached = #cache_ident.write().await;
-                #set_cache_block
-                result
-            }
-        }
-    } else if args.sync_writes {
-        quote! {
-            // try to get a write lock first
-            let mut cached = #cache_ident.write().unwrap();
-            if let Some(result) = &*cached {
-                #return_cache_block
-            }
-
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let mut cached = #cache_ident.write().unwrap();
-            #set_cache_block
-            result
-        }
-    };
-
-    // if you define arguments as mutable, e.g.
-    // #[once]
-    // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
-    //     a += 1;
-    //     b += 1;
-    //     (a, b)
-    // }
-    // then we want the `mut` keywords present on the "inner" function
-    // that wraps your actual block of code.
-    // If the `mut`s are also on the outer method, then you'll
-    // get compiler warnings about your arguments not needing to be `mut`
-    // when they really do need to be.
-    let mut signature_no_muts = signature;
-    let mut sig_inputs = Punctuated::new();
-    for inp in &signature_no_muts.inputs {
-        let item = match inp {
-            FnArg::Receiver(_) => inp.clone(),
-            FnArg::Typed(pat_type) => {
-                let mut pt = pat_type.clone();
-                let pat = match &pat_type.pat.deref() {
-                    Pat::Ident(pat_ident) => {
-                        if pat_ident.mutability.is_some() {
-                            let mut p = pat_ident.clone();
-                            p.mutability = None;
-                            Box::new(Pat::Ident(p))
-                        } else {
-                            Box::new(Pat::Ident(pat_ident.clone()))
-                        }
-                    }
-                    _ => pat_type.pat.clone(),
-                };
-                pt.pat = pat;
-                FnArg::Typed(pt)
-            }
-        };
-        sig_inputs.push(item);
-    }
-    signature_no_muts.inputs = sig_inputs;
-
-    let prime_fn_ident = Ident::new(&format!("{}_prime_cache", &fn_ident), fn_ident.span());
-    let mut prime_sig = signature_no_muts.clone();
-    prime_sig.ident = prime_fn_ident;
-
-    let prime_do_set_return_block = if asyncness.is_some() {
-        quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
-            let mut cached = #cache_ident.write().await;
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let mut cached = #cache_ident.write().unwrap();
-            #set_cache_block
-            result
-        }
-    };
-
-    // make cached static, cached function and prime cached function doc comments
-    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
-    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
-    );
-    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
-        attributes.push(parse_quote! { #[doc = ""] });
-        attributes.push(parse_quote! { #[doc = "# Caching"] });
-        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
-    } else {
-        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
-    }
-
-    // put it all together
-    let expanded = if asyncness.is_some() {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::RwLock::new(#cache_create));
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                let now = ::cached::instant::Instant::now();
-                {
-                    // check if the result is cached
-                    let mut cached = #cache_ident.read().await;
-                    if let Some(result) = &*cached {
-                        #return_cache_block
-                    }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                let now = ::cached::instant::Instant::now();
-                #prime_do_set_return_block
-            }
-        }
-    } else {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::RwLock::new(#cache_create));
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                let now = ::cached::instant::Instant::now();
-                {
-                    // check if the result is cached
-                    let mut cached = #cache_ident.read().unwrap();
-                    if let Some(result) = &*cached {
-                        #return_cache_block
-                    }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                let now = ::cached::instant::Instant::now();
-                #prime_do_set_return_block
-            }
-        }
-    };
-
-    expanded.into()
-}
-
-#[derive(FromMeta)]
-struct IOMacroArgs {
-    map_error: String,
-    #[darling(default)]
-    redis: bool,
-    #[darling(default)]
-    cache_prefix_block: Option<String>,
-    #[darling(default)]
-    name: Option<String>,
-    #[darling(default)]
-    time: Option<u64>,
-    #[darling(default)]
-    time_refresh: Option<bool>,
-    #[darling(default)]
-    key: Option<String>,
-    #[darling(default)]
-    convert: Option<String>,
-    #[darling(default)]
-    with_cached_flag: bool,
-    #[darling(default, rename = "type")]
-    cache_type: Option<String>,
-    #[darling(default, rename = "create")]
-    cache_create: Option<String>,
+    once::once(args, input)
 }
 
 /// # Attributes
 /// - `map_error`: (string, expr closure) specify a closure used to map any IO-store errors into
 ///   the error type returned by your function.
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
+/// - `prime_name`: (optional, string) specify the name of the generated cache-priming function, defaults to
+///   `{fn}_prime_cache`. Use it when another item of the module already has that name.
 /// - `redis`: (optional, bool) default to a `RedisCache` or `AsyncRedisCache`
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCached` or `TimedSizedCache`.
 /// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits.
 /// - `type`: (optional, string type) explicitly specify the cache store type to use.
 /// - `cache_prefix_block`: (optional, string expr) specify an expression used to create the string used as a
 ///   prefix for all cache keys of this function, e.g. `cache_prefix_block = r##"{ "my_prefix" }"##`.
-///   When not specified, the cache prefix will be constructed from the name of the function. This
-///   could result in unexpected conflicts between io_cached-functions of the same name so it's
-///   recommended that you specify a prefix you're sure will be unique.
+///   When not specified, the cache prefix is the path of the function followed by `:`, e.g.
+///   `my_crate::users::fetch_user:`. The path is unique within a crate, but services sharing a redis
+///   instance should also set a namespace unique to each (see `RedisCacheBuilder::set_namespace`).
+/// - `version`: (optional, u64) specify a version included in every cache key of this function
+///   (`{namespace}{prefix}{version}:{key}`). Bumping it makes the values cached by previous versions
+///   unreachable; they expire with their TTL, or can all be removed with `{NAME}.clear_namespace()`.
+/// - `key_encoder`: (optional, string expr) with `redis`, specify a `fn(&K) -> Vec<u8>` encoding the `{key}` part
+///   of cache keys, which defaults to the `Display` output of the key. Use
+///   `key_encoder = "<K as cached::keys::CacheKeyEncode>::encode"` for an encoding that's stable across versions,
+///   or a function of your own, e.g. human-readable keys to debug the contents of redis.
 /// - `create`: (optional, string expr) specify an expression used to create a new cache store, e.g. `create = r##"{ CacheType::new() }"##`.
 /// - `key`: (optional, string type) specify what type to use for the cache key, e.g. `type = "TimedCached<u32, u32>"`.
-///    When `key` is specified, `convert` must also be specified.
+///   When `key` is specified, `convert` must also be specified.
 /// - `convert`: (optional, string expr) specify an expression used to convert function arguments to a cache
 ///   key, e.g. `convert = r##"{ format!("{}:{}", arg1, arg2) }"##`. When `convert` is specified,
 ///   `key` or `type` must also be set.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+/// - A `{fn}_set_enabled(bool)` function is generated to disable (or re-enable) the cache of this function
+///   at runtime, returning whether it was enabled.
 ///
 /// ## Note
 /// The `type`, `create`, `key`, and `convert` attributes must be in a `String`
@@ -1017,496 +338,35 @@ struct IOMacroArgs {
 /// attributes into `Type`s or `Block`s.
 #[proc_macro_attribute]
 pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
-    let attr_args = parse_macro_input!(args as AttributeArgs);
-    let args = match IOMacroArgs::from_list(&attr_args) {
-        Ok(v) => v,
-        Err(e) => {
-            return TokenStream::from(e.write_errors());
-        }
-    };
-    let input = parse_macro_input!(input as ItemFn);
-
-    // pull out the parts of the input
-    let mut attributes = input.attrs;
-    let visibility = input.vis;
-    let signature = input.sig;
-    let body = input.block;
-
-    // pull out the parts of the function signature
-    let fn_ident = signature.ident.clone();
-    let inputs = signature.inputs.clone();
-    let output = signature.output.clone();
-    let asyncness = signature.asyncness;
-
-    // pull out the names and types of the function inputs
-    let input_tys = inputs
-        .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => pat_type.ty.clone(),
-        })
-        .collect::<Vec<Box<Type>>>();
-
-    let input_names = inputs
-        .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => {
-                // if you define arguments as mutable, e.g.
-                // #[cached]
-                // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
-                //     a += 1;
-                //     b += 1;
-                //     (a, b)
-                // }
-                // then we need to strip off the `mut` keyword from the
-                // variable identifiers so we can refer to arguments `a` and `b`
-                // instead of `mut a` and `mut b`
-                match &pat_type.pat.deref() {
-                    Pat::Ident(pat_ident) => {
-                        if pat_ident.mutability.is_some() {
-                            let mut p = pat_ident.clone();
-                            p.mutability = None;
-                            Box::new(Pat::Ident(p))
-                        } else {
-                            Box::new(Pat::Ident(pat_ident.clone()))
-                        }
-                    }
-                    _ => pat_type.pat.clone(),
-                }
-            }
-        })
-        .collect::<Vec<Box<Pat>>>();
-
-    // pull out the output type
-    let output_ty = match &output {
-        ReturnType::Default => quote! {()},
-        ReturnType::Type(_, ty) => quote! {#ty},
-    };
-
-    let output_span = output_ty.span();
-    let output_ts = TokenStream::from(output_ty);
-    let output_parts = output_ts
-        .clone()
-        .into_iter()
-        .filter_map(|tt| match tt {
-            proc_macro::TokenTree::Ident(ident) => Some(ident.to_string()),
-            _ => None,
-        })
-        .collect::<Vec<_>>();
-    let output_string = output_parts.join("::");
-    let output_type_display = output_ts.to_string().replace(' ', "");
-
-    // if `with_cached_flag = true`, then enforce that the return type
-    // is something wrapped in `Return`. Either `Return<T>` or the
-    // fully qualified `cached::Return<T>`
-    if args.with_cached_flag
-        && !output_string.contains("Return")
-        && !output_string.contains("cached::Return")
-    {
-        return syn::Error::new(
-            output_span,
-            format!(
-                "\nWhen specifying `with_cached_flag = true`, \
-                    the return type must be wrapped in `cached::Return<T>`. \n\
-                    The following return types are supported: \n\
-                    |    `Result<cached::Return<T>, E>`\n\
-                    Found type: {t}.",
-                t = output_type_display
-            ),
-        )
-        .to_compile_error()
-        .into();
-    }
-
-    // Find the type of the value to store.
-    // Return type always needs to be a result, so we want the (first) inner type.
-    // For Result<i32, String>, store i32, etc.
-    let cache_value_ty = match output.clone() {
-        ReturnType::Default => {
-            panic!(
-                "#[io_cached] functions must return `Result`s, found {:?}",
-                output_type_display
-            );
-        }
-        ReturnType::Type(_, ty) => {
-            if let Type::Path(typepath) = *ty {
-                let segments = typepath.path.segments;
-                if let PathArguments::AngleBracketed(brackets) = &segments.last().unwrap().arguments
-                {
-                    let inner_ty = brackets.args.first().unwrap();
-                    if output_string.contains("Return") || output_string.contains("cached::Return")
-                    {
-                        if let GenericArgument::Type(Type::Path(typepath)) = inner_ty {
-                            let segments = &typepath.path.segments;
-                            if let PathArguments::AngleBracketed(brackets) =
-                                &segments.last().unwrap().arguments
-                            {
-                                let inner_ty = brackets.args.first().unwrap();
-                                quote! {#inner_ty}
-                            } else {
-                                panic!(
-                                    "#[io_cached] unable to determine cache value type, found {:?}",
-                                    output_type_display
-                                );
-                            }
-                        } else {
-                            panic!(
-                                "#[io_cached] unable to determine cache value type, found {:?}",
-                                output_type_display
-                            );
-                        }
-                    } else {
-                        quote! {#inner_ty}
-                    }
-                } else {
-                    panic!("#[io_cached] functions must return `Result`s")
-                }
-            } else {
-                panic!(
-                    "function return type too complex, #[io_cached] functions must return `Result`s"
-                )
-            }
-        }
-    };
-
-    // make the cache identifier
-    let cache_ident = match args.name {
-        Some(name) => Ident::new(&name, fn_ident.span()),
-        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
-    };
-
-    // make the cache key type and block that converts the inputs into the key type
-    let (cache_key_ty, key_convert_block) = match (&args.key, &args.convert, &args.cache_type) {
-        (Some(key_str), Some(convert_str), _) => {
-            let cache_key_ty = parse_str::<Type>(key_str).expect("unable to parse cache key type");
-
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
-
-            (quote! {#cache_key_ty}, quote! {#key_convert_block})
-        }
-        (None, Some(convert_str), Some(_)) => {
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
-
-            (quote! {}, quote! {#key_convert_block})
-        }
-        (None, None, _) => (
-            quote! {(#(#input_tys),*)},
-            quote! {(#(#input_names.clone()),*)},
-        ),
-        (Some(_), None, _) => panic!("key requires convert to be set"),
-        (None, Some(_), None) => panic!("convert requires key or type to be set"),
-    };
-
-    // make the cache type and create statement
-    let (cache_ty, cache_create) = match (
-        &args.redis,
-        &args.time,
-        &args.time_refresh,
-        &args.cache_prefix_block,
-        &args.cache_type,
-        &args.cache_create,
-    ) {
-        (true, time, time_refresh, cache_prefix, cache_type, cache_create) => {
-            let cache_ty = match cache_type {
-                Some(cache_type) => {
-                    let cache_type =
-                        parse_str::<Type>(cache_type).expect("unable to parse cache type");
-                    quote! { #cache_type }
-                }
-                None => {
-                    if asyncness.is_some() {
-                        quote! { cached::AsyncRedisCache<#cache_key_ty, #cache_value_ty> }
-                    } else {
-                        quote! { cached::RedisCache<#cache_key_ty, #cache_value_ty> }
-                    }
-                }
-            };
-            let cache_create = match cache_create {
-                Some(cache_create) => {
-                    if time.is_some() || time_refresh.is_some() || cache_prefix.is_some() {
-                        panic!("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block");
-                    } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
-                        quote! { #cache_create }
-                    }
-                }
-                None => {
-                    if time.is_none() {
-                        if asyncness.is_some() {
-                            panic!("AsyncRedisCache requires a `time` when `create` block is not specified")
-                        } else {
-                            panic!(
-                                "RedisCache requires a `time` when `create` block is not specified"
-                            )
-                        };
-                    } else {
-                        let cache_prefix = if let Some(cp) = cache_prefix {
-                            cp.to_string()
-                        } else {
-                            format!(" {{ \"cached::proc_macro::io_cached::{}\" }}", cache_ident)
-                        };
-                        let cache_prefix = parse_str::<Block>(cache_prefix.as_ref())
-                            .expect("unable to parse cache_prefix_block");
-                        match time_refresh {
-                            Some(time_refresh) => {
-                                if asyncness.is_some() {
-                                    quote! { cached::AsyncRedisCache::new(#cache_prefix, #time).set_refresh(#time_refresh).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
-                                } else {
-                                    quote! {
-                                        cached::RedisCache::new(#cache_prefix, #time).set_refresh(#time_refresh).build().expect("error constructing RedisCache in #[io_cached] macro")
-                                    }
-                                }
-                            }
-                            None => {
-                                if asyncness.is_some() {
-                                    quote! { cached::AsyncRedisCache::new(#cache_prefix, #time).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
-                                } else {
-                                    quote! {
-                                        cached::RedisCache::new(#cache_prefix, #time).build().expect("error constructing RedisCache in #[io_cached] macro")
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            };
-            (cache_ty, cache_create)
-        }
-        (_, time, time_refresh, cache_prefix, cache_type, cache_create) => {
-            let cache_ty = match cache_type {
-                Some(cache_type) => {
-                    let cache_type =
-                        parse_str::<Type>(cache_type).expect("unable to parse cache type");
-                    quote! { #cache_type }
-                }
-                None => panic!("#[io_cached] cache `type` must be specified"),
-            };
-            let cache_create = match cache_create {
-                Some(cache_create) => {
-                    if time.is_some() || time_refresh.is_some() || cache_prefix.is_some() {
-                        panic!("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block");
-                    } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
-                        quote! { #cache_create }
-                    }
-                }
-                None => {
-                    panic!("#[io_cached] cache `create` block must be specified");
-                }
-            };
-            (cache_ty, cache_create)
-        }
-        #[allow(unreachable_patterns)]
-        _ => panic!("#[io_cached] cache types cache type could not be determined"),
-    };
-
-    let map_error = &args.map_error;
-    let map_error = parse_str::<ExprClosure>(map_error).expect("unable to parse map_error block");
-
-    // make the set cache and return cache blocks
-    let (set_cache_block, return_cache_block) = {
-        let (set_cache_block, return_cache_block) = if args.with_cached_flag {
-            (
-                if asyncness.is_some() {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.value.clone()).await.map_err(#map_error)?;
-                        }
-                    }
-                } else {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.value.clone()).map_err(#map_error)?;
-                        }
-                    }
-                },
-                quote! { let mut r = ::cached::Return::new(result.clone()); r.was_cached = true; return Ok(r) },
-            )
-        } else {
-            (
-                if asyncness.is_some() {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.clone()).await.map_err(#map_error)?;
-                        }
-                    }
-                } else {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.clone()).map_err(#map_error)?;
-                        }
-                    }
-                },
-                quote! { return Ok(result.clone()) },
-            )
-        };
-        (set_cache_block, return_cache_block)
-    };
-
-    let do_set_return_block = if asyncness.is_some() {
-        quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
-            let cache = &#cache_ident.get().await;
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let cache = &#cache_ident;
-            #set_cache_block
-            result
-        }
-    };
-
-    // if you define arguments as mutable, e.g.
-    // #[cached]
-    // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
-    //     a += 1;
-    //     b += 1;
-    //     (a, b)
-    // }
-    // then we want the `mut` keywords present on the "inner" function
-    // that wraps your actual block of code.
-    // If the `mut`s are also on the outer method, then you'll
-    // get compiler warnings about your arguments not needing to be `mut`
-    // when they really do need to be.
-    let mut signature_no_muts = signature;
-    let mut sig_inputs = Punctuated::new();
-    for inp in &signature_no_muts.inputs {
-        let item = match inp {
-            FnArg::Receiver(_) => inp.clone(),
-            FnArg::Typed(pat_type) => {
-                let mut pt = pat_type.clone();
-                let pat = match &pat_type.pat.deref() {
-                    Pat::Ident(pat_ident) => {
-                        if pat_ident.mutability.is_some() {
-                            let mut p = pat_ident.clone();
-                            p.mutability = None;
-                            Box::new(Pat::Ident(p))
-                        } else {
-                            Box::new(Pat::Ident(pat_ident.clone()))
-                        }
-                    }
-                    _ => pat_type.pat.clone(),
-                };
-                pt.pat = pat;
-                FnArg::Typed(pt)
-            }
-        };
-        sig_inputs.push(item);
-    }
-    signature_no_muts.inputs = sig_inputs;
-
-    // create a signature for the cache-priming function
-    let prime_fn_ident = Ident::new(&format!("{}_prime_cache", &fn_ident), fn_ident.span());
-    let mut prime_sig = signature_no_muts.clone();
-    prime_sig.ident = prime_fn_ident;
-
-    let prime_do_set_return_block = if asyncness.is_some() {
-        quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
-            let cache = &#cache_ident.get().await;
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let cache = &#cache_ident;
-            #set_cache_block
-            result
-        }
-    };
-
-    // make cached static, cached function and prime cached function doc comments
-    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
-    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
-    );
-    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
-        attributes.push(parse_quote! { #[doc = ""] });
-        attributes.push(parse_quote! { #[doc = "# Caching"] });
-        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
-    } else {
-        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
-    }
-
-    // put it all together
-    let expanded = if asyncness.is_some() {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            ::cached::lazy_static::lazy_static! {
-                #visibility static ref #cache_ident: ::cached::async_once::AsyncOnce<#cache_ty> = ::cached::async_once::AsyncOnce::new(async move { #cache_create });
-            }
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                use cached::IOCachedAsync;
-                let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let cache = &#cache_ident.get().await;
-                    if let Some(result) = cache.cache_get(&key).await.map_err(#map_error)? {
-                        #return_cache_block
-                    }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                use cached::IOCachedAsync;
-                let key = #key_convert_block;
-                #prime_do_set_return_block
-            }
-        }
-    } else {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> = ::cached::once_cell::sync::Lazy::new(|| #cache_create);
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                use cached::IOCached;
-                let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let cache = &#cache_ident;
-                    if let Some(result) = cache.cache_get(&key).map_err(#map_error)? {
-                        #return_cache_block
-                    }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                use cached::IOCached;
-                let key = #key_convert_block;
-                #prime_do_set_return_block
-            }
-        }
-    };
+    io_cached::io_cached(args, input)
+}
 
-    expanded.into()
+/// Isolate the caches of `#[cached]` functions declared inside a test.
+///
+/// Every `#[cached]` function declared in the body of the annotated function has its cache
+/// cleared (and its metrics reset) before the body runs and again when the function returns
+/// or unwinds, so a test always starts from a cold cache regardless of the order tests run in.
+/// The caches are emptied through the generated `{fn}_cache_flush` functions, whatever their `lock`.
+/// Place it above `#[test]` (or `#[tokio::test]`):
+///
+/// ```rust,ignore
+/// use cached::proc_macro::{cached, cached_macro_test};
+///
+/// #[cached_macro_test]
+/// #[test]
+/// fn counts_a_single_miss() {
+///     #[cached]
+///     fn double(n: u32) -> u32 {
+///         n * 2
+///     }
+///
+///     assert_eq!(double(2), 4);
+///     assert_eq!(double(2), 4);
+///     let cache = DOUBLE.lock().unwrap();
+///     assert_eq!(cache.cache_misses(), Some(1));
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn cached_macro_test(args: TokenStream, input: TokenStream) -> TokenStream {
+    cached_macro_test::cached_macro_test(args, input)
 }
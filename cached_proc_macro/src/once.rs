@@ -0,0 +1,717 @@
+use crate::helpers::{
+    attr_arg_span, check_cached_flag_output, check_not_method, enabled_switch, inner_fn_ident,
+    inner_generics, outer_inputs, prime_fn_ident,
+};
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use quote::quote;
+use std::ops::Deref;
+use syn::{
+    parse_macro_input, parse_quote, AttributeArgs, Ident, ItemFn, PathArguments, ReturnType, Type,
+};
+
+#[derive(FromMeta)]
+struct OnceMacroArgs {
+    #[darling(default)]
+    name: Option<String>,
+    #[darling(default)]
+    prime_name: Option<String>,
+    #[darling(default)]
+    time: Option<u64>,
+    #[darling(default)]
+    sync_writes: bool,
+    #[darling(default)]
+    result: bool,
+    #[darling(default)]
+    option: bool,
+    #[darling(default)]
+    with_cached_flag: bool,
+    #[darling(default)]
+    block_on: bool,
+    #[darling(default)]
+    reset_on_err: bool,
+    #[darling(default)]
+    lock: Option<String>,
+    #[darling(default)]
+    retry_backoff_ms: Option<u64>,
+    #[darling(default)]
+    retry_backoff_max_ms: Option<u64>,
+}
+
+pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    let args = match OnceMacroArgs::from_list(&attr_args) {
+        Ok(v) => v,
+        Err(e) => {
+            return TokenStream::from(e.write_errors());
+        }
+    };
+    let input = parse_macro_input!(input as ItemFn);
+    if let Err(e) = check_not_method(&input.sig, "once") {
+        return e.to_compile_error().into();
+    }
+
+    if args.block_on && input.sig.asyncness.is_none() {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "block_on"),
+            "`block_on` only applies to async functions",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    if args.reset_on_err && !args.result {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "reset_on_err"),
+            "`reset_on_err` only applies to functions with `result = true`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // with `retry_backoff_ms`, the backoff after a failure, and the maximum it grows to
+    let retry_backoff = match (args.retry_backoff_ms, args.retry_backoff_max_ms) {
+        (None, None) => None,
+        (None, Some(_)) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "retry_backoff_max_ms"),
+                "`retry_backoff_max_ms` needs `retry_backoff_ms`",
+            )
+            .to_compile_error()
+            .into();
+        }
+        (Some(_), _) if !args.result => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "retry_backoff_ms"),
+                "`retry_backoff_ms` only applies to functions with `result = true`",
+            )
+            .to_compile_error()
+            .into();
+        }
+        (Some(0), _) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "retry_backoff_ms"),
+                "`retry_backoff_ms` must be greater than zero",
+            )
+            .to_compile_error()
+            .into();
+        }
+        (Some(min), max) => {
+            let max = max.unwrap_or(min);
+            if max < min {
+                return syn::Error::new(
+                    attr_arg_span(&attr_args, "retry_backoff_max_ms"),
+                    "`retry_backoff_max_ms` can't be less than `retry_backoff_ms`",
+                )
+                .to_compile_error()
+                .into();
+            }
+            Some((min, max))
+        }
+    };
+
+    // whether the value is stored in an `ArcSwapOption`, read without locking, instead of a `RwLock`
+    let arcswap = match args.lock.as_deref() {
+        None | Some("rwlock") => false,
+        Some("arcswap") => true,
+        Some(lock) => {
+            return syn::Error::new(
+                attr_arg_span(&attr_args, "lock"),
+                format!("unknown `lock` `{}`, expected `rwlock` or `arcswap`", lock),
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    // pull out the parts of the input
+    let mut attributes = input.attrs;
+    let visibility = input.vis;
+    let signature = input.sig;
+    let body = input.block;
+
+    // pull out the parts of the function signature
+    let fn_ident = signature.ident.clone();
+    let inner_fn_ident = inner_fn_ident(&fn_ident);
+    let inputs = signature.inputs.clone();
+    let output = signature.output.clone();
+    let asyncness = signature.asyncness;
+    let (inner_generics, inner_turbofish, inner_where) = inner_generics(&signature.generics);
+
+    // pull out the names and types of the function inputs
+    // the arguments of the generated functions, and the names passing them on to `inner`
+    let outer_inputs = outer_inputs(&inputs);
+    let input_names = &outer_inputs.names;
+
+    // pull out the output type
+    let output_ty = match &output {
+        ReturnType::Default => quote! {()},
+        ReturnType::Type(_, ty) => quote! {#ty},
+    };
+
+    // with `with_cached_flag = true`, the values are wrapped in `cached::Return<T>`
+    if args.with_cached_flag {
+        let (layers, expected, mode) = match (args.result, args.option) {
+            (true, _) => (1, "Result<cached::Return<T>, E>", Some("`result = true`")),
+            (_, true) => (1, "Option<cached::Return<T>>", Some("`option = true`")),
+            _ => (0, "cached::Return<T>", None),
+        };
+        if let Err(e) = check_cached_flag_output(&output, layers, expected, mode) {
+            return e.to_compile_error().into();
+        }
+    }
+
+    // Find the type of the value to store.
+    // Normally it's the same as the return type of the functions, but
+    // for Options and Results it's the (first) inner type. So for
+    // Option<u32>, store u32, for Result<i32, String>, store i32, etc.
+    let cache_value_ty = match (&args.result, &args.option) {
+        (false, false) => output_ty.clone(),
+        (true, true) => panic!("the result and option attributes are mutually exclusive"),
+        _ => match output.clone() {
+            ReturnType::Default => {
+                panic!("function must return something for result or option attributes")
+            }
+            ReturnType::Type(_, ty) => {
+                if let Type::Path(typepath) = *ty {
+                    let segments = typepath.path.segments;
+                    if let PathArguments::AngleBracketed(brackets) =
+                        &segments.last().unwrap().arguments
+                    {
+                        let inner_ty = brackets.args.first().unwrap();
+                        quote! {#inner_ty}
+                    } else {
+                        panic!("function return type has no inner type")
+                    }
+                } else {
+                    panic!("function return type too complex")
+                }
+            }
+        },
+    };
+
+    // make the cache identifier
+    let cache_ident = match args.name {
+        Some(name) => Ident::new(&name, fn_ident.span()),
+        None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
+    };
+
+    let backoff_ident = Ident::new(&format!("__{}_BACKOFF", cache_ident), cache_ident.span());
+    let backoff_static = match retry_backoff {
+        Some((min, max)) => quote! {
+            #[doc(hidden)]
+            static #backoff_ident: ::cached::once_cell::sync::Lazy<::cached::proc_macro::RetryBackoff<<#output_ty as ::cached::proc_macro::ResultError>::Error>> = ::cached::once_cell::sync::Lazy::new(|| {
+                ::cached::proc_macro::RetryBackoff::new(
+                    ::std::time::Duration::from_millis(#min),
+                    ::std::time::Duration::from_millis(#max),
+                )
+            });
+        },
+        None => quote! {},
+    };
+
+    // make the cache type and create statement
+    let stored_ty = match &args.time {
+        None => quote! { #cache_value_ty },
+        Some(_) => quote! { (::cached::instant::Instant, #cache_value_ty) },
+    };
+    let (cache_ty, cache_create) = (
+        quote! { ::std::option::Option<#stored_ty> },
+        quote! { ::std::option::Option::None },
+    );
+
+    // store (or clear) the cached value, `cached` being the write guard of the `RwLock`
+    let set_cached = |value: proc_macro2::TokenStream| {
+        if arcswap {
+            quote! { #cache_ident.store(::std::option::Option::Some(::std::sync::Arc::new(#value))); }
+        } else {
+            quote! { *cached = ::std::option::Option::Some(#value); }
+        }
+    };
+    let clear_cached = if arcswap {
+        quote! { #cache_ident.store(::std::option::Option::None); }
+    } else {
+        quote! { *cached = ::std::option::Option::None; }
+    };
+
+    // with `with_cached_flag` and `time`, report how long until the cached value expires
+    let set_expires_in = match &args.time {
+        Some(time) => quote! {
+            r.expires_in = ::std::option::Option::Some(
+                ::std::time::Duration::from_secs(#time).saturating_sub(now.duration_since(*created_sec)),
+            );
+        },
+        None => quote! {},
+    };
+
+    // make the set cache and return cache blocks
+    let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
+        (false, false) => {
+            let set_cache_block = if args.time.is_some() {
+                set_cached(quote! { (now, ::std::clone::Clone::clone(&result)) })
+            } else {
+                set_cached(quote! { ::std::clone::Clone::clone(&result) })
+            };
+
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; #set_expires_in return r }
+            } else {
+                quote! { return ::std::clone::Clone::clone(result) }
+            };
+            let return_cache_block = if let Some(time) = &args.time {
+                quote! {
+                    let (created_sec, result) = result;
+                    if !::cached::stores::is_expired(*created_sec, #time, now) {
+                        #return_cache_block
+                    }
+                }
+            } else {
+                quote! { #return_cache_block }
+            };
+            (set_cache_block, return_cache_block)
+        }
+        (true, false) => {
+            // with `reset_on_err`, an error drops the stored (stale) value instead of keeping it
+            let on_err = if args.reset_on_err {
+                quote! { else { #clear_cached } }
+            } else {
+                quote! {}
+            };
+            let set = if args.time.is_some() {
+                set_cached(quote! { (now, ::std::clone::Clone::clone(result)) })
+            } else {
+                set_cached(quote! { ::std::clone::Clone::clone(result) })
+            };
+            let record_backoff = if retry_backoff.is_some() {
+                quote! { #backoff_ident.record(&result, ::cached::instant::Instant::now()); }
+            } else {
+                quote! {}
+            };
+            let set_cache_block = quote! {
+                #record_backoff
+                if let ::std::result::Result::Ok(result) = &result {
+                    #set
+                } #on_err
+            };
+
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; #set_expires_in return ::std::result::Result::Ok(r) }
+            } else {
+                quote! { return ::std::result::Result::Ok(::std::clone::Clone::clone(result)) }
+            };
+            let return_cache_block = if let Some(time) = &args.time {
+                quote! {
+                    let (created_sec, result) = result;
+                    if !::cached::stores::is_expired(*created_sec, #time, now) {
+                        #return_cache_block
+                    }
+                }
+            } else {
+                quote! { #return_cache_block }
+            };
+            (set_cache_block, return_cache_block)
+        }
+        (false, true) => {
+            let set = if args.time.is_some() {
+                set_cached(quote! { (now, ::std::clone::Clone::clone(result)) })
+            } else {
+                set_cached(quote! { ::std::clone::Clone::clone(result) })
+            };
+            let set_cache_block = quote! {
+                if let ::std::option::Option::Some(result) = &result {
+                    #set
+                }
+            };
+
+            let return_cache_block = if args.with_cached_flag {
+                quote! { let mut r = ::std::clone::Clone::clone(result); r.was_cached = true; #set_expires_in return ::std::option::Option::Some(r) }
+            } else {
+                quote! { return ::std::option::Option::Some(::std::clone::Clone::clone(result)) }
+            };
+            let return_cache_block = if let Some(time) = &args.time {
+                quote! {
+                    let (created_sec, result) = result;
+                    if !::cached::stores::is_expired(*created_sec, #time, now) {
+                        #return_cache_block
+                    }
+                }
+            } else {
+                quote! { #return_cache_block }
+            };
+            (set_cache_block, return_cache_block)
+        }
+        _ => panic!("the result and option attributes are mutually exclusive"),
+    };
+
+    // while backing off from a failure, return its error instead of running the function again
+    let check_backoff = if retry_backoff.is_some() {
+        quote! {
+            if let ::std::option::Option::Some(error) = #backoff_ident.check(now) {
+                return ::std::result::Result::Err(error);
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let check_cache_block = if arcswap {
+        quote! {
+            if let ::std::option::Option::Some(result) = #cache_ident.load().as_deref() {
+                #return_cache_block
+            }
+            #check_backoff
+        }
+    } else {
+        quote! {
+            if let ::std::option::Option::Some(result) = &*cached {
+                #return_cache_block
+            }
+            #check_backoff
+        }
+    };
+
+    // a function returning `()` is a run-once guard, only store whether (and when) it ran
+    let returns_unit = match &output {
+        ReturnType::Default => true,
+        ReturnType::Type(_, ty) => {
+            matches!(ty.deref(), Type::Tuple(tuple) if tuple.elems.is_empty())
+        }
+    };
+    let is_run_once = returns_unit && !args.result && !args.option && !args.with_cached_flag;
+    if arcswap && is_run_once {
+        return syn::Error::new(
+            attr_arg_span(&attr_args, "lock"),
+            "`lock = \"arcswap\"` needs a function returning a value to store",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let (cache_ty, cache_create, check_cache_block, set_cache_block) = if is_run_once {
+        match &args.time {
+            None => (
+                quote! { bool },
+                quote! { false },
+                quote! {
+                    if *cached {
+                        return;
+                    }
+                },
+                quote! { *cached = true; },
+            ),
+            Some(time) => (
+                quote! { ::std::option::Option<::cached::instant::Instant> },
+                quote! { ::std::option::Option::None },
+                quote! {
+                    if let ::std::option::Option::Some(ran_at) = &*cached {
+                        if !::cached::stores::is_expired(*ran_at, #time, now) {
+                            return;
+                        }
+                    }
+                },
+                quote! { *cached = ::std::option::Option::Some(now); },
+            ),
+        }
+    } else {
+        (cache_ty, cache_create, check_cache_block, set_cache_block)
+    };
+
+    // run-once guards don't cache a value, so they can't be disabled
+    let (bypass_condition, enabled_items) = if is_run_once {
+        (quote! {}, quote! {})
+    } else {
+        enabled_switch(&fn_ident, &cache_ident, &visibility)
+    };
+    // the function body, and the block calling it without touching the cache while caching is
+    // disabled. Run-once guards keep running once, their cache doesn't hold any value.
+    let (inner_fn, call_inner) = if asyncness.is_some() {
+        (
+            quote! { async fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body; },
+            quote! { #inner_fn_ident #inner_turbofish(#(#input_names),*).await },
+        )
+    } else {
+        (
+            quote! { fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body; },
+            quote! { #inner_fn_ident #inner_turbofish(#(#input_names),*) },
+        )
+    };
+    let bypass_block = if is_run_once {
+        quote! {}
+    } else {
+        quote! {
+            if #bypass_condition {
+                return #call_inner;
+            }
+        }
+    };
+
+    // with `lock = "arcswap"`, values are swapped in without locking, and `sync_writes` runs the
+    // function under a mutex only taken on misses
+    let write_lock_ident = Ident::new(&format!("__{}_WRITE", cache_ident), cache_ident.span());
+    let do_set_return_block = if arcswap {
+        let lock = match (asyncness.is_some(), args.sync_writes) {
+            (_, false) => quote! {},
+            (true, true) => quote! {
+                let _write = #write_lock_ident.lock().await;
+                #check_cache_block
+            },
+            (false, true) => quote! {
+                let _write = #write_lock_ident.lock().unwrap();
+                #check_cache_block
+            },
+        };
+        quote! {
+            #lock
+            // run the function and cache the result
+            let result = #call_inner;
+            #set_cache_block
+            result
+        }
+    } else if asyncness.is_some() {
+        if args.sync_writes {
+            quote! {
+                // try to get a write lock first
+                let mut cached = #cache_ident.write().await;
+                #check_cache_block
+
+                // run the function and cache the result
+                let result = #inner_fn_ident #inner_turbofish(#(#input_names),*).await;
+                #set_cache_block
+                result
+            }
+        } else {
+            quote! {
+                // run the function and cache the result
+                let result = #inner_fn_ident #inner_turbofish(#(#input_names),*).await;
+                let mut cached = #cache_ident.write().await;
+                #set_cache_block
+                result
+            }
+        }
+    } else if args.sync_writes {
+        quote! {
+            // try to get a write lock first
+            let mut cached = #cache_ident.write().unwrap();
+            #check_cache_block
+
+            // run the function and cache the result
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*);
+            #set_cache_block
+            result
+        }
+    } else {
+        quote! {
+            // run the function and cache the result
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*);
+            let mut cached = #cache_ident.write().unwrap();
+            #set_cache_block
+            result
+        }
+    };
+
+    // the generated functions take the arguments without their binding modes, e.g. a
+    // `mut a: i32` argument is only `mut` in the `inner` function that mutates it
+    let mut signature_no_muts = signature;
+    signature_no_muts.inputs = outer_inputs.inputs.clone();
+
+    let prime_fn_ident = match prime_fn_ident(&fn_ident, args.prime_name.as_deref(), &attr_args) {
+        Ok(ident) => ident,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let mut prime_sig = signature_no_muts.clone();
+    prime_sig.ident = prime_fn_ident;
+
+    let prime_do_set_return_block = if arcswap {
+        quote! {
+            // run the function and cache the result
+            #inner_fn
+            let result = #call_inner;
+            #set_cache_block
+            result
+        }
+    } else if asyncness.is_some() {
+        quote! {
+            // run the function and cache the result
+            async fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body;
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*).await;
+            let mut cached = #cache_ident.write().await;
+            #set_cache_block
+            result
+        }
+    } else {
+        quote! {
+            // run the function and cache the result
+            fn #inner_fn_ident #inner_generics(#inputs) #output #inner_where #body;
+            let result = #inner_fn_ident #inner_turbofish(#(#input_names),*);
+            let mut cached = #cache_ident.write().unwrap();
+            #set_cache_block
+            result
+        }
+    };
+
+    // for async functions, optionally make a blocking variant for sync call sites
+    let blocking_fn = if args.block_on {
+        let mut blocking_sig = signature_no_muts.clone();
+        blocking_sig.asyncness = None;
+        blocking_sig.ident = Ident::new(&format!("{}_blocking", &fn_ident), fn_ident.span());
+        let blocking_fn_doc = format!(
+            "Calls [`{}`] from synchronous code, blocking the current thread until it completes.",
+            fn_ident
+        );
+        quote! {
+            #[doc = #blocking_fn_doc]
+            #[allow(dead_code)]
+            #visibility #blocking_sig {
+                ::cached::async_sync::block_on(#fn_ident #inner_turbofish(#(#input_names),*))
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // for run-once guards, a function telling whether the guarded function has run
+    let has_run_fn = if is_run_once {
+        let has_run_ident = Ident::new(&format!("{}_has_run", &fn_ident), fn_ident.span());
+        let has_run_doc = format!(
+            "Returns whether [`{}`] has run (and, with a `time`, that run hasn't expired).",
+            fn_ident
+        );
+        let (asyncness, read) = if asyncness.is_some() {
+            (quote! { async }, quote! { #cache_ident.read().await })
+        } else {
+            (quote! {}, quote! { #cache_ident.read().unwrap() })
+        };
+        let has_run = match &args.time {
+            None => quote! { *#read },
+            Some(time) => quote! {
+                match *#read {
+                    ::std::option::Option::Some(ran_at) => !::cached::stores::is_expired(ran_at, #time, ::cached::instant::Instant::now()),
+                    ::std::option::Option::None => false,
+                }
+            },
+        };
+        quote! {
+            #[doc = #has_run_doc]
+            #[allow(dead_code)]
+            #visibility #asyncness fn #has_run_ident() -> bool {
+                #has_run
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // make cached static, cached function and prime cached function doc comments
+    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
+    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
+    let cache_fn_doc_extra = format!(
+        "This is a cached function that uses the [`{}`] cached static.",
+        cache_ident
+    );
+    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
+        attributes.push(parse_quote! { #[doc = ""] });
+        attributes.push(parse_quote! { #[doc = "# Caching"] });
+        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+    } else {
+        attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
+    }
+
+    // put it all together
+    let expanded = if arcswap {
+        let write_lock = match (asyncness.is_some(), args.sync_writes) {
+            (_, false) => quote! {},
+            (true, true) => quote! {
+                #[doc(hidden)]
+                static #write_lock_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::Mutex<()>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::Mutex::new(()));
+            },
+            (false, true) => quote! {
+                #[doc(hidden)]
+                static #write_lock_ident: ::std::sync::Mutex<()> = ::std::sync::Mutex::new(());
+            },
+        };
+        quote! {
+            // Cached static
+            #[doc = #cache_ident_doc]
+            #visibility static #cache_ident: ::cached::arc_swap::ArcSwapOption<#stored_ty> = ::cached::arc_swap::ArcSwapOption::const_empty();
+            #write_lock
+            #backoff_static
+            #enabled_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #inner_fn
+                #bypass_block
+                let now = ::cached::instant::Instant::now();
+                #check_cache_block
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                let now = ::cached::instant::Instant::now();
+                #prime_do_set_return_block
+            }
+            #blocking_fn
+        }
+    } else if asyncness.is_some() {
+        quote! {
+            // Cached static
+            #[doc = #cache_ident_doc]
+            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::RwLock::new(#cache_create));
+            #backoff_static
+            #enabled_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #inner_fn
+                #bypass_block
+                let now = ::cached::instant::Instant::now();
+                {
+                    // check if the result is cached
+                    let mut cached = #cache_ident.read().await;
+                    #check_cache_block
+                }
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                let now = ::cached::instant::Instant::now();
+                #prime_do_set_return_block
+            }
+            #blocking_fn
+            #has_run_fn
+        }
+    } else {
+        quote! {
+            // Cached static
+            #[doc = #cache_ident_doc]
+            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::std::sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::std::sync::RwLock::new(#cache_create));
+            #backoff_static
+            #enabled_items
+            // Cached function
+            #(#attributes)*
+            #visibility #signature_no_muts {
+                #inner_fn
+                #bypass_block
+                let now = ::cached::instant::Instant::now();
+                {
+                    // check if the result is cached
+                    let mut cached = #cache_ident.read().unwrap();
+                    #check_cache_block
+                }
+                #do_set_return_block
+            }
+            // Prime cached function
+            #[doc = #prime_fn_indent_doc]
+            #[allow(dead_code)]
+            #visibility #prime_sig {
+                let now = ::cached::instant::Instant::now();
+                #prime_do_set_return_block
+            }
+            #has_run_fn
+        }
+    };
+
+    expanded.into()
+}
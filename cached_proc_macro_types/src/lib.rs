@@ -1,12 +1,18 @@
+use std::time::Duration;
+
 #[derive(Clone)]
 pub struct Return<T> {
     pub was_cached: bool,
+    /// How long until the cached value expires, set when it's returned from a timed cache.
+    /// `None` for values that were just computed or don't expire by time.
+    pub expires_in: Option<Duration>,
     pub value: T,
 }
 impl<T> Return<T> {
     pub fn new(value: T) -> Self {
         Self {
             was_cached: false,
+            expires_in: None,
             value,
         }
     }
@@ -25,3 +31,25 @@ impl<T> std::ops::DerefMut for Return<T> {
         &mut self.value
     }
 }
+
+/// Compares the values only, whether they were cached or not, so a `Return<T>` can stand in for
+/// a `T` when sorting or taking the maximum
+impl<T: PartialEq> PartialEq for Return<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Return<T> {}
+
+impl<T: PartialOrd> PartialOrd for Return<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<T: Ord> Ord for Return<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.value.cmp(&other.value)
+    }
+}
@@ -0,0 +1,84 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use cached::proc_macro::cached;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+struct AppState {
+    /// How many times the users were looked up
+    lookups: Arc<AtomicUsize>,
+}
+
+/// Cache the users by the id of their path. With `extractor`, the `State` argument is left out of
+/// the key, and the `Path<u64>` extractor is unwrapped so the key is the `u64` id.
+#[cached(size = 100, time = 60, result = true, extractor = true)]
+async fn get_user(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<String, StatusCode> {
+    state.lookups.fetch_add(1, Ordering::SeqCst);
+    match id {
+        1 => Ok("Ferris".to_string()),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+fn app(state: AppState) -> Router {
+    Router::new()
+        .route("/users/:id", get(get_user))
+        .with_state(state)
+}
+
+#[tokio::main]
+async fn main() {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    let state = AppState::default();
+    let app = app(state.clone());
+    for _ in 0..2 {
+        let request = Request::get("/users/1").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        println!("GET /users/1: {}", response.status());
+    }
+    println!(
+        "users looked up {} time(s)",
+        state.lookups.load(Ordering::SeqCst)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn handler_runs_once() {
+        let state = AppState::default();
+        let app = app(state.clone());
+        for _ in 0..2 {
+            let request = Request::get("/users/1").body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], b"Ferris");
+        }
+        assert_eq!(state.lookups.load(Ordering::SeqCst), 1);
+
+        // errors aren't cached
+        for _ in 0..2 {
+            let request = Request::get("/users/2").body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+        assert_eq!(state.lookups.load(Ordering::SeqCst), 3);
+    }
+}
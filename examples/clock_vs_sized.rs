@@ -0,0 +1,98 @@
+/*
+Compares `ClockCache` and `SizedCache` on a zipfian trace: the hit rate of the trace replayed
+against each store, and the read throughput of threads sharing a store behind a `Mutex`.
+
+Run with `cargo run --release --example clock_vs_sized`
+*/
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use cached::{Cached, ClockCache, SizedCache};
+
+const KEYS: usize = 10_000;
+const CAPACITY: usize = 1_000;
+const TRACE_LEN: usize = 500_000;
+const THREADS: usize = 4;
+
+/// Keys drawn from a zipfian distribution with exponent 1: key `k` is drawn in proportion to `1 / (k + 1)`
+fn zipfian_trace(len: usize, seed: u64) -> Vec<u32> {
+    let mut cdf = Vec::with_capacity(KEYS);
+    let mut total = 0.0;
+    for k in 0..KEYS {
+        total += 1.0 / (k + 1) as f64;
+        cdf.push(total);
+    }
+
+    // xorshift, so runs are reproducible without depending on a random number crate
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let target = (state >> 11) as f64 / (1u64 << 53) as f64 * total;
+            cdf.partition_point(|p| *p < target) as u32
+        })
+        .collect()
+}
+
+fn hit_rate<C: Cached<u32, u32>>(mut cache: C, trace: &[u32]) -> f64 {
+    for key in trace {
+        if cache.cache_get(key).is_none() {
+            cache.cache_set(*key, *key);
+        }
+    }
+    let hits = cache.cache_hits().unwrap() as f64;
+    hits / trace.len() as f64
+}
+
+/// Reads per second of `THREADS` threads replaying `trace` against a shared, warmed up `cache`
+fn read_throughput<C>(mut cache: C, trace: &Arc<Vec<u32>>) -> f64
+where
+    C: Cached<u32, u32> + Send + 'static,
+{
+    for key in trace.iter() {
+        if cache.cache_get(key).is_none() {
+            cache.cache_set(*key, *key);
+        }
+    }
+    let cache = Arc::new(Mutex::new(cache));
+
+    let start = Instant::now();
+    let threads = (0..THREADS)
+        .map(|_| {
+            let cache = Arc::clone(&cache);
+            let trace = Arc::clone(trace);
+            thread::spawn(move || {
+                for key in trace.iter() {
+                    cache.lock().unwrap().cache_get(key);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    (THREADS * trace.len()) as f64 / start.elapsed().as_secs_f64()
+}
+
+pub fn main() {
+    let trace = Arc::new(zipfian_trace(TRACE_LEN, 0x2545_f491_4f6c_dd1d));
+    println!(
+        "{} reads of {} zipfian keys, caching up to {}, {} reader threads\n",
+        TRACE_LEN, KEYS, CAPACITY, THREADS
+    );
+
+    println!(
+        "SizedCache: hit rate {:.3}, {:.0} reads/s",
+        hit_rate(SizedCache::with_size(CAPACITY), &trace),
+        read_throughput(SizedCache::with_size(CAPACITY), &trace),
+    );
+    println!(
+        "ClockCache: hit rate {:.3}, {:.0} reads/s",
+        hit_rate(ClockCache::with_size(CAPACITY), &trace),
+        read_throughput(ClockCache::with_size(CAPACITY), &trace),
+    );
+}
@@ -0,0 +1,85 @@
+/*
+Compares the read throughput of threads sharing a cache of small `Copy` values: a `SizedCache`
+behind a `Mutex` (the default of `#[cached]`), an `UnboundCache` behind a `RwLock` read with
+`CachedRead::cache_read` (`#[cached(lock = "rwlock")]`), and a `DashMapCache` without an outer lock
+(`#[cached(lock = "none")]`).
+
+The `Mutex` serializes the readers, the `RwLock` lets them read at once, and the `DashMapCache`
+only locks the shard of the key being read, but all three count hits in a single counter shared by
+every thread. Which store wins depends on the number of cores: on a single core, the uncontended
+`Mutex` is the cheapest.
+
+Run with `cargo run --release --example concurrent_reads --features dashmap_store`
+*/
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Instant;
+
+use cached::stores::DashMapCache;
+use cached::{Cached, CachedRead, SizedCache, UnboundCache};
+
+const KEYS: u32 = 1_000;
+const READS: usize = 1_000_000;
+
+/// Reads per second of `threads` threads each reading every key in turn with `read`
+fn throughput<C, F>(cache: Arc<C>, threads: usize, read: F) -> f64
+where
+    C: Send + Sync + 'static,
+    F: Fn(&C, u32) -> Option<u64> + Copy + Send + 'static,
+{
+    let start = Instant::now();
+    let handles = (0..threads)
+        .map(|_| {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for i in 0..READS {
+                    assert!(read(&cache, i as u32 % KEYS).is_some());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (threads * READS) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn read_sized(cache: &Mutex<SizedCache<u32, u64>>, key: u32) -> Option<u64> {
+    cache.lock().unwrap().cache_get(&key).copied()
+}
+
+fn read_unbound(cache: &RwLock<UnboundCache<u32, u64>>, key: u32) -> Option<u64> {
+    cache.read().unwrap().cache_read(&key).copied()
+}
+
+pub fn main() {
+    let mut sized = SizedCache::with_size(KEYS as usize);
+    let mut unbound = UnboundCache::new();
+    let dashmap = DashMapCache::new();
+    for key in 0..KEYS {
+        sized.cache_set(key, u64::from(key));
+        unbound.cache_set(key, u64::from(key));
+        dashmap.cache_set(key, u64::from(key));
+    }
+    let sized = Arc::new(Mutex::new(sized));
+    let unbound = Arc::new(RwLock::new(unbound));
+    let dashmap = Arc::new(dashmap);
+
+    println!("{} reads of {} cached keys per thread\n", READS, KEYS);
+    for threads in [1, 2, 4, 8] {
+        println!("{} reader threads:", threads);
+        println!(
+            "  Mutex<SizedCache>:    {:>12.0} reads/s",
+            throughput(Arc::clone(&sized), threads, read_sized),
+        );
+        println!(
+            "  RwLock<UnboundCache>: {:>12.0} reads/s",
+            throughput(Arc::clone(&unbound), threads, read_unbound),
+        );
+        println!(
+            "  DashMapCache:         {:>12.0} reads/s",
+            throughput(Arc::clone(&dashmap), threads, |c, k| c.cache_get(&k)),
+        );
+    }
+}
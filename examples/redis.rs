@@ -33,7 +33,7 @@ fn cached_sleep_secs(secs: u64) -> Result<(), ExampleError> {
     Ok(())
 }
 
-// If not `cache_prefix_block` is specified, then the function name
+// If not `cache_prefix_block` is specified, then the function path
 // is used to create a prefix for cache keys used by this function
 #[io_cached(
     redis = true,
@@ -0,0 +1,552 @@
+/*!
+Synchronization primitives and helpers used by async cached functions
+*/
+use std::future::Future;
+use std::time::Duration;
+
+pub use tokio::sync::Mutex;
+use tokio::sync::MutexGuard;
+pub use tokio::sync::OnceCell;
+pub use tokio::sync::RwLock;
+
+#[cfg(feature = "proc_macro")]
+use crate::proc_macro::{ColdStartTimeout, InFlight};
+use crate::Cached;
+
+/// Look up `key` in `cache`, computing and caching the value on a miss.
+///
+/// This is what `#[cached]` async functions expand to. `on_hit` builds the function's return
+/// value from a cached value and how long until it expires (see [`Cached::cache_get_with_expiry`]),
+/// and `to_cache` selects the part of a computed result that should be cached (e.g. only `Ok`
+/// values), so one helper covers plain, `Result` and `Option` returning functions.
+///
+/// `on_miss` is called with the key right before computing the value, only by the caller that
+/// computes it. The cache lock is not held while calling it, unless `sync_writes` holds it for
+/// computing.
+///
+/// The cache lock is not held while `compute` runs, so concurrent misses on the same key compute
+/// the value concurrently. With `sync_writes`, the lock is held while computing instead, and
+/// callers waiting on it will find the cached value once they acquire it.
+///
+/// ```rust
+/// use cached::async_sync::{get_or_set_async, Mutex};
+/// use cached::SizedCache;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let cache = Mutex::new(SizedCache::with_size(10));
+/// let value = get_or_set_async(
+///     &cache,
+///     "key",
+///     false,
+///     |cached: &u32, _| *cached,
+///     |_key| {},
+///     || async { 42 },
+///     |computed| Some(*computed),
+/// )
+/// .await;
+/// assert_eq!(value, 42);
+/// # }
+/// ```
+pub async fn get_or_set_async<C, K, V, R, Fut>(
+    cache: &Mutex<C>,
+    key: K,
+    sync_writes: bool,
+    on_hit: impl FnOnce(&V, Option<Duration>) -> R,
+    on_miss: impl FnOnce(&K),
+    compute: impl FnOnce() -> Fut,
+    to_cache: impl FnOnce(&R) -> Option<V>,
+) -> R
+where
+    C: Cached<K, V>,
+    Fut: Future<Output = R>,
+{
+    let result = get_or_set(
+        cache,
+        key,
+        sync_writes,
+        None,
+        on_hit,
+        on_miss,
+        compute,
+        to_cache,
+    );
+    match result.await {
+        Some(result) => result,
+        None => unreachable!("the cache lock can't time out without a timeout"),
+    }
+}
+
+/// Look up `key` in `cache` like [`get_or_set_async`] with `sync_writes`, except that callers
+/// give up with [`ColdStartTimeout`] when the cache stays locked for longer than `timeout`,
+/// e.g. by another caller computing a value on a cold start.
+///
+/// This is what async `#[cached(sync_writes = true, cold_start_timeout = N)]` functions
+/// expand to.
+#[cfg(feature = "proc_macro")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_or_set_async_with_timeout<C, K, V, R, Fut>(
+    cache: &Mutex<C>,
+    key: K,
+    timeout: Duration,
+    on_hit: impl FnOnce(&V, Option<Duration>) -> R,
+    on_miss: impl FnOnce(&K),
+    compute: impl FnOnce() -> Fut,
+    to_cache: impl FnOnce(&R) -> Option<V>,
+) -> Result<R, ColdStartTimeout>
+where
+    C: Cached<K, V>,
+    Fut: Future<Output = R>,
+{
+    let result = get_or_set(
+        cache,
+        key,
+        true,
+        Some(timeout),
+        on_hit,
+        on_miss,
+        compute,
+        to_cache,
+    );
+    result.await.ok_or(ColdStartTimeout)
+}
+
+/// Look up `key` in `cache` like [`get_or_set_async`], except that computing a value is given
+/// up after `deadline`: the `compute` future is dropped, and the value cached for `key` is
+/// returned even if it expired (see [`Cached::cache_get_stale`]), or `on_timeout()` when the
+/// store doesn't hold one. Stale values are passed to `on_hit` with a zero expiry.
+///
+/// This is what async `#[cached(compute_timeout_ms = N)]` functions expand to. With
+/// `sync_writes`, the lock held while computing is released on timeout, so the next caller
+/// computes the value again.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use cached::async_sync::{get_or_set_async_with_deadline, Mutex};
+/// use cached::SizedCache;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let cache = Mutex::new(SizedCache::with_size(10));
+/// let value = get_or_set_async_with_deadline(
+///     &cache,
+///     "key",
+///     false,
+///     Duration::from_millis(10),
+///     |cached: &u32, _| Some(*cached),
+///     |_key| {},
+///     || async {
+///         tokio::time::sleep(Duration::from_secs(1)).await;
+///         Some(42)
+///     },
+///     |computed| *computed,
+///     || None,
+/// )
+/// .await;
+/// assert_eq!(value, None);
+/// # }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub async fn get_or_set_async_with_deadline<C, K, V, R, Fut>(
+    cache: &Mutex<C>,
+    key: K,
+    sync_writes: bool,
+    deadline: Duration,
+    on_hit: impl FnOnce(&V, Option<Duration>) -> R,
+    on_miss: impl FnOnce(&K),
+    compute: impl FnOnce() -> Fut,
+    to_cache: impl FnOnce(&R) -> Option<V>,
+    on_timeout: impl FnOnce() -> R,
+) -> R
+where
+    C: Cached<K, V>,
+    V: Clone,
+    Fut: Future<Output = R>,
+{
+    let stale = {
+        // check if the result is cached, keeping an expired value to fall back on
+        let mut cache = cache.lock().await;
+        let stale = match cache.cache_get_with_meta(&key) {
+            Some(_) => None,
+            None => cache.cache_get_stale(&key).cloned(),
+        };
+        if let Some((cached, expires_in)) = cache.cache_get_with_expiry(&key) {
+            return on_hit(cached, expires_in);
+        }
+        stale
+    };
+
+    let mut locked = None;
+    if sync_writes {
+        // hold the lock while computing so concurrent callers wait for this value
+        let cache = locked.insert(cache.lock().await);
+        if let Some((cached, expires_in)) = cache.cache_get_with_expiry(&key) {
+            return on_hit(cached, expires_in);
+        }
+    }
+    on_miss(&key);
+    let result = match tokio::time::timeout(deadline, compute()).await {
+        Ok(result) => result,
+        Err(_) => {
+            return match stale {
+                Some(stale) => on_hit(&stale, Some(Duration::from_secs(0))),
+                None => on_timeout(),
+            }
+        }
+    };
+    if let Some(value) = to_cache(&result) {
+        match locked.as_mut() {
+            Some(cache) => cache.cache_set(key, value),
+            None => cache.lock().await.cache_set(key, value),
+        };
+    }
+    result
+}
+
+/// Lock `cache`, waiting for at most `timeout` if set. `None` if it timed out.
+async fn lock<C>(cache: &Mutex<C>, timeout: Option<Duration>) -> Option<MutexGuard<'_, C>> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, cache.lock()).await.ok(),
+        None => Some(cache.lock().await),
+    }
+}
+
+/// [`get_or_set_async`], `None` if locking the cache timed out
+#[allow(clippy::too_many_arguments)]
+async fn get_or_set<C, K, V, R, Fut>(
+    cache: &Mutex<C>,
+    key: K,
+    sync_writes: bool,
+    lock_timeout: Option<Duration>,
+    on_hit: impl FnOnce(&V, Option<Duration>) -> R,
+    on_miss: impl FnOnce(&K),
+    compute: impl FnOnce() -> Fut,
+    to_cache: impl FnOnce(&R) -> Option<V>,
+) -> Option<R>
+where
+    C: Cached<K, V>,
+    Fut: Future<Output = R>,
+{
+    {
+        // check if the result is cached
+        let mut cache = lock(cache, lock_timeout).await?;
+        if let Some((cached, expires_in)) = cache.cache_get_with_expiry(&key) {
+            return Some(on_hit(cached, expires_in));
+        }
+    }
+
+    if sync_writes {
+        // hold the lock while computing so concurrent callers wait for this value
+        let mut cache = lock(cache, lock_timeout).await?;
+        if let Some((cached, expires_in)) = cache.cache_get_with_expiry(&key) {
+            return Some(on_hit(cached, expires_in));
+        }
+        on_miss(&key);
+        let result = compute().await;
+        if let Some(value) = to_cache(&result) {
+            cache.cache_set(key, value);
+        }
+        Some(result)
+    } else {
+        on_miss(&key);
+        let result = compute().await;
+        if let Some(value) = to_cache(&result) {
+            cache.lock().await.cache_set(key, value);
+        }
+        Some(result)
+    }
+}
+
+/// Look up `key` in `cache` like [`get_or_set_async`] with `sync_writes`, except that callers
+/// missing a key whose value another caller is computing don't wait for it: they return
+/// `placeholder()` right away, and only the value computed by the first caller is cached.
+///
+/// This is what async `#[cached(placeholder = "...")]` functions expand to. The cache lock is
+/// not held while `compute` runs, `in_flight` tracks the keys being computed instead.
+///
+/// ```rust
+/// use cached::async_sync::{get_or_set_async_or_placeholder, Mutex};
+/// use cached::proc_macro::InFlight;
+/// use cached::SizedCache;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let cache = Mutex::new(SizedCache::with_size(10));
+/// let in_flight = InFlight::new();
+/// let value = get_or_set_async_or_placeholder(
+///     &cache,
+///     &in_flight,
+///     "key",
+///     |cached: &u32, _| *cached,
+///     |_key| {},
+///     || async { 42 },
+///     |computed| Some(*computed),
+///     || 0,
+/// )
+/// .await;
+/// assert_eq!(value, 42);
+/// # }
+/// ```
+#[cfg(feature = "proc_macro")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_or_set_async_or_placeholder<C, K, V, R, Fut>(
+    cache: &Mutex<C>,
+    in_flight: &InFlight<K>,
+    key: K,
+    on_hit: impl FnOnce(&V, Option<Duration>) -> R,
+    on_miss: impl FnOnce(&K),
+    compute: impl FnOnce() -> Fut,
+    to_cache: impl FnOnce(&R) -> Option<V>,
+    placeholder: impl FnOnce() -> R,
+) -> R
+where
+    C: Cached<K, V>,
+    K: std::hash::Hash + Eq + Clone,
+    Fut: Future<Output = R>,
+{
+    {
+        // check if the result is cached
+        let mut cache = cache.lock().await;
+        if let Some((cached, expires_in)) = cache.cache_get_with_expiry(&key) {
+            return on_hit(cached, expires_in);
+        }
+    }
+
+    let _computing = match in_flight.start(&key) {
+        Some(computing) => computing,
+        None => return placeholder(),
+    };
+    {
+        // the value may have been cached since the lookup
+        let mut cache = cache.lock().await;
+        if let Some((cached, expires_in)) = cache.cache_get_with_expiry(&key) {
+            return on_hit(cached, expires_in);
+        }
+    }
+    on_miss(&key);
+    let result = compute().await;
+    if let Some(value) = to_cache(&result) {
+        cache.lock().await.cache_set(key, value);
+    }
+    result
+}
+
+/// Look up `key` in a cache behind a `std` mutex from async code, computing and caching the value
+/// on a miss.
+///
+/// Sync `#[cached]` functions keep their cache behind a [`std::sync::Mutex`] while async ones use
+/// a tokio [`Mutex`], so a sync and an async variant of the same function can't both be
+/// `#[cached]` over one cache. Instead, cache the sync variant and have the async one go through
+/// its cache with this helper. The `std` lock is only held to look up and to store the value,
+/// never across an `.await`, so it doesn't block the runtime while computing. Like
+/// `#[cached]` functions without `sync_writes`, concurrent misses on the same key compute the
+/// value concurrently, whether they come from the sync or the async variant.
+///
+/// ```rust
+/// use cached::async_sync::get_or_set_bridged;
+/// use cached::proc_macro::cached;
+///
+/// #[cached]
+/// fn square(n: u64) -> u64 {
+///     n * n
+/// }
+///
+/// async fn square_async(n: u64) -> u64 {
+///     get_or_set_bridged(&SQUARE, n, || async move { n * n }).await
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// assert_eq!(square(3), 9);
+/// // served from the cache of `square`
+/// assert_eq!(square_async(3).await, 9);
+/// # }
+/// ```
+pub async fn get_or_set_bridged<C, K, V, Fut>(
+    cache: &std::sync::Mutex<C>,
+    key: K,
+    compute: impl FnOnce() -> Fut,
+) -> V
+where
+    C: Cached<K, V>,
+    V: Clone,
+    Fut: Future<Output = V>,
+{
+    if let Some(cached) = cache.lock().unwrap().cache_get(&key) {
+        return cached.clone();
+    }
+    let value = compute().await;
+    cache.lock().unwrap().cache_set(key, value.clone());
+    value
+}
+
+/// Run a future to completion from synchronous code, blocking the current thread.
+///
+/// When called from within a multi-threaded tokio runtime, the future runs on that runtime
+/// (see [`tokio::task::block_in_place`]). Otherwise, a current-thread runtime with all drivers
+/// enabled is created to run it.
+///
+/// # Panics
+///
+/// Panics when called from within a current-thread tokio runtime, since blocking it would
+/// prevent the future from ever making progress.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("unable to build a runtime to block on")
+            .block_on(future),
+    }
+}
+
+#[cfg(test)]
+/// Helper tests
+mod tests {
+    use super::*;
+    use crate::UnboundCache;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn get_or_set_async_caches_value() {
+        let cache = Mutex::new(UnboundCache::new());
+        let calls = AtomicUsize::new(0);
+        for _ in 0..2 {
+            let value = get_or_set_async(
+                &cache,
+                1,
+                false,
+                |cached: &u32, _| *cached,
+                |_key| {},
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    10
+                },
+                |computed| Some(*computed),
+            )
+            .await;
+            assert_eq!(value, 10);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let cache = cache.lock().await;
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn get_or_set_async_on_miss() {
+        let cache = Mutex::new(UnboundCache::new());
+        let misses = std::sync::Mutex::new(Vec::new());
+        for key in [1, 2, 1] {
+            get_or_set_async(
+                &cache,
+                key,
+                false,
+                |cached: &u32, _| *cached,
+                |key: &u32| {
+                    assert!(
+                        cache.try_lock().is_ok(),
+                        "cache locked while calling on_miss"
+                    );
+                    misses.lock().unwrap().push(*key);
+                },
+                || async { 10 },
+                |computed| Some(*computed),
+            )
+            .await;
+        }
+        assert_eq!(*misses.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn get_or_set_async_on_miss_sync_writes() {
+        let cache = Mutex::new(UnboundCache::new());
+        let misses = AtomicUsize::new(0);
+        let callers = (0..4).map(|_| {
+            get_or_set_async(
+                &cache,
+                1,
+                true,
+                |cached: &u32, _| *cached,
+                |_key: &u32| {
+                    misses.fetch_add(1, Ordering::SeqCst);
+                },
+                || async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    10
+                },
+                |computed| Some(*computed),
+            )
+        });
+        for value in futures::future::join_all(callers).await {
+            assert_eq!(value, 10);
+        }
+        // the callers waiting for the lock find the value cached
+        assert_eq!(misses.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn get_or_set_async_skips_uncached_results() {
+        let cache = Mutex::new(UnboundCache::new());
+        let result: Result<u32, ()> = get_or_set_async(
+            &cache,
+            1,
+            false,
+            |cached: &u32, _| Ok(*cached),
+            |_key| {},
+            || async { Err(()) },
+            |computed: &Result<u32, ()>| computed.ok(),
+        )
+        .await;
+        assert_eq!(result, Err(()));
+        assert_eq!(cache.lock().await.cache_size(), 0);
+    }
+
+    #[tokio::test]
+    async fn get_or_set_async_sync_writes() {
+        let cache = Arc::new(Mutex::new(UnboundCache::new()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tasks = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    get_or_set_async(
+                        &cache,
+                        1,
+                        true,
+                        |cached: &u32, _| *cached,
+                        |_key| {},
+                        || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                            10
+                        },
+                        |computed| Some(*computed),
+                    )
+                    .await
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), 10);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn block_on_without_runtime() {
+        let value = block_on(async {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            1
+        });
+        assert_eq!(value, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn block_on_within_runtime() {
+        assert_eq!(block_on(async { 1 }), 1);
+    }
+}
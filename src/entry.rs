@@ -0,0 +1,348 @@
+/*!
+Entries of a cache store for manual filling, and per-key locks to fill a shared store without
+holding its lock while computing
+*/
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Condvar, Mutex, PoisonError};
+
+use crate::Cached;
+
+/// A view into a single key of a cache store, returned by [`Cached::cache_entry`]
+///
+/// Whether the entry is occupied is decided when it's looked up. The store is borrowed
+/// mutably for as long as the entry lives, so no other caller can fill or remove it in
+/// between, but values of timed stores may still expire.
+pub enum Entry<'a, K, V, C: ?Sized> {
+    /// A value is cached for the key
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    /// No value is cached for the key
+    Vacant(VacantEntry<'a, K, V, C>),
+}
+
+/// An occupied [`Entry`]
+pub struct OccupiedEntry<'a, K, V, C: ?Sized> {
+    cache: &'a mut C,
+    key: K,
+    _value: PhantomData<fn() -> V>,
+}
+
+/// A vacant [`Entry`]
+pub struct VacantEntry<'a, K, V, C: ?Sized> {
+    cache: &'a mut C,
+    key: K,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<'a, K, V, C: Cached<K, V> + ?Sized> Entry<'a, K, V, C> {
+    pub(crate) fn new(cache: &'a mut C, key: K) -> Self {
+        if cache.cache_get(&key).is_some() {
+            Entry::Occupied(OccupiedEntry {
+                cache,
+                key,
+                _value: PhantomData,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                cache,
+                key,
+                _value: PhantomData,
+            })
+        }
+    }
+
+    /// Return the key of this entry
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Return the cached value, caching the value computed by `f` first if the entry is
+    /// vacant (or its value expired since it was looked up)
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.cache.cache_get_or_set_with(entry.key, f),
+            Entry::Vacant(entry) => entry.cache.cache_get_or_set_with(entry.key, f),
+        }
+    }
+
+    /// Return the cached value, caching `default` first if the entry is vacant
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+}
+
+impl<'a, K, V, C: Cached<K, V> + ?Sized> OccupiedEntry<'a, K, V, C> {
+    /// Return the key of this entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Return the cached value, or `None` if it expired since the entry was looked up
+    pub fn into_mut(self) -> Option<&'a mut V> {
+        self.cache.cache_get_mut(&self.key)
+    }
+
+    /// Remove the cached value and return it
+    pub fn remove(self) -> Option<V> {
+        self.cache.cache_remove(&self.key)
+    }
+}
+
+impl<'a, K, V, C: Cached<K, V> + ?Sized> VacantEntry<'a, K, V, C> {
+    /// Return the key of this entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Return the key of this entry, without caching a value
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Cache `value` for the key of this entry and return it
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.cache.cache_get_or_set_with(self.key, || value)
+    }
+}
+
+/// Per-key locks, to fill a cache store shared behind a `Mutex` without holding the lock of
+/// the whole store while computing a value
+///
+/// The store's lock is only held to look up and to set values. While one caller computes the
+/// value of a key, other callers of [`KeyLocks::cache_get_or_lock`] for the same key wait for
+/// it, and then find the cached value. Callers of other keys aren't blocked.
+///
+/// ```rust
+/// use cached::entry::{GetOrLock, KeyLocks};
+/// use cached::UnboundCache;
+/// use std::sync::Mutex;
+///
+/// let cache = Mutex::new(UnboundCache::new());
+/// let locks = KeyLocks::new();
+///
+/// let value = match locks.cache_get_or_lock(&cache, 2) {
+///     GetOrLock::Value(value) => value,
+///     // only the key is locked while computing
+///     GetOrLock::Locked(guard) => guard.fill(2 * 2),
+/// };
+/// assert_eq!(value, 4);
+/// assert!(matches!(locks.cache_get_or_lock(&cache, 2), GetOrLock::Value(4)));
+/// ```
+pub struct KeyLocks<K> {
+    locked: Mutex<HashSet<K>>,
+    unlocked: Condvar,
+}
+
+impl<K> Default for KeyLocks<K> {
+    fn default() -> Self {
+        KeyLocks {
+            locked: Mutex::new(HashSet::new()),
+            unlocked: Condvar::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> KeyLocks<K> {
+    /// Create a set of per-key locks without any key locked
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lock `key`, waiting while another guard holds it. The key is unlocked when the
+    /// returned guard is dropped.
+    pub fn lock(&self, key: &K) -> KeyLockGuard<'_, K> {
+        let mut locked = self.locked.lock().unwrap_or_else(PoisonError::into_inner);
+        while locked.contains(key) {
+            locked = self
+                .unlocked
+                .wait(locked)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        locked.insert(key.clone());
+        KeyLockGuard {
+            locks: self,
+            key: key.clone(),
+        }
+    }
+
+    /// Return whether `key` is currently locked
+    pub fn is_locked(&self, key: &K) -> bool {
+        self.locked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(key)
+    }
+
+    /// Return a clone of the value cached for `key`, or lock `key` so that the caller can
+    /// compute its value and cache it with [`FillGuard::fill`].
+    ///
+    /// If another caller holds the lock of `key`, this waits for it and looks the key up
+    /// again, so a value is computed once even when requested concurrently. Dropping the
+    /// guard without filling it (e.g. when computing the value failed) lets the next waiter
+    /// lock the key instead.
+    pub fn cache_get_or_lock<'a, C, V>(
+        &'a self,
+        cache: &'a Mutex<C>,
+        key: K,
+    ) -> GetOrLock<'a, K, V, C>
+    where
+        C: Cached<K, V>,
+        V: Clone,
+    {
+        if let Some(value) = cache.lock().unwrap().cache_get(&key) {
+            return GetOrLock::Value(value.clone());
+        }
+        let lock = self.lock(&key);
+        if let Some(value) = cache.lock().unwrap().cache_get(&key) {
+            return GetOrLock::Value(value.clone());
+        }
+        GetOrLock::Locked(FillGuard {
+            cache,
+            lock,
+            _value: PhantomData,
+        })
+    }
+}
+
+/// Holds the lock of a key of [`KeyLocks`], unlocking it when dropped
+pub struct KeyLockGuard<'a, K: Hash + Eq> {
+    locks: &'a KeyLocks<K>,
+    key: K,
+}
+
+impl<'a, K: Hash + Eq> KeyLockGuard<'a, K> {
+    /// Return the locked key
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<'a, K: Hash + Eq> Drop for KeyLockGuard<'a, K> {
+    fn drop(&mut self) {
+        self.locks
+            .locked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+        self.locks.unlocked.notify_all();
+    }
+}
+
+/// The result of [`KeyLocks::cache_get_or_lock`]
+pub enum GetOrLock<'a, K: Hash + Eq, V, C> {
+    /// A clone of the cached value
+    Value(V),
+    /// The key is locked for the caller to compute and cache its value
+    Locked(FillGuard<'a, K, V, C>),
+}
+
+/// Holds the lock of a key that has no cached value, see [`KeyLocks::cache_get_or_lock`]
+pub struct FillGuard<'a, K: Hash + Eq, V, C> {
+    cache: &'a Mutex<C>,
+    lock: KeyLockGuard<'a, K>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<'a, K: Hash + Eq + Clone, V: Clone, C: Cached<K, V>> FillGuard<'a, K, V, C> {
+    /// Return the locked key
+    pub fn key(&self) -> &K {
+        self.lock.key()
+    }
+
+    /// Cache `value` for the locked key, unlock it and return `value`
+    pub fn fill(self, value: V) -> V {
+        self.cache
+            .lock()
+            .unwrap()
+            .cache_set(self.lock.key().clone(), value.clone());
+        value
+    }
+}
+
+#[cfg(test)]
+/// Entry and per-key lock tests
+mod tests {
+    use super::*;
+    use crate::{SizedCache, UnboundCache};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn entry() {
+        let mut c = SizedCache::with_size(2);
+        match c.cache_entry(1) {
+            Entry::Vacant(entry) => assert_eq!(*entry.insert(10), 10),
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        match c.cache_entry(1) {
+            Entry::Occupied(entry) => {
+                assert_eq!(entry.key(), &1);
+                *entry.into_mut().unwrap() += 1;
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert_eq!(c.cache_get(&1), Some(&11));
+
+        assert_eq!(*c.cache_entry(1).or_insert_with(|| unreachable!()), 11);
+        assert_eq!(*c.cache_entry(2).or_insert(20), 20);
+
+        match c.cache_entry(2) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), Some(20)),
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(matches!(c.cache_entry(2), Entry::Vacant(_)));
+    }
+
+    #[test]
+    fn get_or_lock_computes_once() {
+        let cache = Arc::new(Mutex::new(UnboundCache::new()));
+        let locks = Arc::new(KeyLocks::new());
+        let computed = Arc::new(AtomicUsize::new(0));
+
+        let handles = (0..4)
+            .map(|_| {
+                let (cache, locks, computed) = (cache.clone(), locks.clone(), computed.clone());
+                thread::spawn(move || match locks.cache_get_or_lock(&cache, "key") {
+                    GetOrLock::Value(value) => value,
+                    GetOrLock::Locked(guard) => {
+                        // the store itself isn't locked while computing
+                        assert!(cache.try_lock().is_ok());
+                        thread::sleep(Duration::from_millis(50));
+                        computed.fetch_add(1, Ordering::SeqCst);
+                        guard.fill(42)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 42);
+        }
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+        assert!(!locks.is_locked(&"key"));
+    }
+
+    #[test]
+    fn dropped_guard_unlocks_the_key() {
+        let cache = Mutex::new(UnboundCache::<u32, u32>::new());
+        let locks = KeyLocks::new();
+        match locks.cache_get_or_lock(&cache, 1) {
+            GetOrLock::Locked(guard) => {
+                assert!(locks.is_locked(&1));
+                assert!(!locks.is_locked(&2));
+                assert_eq!(guard.key(), &1);
+            }
+            GetOrLock::Value(_) => panic!("expected the key to be locked"),
+        }
+        assert!(!locks.is_locked(&1));
+        assert!(matches!(
+            locks.cache_get_or_lock(&cache, 1),
+            GetOrLock::Locked(_)
+        ));
+    }
+}
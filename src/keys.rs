@@ -0,0 +1,237 @@
+/*!
+Wrappers turning values that aren't `Hash + Eq`, like floats, into cache keys
+
+```rust
+use cached::keys::F64Key;
+use cached::proc_macro::cached;
+
+#[cached(key = "F64Key", convert = r#"{ F64Key::from(rate) }"#)]
+fn price(rate: f64) -> f64 {
+    rate * 100.0
+}
+
+assert_eq!(price(1.5), 150.0);
+assert_eq!(price(1.5), 150.0);
+```
+
+With the `redis_store` feature, [`CacheKeyEncode`](https://docs.rs/cached/latest/cached/keys/trait.CacheKeyEncode.html)
+turns keys into the bytes IO stores key their values with, see
+[`RedisCacheBuilder::set_key_encoder`](https://docs.rs/cached/latest/cached/stores/struct.RedisCacheBuilder.html#method.set_key_encoder).
+*/
+#[cfg(feature = "redis_store")]
+use serde::Serialize;
+use std::hash::{Hash, Hasher};
+
+/// Normalize the bit pattern of a float so values comparing equal make equal keys:
+/// `-0.0` is stored as `0.0`, and every NaN as the same NaN.
+fn normalized_bits(value: f64) -> u64 {
+    if value == 0.0 {
+        0
+    } else if value.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        value.to_bits()
+    }
+}
+
+/// A `Hash + Eq` cache key holding the bit pattern of an `f64`
+///
+/// `0.0` and `-0.0` make the same key, and so do all NaNs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct F64Key(u64);
+
+impl F64Key {
+    /// Create a key for `value`
+    pub fn new(value: f64) -> F64Key {
+        F64Key(normalized_bits(value))
+    }
+
+    /// Return the (normalized) value this key was created from
+    pub fn value(&self) -> f64 {
+        f64::from_bits(self.0)
+    }
+}
+
+impl From<f64> for F64Key {
+    fn from(value: f64) -> F64Key {
+        F64Key::new(value)
+    }
+}
+
+impl From<f32> for F64Key {
+    fn from(value: f32) -> F64Key {
+        F64Key::new(value.into())
+    }
+}
+
+/// A `Hash + Eq` cache key holding a sequence of bytes
+///
+/// Created from bytes or strings, or from slices of floats using the same bit patterns
+/// as [`F64Key`], e.g. to cache a function taking a `&[f64]`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BytesKey(Box<[u8]>);
+
+impl BytesKey {
+    /// Return the bytes of this key
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<&[u8]> for BytesKey {
+    fn from(bytes: &[u8]) -> BytesKey {
+        BytesKey(bytes.into())
+    }
+}
+
+impl From<Vec<u8>> for BytesKey {
+    fn from(bytes: Vec<u8>) -> BytesKey {
+        BytesKey(bytes.into_boxed_slice())
+    }
+}
+
+impl From<&str> for BytesKey {
+    fn from(s: &str) -> BytesKey {
+        BytesKey::from(s.as_bytes())
+    }
+}
+
+impl From<&[f64]> for BytesKey {
+    fn from(values: &[f64]) -> BytesKey {
+        values
+            .iter()
+            .flat_map(|value| normalized_bits(*value).to_le_bytes())
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl From<&[f32]> for BytesKey {
+    fn from(values: &[f32]) -> BytesKey {
+        values
+            .iter()
+            .flat_map(|value| normalized_bits((*value).into()).to_le_bytes())
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Hash `value` into a `u64` cache key with a new `H` hasher
+///
+/// Used by `#[cached(hash_only_key = true)]` functions, whose arguments only need to be `Hash`:
+/// the cache stores the hash instead of the arguments, so they don't need to be `Eq`, `Clone` or
+/// owned. Two arguments with the same hash are the same key, so a (rare) collision returns the
+/// value cached for other arguments.
+///
+/// ```rust
+/// use cached::keys::hash_key;
+/// use std::collections::hash_map::DefaultHasher;
+///
+/// assert_eq!(
+///     hash_key::<DefaultHasher, _>(&("a", 1)),
+///     hash_key::<DefaultHasher, _>(&("a", 1))
+/// );
+/// ```
+pub fn hash_key<H: Hasher + Default, T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = H::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Encoding of cache keys into the bytes IO stores key their values with
+///
+/// Implemented for every `Serialize` type with [`bincode`](https://docs.rs/bincode/1)'s
+/// default encoding, which only depends on the serde data model of the key: not on its
+/// `Debug` or `Display` output, nor on the version of `cached`. The encoding of a key doesn't
+/// change within a major version of `cached`, so cached values stay reachable across upgrades.
+/// It does change with the shape of the key type: field and variant names aren't encoded,
+/// but reordering fields or variants, or changing their types, makes new keys.
+///
+/// ```rust
+/// use cached::keys::CacheKeyEncode;
+///
+/// assert_eq!((7u16, "ab").encode(), [7, 0, 2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b']);
+/// ```
+///
+/// Panics if the key fails to serialize, e.g. a sequence of unknown length.
+#[cfg(feature = "redis_store")]
+pub trait CacheKeyEncode {
+    /// Return the bytes of this key
+    fn encode(&self) -> Vec<u8>;
+}
+
+#[cfg(feature = "redis_store")]
+impl<T: Serialize + ?Sized> CacheKeyEncode for T {
+    fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("error encoding cache key")
+    }
+}
+
+#[cfg(test)]
+/// Key wrapper tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f64_key() {
+        assert_eq!(F64Key::from(1.5), F64Key::new(1.5));
+        assert_eq!(F64Key::from(0.0), F64Key::from(-0.0));
+        assert_eq!(F64Key::from(f64::NAN), F64Key::from(-f64::NAN));
+        assert_ne!(F64Key::from(1.0), F64Key::from(1.0 + f64::EPSILON));
+        assert_eq!(F64Key::from(1.5f32).value(), 1.5);
+    }
+
+    #[test]
+    fn bytes_key() {
+        assert_eq!(BytesKey::from("ab"), BytesKey::from(&b"ab"[..]));
+        assert_eq!(
+            BytesKey::from(&[0.0, 1.0][..]),
+            BytesKey::from(&[-0.0, 1.0][..])
+        );
+        assert_ne!(BytesKey::from(&[1.0][..]), BytesKey::from(&[1.0, 1.0][..]));
+        assert_eq!(BytesKey::from(&[1.0f32][..]), BytesKey::from(&[1.0f64][..]));
+    }
+
+    #[cfg(feature = "redis_store")]
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    enum Account {
+        Guest,
+        User { id: u16, name: String },
+    }
+
+    #[test]
+    #[cfg(feature = "redis_store")]
+    fn cache_key_encode() {
+        let keys = [
+            Account::Guest,
+            Account::User {
+                id: 258,
+                name: "ab".to_string(),
+            },
+        ];
+        for key in keys {
+            let decoded: Account = bincode::deserialize(&key.encode()).unwrap();
+            assert_eq!(decoded, key);
+        }
+    }
+
+    /// Keys encoded by earlier versions, which must keep finding their values
+    #[test]
+    #[cfg(feature = "redis_store")]
+    fn cache_key_encode_golden() {
+        assert_eq!(42u32.encode(), [42, 0, 0, 0]);
+        assert_eq!((-2i64).encode(), [254, 255, 255, 255, 255, 255, 255, 255]);
+        assert_eq!("ab".encode(), [2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b']);
+        assert_eq!((1u8, true, Some('x')).encode(), [1, 1, 1, b'x']);
+        assert_eq!(vec![1u16, 2].encode(), [2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 0]);
+        assert_eq!(Account::Guest.encode(), [0, 0, 0, 0]);
+        assert_eq!(
+            Account::User {
+                id: 258,
+                name: "ab".to_string()
+            }
+            .encode(),
+            [1, 0, 0, 0, 2, 1, 2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b']
+        );
+    }
+}
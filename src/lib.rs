@@ -27,6 +27,13 @@ of un-cached arguments, specify `#[cached(sync_writes = true)]` / `#[once(sync_w
 - `redis_store`: Include Redis cache store
 - `redis_async_std`: Include async Redis support using `async-std` and `async-std` tls support, implies `redis_store` and `async`
 - `redis_tokio`: Include async Redis support using `tokio` and `tokio` tls support, implies `redis_store` and `async`
+- `log`: Log a warning when an `UnboundCache` created with a warning threshold grows past it
+- `chaos`: Include `ChaosCache`, a cache adapter injecting misses, evictions and latency for tests
+- `dashmap_store`: Include `DashMapCache`, a concurrent store backed by a `dashmap::DashMap`, shared between threads without an outer `Mutex`
+- `mmap_store`: Include `MmapCache`, a cache store of plain-old-data keys and values persisted in a memory-mapped file
+- `arcswap`: Support `#[once(lock = "arcswap")]`, storing the value of a `#[once]` function in an `arc_swap::ArcSwapOption` read without locking
+- `lru_store`: Include `LruCacheAdapter`, using an `lru::LruCache` as a cache store
+- `testing`: Generate a `{fn}_cache_scope()` function for `#[cached]` functions, swapping in a fresh cache on the current thread for tests, see [`testing`](https://docs.rs/cached/latest/cached/testing/index.html)
 - `wasm`: Enable WASM support. Note that this feature is incompatible with all Redis features (`redis_store`, `redis_async_std`, `redis_tokio`)
 
 The procedural macros (`#[cached]`, `#[once]`, `#[io_cached]`) offer more features, including async support.
@@ -150,7 +157,7 @@ Due to the requirements of storing arguments and return values in a global cache
   - For all store types, except Redis, must either be owned and implement `Hash + Eq + Clone`,
     the `cached_key!` macro is used with a `Key` block specifying key construction, or
     a `convert` expression is specified on a procedural macro to specify how to construct a key
-    of a `Hash + Eq + Clone` type.
+    of a `Hash + Eq + Clone` type. The [`keys`](crate::keys) module has wrappers making keys of floats.
   - For the Redis store type, must either be owned and implement `Display`, or the `cached_key!` & `Key`
     or procedural macro & `convert` expression used to specify how to construct a key of a `Display` type.
 - Arguments and return values will be `cloned` in the process of insertion and retrieval. Except for Redis
@@ -159,6 +166,10 @@ Due to the requirements of storing arguments and return values in a global cache
 - Macro-defined functions cannot live directly under `impl` blocks since macros expand to a
   `once_cell`/`lazy_static` initialization and one or more function definitions.
 - Macro-defined functions cannot accept `Self` types as a parameter.
+- Each macro-defined function has its own cache, and async functions lock theirs with a tokio
+  mutex, so a sync function and its async variant can't both be macro-defined over a shared cache.
+  Define the sync one with `#[cached]` and look its cache up from the async one with
+  `cached::async_sync::get_or_set_bridged`.
 
 
 */
@@ -170,28 +181,58 @@ pub extern crate lazy_static;
 pub extern crate once_cell;
 
 #[cfg(feature = "proc_macro")]
-pub use proc_macro::Return;
+pub use proc_macro::{CacheConfig, CacheMiss, ColdStartTimeout, ComputeTimeout, Return};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 #[cfg(any(feature = "redis_async_std", feature = "redis_tokio"))]
 pub use stores::AsyncRedisCache;
 pub use stores::{
-    CanExpire, ExpiringValueCache, SizedCache, TimedCache, TimedSizedCache, UnboundCache,
+    invalidate_group, CacheEntry, CanExpire, ClockCache, EvictAfterSetCache, ExpiringValueCache,
+    FallibleCache, GroupedCache, MultiLevelCache, NullCache, PartitionedCache, PriorityCache,
+    PromotionPolicy, SizedCache, TimedCache, TimedSizedCache, UnboundCache, UpdateResult,
+    WeakCache, WindowedStats,
 };
+#[cfg(feature = "mmap_store")]
+pub use stores::{MmapCache, MmapCacheError};
 #[cfg(feature = "redis_store")]
 pub use stores::{RedisCache, RedisCacheError};
 #[cfg(feature = "async")]
 use {async_trait::async_trait, futures::Future};
 
+pub mod entry;
+pub mod keys;
 mod lru_list;
 pub mod macros;
 #[cfg(feature = "proc_macro")]
 pub mod proc_macro;
 pub mod stores;
+#[cfg(feature = "arcswap")]
+pub use arc_swap;
 pub use instant;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 #[cfg(any(feature = "proc_macro", feature = "async"))]
-pub mod async_sync {
-    pub use tokio::sync::Mutex;
-    pub use tokio::sync::RwLock;
+pub mod async_sync;
+
+static CACHING_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enable or disable the caches of every function defined with the `#[cached]`, `#[once]`
+/// and `#[io_cached]` macros, e.g. to rule out stale values during an incident without
+/// redeploying. While disabled, those functions call the function body and return its result
+/// without reading or writing their cache. Caching is enabled by default. The cache of a single
+/// function is disabled with the `{fn}_set_enabled` function generated next to it.
+///
+/// Values cached before caching was disabled are served again once it's re-enabled, so clear
+/// the caches too if they may be stale. The `{fn}_prime_cache` functions, the declarative
+/// `cached!` macros and cache stores used directly aren't affected.
+pub fn set_caching_enabled(enabled: bool) {
+    CACHING_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Return whether the caches of macro-defined functions are used, see [`set_caching_enabled`]
+pub fn caching_enabled() -> bool {
+    CACHING_ENABLED.load(Ordering::Relaxed)
 }
 
 /// Cache operations
@@ -199,18 +240,98 @@ pub trait Cached<K, V> {
     /// Attempt to retrieve a cached value
     fn cache_get(&mut self, k: &K) -> Option<&V>;
 
+    /// Attempt to retrieve a cached value along with how long until it expires, which is
+    /// `None` for stores whose values don't expire by time
+    fn cache_get_with_expiry(&mut self, k: &K) -> Option<(&V, Option<Duration>)> {
+        self.cache_get(k).map(|v| (v, None))
+    }
+
     /// Attempt to retrieve a cached value with mutable access
     fn cache_get_mut(&mut self, k: &K) -> Option<&mut V>;
 
     /// Insert a key, value pair and return the previous value
     fn cache_set(&mut self, k: K, v: V) -> Option<V>;
 
+    /// Insert many key, value pairs, in order, e.g. to store a batch of values with a single
+    /// acquisition of the lock guarding the cache
+    fn cache_extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I)
+    where
+        Self: Sized,
+    {
+        for (k, v) in entries {
+            self.cache_set(k, v);
+        }
+    }
+
     /// Get or insert a key, value pair
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V;
 
+    /// Read the value cached for `k` and replace it with the value computed by `f`, or remove it
+    /// if `f` returns `None`, all under a single borrow of the store, e.g. to update a cached
+    /// aggregate without another task changing it in between.
+    ///
+    /// The read counts as a hit or miss, and the new value is stored as with
+    /// [`Cached::cache_set`] (refreshing its lifespan in timed stores). Returns what happened
+    /// to the cached value.
+    fn cache_update<F: FnOnce(Option<&V>) -> Option<V>>(&mut self, k: &K, f: F) -> UpdateResult
+    where
+        K: Clone,
+        Self: Sized,
+    {
+        let current = self.cache_get(k);
+        let existed = current.is_some();
+        match (existed, f(current)) {
+            (false, Some(v)) => {
+                self.cache_set(k.clone(), v);
+                UpdateResult::Inserted
+            }
+            (true, Some(v)) => {
+                self.cache_set(k.clone(), v);
+                UpdateResult::Updated
+            }
+            (true, None) => {
+                self.cache_remove(k);
+                UpdateResult::Removed
+            }
+            (false, None) => UpdateResult::Unchanged,
+        }
+    }
+
+    /// Look up `k` and return an [`Entry`](entry::Entry) to inspect and fill it, like
+    /// `HashMap::entry`. The lookup counts as a hit or miss.
+    ///
+    /// The entry borrows the store mutably, so with a store shared behind a lock, the lock is
+    /// held for as long as the entry lives. To compute values without holding the lock of the
+    /// whole store, use [`KeyLocks`](entry::KeyLocks) instead.
+    fn cache_entry(&mut self, k: K) -> entry::Entry<'_, K, V, Self>
+    where
+        Self: Sized,
+    {
+        entry::Entry::new(self, k)
+    }
+
     /// Remove a cached value
     fn cache_remove(&mut self, k: &K) -> Option<V>;
 
+    /// Keep only the cached values for which `f` returns `true`, with mutable access to the
+    /// values so they can be updated during the same scan, like `HashMap::retain`.
+    ///
+    /// Expired values are removed without being passed to `f`. The default implementation
+    /// passes `f` copies of the values from [`Cached::cache_snapshot`] and removes the rejected
+    /// ones, so changes to the values aren't kept. The built-in stores update them in place.
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F)
+    where
+        Self: Sized,
+        K: Clone + Ord,
+        V: Clone,
+    {
+        for (k, mut v) in self.cache_snapshot() {
+            if !f(&k, &mut v) {
+                self.cache_remove(&k);
+            }
+        }
+    }
+
     /// Remove all cached values. Keeps the allocated memory for reuse.
     fn cache_clear(&mut self);
 
@@ -223,6 +344,40 @@ pub trait Cached<K, V> {
     /// Return the current cache size (number of elements)
     fn cache_size(&self) -> usize;
 
+    /// Return a copy of the cached key, value pairs, sorted by key.
+    ///
+    /// Expired values are excluded. Timed stores order their entries by remaining lifespan
+    /// instead, most recently stored first. Returns an empty `Vec` for stores that don't support
+    /// listing their contents.
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        Vec::new()
+    }
+
+    /// Return a cached value along with when it was stored, how long until it expires and how
+    /// many times it was retrieved, e.g. for dashboards inspecting a cache. Stores fill in the
+    /// metadata they keep, e.g. `SizedCache` and `TimedSizedCache` count hits per entry while
+    /// `TimedCache` doesn't.
+    ///
+    /// Unlike [`Cached::cache_get`], this doesn't count as a hit or miss, refresh the value, or
+    /// update its position in the eviction order. Expired values are not returned. Returns `None`
+    /// for stores that don't support it.
+    fn cache_get_with_meta(&self, _k: &K) -> Option<CacheEntry<'_, V>> {
+        None
+    }
+
+    /// Return the value cached for `k` even if it expired, as long as the store still holds it,
+    /// e.g. to serve a stale value when computing a fresh one takes too long.
+    ///
+    /// Like [`Cached::cache_get_with_meta`], this doesn't count as a hit or miss. Stores whose
+    /// values don't expire return the same value as [`Cached::cache_get_with_meta`].
+    fn cache_get_stale(&self, k: &K) -> Option<&V> {
+        self.cache_get_with_meta(k).map(|entry| entry.value)
+    }
+
     /// Return the number of times a cached value was successfully retrieved
     fn cache_hits(&self) -> Option<u64> {
         None
@@ -233,6 +388,14 @@ pub trait Cached<K, V> {
         None
     }
 
+    /// Return the hits and misses of the last `window` of time, rounded up to whole buckets.
+    ///
+    /// Only stores created with windowed stats enabled (e.g. [`SizedCache::with_windowed_stats`])
+    /// keep these; others return empty stats with a zero `window`.
+    fn cache_stats_windowed(&self, _window: Duration) -> WindowedStats {
+        WindowedStats::default()
+    }
+
     /// Return the cache capacity
     fn cache_capacity(&self) -> Option<usize> {
         None
@@ -249,6 +412,58 @@ pub trait Cached<K, V> {
     }
 }
 
+/// Cache lookups through a shared reference, for stores that can serve cached values without
+/// exclusive access, e.g. to many threads at once under the read half of a `RwLock`
+///
+/// Stores implement it when their lookups don't need to refresh values or update an eviction
+/// order, keeping their hit and miss counts with interior mutability. Other operations still
+/// go through [`Cached`] and its `&mut self` methods. `#[cached(lock = "rwlock")]` requires the
+/// store to implement it.
+pub trait CachedRead<K, V>: Cached<K, V> {
+    /// Attempt to retrieve a cached value, counting a hit or miss like [`Cached::cache_get`]
+    fn cache_read(&self, k: &K) -> Option<&V>;
+
+    /// Attempt to retrieve a cached value along with how long until it expires, like
+    /// [`Cached::cache_get_with_expiry`]
+    fn cache_read_with_expiry(&self, k: &K) -> Option<(&V, Option<Duration>)> {
+        self.cache_read(k).map(|v| (v, None))
+    }
+}
+
+/// Cache operations through a shared reference returning clones of the cached values, for stores
+/// shared between threads without an outer lock
+///
+/// `#[cached(lock = "none")]` keeps a store implementing it in a plain `static`, e.g. a
+/// [`DashMapCache`](https://docs.rs/cached/latest/cached/stores/struct.DashMapCache.html)
+/// with the `dashmap_store` feature. Values are cloned out of the store since no lock guard
+/// outlives a call.
+pub trait ConcurrentCached<K, V> {
+    /// Attempt to retrieve a clone of a cached value
+    fn cache_get(&self, k: &K) -> Option<V>;
+
+    /// Insert a key, value pair and return the previous value
+    fn cache_set(&self, k: K, v: V) -> Option<V>;
+
+    /// Remove a cached value
+    fn cache_remove(&self, k: &K) -> Option<V>;
+
+    /// Remove all cached values
+    fn cache_clear(&self);
+
+    /// Return the current cache size (number of elements)
+    fn cache_size(&self) -> usize;
+
+    /// Return the number of times a cached value was successfully retrieved
+    fn cache_hits(&self) -> Option<u64> {
+        None
+    }
+
+    /// Return the number of times a cached value was unable to be retrieved
+    fn cache_misses(&self) -> Option<u64> {
+        None
+    }
+}
+
 #[cfg(feature = "async")]
 #[async_trait]
 pub trait CachedAsync<K, V> {
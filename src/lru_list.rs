@@ -8,6 +8,8 @@ pub struct LRUList<T> {
 #[derive(Clone, Debug)]
 struct ListEntry<T> {
     value: Option<T>,
+    // number of hits of the value, see `LRUList::count_hit`
+    hits: u64,
     next: usize,
     prev: usize,
 }
@@ -23,11 +25,13 @@ impl<T> LRUList<T> {
         let mut values = Vec::with_capacity(capacity + 2);
         values.push(ListEntry::<T> {
             value: None,
+            hits: 0,
             next: 0,
             prev: 0,
         });
         values.push(ListEntry::<T> {
             value: None,
+            hits: 0,
             next: 1,
             prev: 1,
         });
@@ -58,6 +62,7 @@ impl<T> LRUList<T> {
         if self.values[Self::FREE].next == Self::FREE {
             self.values.push(ListEntry::<T> {
                 value: None,
+                hits: 0,
                 next: Self::FREE,
                 prev: Self::FREE,
             });
@@ -65,6 +70,7 @@ impl<T> LRUList<T> {
         }
         let index = self.values[Self::FREE].next;
         self.values[index].value = Some(value);
+        self.values[index].hits = 0;
         self.unlink(index);
         self.link_after(index, Self::OCCUPIED);
         index
@@ -80,6 +86,10 @@ impl<T> LRUList<T> {
         self.values[Self::OCCUPIED].prev
     }
 
+    pub(crate) fn prev(&self, index: usize) -> usize {
+        self.values[index].prev
+    }
+
     pub(crate) fn get(&self, index: usize) -> &T {
         self.values[index].value.as_ref().expect("invalid index")
     }
@@ -89,24 +99,47 @@ impl<T> LRUList<T> {
     }
 
     pub(crate) fn set(&mut self, index: usize, value: T) -> Option<T> {
+        self.values[index].hits = 0;
         self.values[index].value.replace(value)
     }
 
+    /// Count a hit of the value at `index`, until it's replaced
+    pub(crate) fn count_hit(&mut self, index: usize) {
+        self.values[index].hits += 1;
+    }
+
+    pub(crate) fn hits(&self, index: usize) -> u64 {
+        self.values[index].hits
+    }
+
     pub(crate) fn clear(&mut self) {
         self.values.clear();
         self.values.push(ListEntry::<T> {
             value: None,
+            hits: 0,
             next: 0,
             prev: 0,
         });
         self.values.push(ListEntry::<T> {
             value: None,
+            hits: 0,
             next: 1,
             prev: 1,
         });
     }
 
-    pub fn iter(&self) -> LRUListIterator<T> {
+    /// The indices of the values, from the front of the list to the back
+    pub(crate) fn indices(&self) -> Vec<usize> {
+        let mut indices = Vec::new();
+        let mut index = self.values[Self::OCCUPIED].next;
+        while index != Self::OCCUPIED {
+            indices.push(index);
+            index = self.values[index].next;
+        }
+        indices
+    }
+
+    pub fn iter(&self) -> LRUListIterator<'_, T> {
         LRUListIterator::<T> {
             list: self,
             index: Self::OCCUPIED,
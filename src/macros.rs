@@ -220,8 +220,41 @@ cached_control!{
 ```
 
 
+### `cached_map!` Usage:
+
+`cached_map!` creates an `UnboundCache` holding the given key, value pairs, e.g. to prime a
+cache in tests or when warming up. The key and value types are inferred from the pairs.
+
+```rust
+#[macro_use] extern crate cached;
+
+use cached::Cached;
+
+# pub fn main() {
+let mut cache = cached_map! {
+    "one" => 1,
+    "two" => 2,
+};
+assert_eq!(cache.cache_get(&"two"), Some(&2));
+# }
+```
+
  */
 
+/// Create an `UnboundCache` holding the given `key => value` pairs, see the [module docs](self)
+#[macro_export]
+macro_rules! cached_map {
+    (@unit $($x:tt)*) => { () };
+
+    ($($key:expr => $value:expr),* $(,)?) => {{
+        let mut cache = $crate::UnboundCache::with_capacity(
+            <[()]>::len(&[$($crate::cached_map!(@unit $key)),*])
+        );
+        $( $crate::Cached::cache_set(&mut cache, $key, $value); )*
+        cache
+    }};
+}
+
 #[macro_export]
 macro_rules! cached {
     // Use default cached::Cache
@@ -445,7 +478,8 @@ macro_rules! cached_control {
         static $cachename: $crate::once_cell::sync::Lazy<::std::sync::Mutex<$cachetype>>
             = $crate::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new($cacheinstance));
 
-        #[allow(unused_parens)]
+        // `PostExec` blocks typically return early on errors with a `match`
+        #[allow(unused_parens, clippy::question_mark)]
         pub fn $name($($arg: $argtype),*) -> $ret {
             let key = $key;
             {
@@ -474,7 +508,8 @@ macro_rules! cached_control {
         static $cachename: $crate::once_cell::sync::Lazy<::std::sync::Mutex<$cachetype>>
             = $crate::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new($cacheinstance));
 
-        #[allow(unused_parens)]
+        // `PostExec` blocks typically return early on errors with a `match`
+        #[allow(unused_parens, clippy::question_mark)]
         pub async fn $name($($arg: $argtype),*) -> $ret {
             let key = $key;
             {
@@ -222,6 +222,31 @@ fn keyed(a: &str, b: &str) -> usize {
 
 ----
 
+```rust
+use cached::proc_macro::cached;
+
+/// Make a string key case-insensitive, either in a `convert` block or, without having to
+/// repeat the key type, by transforming the key made from the arguments.
+#[cached(key = "String", convert = r#"{ name.to_lowercase() }"#)]
+fn user_id(name: &str) -> usize {
+    name.len()
+}
+
+#[cached(key_transform = r#"{ |k| k.to_lowercase() }"#)]
+fn greeting(name: String) -> String {
+    format!("hello {}", name)
+}
+
+# pub fn main() {
+assert_eq!(user_id("Alice"), user_id("ALICE"));
+assert_eq!(greeting("Bob".to_string()), "hello Bob");
+// served from the cache of "Bob"
+assert_eq!(greeting("BOB".to_string()), "hello Bob");
+# }
+```
+
+----
+
 ```rust,no_run
 use cached::proc_macro::once;
 
@@ -335,5 +360,459 @@ pub use cached_proc_macro::once;
 /// `cached::IOCachedAsync` for async functions)
 pub use cached_proc_macro::io_cached;
 
+/// Clear the caches of `#[cached]` functions declared inside a test before and after it runs.
+pub use cached_proc_macro::cached_macro_test;
+
 /// Used to wrap a function result so callers can see whether the result was cached.
 pub use cached_proc_macro_types::Return;
+
+use crate::Cached;
+use std::collections::HashSet;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::task::{Context, Waker};
+use std::time::{Duration, Instant};
+
+/// The error returned by an `error_on_miss` cached function when its cache has no value
+/// for the arguments. The function itself isn't called on misses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheMiss;
+
+impl std::fmt::Display for CacheMiss {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no cached value")
+    }
+}
+
+impl std::error::Error for CacheMiss {}
+
+/// The error returned by a `cold_start_timeout` cached function when its cache stays locked
+/// for longer than the timeout, e.g. by a caller computing a value with `sync_writes`.
+/// The function itself isn't called then.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColdStartTimeout;
+
+impl std::fmt::Display for ColdStartTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out waiting for the cache lock")
+    }
+}
+
+impl std::error::Error for ColdStartTimeout {}
+
+/// The error returned by a `compute_timeout_ms` cached function when computing a value takes
+/// longer than the timeout and no stale value is cached. The computation is dropped then.
+///
+/// Functions returning a `Result` convert it into their error type with `From`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComputeTimeout;
+
+impl std::fmt::Display for ComputeTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out computing the value")
+    }
+}
+
+impl std::error::Error for ComputeTimeout {}
+
+/// Lock `mutex`, giving up with [`ColdStartTimeout`] if it can't be locked within `timeout`.
+///
+/// This is what sync `#[cached(cold_start_timeout = N)]` functions lock their cache with. The
+/// standard mutex can't wait with a timeout, so it's retried with a backoff of up to 16ms.
+/// Like the other cached functions, it panics if the mutex is poisoned.
+///
+/// ```rust
+/// use std::sync::Mutex;
+/// use std::time::Duration;
+/// use cached::proc_macro::{lock_with_timeout, ColdStartTimeout};
+///
+/// let mutex = Mutex::new(1);
+/// let guard = lock_with_timeout(&mutex, Duration::from_millis(10)).unwrap();
+/// assert_eq!(
+///     lock_with_timeout(&mutex, Duration::from_millis(10)).err(),
+///     Some(ColdStartTimeout)
+/// );
+/// drop(guard);
+/// ```
+pub fn lock_with_timeout<T>(
+    mutex: &Mutex<T>,
+    timeout: Duration,
+) -> Result<MutexGuard<'_, T>, ColdStartTimeout> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = Duration::from_millis(1);
+    loop {
+        match mutex.try_lock() {
+            Ok(guard) => return Ok(guard),
+            Err(TryLockError::Poisoned(poisoned)) => panic!("{}", poisoned),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(ColdStartTimeout);
+        }
+        std::thread::sleep(backoff.min(remaining));
+        backoff = (backoff * 2).min(Duration::from_millis(16));
+    }
+}
+
+/// Spawn the thread removing the values of an `evict_after_set` cached function once they're
+/// due. `evict_due` removes the due values from the cache and returns when the next one is, see
+/// [`EvictAfterSetCache::evict_due`](crate::EvictAfterSetCache::evict_due). With no value
+/// scheduled for removal, the thread checks again after `seconds`, the earliest a value set in
+/// the meantime can be due.
+pub fn spawn_evict_after_set<F>(seconds: u64, mut evict_due: F)
+where
+    F: FnMut() -> Option<Instant> + Send + 'static,
+{
+    std::thread::Builder::new()
+        .name("cached-evict-after-set".to_string())
+        .spawn(move || loop {
+            let wait = match evict_due() {
+                Some(due) => due.saturating_duration_since(Instant::now()),
+                None => Duration::from_secs(seconds),
+            };
+            std::thread::sleep(wait);
+        })
+        .expect("unable to spawn the `evict_after_set` thread");
+}
+
+/// Poll `flush`, the future of an async `{fn}_cache_flush` function, once: the cache is emptied
+/// unless another caller holds its lock. Used by `#[cached_macro_test]` to empty caches where it
+/// can neither `.await` nor block, e.g. while a test running in a runtime unwinds.
+#[doc(hidden)]
+pub fn flush_now<F: Future<Output = ()>>(flush: F) {
+    let mut flush = std::pin::pin!(flush);
+    let _ = flush.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+}
+
+/// The keys whose value is being computed by the callers of a `#[cached(placeholder = "...")]`
+/// function, so concurrent callers missing the same key return the placeholder instead of
+/// waiting for the value.
+///
+/// ```rust
+/// use cached::proc_macro::InFlight;
+///
+/// let in_flight = InFlight::new();
+/// let computing = in_flight.start(&"key").unwrap();
+/// // another caller is computing the value of "key"
+/// assert!(in_flight.start(&"key").is_none());
+/// drop(computing);
+/// assert!(in_flight.start(&"key").is_some());
+/// ```
+#[derive(Debug)]
+pub struct InFlight<K> {
+    keys: Mutex<HashSet<K>>,
+}
+
+impl<K: Hash + Eq + Clone> InFlight<K> {
+    /// Creates an empty `InFlight`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> InFlight<K> {
+        InFlight {
+            keys: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Mark the value of `key` as being computed until the returned guard is dropped, even
+    /// by a panic. Returns `None` if another caller is already computing it.
+    pub fn start(&self, key: &K) -> Option<InFlightGuard<'_, K>> {
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        if keys.insert(key.clone()) {
+            Some(InFlightGuard {
+                in_flight: self,
+                key: key.clone(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Marks the value of a key as being computed until it's dropped, see [`InFlight::start`]
+#[derive(Debug)]
+pub struct InFlightGuard<'a, K: Hash + Eq> {
+    in_flight: &'a InFlight<K>,
+    key: K,
+}
+
+impl<K: Hash + Eq> Drop for InFlightGuard<'_, K> {
+    fn drop(&mut self) {
+        self.in_flight
+            .keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
+
+/// A counting semaphore limiting how many threads run the body of a
+/// `#[cached(max_inflight = N)]` function at once. The threads beyond the limit block until
+/// a permit is released.
+///
+/// ```rust
+/// use cached::proc_macro::InflightLimit;
+///
+/// let limit = InflightLimit::new(1);
+/// let permit = limit.acquire();
+/// // the only permit is taken
+/// assert!(limit.try_acquire().is_none());
+/// drop(permit);
+/// assert!(limit.try_acquire().is_some());
+/// ```
+#[derive(Debug)]
+pub struct InflightLimit {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl InflightLimit {
+    /// Creates a semaphore with `permits` permits.
+    ///
+    /// Panics if `permits` is zero, as nothing could ever run.
+    pub fn new(permits: usize) -> InflightLimit {
+        assert!(permits > 0, "`InflightLimit` needs at least one permit");
+        InflightLimit {
+            available: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Take a permit until the returned guard is dropped, even by a panic, blocking until one
+    /// is available
+    pub fn acquire(&self) -> InflightPermit<'_> {
+        let available = self
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut available = self
+            .released
+            .wait_while(available, |available| *available == 0)
+            .unwrap_or_else(PoisonError::into_inner);
+        *available -= 1;
+        InflightPermit { limit: self }
+    }
+
+    /// Take a permit until the returned guard is dropped, `None` if none is available
+    pub fn try_acquire(&self) -> Option<InflightPermit<'_>> {
+        let mut available = self
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if *available == 0 {
+            return None;
+        }
+        *available -= 1;
+        Some(InflightPermit { limit: self })
+    }
+}
+
+/// A permit of an [`InflightLimit`], released when it's dropped
+#[derive(Debug)]
+pub struct InflightPermit<'a> {
+    limit: &'a InflightLimit,
+}
+
+impl Drop for InflightPermit<'_> {
+    fn drop(&mut self) {
+        *self
+            .limit
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += 1;
+        self.limit.released.notify_one();
+    }
+}
+
+/// The failures of a `#[once(result = true, retry_backoff_ms = ...)]` function, so callers get the
+/// last error back instead of retrying until its backoff elapsed.
+///
+/// The backoff starts at `min` after a failure and doubles with each consecutive failure, up to
+/// `max`. A success forgets the failures.
+///
+/// ```rust
+/// use cached::instant::Instant;
+/// use cached::proc_macro::RetryBackoff;
+/// use std::time::Duration;
+///
+/// let backoff = RetryBackoff::new(Duration::from_secs(1), Duration::from_secs(60));
+/// let start = Instant::now();
+/// backoff.record(&Err::<(), _>("down"), start);
+/// assert_eq!(backoff.check(start + Duration::from_millis(500)), Some("down"));
+/// assert_eq!(backoff.check(start + Duration::from_secs(1)), None);
+///
+/// // failing again doubles the backoff
+/// backoff.record(&Err::<(), _>("still down"), start + Duration::from_secs(1));
+/// assert_eq!(backoff.check(start + Duration::from_secs(2)), Some("still down"));
+/// assert_eq!(backoff.check(start + Duration::from_secs(3)), None);
+///
+/// backoff.record(&Ok::<_, &str>(()), start + Duration::from_secs(3));
+/// assert_eq!(backoff.check(start + Duration::from_secs(3)), None);
+/// ```
+#[derive(Debug)]
+pub struct RetryBackoff<E> {
+    min: Duration,
+    max: Duration,
+    // the last error, when it happened and how long to back off from it
+    failure: Mutex<Option<(E, instant::Instant, Duration)>>,
+}
+
+impl<E: Clone> RetryBackoff<E> {
+    /// Creates the backoff of a function that hasn't failed yet.
+    ///
+    /// Panics if `min` is zero or greater than `max`.
+    pub fn new(min: Duration, max: Duration) -> RetryBackoff<E> {
+        assert!(
+            !min.is_zero() && min <= max,
+            "`RetryBackoff` needs a non-zero minimum backoff, at most the maximum"
+        );
+        RetryBackoff {
+            min,
+            max,
+            failure: Mutex::new(None),
+        }
+    }
+
+    /// The last error if the function is still backing off from it at `now`
+    pub fn check(&self, now: instant::Instant) -> Option<E> {
+        let failure = self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*failure {
+            Some((error, failed_at, backoff)) if now < *failed_at + *backoff => Some(error.clone()),
+            _ => None,
+        }
+    }
+
+    /// Record the result of running the function, completed at `now`
+    pub fn record<T>(&self, result: &Result<T, E>, now: instant::Instant) {
+        let mut failure = self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+        *failure = match result {
+            Ok(_) => None,
+            Err(error) => {
+                let backoff = match &*failure {
+                    Some((_, _, backoff)) => backoff.saturating_mul(2).min(self.max),
+                    None => self.min,
+                };
+                Some((error.clone(), now, backoff))
+            }
+        };
+    }
+}
+
+/// The part of the cache key made from an argument of a `#[cached(extractor = true)]` function,
+/// e.g. the `T` of the `Path<T>` extractor of a web handler.
+///
+/// Every type dereferencing to a `Clone` type, such as the extractors of axum and actix-web
+/// (`Path<T>`, `Query<T>`, `Json<T>`, ...), contributes a clone of the value it wraps. Implement
+/// it for extractors of your own:
+///
+/// ```rust
+/// use cached::proc_macro::KeyExtract;
+///
+/// /// The id of the user making a request, extracted from its headers
+/// struct UserId {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// impl KeyExtract for UserId {
+///     type Key = u64;
+///
+///     fn extract_key(&self) -> u64 {
+///         self.id
+///     }
+/// }
+/// ```
+pub trait KeyExtract {
+    /// The part of the cache key made from the argument
+    type Key;
+
+    /// Make the part of the cache key from the argument
+    fn extract_key(&self) -> Self::Key;
+}
+
+impl<T> KeyExtract for T
+where
+    T: std::ops::Deref,
+    T::Target: Clone + Sized,
+{
+    type Key = T::Target;
+
+    fn extract_key(&self) -> T::Target {
+        (**self).clone()
+    }
+}
+
+/// The cache of a `#[cached(config = "...")]` function, configured in plain Rust instead of
+/// `type`, `create`, `key` and `convert` attribute strings.
+///
+/// `A` is the type of the arguments of the function: a tuple of their types, or the type of the
+/// only argument. The arguments are cloned to make the key, as they are for the default keys.
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::{CacheConfig, Cached, SizedCache};
+///
+/// struct KeyedConfig;
+///
+/// impl CacheConfig<(&str, &str)> for KeyedConfig {
+///     type Key = String;
+///     type Value = usize;
+///     type Cache = SizedCache<String, usize>;
+///
+///     fn create() -> Self::Cache {
+///         SizedCache::with_size(100)
+///     }
+///
+///     fn key((a, b): (&str, &str)) -> String {
+///         format!("{}{}", a, b)
+///     }
+///
+///     fn should_cache(size: &usize) -> bool {
+///         *size > 0
+///     }
+/// }
+///
+/// // same as `type = "SizedCache<String, usize>", create = "{ SizedCache::with_size(100) }",
+/// // convert = r#"{ format!("{}{}", a, b) }"#`, without caching empty results
+/// #[cached(config = "KeyedConfig")]
+/// fn keyed(a: &str, b: &str) -> usize {
+///     a.len() + b.len()
+/// }
+///
+/// # pub fn main() {
+/// assert_eq!(keyed("a", "b"), 2);
+/// assert_eq!(keyed("", ""), 0);
+/// assert_eq!(KEYED.lock().unwrap().cache_size(), 1);
+/// # }
+/// ```
+pub trait CacheConfig<A> {
+    /// The type of the cache keys
+    type Key;
+    /// The type of the cached values, the `T` of `Result<T, E>` or `Option<T>` functions
+    /// using `result` or `option`
+    type Value;
+    /// The cache store
+    type Cache: Cached<Self::Key, Self::Value>;
+
+    /// Create the cache store, called when the function is first used
+    fn create() -> Self::Cache;
+
+    /// Make the cache key from the arguments of the function
+    fn key(args: A) -> Self::Key;
+
+    /// Whether a computed value should be cached, all of them by default
+    fn should_cache(_value: &Self::Value) -> bool {
+        true
+    }
+}
+
+/// Names the error type of the `Result` returned by a `result = true` cached function
+/// when it's hidden behind an alias like `io::Result<T>`. Used by generated code.
+#[doc(hidden)]
+pub trait ResultError {
+    type Error;
+}
+
+impl<T, E> ResultError for Result<T, E> {
+    type Error = E;
+}
@@ -0,0 +1,362 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{Cached, IOCached};
+
+/// The outcome of a lookup in a [`ChaosCache`] script
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChaosLookup {
+    /// Look the key up in the wrapped cache
+    Pass,
+    /// Report a miss, evicting the key from the wrapped cache
+    Miss,
+}
+
+/// Cache adapter injecting failures into any `Cached` or `IOCached` store, to test how code
+/// depending on a cache behaves when it's cold, full, or slow
+///
+/// Available with the `chaos` feature, meant for tests. Swap it in with the `create` block of
+/// a macro in test builds:
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::stores::{ChaosCache, ChaosLookup};
+/// use cached::UnboundCache;
+///
+/// #[cached(
+///     type = "ChaosCache<UnboundCache<u32, u32>>",
+///     create = r#"{ ChaosCache::new(UnboundCache::new()).with_script([ChaosLookup::Miss]) }"#
+/// )]
+/// fn double(n: u32) -> u32 {
+///     n * 2
+/// }
+///
+/// assert_eq!(double(1), 2);
+/// assert_eq!(double(1), 2);
+/// let cache = DOUBLE.lock().unwrap();
+/// assert_eq!(cache.injected_misses(), 1);
+/// ```
+///
+/// Injected misses aren't counted by the metrics of the wrapped cache.
+#[derive(Debug)]
+pub struct ChaosCache<C> {
+    inner: C,
+    miss_probability: f64,
+    evict_on_insert: bool,
+    latency: Duration,
+    script: Mutex<VecDeque<ChaosLookup>>,
+    rng: AtomicU64,
+    injected_misses: AtomicU64,
+}
+
+impl<C> ChaosCache<C> {
+    /// Wrap `inner`, initially without injecting any failure
+    pub fn new(inner: C) -> ChaosCache<C> {
+        ChaosCache {
+            inner,
+            miss_probability: 0.0,
+            evict_on_insert: false,
+            latency: Duration::from_secs(0),
+            script: Mutex::new(VecDeque::new()),
+            rng: AtomicU64::new(0x2545_f491_4f6c_dd1d),
+            injected_misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Report a miss for `probability` (between `0.0` and `1.0`) of the lookups
+    pub fn with_miss_probability(mut self, probability: f64) -> Self {
+        self.miss_probability = probability;
+        self
+    }
+
+    /// Seed the random number generator deciding which lookups miss, for reproducible runs
+    pub fn with_seed(self, seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        self.rng.store(seed.max(1), Ordering::Relaxed);
+        self
+    }
+
+    /// Evict every other value on each insert, as if the cache was always full and had room
+    /// for a single value. `cache_get_or_set_with` then always computes the value.
+    pub fn with_evict_on_insert(mut self, evict_on_insert: bool) -> Self {
+        self.evict_on_insert = evict_on_insert;
+        self
+    }
+
+    /// Sleep for `latency` in every operation, e.g. to simulate a slow IO-backed store
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Decide the outcome of the next lookups in order. Once the script runs out, lookups
+    /// miss with the configured probability again.
+    pub fn with_script<I: IntoIterator<Item = ChaosLookup>>(self, script: I) -> Self {
+        self.script.lock().unwrap().extend(script);
+        self
+    }
+
+    /// Return the number of lookups that were forced to miss
+    pub fn injected_misses(&self) -> u64 {
+        self.injected_misses.load(Ordering::Relaxed)
+    }
+
+    /// Return a reference to the wrapped cache
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Return the wrapped cache
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn delay(&self) {
+        if !self.latency.is_zero() {
+            std::thread::sleep(self.latency);
+        }
+    }
+
+    /// Whether the next lookup must miss, from the script or at random
+    fn force_miss(&self) -> bool {
+        let miss = match self.script.lock().unwrap().pop_front() {
+            Some(lookup) => lookup == ChaosLookup::Miss,
+            None => self.miss_probability > 0.0 && self.random() < self.miss_probability,
+        };
+        if miss {
+            self.injected_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        miss
+    }
+
+    /// A random number in `[0, 1)`, from a xorshift generator
+    fn random(&self) -> f64 {
+        let next = |mut x: u64| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+        let previous = self
+            .rng
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(next(x)))
+            .unwrap();
+        (next(previous) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl<K, V, C: Cached<K, V>> Cached<K, V> for ChaosCache<C> {
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        self.delay();
+        if self.force_miss() {
+            self.inner.cache_remove(k);
+            return None;
+        }
+        self.inner.cache_get(k)
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.delay();
+        if self.force_miss() {
+            self.inner.cache_remove(k);
+            return None;
+        }
+        self.inner.cache_get_mut(k)
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        self.delay();
+        if self.evict_on_insert {
+            let previous = self.inner.cache_remove(&k);
+            self.inner.cache_clear();
+            self.inner.cache_set(k, v);
+            return previous;
+        }
+        self.inner.cache_set(k, v)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        self.delay();
+        if self.evict_on_insert {
+            self.inner.cache_clear();
+        } else if self.force_miss() {
+            self.inner.cache_remove(&k);
+        }
+        self.inner.cache_get_or_set_with(k, f)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        self.delay();
+        self.inner.cache_remove(k)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.inner.cache_retain(f);
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.inner.cache_snapshot()
+    }
+
+    fn cache_clear(&mut self) {
+        self.inner.cache_clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.inner.cache_reset();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.injected_misses.store(0, Ordering::Relaxed);
+        self.inner.cache_reset_metrics();
+    }
+
+    fn cache_size(&self) -> usize {
+        self.inner.cache_size()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+}
+
+impl<K, V, C: IOCached<K, V>> IOCached<K, V> for ChaosCache<C> {
+    type Error = C::Error;
+
+    fn cache_get(&self, k: &K) -> Result<Option<V>, Self::Error> {
+        self.delay();
+        if self.force_miss() {
+            self.inner.cache_remove(k)?;
+            return Ok(None);
+        }
+        self.inner.cache_get(k)
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Result<Option<V>, Self::Error> {
+        self.delay();
+        if self.evict_on_insert {
+            // an IO store can't be cleared, so only the inserted value is evicted
+            return self.inner.cache_remove(&k);
+        }
+        self.inner.cache_set(k, v)
+    }
+
+    fn cache_remove(&self, k: &K) -> Result<Option<V>, Self::Error> {
+        self.delay();
+        self.inner.cache_remove(k)
+    }
+
+    fn cache_set_refresh(&mut self, refresh: bool) -> bool {
+        self.inner.cache_set_refresh(refresh)
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+}
+
+#[cfg(test)]
+/// Chaos adapter tests
+mod tests {
+    use instant::Instant;
+
+    use super::*;
+    use crate::UnboundCache;
+
+    #[test]
+    fn passes_through() {
+        let mut c = ChaosCache::new(UnboundCache::new());
+        assert_eq!(c.cache_set(1, 10), None);
+        assert_eq!(c.cache_get(&1), Some(&10));
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &10);
+        assert_eq!(c.injected_misses(), 0);
+        assert_eq!(c.cache_hits(), Some(2));
+    }
+
+    #[test]
+    fn scripted_misses() {
+        let mut c = ChaosCache::new(UnboundCache::new()).with_script([
+            ChaosLookup::Miss,
+            ChaosLookup::Pass,
+            ChaosLookup::Miss,
+        ]);
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), None);
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), Some(&10));
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &11);
+        // the script ran out
+        assert_eq!(c.cache_get(&1), Some(&11));
+        assert_eq!(c.injected_misses(), 2);
+    }
+
+    #[test]
+    fn miss_probability() {
+        let lookups = |probability| {
+            let mut c = ChaosCache::new(UnboundCache::new())
+                .with_miss_probability(probability)
+                .with_seed(7);
+            for _ in 0..1000 {
+                c.cache_get_or_set_with(1, || 10);
+            }
+            c.injected_misses()
+        };
+        assert_eq!(lookups(0.0), 0);
+        assert_eq!(lookups(1.0), 1000);
+        let some = lookups(0.3);
+        assert!((200..400).contains(&some), "{} misses", some);
+        // the same seed gives the same misses
+        assert_eq!(lookups(0.3), some);
+    }
+
+    #[test]
+    fn evict_on_insert() {
+        let mut c = ChaosCache::new(UnboundCache::new()).with_evict_on_insert(true);
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_set(2, 20), None);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get(&2), Some(&20));
+        assert_eq!(c.cache_set(2, 21), Some(20));
+        assert_eq!(c.cache_get_or_set_with(2, || 22), &22);
+        assert_eq!(c.cache_size(), 1);
+    }
+
+    #[test]
+    fn latency() {
+        let mut c = ChaosCache::new(UnboundCache::new()).with_latency(Duration::from_millis(20));
+        let start = Instant::now();
+        c.cache_set(1, 10);
+        c.cache_get(&1);
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,358 @@
+use super::windowed::{WindowedCounter, WindowedStats};
+use super::{CacheEntry, Cached};
+use std::cmp::Eq;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// Approximate LRU / `Clock` Cache
+///
+/// Stores up to a specified size, then evicts with the CLOCK (second chance) algorithm: a
+/// hand sweeps the entries in insertion order, evicting the first one that wasn't read since
+/// the hand last passed it. Hit rates are close to a [`SizedCache`](crate::SizedCache), but
+/// reading a value only sets its referenced bit instead of moving it in a recency list, so
+/// read-heavy workloads write less memory, e.g. a hot cache shared by many threads.
+///
+/// Run `cargo run --release --example clock_vs_sized` to compare both stores.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct ClockCache<K, V> {
+    // `store` contains the index of each key's slot in `slots`
+    pub(super) store: HashMap<K, usize>,
+    pub(super) slots: Vec<Option<Slot<K, V>>>,
+    // slots emptied by `cache_remove`, reused by the next inserts
+    pub(super) free: Vec<usize>,
+    pub(super) hand: usize,
+    pub(super) capacity: usize,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+    pub(super) window: WindowedCounter,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Slot<K, V> {
+    key: K,
+    value: V,
+    referenced: bool,
+}
+
+impl<K: Hash + Eq + Clone, V> ClockCache<K, V> {
+    /// Creates a new `ClockCache` with a given size limit and pre-allocated backing data
+    pub fn with_size(size: usize) -> ClockCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `ClockCache` must be greater than zero.")
+        }
+        ClockCache {
+            store: HashMap::with_capacity(size),
+            slots: Vec::with_capacity(size),
+            free: Vec::new(),
+            hand: 0,
+            capacity: size,
+            hits: 0,
+            misses: 0,
+            window: WindowedCounter::default(),
+        }
+    }
+
+    /// Also count hits and misses in `buckets` intervals of `bucket_width`, read with
+    /// [`Cached::cache_stats_windowed`], like [`SizedCache::with_windowed_stats`](crate::SizedCache::with_windowed_stats).
+    ///
+    /// Panics if `bucket_width` or `buckets` is zero.
+    pub fn with_windowed_stats(mut self, bucket_width: Duration, buckets: usize) -> Self {
+        self.window.enable(bucket_width, buckets);
+        self
+    }
+
+    /// Return an iterator of keys in the order of the clock, which is the insertion order
+    /// of the keys unless some were removed
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.slots.iter().flatten().map(|slot| &slot.key)
+    }
+
+    /// Return an iterator of values in the order of the clock
+    pub fn value_order(&self) -> impl Iterator<Item = &V> {
+        self.slots.iter().flatten().map(|slot| &slot.value)
+    }
+
+    /// The index of the slot of `key`, marking it as referenced
+    fn reference(&mut self, key: &K) -> Option<usize> {
+        match self.store.get(key) {
+            Some(&index) => {
+                self.hits += 1;
+                self.window.hit();
+                if let Some(slot) = self.slots[index].as_mut() {
+                    slot.referenced = true;
+                }
+                Some(index)
+            }
+            None => {
+                self.misses += 1;
+                self.window.miss();
+                None
+            }
+        }
+    }
+
+    /// Store a new key in a free slot, or in the slot of the entry evicted by the hand
+    fn insert(&mut self, key: K, value: V) -> usize {
+        let slot = Some(Slot {
+            key: key.clone(),
+            value,
+            referenced: false,
+        });
+        let index = if let Some(index) = self.free.pop() {
+            self.slots[index] = slot;
+            index
+        } else if self.slots.len() < self.capacity {
+            self.slots.push(slot);
+            self.slots.len() - 1
+        } else {
+            let index = self.sweep();
+            if let Some(evicted) = self.slots[index].take() {
+                self.store.remove(&evicted.key);
+            }
+            self.slots[index] = slot;
+            index
+        };
+        self.store.insert(key, index);
+        index
+    }
+
+    /// Advance the hand to the first entry that wasn't referenced since its last pass,
+    /// clearing the referenced bits it passes, and return the index of that entry
+    fn sweep(&mut self) -> usize {
+        loop {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            match self.slots[index].as_mut() {
+                Some(slot) if slot.referenced => slot.referenced = false,
+                _ => return index,
+            }
+        }
+    }
+
+    fn value_mut(&mut self, index: usize) -> &mut V {
+        &mut self.slots[index]
+            .as_mut()
+            .expect("ClockCache::store points at an empty slot")
+            .value
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for ClockCache<K, V> {
+    fn cache_get(&mut self, key: &K) -> Option<&V> {
+        let index = self.reference(key)?;
+        Some(self.value_mut(index))
+    }
+
+    fn cache_get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.reference(key)?;
+        Some(self.value_mut(index))
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        match self.store.get(&key) {
+            Some(&index) => Some(std::mem::replace(self.value_mut(index), val)),
+            None => {
+                self.insert(key, val);
+                None
+            }
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let index = match self.reference(&key) {
+            Some(index) => index,
+            None => self.insert(key, f()),
+        };
+        self.value_mut(index)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        let index = self.store.remove(k)?;
+        self.free.push(index);
+        self.slots[index].take().map(|slot| slot.value)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        for (index, entry) in self.slots.iter_mut().enumerate() {
+            if let Some(slot) = entry {
+                if !f(&slot.key, &mut slot.value) {
+                    self.store.remove(&slot.key);
+                    self.free.push(index);
+                    *entry = None;
+                }
+            }
+        }
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.slots.clear();
+        self.free.clear();
+        self.hand = 0;
+    }
+
+    fn cache_reset(&mut self) {
+        // ClockCache uses cache_clear because capacity is fixed.
+        self.cache_clear();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+        self.hits = 0;
+        self.window.reset();
+    }
+
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .slots
+            .iter()
+            .flatten()
+            .map(|slot| (slot.key.clone(), slot.value.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        let index = *self.store.get(k)?;
+        self.slots[index]
+            .as_ref()
+            .map(|slot| CacheEntry::new(&slot.value))
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.window.stats(window)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+/// Inserts the entries in order, like [`Cached::cache_extend`]
+impl<K: Hash + Eq + Clone, V> Extend<(K, V)> for ClockCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V> CachedAsync<K, V> for ClockCache<K, V>
+where
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        let index = match self.reference(&k) {
+            Some(index) => index,
+            None => {
+                let val = f().await;
+                self.insert(k, val)
+            }
+        };
+        self.value_mut(index)
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        let index = match self.reference(&k) {
+            Some(index) => index,
+            None => {
+                let val = f().await?;
+                self.insert(k, val)
+            }
+        };
+        Ok(self.value_mut(index))
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_cache() {
+        let mut c = ClockCache::with_size(3);
+        assert!(c.cache_get(&1).is_none());
+        assert!(c.cache_set(1, 100).is_none());
+        assert!(c.cache_set(2, 200).is_none());
+        assert!(c.cache_set(3, 300).is_none());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_set(3, 301), Some(300));
+
+        // 1 was read, so it gets a second chance and 2 is evicted
+        assert!(c.cache_set(4, 400).is_none());
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 4, 3]);
+
+        // the hand cleared the referenced bit of 1 on its way, so 3 goes next, then 1
+        assert!(c.cache_set(5, 500).is_none());
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 4, 5]);
+        assert!(c.cache_set(6, 600).is_none());
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [6, 4, 5]);
+
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_capacity(), Some(3));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+
+    #[test]
+    fn clock_cache_remove() {
+        let mut c = ClockCache::with_size(2);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_remove(&1), Some(100));
+        assert_eq!(c.cache_remove(&1), None);
+        // the free slot is reused without evicting
+        assert_eq!(*c.cache_get_or_set_with(3, || 300), 300);
+        assert_eq!(*c.cache_get_or_set_with(3, || 301), 300);
+        assert_eq!(c.cache_snapshot(), vec![(2, 200), (3, 300)]);
+
+        c.cache_retain(|k, v| {
+            *v += 1;
+            *k == 3
+        });
+        assert_eq!(c.cache_snapshot(), vec![(3, 301)]);
+        assert_eq!(c.cache_get_with_meta(&3).map(|e| *e.value), Some(301));
+        c.cache_set(4, 400);
+        c.cache_set(5, 500);
+        assert_eq!(c.cache_size(), 2);
+
+        c.cache_reset();
+        assert_eq!(c.cache_size(), 0);
+        assert!(c.cache_get(&5).is_none());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::ConcurrentCached;
+use ::dashmap::DashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Concurrent Cache
+///
+/// Available with the `dashmap_store` feature. An unbounded store backed by a
+/// [`dashmap::DashMap`](::dashmap::DashMap), sharded so that threads reading and writing
+/// different keys rarely wait for each other. Its methods take `&self`, so it's shared as a
+/// plain `static` (or in an `Arc`) without an outer `Mutex`, e.g. for small `Copy` values read
+/// from many threads, where a lock around the whole store is the bottleneck.
+///
+/// It isn't a [`Cached`](crate::Cached) store, whose values are borrowed from a locked store,
+/// which a `DashMap` only lends through per-shard guards. Values are returned as clones instead,
+/// and it implements [`ConcurrentCached`] for `#[cached(lock = "none")]`, keeping it in a plain
+/// `static`:
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::stores::DashMapCache;
+///
+/// #[cached(lock = "none", type = "DashMapCache<u32, u64>", create = "{ DashMapCache::new() }")]
+/// fn square(n: u32) -> u64 {
+///     u64::from(n) * u64::from(n)
+/// }
+///
+/// assert_eq!(square(3), 9);
+/// assert_eq!(square(3), 9);
+/// assert_eq!(SQUARE.cache_hits(), 1);
+/// ```
+///
+/// Or by hand:
+///
+/// ```rust
+/// use cached::once_cell::sync::Lazy;
+/// use cached::stores::DashMapCache;
+///
+/// static SQUARES: Lazy<DashMapCache<u32, u64>> = Lazy::new(DashMapCache::new);
+///
+/// fn square(n: u32) -> u64 {
+///     SQUARES.cache_get_or_set_with(n, || u64::from(n) * u64::from(n))
+/// }
+///
+/// assert_eq!(square(3), 9);
+/// assert_eq!(square(3), 9);
+/// assert_eq!(SQUARES.cache_hits(), 1);
+/// ```
+///
+/// Note: This cache is in-memory only
+#[derive(Debug)]
+pub struct DashMapCache<K: Hash + Eq, V> {
+    store: DashMap<K, V>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq, V> Default for DashMapCache<K, V> {
+    fn default() -> Self {
+        DashMapCache::new()
+    }
+}
+
+impl<K: Hash + Eq, V> DashMapCache<K, V> {
+    /// Creates an empty `DashMapCache`
+    pub fn new() -> DashMapCache<K, V> {
+        DashMapCache::from_map(DashMap::new())
+    }
+
+    /// Creates an empty `DashMapCache` with a given pre-allocated capacity
+    pub fn with_capacity(size: usize) -> DashMapCache<K, V> {
+        DashMapCache::from_map(DashMap::with_capacity(size))
+    }
+
+    fn from_map(store: DashMap<K, V>) -> DashMapCache<K, V> {
+        DashMapCache {
+            store,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a reference to the cache's `store`
+    pub fn get_store(&self) -> &DashMap<K, V> {
+        &self.store
+    }
+
+    /// Attempt to retrieve a clone of a cached value
+    pub fn cache_get(&self, k: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let value = self.store.get(k).map(|v| v.value().clone());
+        self.count(value.is_some());
+        value
+    }
+
+    /// Insert a key, value pair and return the previous value
+    pub fn cache_set(&self, k: K, v: V) -> Option<V> {
+        self.store.insert(k, v)
+    }
+
+    /// Return a clone of the value cached for `k`, or cache and return the value computed by `f`.
+    ///
+    /// No lock is held while `f` runs, so callers missing the same key at once may each compute
+    /// a value. The first value stored is kept, and returned to all of them.
+    pub fn cache_get_or_set_with<F: FnOnce() -> V>(&self, k: K, f: F) -> V
+    where
+        V: Clone,
+    {
+        if let Some(v) = self.cache_get(&k) {
+            return v;
+        }
+        let v = f();
+        self.store.entry(k).or_insert(v).value().clone()
+    }
+
+    /// Remove a cached value
+    pub fn cache_remove(&self, k: &K) -> Option<V> {
+        self.store.remove(k).map(|(_, v)| v)
+    }
+
+    /// Remove all cached values
+    pub fn cache_clear(&self) {
+        self.store.clear();
+    }
+
+    /// Reset misses/hits counters
+    pub fn cache_reset_metrics(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Return the current cache size (number of elements)
+    pub fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Return the number of times a cached value was successfully retrieved
+    pub fn cache_hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of times a cached value was unable to be retrieved
+    pub fn cache_misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn count(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> ConcurrentCached<K, V> for DashMapCache<K, V> {
+    fn cache_get(&self, k: &K) -> Option<V> {
+        DashMapCache::cache_get(self, k)
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Option<V> {
+        DashMapCache::cache_set(self, k, v)
+    }
+
+    fn cache_remove(&self, k: &K) -> Option<V> {
+        DashMapCache::cache_remove(self, k)
+    }
+
+    fn cache_clear(&self) {
+        DashMapCache::cache_clear(self)
+    }
+
+    fn cache_size(&self) -> usize {
+        DashMapCache::cache_size(self)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(DashMapCache::cache_hits(self))
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(DashMapCache::cache_misses(self))
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basic_cache() {
+        let c = DashMapCache::new();
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(100));
+        assert_eq!(c.cache_set(1, 200), Some(100));
+        assert_eq!(c.cache_hits(), 1);
+        assert_eq!(c.cache_misses(), 1);
+
+        assert_eq!(c.cache_get_or_set_with(2, || 20), 20);
+        assert_eq!(c.cache_get_or_set_with(2, || unreachable!()), 20);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_remove(&1), Some(200));
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+
+        c.cache_reset_metrics();
+        assert_eq!((c.cache_hits(), c.cache_misses()), (0, 0));
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let c = Arc::new(DashMapCache::with_capacity(100));
+        let handles = (0..4)
+            .map(|t| {
+                let c = Arc::clone(&c);
+                thread::spawn(move || {
+                    for n in 0..100u64 {
+                        assert_eq!(c.cache_get_or_set_with(n, || n * 2), n * 2);
+                    }
+                    t
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(c.cache_size(), 100);
+        assert_eq!(c.cache_hits() + c.cache_misses(), 400);
+    }
+}
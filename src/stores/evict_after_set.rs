@@ -0,0 +1,296 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::Duration;
+
+use instant::Instant;
+
+use super::{CacheEntry, UpdateResult, WindowedStats};
+use crate::Cached;
+
+/// Cache store removing each value a fixed number of seconds after it was set
+///
+/// Unlike the lifespan of a timed store, retrieving a value never postpones its removal, and the
+/// value is removed from the wrapped store rather than only treated as expired. Setting a key
+/// again schedules the removal of its new value instead. Used by
+/// `#[cached(evict_after_set = N)]` functions, whose background thread calls
+/// [`EvictAfterSetCache::evict_due`] when values are due; otherwise due values are removed
+/// before each operation of the cache. It can wrap any store:
+///
+/// ```rust
+/// use cached::{Cached, EvictAfterSetCache, UnboundCache};
+///
+/// let mut cache = EvictAfterSetCache::new(30, UnboundCache::new());
+/// cache.cache_set(1, "one");
+/// assert_eq!(cache.cache_get(&1), Some(&"one"));
+/// // nothing is due for another 30 seconds
+/// assert!(cache.evict_due().is_some());
+/// assert_eq!(cache.cache_size(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct EvictAfterSetCache<C, K> {
+    inner: C,
+    seconds: u64,
+    // when the current value of each key is due, and the keys in the order they're due, which
+    // may since have been set again or removed
+    due: HashMap<K, Instant>,
+    queue: VecDeque<(Instant, K)>,
+}
+
+impl<C, K: Hash + Eq + Clone> EvictAfterSetCache<C, K> {
+    /// Wrap `inner`, removing each value `seconds` after it was set
+    pub fn new(seconds: u64, inner: C) -> EvictAfterSetCache<C, K> {
+        EvictAfterSetCache {
+            inner,
+            seconds,
+            due: HashMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Return how many seconds after being set values are removed
+    pub fn seconds(&self) -> u64 {
+        self.seconds
+    }
+
+    /// Return a reference to the wrapped cache, which may still hold values that are due
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Remove the values that are due, returning when the next one is, `None` if no value
+    /// is scheduled for removal
+    pub fn evict_due<V>(&mut self) -> Option<Instant>
+    where
+        C: Cached<K, V>,
+    {
+        self.evict_due_at(Instant::now())
+    }
+
+    fn evict_due_at<V>(&mut self, now: Instant) -> Option<Instant>
+    where
+        C: Cached<K, V>,
+    {
+        while let Some((due, _)) = self.queue.front() {
+            if *due > now {
+                return Some(*due);
+            }
+            let (due, key) = self.queue.pop_front()?;
+            if self.due.get(&key) == Some(&due) {
+                self.due.remove(&key);
+                self.inner.cache_remove(&key);
+            }
+        }
+        None
+    }
+
+    /// Schedule the removal of the value of `key` set at `now`
+    fn schedule(&mut self, key: K, now: Instant) {
+        let due = now + Duration::from_secs(self.seconds);
+        self.due.insert(key.clone(), due);
+        self.queue.push_back((due, key));
+    }
+
+    /// Return a mutable reference to the wrapped cache, without the values that are due
+    fn get_mut<V>(&mut self) -> &mut C
+    where
+        C: Cached<K, V>,
+    {
+        self.evict_due();
+        &mut self.inner
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, C: Cached<K, V>> Cached<K, V> for EvictAfterSetCache<C, K> {
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        self.get_mut().cache_get(k)
+    }
+
+    fn cache_get_with_expiry(&mut self, k: &K) -> Option<(&V, Option<Duration>)> {
+        self.get_mut().cache_get_with_expiry(k)
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.get_mut().cache_get_mut(k)
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        let now = Instant::now();
+        self.evict_due_at(now);
+        self.schedule(k.clone(), now);
+        self.inner.cache_set(k, v)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        let now = Instant::now();
+        self.evict_due_at(now);
+        let key = k.clone();
+        let mut set = false;
+        // borrow the fields apart to schedule the removal while holding the value
+        let EvictAfterSetCache {
+            inner,
+            seconds,
+            due,
+            queue,
+        } = self;
+        let value = inner.cache_get_or_set_with(k, || {
+            set = true;
+            f()
+        });
+        if set {
+            let at = now + Duration::from_secs(*seconds);
+            due.insert(key.clone(), at);
+            queue.push_back((at, key));
+        }
+        value
+    }
+
+    fn cache_update<F: FnOnce(Option<&V>) -> Option<V>>(&mut self, k: &K, f: F) -> UpdateResult
+    where
+        K: Clone,
+    {
+        let now = Instant::now();
+        self.evict_due_at(now);
+        let result = self.inner.cache_update(k, f);
+        match result {
+            UpdateResult::Inserted | UpdateResult::Updated => self.schedule(k.clone(), now),
+            UpdateResult::Removed => {
+                self.due.remove(k);
+            }
+            UpdateResult::Unchanged => {}
+        }
+        result
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        self.due.remove(k);
+        self.get_mut().cache_remove(k)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.get_mut().cache_retain(f);
+    }
+
+    fn cache_clear(&mut self) {
+        self.due.clear();
+        self.queue.clear();
+        self.inner.cache_clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.due = HashMap::new();
+        self.queue = VecDeque::new();
+        self.inner.cache_reset();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.inner.cache_reset_metrics();
+    }
+
+    fn cache_size(&self) -> usize {
+        self.inner.cache_size()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.inner.cache_snapshot()
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.inner.cache_get_with_meta(k)
+    }
+
+    fn cache_get_stale(&self, k: &K) -> Option<&V> {
+        self.inner.cache_get_stale(k)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.inner.cache_stats_windowed(window)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use crate::{SizedCache, UnboundCache};
+
+    #[test]
+    fn evicts_after_set() {
+        let mut c = EvictAfterSetCache::new(10, UnboundCache::new());
+        let start = Instant::now();
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_get(&1), Some(&100));
+
+        // retrieving a value doesn't postpone its removal
+        let due = c.evict_due_at(start + Duration::from_secs(9)).unwrap();
+        assert!(due <= start + Duration::from_secs(11));
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.evict_due_at(start + Duration::from_secs(11)), None);
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.get_ref().cache_misses(), Some(0));
+    }
+
+    #[test]
+    fn set_again() {
+        let mut c = EvictAfterSetCache::new(10, SizedCache::with_size(2));
+        let start = Instant::now();
+        c.cache_set(1, 100);
+        c.schedule(1, start + Duration::from_secs(5));
+        // only the removal of the newest value is due
+        c.evict_due_at(start + Duration::from_secs(11));
+        assert_eq!(c.cache_size(), 1);
+        c.evict_due_at(start + Duration::from_secs(16));
+        assert_eq!(c.cache_size(), 0);
+
+        // removed values aren't removed again once set by other means
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_remove(&2), Some(200));
+        c.inner.cache_set(2, 201);
+        c.evict_due_at(start + Duration::from_secs(20));
+        assert_eq!(c.cache_get(&2), Some(&201));
+    }
+
+    #[test]
+    fn get_or_set_with() {
+        let mut c = EvictAfterSetCache::new(10, UnboundCache::new());
+        assert_eq!(c.cache_get_or_set_with(1, || 100), &100);
+        assert_eq!(c.cache_get_or_set_with(1, || 200), &100);
+        assert_eq!(c.queue.len(), 1);
+        assert_eq!(c.cache_update(&2, |_| Some(200)), UpdateResult::Inserted);
+        assert_eq!(c.queue.len(), 2);
+
+        c.evict_due_at(Instant::now() + Duration::from_secs(11));
+        assert_eq!(c.cache_size(), 0);
+        c.cache_set(3, 300);
+        c.cache_clear();
+        assert_eq!(c.evict_due(), None);
+    }
+}
@@ -1,6 +1,8 @@
-use super::{Cached, SizedCache};
+use super::windowed::{WindowedCounter, WindowedStats};
+use super::{CacheEntry, Cached, SizedCache};
 use crate::stores::timed::Status;
 use std::hash::Hash;
+use std::time::Duration;
 
 /// The CanExpire trait defines a function for implementations to determine if
 /// the value has expired.
@@ -21,6 +23,7 @@ pub struct ExpiringValueCache<K: Hash + Eq, V: CanExpire> {
     pub(super) store: SizedCache<K, V>,
     pub(super) hits: u64,
     pub(super) misses: u64,
+    pub(super) window: WindowedCounter,
 }
 
 impl<K: Clone + Hash + Eq, V: CanExpire> ExpiringValueCache<K, V> {
@@ -31,6 +34,7 @@ impl<K: Clone + Hash + Eq, V: CanExpire> ExpiringValueCache<K, V> {
             store: SizedCache::with_size(size),
             hits: 0,
             misses: 0,
+            window: WindowedCounter::default(),
         }
     }
 
@@ -44,6 +48,15 @@ impl<K: Clone + Hash + Eq, V: CanExpire> ExpiringValueCache<K, V> {
             None => Status::NotFound,
         }
     }
+    /// Keep rolling hit and miss counts over the last `buckets` intervals of `bucket_width`,
+    /// read with [`Cached::cache_stats_windowed`], e.g. to alert on the hit rate of the last
+    /// few minutes. Each lookup only bumps the count of the current interval.
+    ///
+    /// Panics if `bucket_width` or `buckets` is zero.
+    pub fn with_windowed_stats(mut self, bucket_width: Duration, buckets: usize) -> Self {
+        self.window.enable(bucket_width, buckets);
+        self
+    }
 }
 
 // https://docs.rs/cached/latest/cached/trait.Cached.html
@@ -52,14 +65,17 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         match self.status(k) {
             Status::NotFound => {
                 self.misses += 1;
+                self.window.miss();
                 None
             }
             Status::Found => {
                 self.hits += 1;
+                self.window.hit();
                 self.store.cache_get(k)
             }
             Status::Expired => {
                 self.misses += 1;
+                self.window.miss();
                 self.store.cache_remove(k);
                 None
             }
@@ -70,14 +86,17 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         match self.status(k) {
             Status::NotFound => {
                 self.misses += 1;
+                self.window.miss();
                 None
             }
             Status::Found => {
                 self.hits += 1;
+                self.window.hit();
                 self.store.cache_get_mut(k)
             }
             Status::Expired => {
                 self.misses += 1;
+                self.window.miss();
                 self.store.cache_remove(k);
                 None
             }
@@ -90,8 +109,10 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
         let (was_present, was_valid, v) = self.store.get_or_set_with_if(k, f, |v| !v.is_expired());
         if was_present && was_valid {
             self.hits += 1;
+            self.window.hit();
         } else {
             self.misses += 1;
+            self.window.miss();
         }
         v
     }
@@ -101,6 +122,13 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
     fn cache_remove(&mut self, k: &K) -> Option<V> {
         self.store.cache_remove(k)
     }
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.store.cache_retain(|k, v| !v.is_expired() && f(k, v));
+    }
     fn cache_clear(&mut self) {
         self.store.cache_clear();
     }
@@ -110,15 +138,56 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
     fn cache_size(&self) -> usize {
         self.store.cache_size()
     }
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .store
+            .iter_order()
+            .filter(|(_, v)| !v.is_expired())
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_stale(&self, k: &K) -> Option<&V> {
+        self.store.peek(k)
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.store
+            .peek(k)
+            .filter(|v| !v.is_expired())
+            .map(CacheEntry::new)
+    }
+
     fn cache_hits(&self) -> Option<u64> {
         Some(self.hits)
     }
     fn cache_misses(&self) -> Option<u64> {
         Some(self.misses)
     }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.window.stats(window)
+    }
+
     fn cache_reset_metrics(&mut self) {
         self.hits = 0;
         self.misses = 0;
+        self.window.reset();
+    }
+}
+
+/// Inserts the entries in order, like [`Cached::cache_extend`]. Once the cache is full, each
+/// new key evicts the least recently used entry, so extending it with more distinct keys than
+/// its size keeps the last ones.
+impl<K: Hash + Eq + Clone, V: CanExpire> Extend<(K, V)> for ExpiringValueCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
     }
 }
 
@@ -229,4 +298,13 @@ mod tests {
         assert_eq!(c.cache_hits(), Some(0));
         assert_eq!(c.cache_misses(), Some(1));
     }
+
+    #[test]
+    fn snapshot() {
+        let mut c: ExpiringValueCache<u8, ExpiredU8> = ExpiringValueCache::with_size(3);
+        c.cache_set(3, 3);
+        c.cache_set(1, 1);
+        c.cache_set(2, 20);
+        assert_eq!(c.cache_snapshot(), [(1, 1), (3, 3)]);
+    }
 }
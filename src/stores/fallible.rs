@@ -0,0 +1,256 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use super::{CacheEntry, NullCache, UpdateResult, WindowedStats};
+use crate::Cached;
+
+/// Cache store whose creation may have failed, falling back to a [`NullCache`] caching nothing
+///
+/// Used by `#[cached(create_try = "..", on_create_error = "null")]` functions so their cache
+/// has a single type whether or not the store could be created, and the function keeps
+/// working (uncached) when it couldn't.
+///
+/// ```rust
+/// use cached::stores::FallibleCache;
+/// use cached::{Cached, SizedCache};
+///
+/// let created: Result<SizedCache<u32, u32>, String> = Err("no memory left".to_string());
+/// let mut cache = FallibleCache::from_result("DOUBLE", created);
+/// assert!(cache.is_null());
+/// cache.cache_set(1, 2);
+/// assert_eq!(cache.cache_get(&1), None);
+/// ```
+#[derive(Clone, Debug)]
+pub enum FallibleCache<C, K, V> {
+    /// The store was created
+    Store(C),
+    /// Creating the store failed, nothing is cached
+    Null(NullCache<K, V>),
+}
+
+impl<C, K, V> FallibleCache<C, K, V> {
+    /// Use the created store, or a [`NullCache`] if creating it failed. With the `log` feature,
+    /// the error is logged along with the `name` of the cache.
+    pub fn from_result<E: Debug>(name: &str, result: Result<C, E>) -> FallibleCache<C, K, V> {
+        match result {
+            Ok(store) => FallibleCache::Store(store),
+            Err(e) => {
+                log_create_error(name, &e);
+                FallibleCache::Null(NullCache::new())
+            }
+        }
+    }
+
+    /// Return whether creating the store failed, so nothing is cached
+    pub fn is_null(&self) -> bool {
+        matches!(self, FallibleCache::Null(_))
+    }
+
+    /// Return a reference to the created store, if any
+    pub fn store(&self) -> Option<&C> {
+        match self {
+            FallibleCache::Store(store) => Some(store),
+            FallibleCache::Null(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_create_error<E: Debug>(name: &str, e: &E) {
+    log::error!(
+        "unable to create cache `{}`, caching is disabled: {:?}",
+        name,
+        e
+    );
+}
+
+#[cfg(not(feature = "log"))]
+fn log_create_error<E: Debug>(_name: &str, _e: &E) {}
+
+impl<C: Cached<K, V>, K, V> Cached<K, V> for FallibleCache<C, K, V> {
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        match self {
+            FallibleCache::Store(store) => store.cache_get(k),
+            FallibleCache::Null(null) => null.cache_get(k),
+        }
+    }
+
+    fn cache_get_with_expiry(&mut self, k: &K) -> Option<(&V, Option<Duration>)> {
+        match self {
+            FallibleCache::Store(store) => store.cache_get_with_expiry(k),
+            FallibleCache::Null(null) => null.cache_get_with_expiry(k),
+        }
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        match self {
+            FallibleCache::Store(store) => store.cache_get_mut(k),
+            FallibleCache::Null(null) => null.cache_get_mut(k),
+        }
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        match self {
+            FallibleCache::Store(store) => store.cache_set(k, v),
+            FallibleCache::Null(null) => null.cache_set(k, v),
+        }
+    }
+
+    fn cache_extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        match self {
+            FallibleCache::Store(store) => store.cache_extend(entries),
+            FallibleCache::Null(null) => null.cache_extend(entries),
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        match self {
+            FallibleCache::Store(store) => store.cache_get_or_set_with(k, f),
+            FallibleCache::Null(null) => null.cache_get_or_set_with(k, f),
+        }
+    }
+
+    fn cache_update<F: FnOnce(Option<&V>) -> Option<V>>(&mut self, k: &K, f: F) -> UpdateResult
+    where
+        K: Clone,
+    {
+        match self {
+            FallibleCache::Store(store) => store.cache_update(k, f),
+            FallibleCache::Null(null) => null.cache_update(k, f),
+        }
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        match self {
+            FallibleCache::Store(store) => store.cache_remove(k),
+            FallibleCache::Null(null) => null.cache_remove(k),
+        }
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        match self {
+            FallibleCache::Store(store) => store.cache_retain(f),
+            FallibleCache::Null(null) => null.cache_retain(f),
+        }
+    }
+
+    fn cache_clear(&mut self) {
+        match self {
+            FallibleCache::Store(store) => store.cache_clear(),
+            FallibleCache::Null(null) => null.cache_clear(),
+        }
+    }
+
+    fn cache_reset(&mut self) {
+        match self {
+            FallibleCache::Store(store) => store.cache_reset(),
+            FallibleCache::Null(null) => null.cache_reset(),
+        }
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        match self {
+            FallibleCache::Store(store) => store.cache_reset_metrics(),
+            FallibleCache::Null(null) => null.cache_reset_metrics(),
+        }
+    }
+
+    fn cache_size(&self) -> usize {
+        match self {
+            FallibleCache::Store(store) => store.cache_size(),
+            FallibleCache::Null(null) => null.cache_size(),
+        }
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        match self {
+            FallibleCache::Store(store) => store.cache_snapshot(),
+            FallibleCache::Null(null) => null.cache_snapshot(),
+        }
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        match self {
+            FallibleCache::Store(store) => store.cache_get_with_meta(k),
+            FallibleCache::Null(null) => null.cache_get_with_meta(k),
+        }
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        match self {
+            FallibleCache::Store(store) => store.cache_hits(),
+            FallibleCache::Null(null) => null.cache_hits(),
+        }
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        match self {
+            FallibleCache::Store(store) => store.cache_misses(),
+            FallibleCache::Null(null) => null.cache_misses(),
+        }
+    }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        match self {
+            FallibleCache::Store(store) => store.cache_stats_windowed(window),
+            FallibleCache::Null(null) => null.cache_stats_windowed(window),
+        }
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        match self {
+            FallibleCache::Store(store) => store.cache_capacity(),
+            FallibleCache::Null(null) => null.cache_capacity(),
+        }
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        match self {
+            FallibleCache::Store(store) => store.cache_lifespan(),
+            FallibleCache::Null(null) => null.cache_lifespan(),
+        }
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        match self {
+            FallibleCache::Store(store) => store.cache_set_lifespan(seconds),
+            FallibleCache::Null(null) => null.cache_set_lifespan(seconds),
+        }
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use crate::UnboundCache;
+
+    #[test]
+    fn created() {
+        let mut c = FallibleCache::from_result("TEST", Ok::<_, ()>(UnboundCache::new()));
+        assert!(!c.is_null());
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), Some(&10));
+        assert_eq!(c.store().map(|store| store.cache_size()), Some(1));
+    }
+
+    #[test]
+    fn failed() {
+        let mut c: FallibleCache<UnboundCache<u32, u32>, _, _> =
+            FallibleCache::from_result("TEST", Err("unavailable"));
+        assert!(c.is_null());
+        assert!(c.store().is_none());
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &11);
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use super::{CacheEntry, UpdateResult, WindowedStats};
+use crate::Cached;
+
+/// The generation of every invalidation group, bumped to invalidate the group
+static GROUPS: Lazy<Mutex<HashMap<String, Arc<AtomicU64>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The generation counter of the invalidation group `group`
+fn group_generation(group: &str) -> Arc<AtomicU64> {
+    let mut groups = GROUPS.lock().unwrap();
+    match groups.get(group) {
+        Some(generation) => generation.clone(),
+        None => {
+            let generation = Arc::new(AtomicU64::new(0));
+            groups.insert(group.to_string(), generation.clone());
+            generation
+        }
+    }
+}
+
+/// Invalidate every cache of the invalidation group `group`, e.g. the caches of all the
+/// `#[cached(invalidation_group = "users")]` functions after a user changed.
+///
+/// The group is invalidated at once: after this returns, none of its caches returns a value
+/// cached before the call. Each cache drops its values the next time it's used.
+pub fn invalidate_group(group: &str) {
+    group_generation(group).fetch_add(1, Ordering::AcqRel);
+}
+
+/// Cache store belonging to an invalidation group, emptied by [`invalidate_group`]
+///
+/// Used by `#[cached(invalidation_group = "..")]` functions, it can wrap any store:
+///
+/// ```rust
+/// use cached::stores::{invalidate_group, GroupedCache};
+/// use cached::{Cached, SizedCache};
+///
+/// let mut names = GroupedCache::new("users", SizedCache::with_size(10));
+/// let mut emails = GroupedCache::new("users", SizedCache::with_size(10));
+/// names.cache_set(1, "Ferris");
+/// emails.cache_set(1, "ferris@example.com");
+///
+/// invalidate_group("users");
+/// assert_eq!(names.cache_get(&1), None);
+/// assert_eq!(emails.cache_size(), 0);
+/// ```
+#[derive(Debug)]
+pub struct GroupedCache<C> {
+    inner: C,
+    generation: Arc<AtomicU64>,
+    seen: u64,
+}
+
+impl<C> GroupedCache<C> {
+    /// Wrap `inner`, making it part of the invalidation group `group`
+    pub fn new(group: &str, inner: C) -> GroupedCache<C> {
+        let generation = group_generation(group);
+        let seen = generation.load(Ordering::Acquire);
+        GroupedCache {
+            inner,
+            generation,
+            seen,
+        }
+    }
+
+    /// Return whether the group was invalidated since the values were cached
+    fn is_stale(&self) -> bool {
+        self.generation.load(Ordering::Acquire) != self.seen
+    }
+
+    /// Return a reference to the wrapped cache, which may still hold invalidated values
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the wrapped cache, emptied first if the group was invalidated
+    pub fn get_mut<K, V>(&mut self) -> &mut C
+    where
+        C: Cached<K, V>,
+    {
+        let generation = self.generation.load(Ordering::Acquire);
+        if generation != self.seen {
+            self.inner.cache_clear();
+            self.seen = generation;
+        }
+        &mut self.inner
+    }
+}
+
+impl<K, V, C: Cached<K, V>> Cached<K, V> for GroupedCache<C> {
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        self.get_mut().cache_get(k)
+    }
+
+    fn cache_get_with_expiry(&mut self, k: &K) -> Option<(&V, Option<Duration>)> {
+        self.get_mut().cache_get_with_expiry(k)
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.get_mut().cache_get_mut(k)
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        self.get_mut().cache_set(k, v)
+    }
+
+    fn cache_extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.get_mut().cache_extend(entries)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        self.get_mut().cache_get_or_set_with(k, f)
+    }
+
+    fn cache_update<F: FnOnce(Option<&V>) -> Option<V>>(&mut self, k: &K, f: F) -> UpdateResult
+    where
+        K: Clone,
+    {
+        self.get_mut().cache_update(k, f)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        self.get_mut().cache_remove(k)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.get_mut().cache_retain(f);
+    }
+
+    fn cache_clear(&mut self) {
+        self.get_mut().cache_clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.get_mut().cache_reset();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.inner.cache_reset_metrics();
+    }
+
+    fn cache_size(&self) -> usize {
+        if self.is_stale() {
+            0
+        } else {
+            self.inner.cache_size()
+        }
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        if self.is_stale() {
+            Vec::new()
+        } else {
+            self.inner.cache_snapshot()
+        }
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        if self.is_stale() {
+            None
+        } else {
+            self.inner.cache_get_with_meta(k)
+        }
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.inner.cache_hits()
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.inner.cache_misses()
+    }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.inner.cache_stats_windowed(window)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        self.inner.cache_capacity()
+    }
+
+    fn cache_lifespan(&self) -> Option<u64> {
+        self.inner.cache_lifespan()
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        self.inner.cache_set_lifespan(seconds)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use crate::UnboundCache;
+
+    #[test]
+    fn invalidates_group() {
+        let mut a = GroupedCache::new("grouped::invalidates_group", UnboundCache::new());
+        let mut b = GroupedCache::new("grouped::invalidates_group", UnboundCache::new());
+        let mut other = GroupedCache::new("grouped::other", UnboundCache::new());
+        a.cache_set(1, 10);
+        b.cache_set(1, 10);
+        other.cache_set(1, 10);
+
+        invalidate_group("grouped::invalidates_group");
+        assert_eq!(a.cache_size(), 0);
+        assert!(a.cache_snapshot().is_empty());
+        assert_eq!(a.get_ref().cache_size(), 1);
+        assert_eq!(a.cache_get(&1), None);
+        assert_eq!(a.get_ref().cache_size(), 0);
+        assert_eq!(b.cache_get_or_set_with(1, || 11), &11);
+        assert_eq!(other.cache_get(&1), Some(&10));
+
+        // values cached after the invalidation are kept
+        a.cache_set(2, 20);
+        assert_eq!(a.cache_get(&2), Some(&20));
+    }
+
+    #[test]
+    fn created_after_invalidation() {
+        invalidate_group("grouped::created_after_invalidation");
+        let mut c = GroupedCache::new("grouped::created_after_invalidation", UnboundCache::new());
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), Some(&10));
+    }
+}
@@ -0,0 +1,266 @@
+use super::{CacheEntry, Cached};
+use ::lru::LruCache;
+use std::fmt;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// Adapter using an [`lru::LruCache`](::lru::LruCache) as a `Cached` store
+///
+/// Available with the `lru_store` feature, for code already depending on the `lru` crate.
+/// Plug it into the macros with `type` and `create`:
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::stores::LruCacheAdapter;
+///
+/// #[cached(
+///     type = "LruCacheAdapter<u32, u32>",
+///     create = r#"{ LruCacheAdapter::with_size(100) }"#
+/// )]
+/// fn double(n: u32) -> u32 {
+///     n * 2
+/// }
+///
+/// assert_eq!(double(1), 2);
+/// ```
+///
+/// It behaves like a [`SizedCache`](crate::SizedCache), with these differences:
+/// - the capacity is a `NonZeroUsize`, and an `LruCache::unbounded()` reports no capacity
+/// - it doesn't keep windowed stats, `cache_stats_windowed` returns empty stats
+/// - `cache_retain` clones the keys of the entries it removes
+pub struct LruCacheAdapter<K: Hash + Eq, V> {
+    inner: LruCache<K, V>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Hash + Eq, V> fmt::Debug for LruCacheAdapter<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LruCacheAdapter")
+            .field("inner", &self.inner)
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq, V> LruCacheAdapter<K, V> {
+    /// Wrap `inner`, keeping its entries and capacity
+    pub fn new(inner: LruCache<K, V>) -> LruCacheAdapter<K, V> {
+        LruCacheAdapter {
+            inner,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Wrap a new `LruCache` holding up to `size` entries
+    ///
+    /// Panics if `size` is zero
+    pub fn with_size(size: usize) -> LruCacheAdapter<K, V> {
+        let size = NonZeroUsize::new(size)
+            .expect("`size` of `LruCacheAdapter` must be greater than zero.");
+        LruCacheAdapter::new(LruCache::new(size))
+    }
+
+    /// Return a reference to the wrapped `LruCache`
+    pub fn get_ref(&self) -> &LruCache<K, V> {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the wrapped `LruCache`, e.g. to `resize` it
+    pub fn get_mut(&mut self) -> &mut LruCache<K, V> {
+        &mut self.inner
+    }
+
+    /// Unwrap the `LruCache`
+    pub fn into_inner(self) -> LruCache<K, V> {
+        self.inner
+    }
+
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> From<LruCache<K, V>> for LruCacheAdapter<K, V> {
+    fn from(inner: LruCache<K, V>) -> Self {
+        LruCacheAdapter::new(inner)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for LruCacheAdapter<K, V> {
+    fn cache_get(&mut self, key: &K) -> Option<&V> {
+        let hit = self.inner.contains(key);
+        self.record(hit);
+        self.inner.get(key)
+    }
+
+    fn cache_get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let hit = self.inner.contains(key);
+        self.record(hit);
+        self.inner.get_mut(key)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.inner.put(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let hit = self.inner.contains(&key);
+        self.record(hit);
+        self.inner.get_or_insert_mut(key, f)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        self.inner.pop(k)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let removed = self
+            .inner
+            .iter_mut()
+            .filter_map(|(k, v)| if f(k, v) { None } else { Some(k.clone()) })
+            .collect::<Vec<_>>();
+        for k in removed {
+            self.inner.pop(&k);
+        }
+    }
+
+    fn cache_clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        // the capacity of the `LruCache` is kept, like a `SizedCache`
+        self.cache_clear();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .inner
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.inner.peek(k).map(CacheEntry::new)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        // `LruCache::unbounded` is bounded by `usize::MAX`
+        Some(self.inner.cap().get()).filter(|cap| *cap != usize::MAX)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V> CachedAsync<K, V> for LruCacheAdapter<K, V>
+where
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        let hit = self.inner.contains(&k);
+        self.record(hit);
+        if !hit {
+            let val = f().await;
+            self.inner.put(k.clone(), val);
+        }
+        self.inner.get_mut(&k).unwrap()
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        let hit = self.inner.contains(&k);
+        self.record(hit);
+        if !hit {
+            let val = f().await?;
+            self.inner.put(k.clone(), val);
+        }
+        Ok(self.inner.get_mut(&k).unwrap())
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_adapter() {
+        let mut c = LruCacheAdapter::with_size(2);
+        assert!(c.cache_get(&1).is_none());
+        assert!(c.cache_set(1, 100).is_none());
+        assert!(c.cache_set(2, 200).is_none());
+        assert_eq!(c.cache_get(&1), Some(&100));
+        // 2 is the least recently used
+        assert!(c.cache_set(3, 300).is_none());
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_capacity(), Some(2));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+
+        assert_eq!(*c.cache_get_or_set_with(4, || 400), 400);
+        assert_eq!(c.cache_snapshot(), vec![(3, 300), (4, 400)]);
+        c.cache_retain(|k, _| *k == 3);
+        assert_eq!(c.cache_snapshot(), vec![(3, 300)]);
+        assert_eq!(c.cache_remove(&3), Some(300));
+        c.cache_reset();
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_capacity(), Some(2));
+    }
+
+    #[test]
+    fn lru_adapter_unbounded() {
+        let mut c = LruCacheAdapter::from(LruCache::unbounded());
+        c.cache_set(1, 100);
+        assert_eq!(c.cache_get_with_meta(&1).map(|e| *e.value), Some(100));
+        assert_eq!(c.cache_capacity(), None);
+        assert_eq!(c.into_inner().len(), 1);
+    }
+}
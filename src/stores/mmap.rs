@@ -0,0 +1,665 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::marker::PhantomData;
+use std::mem;
+use std::path::Path;
+
+use bytemuck::Pod;
+use memmap2::MmapMut;
+use thiserror::Error;
+
+use super::{CacheEntry, Cached};
+
+/// Magic bytes starting every cache file
+const MAGIC: [u8; 8] = *b"CACHEDMM";
+/// Version of the cache file format, bumped on every incompatible change of the format.
+///
+/// Version 1 files start with a 64 bytes header, in native byte order:
+///
+/// | offset | field                                  |
+/// |--------|----------------------------------------|
+/// | 0      | magic bytes `CACHEDMM`                 |
+/// | 8      | format version (`u32`)                 |
+/// | 12     | size of the key type (`u32`)           |
+/// | 16     | size of the value type (`u32`)         |
+/// | 20     | size of a slot (`u32`)                 |
+/// | 24     | capacity, the number of slots (`u64`)  |
+/// | 32     | number of cached entries (`u64`)       |
+///
+/// followed by `capacity` slots, each made of a state byte (empty, occupied or removed), the key
+/// and the value, padded to their alignment.
+pub const MMAP_FORMAT_VERSION: u32 = 1;
+
+const VERSION_OFFSET: usize = 8;
+const KEY_SIZE_OFFSET: usize = 12;
+const VALUE_SIZE_OFFSET: usize = 16;
+const SLOT_SIZE_OFFSET: usize = 20;
+const CAPACITY_OFFSET: usize = 24;
+const LEN_OFFSET: usize = 32;
+/// Size of the header, the slots start right after it
+const HEADER_SIZE: usize = 64;
+/// Largest supported alignment of the key and value types. The mapping is page aligned and the
+/// slots start at `HEADER_SIZE`, so every slot is aligned to up to `HEADER_SIZE` bytes.
+const MAX_ALIGN: usize = HEADER_SIZE;
+/// Number of slots probed for a key, past them a new key replaces the entry in its first slot
+const PROBES: usize = 16;
+
+const EMPTY: u8 = 0;
+const OCCUPIED: u8 = 1;
+const REMOVED: u8 = 2;
+
+#[derive(Error, Debug)]
+pub enum MmapCacheError {
+    #[error("error opening the cache file")]
+    Io(#[from] std::io::Error),
+    #[error("the cache file is already open, in this or another process")]
+    Locked,
+    #[error("not a cache file")]
+    NotACacheFile,
+    #[error("the cache file has format version {found}, expected version {expected}")]
+    Version { found: u32, expected: u32 },
+    #[error("the cache file was created for other key or value types, or another capacity")]
+    Layout,
+}
+
+/// Where the key and value are within a slot
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    key_offset: usize,
+    value_offset: usize,
+    slot_size: usize,
+}
+
+impl Layout {
+    fn of<K, V>() -> Layout {
+        let key_offset = align_up(1, mem::align_of::<K>());
+        let value_offset = align_up(key_offset + mem::size_of::<K>(), mem::align_of::<V>());
+        let slot_size = align_up(
+            value_offset + mem::size_of::<V>(),
+            mem::align_of::<K>().max(mem::align_of::<V>()),
+        );
+        Layout {
+            key_offset,
+            value_offset,
+            slot_size,
+        }
+    }
+}
+
+/// Round `n` up to a multiple of `align`, a power of two
+fn align_up(n: usize, align: usize) -> usize {
+    (n + align - 1) & !(align - 1)
+}
+
+/// Cache store persisted in a memory-mapped file, surviving process restarts
+///
+/// Stores up to `capacity` fixed-size keys and values, read and written in place in the mapped
+/// file, so lookups run at nearly in-memory speed without (de)serializing anything, e.g. to
+/// memoize numeric functions across runs.
+///
+/// Keys and values must be [`Pod`] ("plain old data", such as integers, floats and arrays or
+/// `#[repr(C)]` structs of them): they are stored as their raw bytes, keys are compared and
+/// hashed by their bytes (so `0.0` and `-0.0` are different keys), and values are returned by
+/// reference into the file. Slots are padded so keys and values are properly aligned, types
+/// aligned to more than 64 bytes aren't supported.
+///
+/// The file is a fixed-size hash table: a key is stored in one of the 16 slots following its
+/// hash, and once those are taken, it replaces the entry in the first one. The file starts
+/// with a header recording the [format version](MMAP_FORMAT_VERSION), the sizes of the key and
+/// value types and the capacity, and opening a file written with a different version, types or
+/// capacity fails rather than misreading it. Values are stored in native byte order, so a file
+/// can't be moved between machines of different endianness.
+///
+/// Changes are written back to the file by the OS, call [`MmapCache::flush`] to write them
+/// right away. The cache holds an exclusive lock on the file while it's open, so opening it
+/// again, in this or another process, fails until the cache is dropped. The lock is advisory:
+/// the file must not be modified by other means while it's open.
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::MmapCache;
+///
+/// #[cached(
+///     type = "MmapCache<u64, f64>",
+///     create = r#"{
+///         let path = std::env::temp_dir().join("cached-harmonic.cache");
+///         MmapCache::open(path, 1000).expect("error opening the cache file")
+///     }"#
+/// )]
+/// fn harmonic(n: u64) -> f64 {
+///     (1..=n).map(|i| 1.0 / i as f64).sum()
+/// }
+///
+/// assert_eq!(harmonic(2), 1.5);
+/// ```
+pub struct MmapCache<K, V> {
+    map: MmapMut,
+    // holds the lock on the file for as long as it's mapped
+    _file: File,
+    capacity: usize,
+    layout: Layout,
+    hits: u64,
+    misses: u64,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<K, V> fmt::Debug for MmapCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+impl<K: Pod, V: Pod> MmapCache<K, V> {
+    /// Open the cache file at `path`, holding up to `capacity` entries, creating it if it
+    /// doesn't exist. The file keeps the entries cached when it was last used.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be opened or mapped, is already open (see
+    /// [`MmapCacheError::Locked`]), or isn't a cache file of this
+    /// [format version](MMAP_FORMAT_VERSION) written for the same key and value types and
+    /// `capacity`. Such a file can be deleted to start over with an empty cache.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `capacity` is 0, or if the key or value type is aligned to more than
+    /// 64 bytes.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+    ) -> Result<MmapCache<K, V>, MmapCacheError> {
+        assert!(capacity > 0, "`capacity` must be greater than zero");
+        assert!(
+            mem::align_of::<K>() <= MAX_ALIGN && mem::align_of::<V>() <= MAX_ALIGN,
+            "key and value types aligned to more than {} bytes aren't supported",
+            MAX_ALIGN
+        );
+        let layout = Layout::of::<K, V>();
+        let file_size = capacity
+            .checked_mul(layout.slot_size)
+            .and_then(|slots| slots.checked_add(HEADER_SIZE))
+            .expect("cache file size overflow");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(MmapCacheError::Locked),
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        let existing_size = file.metadata()?.len();
+        let created = existing_size == 0;
+        if created {
+            file.set_len(file_size as u64)?;
+        } else if existing_size < HEADER_SIZE as u64 {
+            return Err(MmapCacheError::NotACacheFile);
+        }
+
+        // SAFETY: the mapping is only sound while no one else modifies the file. The exclusive
+        // lock taken above, held until the cache is dropped, keeps other caches from opening it.
+        // Every access goes through `map`, bounds checked.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let mut cache = MmapCache {
+            map,
+            _file: file,
+            capacity,
+            layout,
+            hits: 0,
+            misses: 0,
+            _types: PhantomData,
+        };
+        if created {
+            cache.write_header();
+        } else {
+            cache.check_header(file_size)?;
+        }
+        Ok(cache)
+    }
+
+    /// Write the changes to the file now, rather than when the OS gets to it
+    ///
+    /// # Errors
+    ///
+    /// Fails if the changes couldn't be written
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.map.flush()
+    }
+
+    fn write_header(&mut self) {
+        self.map[..MAGIC.len()].copy_from_slice(&MAGIC);
+        self.write_u32(VERSION_OFFSET, MMAP_FORMAT_VERSION);
+        self.write_u32(KEY_SIZE_OFFSET, mem::size_of::<K>() as u32);
+        self.write_u32(VALUE_SIZE_OFFSET, mem::size_of::<V>() as u32);
+        self.write_u32(SLOT_SIZE_OFFSET, self.layout.slot_size as u32);
+        self.write_u64(CAPACITY_OFFSET, self.capacity as u64);
+        self.write_u64(LEN_OFFSET, 0);
+    }
+
+    fn check_header(&self, file_size: usize) -> Result<(), MmapCacheError> {
+        if self.map[..MAGIC.len()] != MAGIC {
+            return Err(MmapCacheError::NotACacheFile);
+        }
+        let version = self.read_u32(VERSION_OFFSET);
+        if version != MMAP_FORMAT_VERSION {
+            return Err(MmapCacheError::Version {
+                found: version,
+                expected: MMAP_FORMAT_VERSION,
+            });
+        }
+        let same_layout = self.read_u32(KEY_SIZE_OFFSET) as usize == mem::size_of::<K>()
+            && self.read_u32(VALUE_SIZE_OFFSET) as usize == mem::size_of::<V>()
+            && self.read_u32(SLOT_SIZE_OFFSET) as usize == self.layout.slot_size
+            && self.read_u64(CAPACITY_OFFSET) == self.capacity as u64
+            && self.map.len() == file_size;
+        if same_layout {
+            Ok(())
+        } else {
+            Err(MmapCacheError::Layout)
+        }
+    }
+
+    /// Return the slot where `k` is stored
+    fn find(&self, k: &K) -> Option<usize> {
+        let key = bytemuck::bytes_of(k);
+        for slot in self.probes(k) {
+            match self.state(slot) {
+                EMPTY => return None,
+                OCCUPIED if self.key_bytes(slot) == key => return Some(slot),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Store `k`, which isn't cached yet, and `v`, returning their slot
+    fn insert(&mut self, k: &K, v: &V) -> usize {
+        let slot = match self.probes(k).find(|&slot| self.state(slot) != OCCUPIED) {
+            Some(slot) => {
+                self.write_u64(LEN_OFFSET, self.len() as u64 + 1);
+                slot
+            }
+            None => {
+                // evict the entry in the first slot, removing it before it's overwritten
+                let slot = self.home(k);
+                self.set_state(slot, REMOVED);
+                slot
+            }
+        };
+        let key_start = self.slot_start(slot) + self.layout.key_offset;
+        self.map[key_start..key_start + mem::size_of::<K>()].copy_from_slice(bytemuck::bytes_of(k));
+        *self.value_mut(slot) = *v;
+        // mark the slot occupied last, so an interrupted write doesn't expose a partial entry
+        self.set_state(slot, OCCUPIED);
+        slot
+    }
+
+    /// The first slot probed for `k`, derived from a hash of its bytes that stays the same
+    /// across processes (FNV-1a)
+    fn home(&self, k: &K) -> usize {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in bytemuck::bytes_of(k) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        (hash % self.capacity as u64) as usize
+    }
+
+    fn probes(&self, k: &K) -> impl Iterator<Item = usize> {
+        let home = self.home(k);
+        let capacity = self.capacity;
+        (0..PROBES.min(capacity)).map(move |n| (home + n) % capacity)
+    }
+}
+
+impl<K, V> MmapCache<K, V> {
+    fn len(&self) -> usize {
+        self.read_u64(LEN_OFFSET) as usize
+    }
+
+    fn slot_start(&self, slot: usize) -> usize {
+        HEADER_SIZE + slot * self.layout.slot_size
+    }
+
+    fn state(&self, slot: usize) -> u8 {
+        self.map[self.slot_start(slot)]
+    }
+
+    fn set_state(&mut self, slot: usize, state: u8) {
+        let start = self.slot_start(slot);
+        self.map[start] = state;
+    }
+
+    fn key_bytes(&self, slot: usize) -> &[u8] {
+        let start = self.slot_start(slot) + self.layout.key_offset;
+        &self.map[start..start + mem::size_of::<K>()]
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_ne_bytes(self.map[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn write_u32(&mut self, offset: usize, n: u32) {
+        self.map[offset..offset + 4].copy_from_slice(&n.to_ne_bytes());
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_ne_bytes(self.map[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn write_u64(&mut self, offset: usize, n: u64) {
+        self.map[offset..offset + 8].copy_from_slice(&n.to_ne_bytes());
+    }
+}
+
+impl<K, V: Pod> MmapCache<K, V> {
+    fn key(&self, slot: usize) -> K
+    where
+        K: Pod,
+    {
+        bytemuck::pod_read_unaligned(self.key_bytes(slot))
+    }
+
+    fn value(&self, slot: usize) -> &V {
+        let start = self.slot_start(slot) + self.layout.value_offset;
+        bytemuck::from_bytes(&self.map[start..start + mem::size_of::<V>()])
+    }
+
+    fn value_mut(&mut self, slot: usize) -> &mut V {
+        let start = self.slot_start(slot) + self.layout.value_offset;
+        bytemuck::from_bytes_mut(&mut self.map[start..start + mem::size_of::<V>()])
+    }
+}
+
+impl<K: Pod, V: Pod> Cached<K, V> for MmapCache<K, V> {
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        match self.find(k) {
+            Some(slot) => {
+                self.hits += 1;
+                Some(self.value(slot))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        match self.find(k) {
+            Some(slot) => {
+                self.hits += 1;
+                Some(self.value_mut(slot))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        match self.find(&k) {
+            Some(slot) => Some(mem::replace(self.value_mut(slot), v)),
+            None => {
+                self.insert(&k, &v);
+                None
+            }
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        let slot = match self.find(&k) {
+            Some(slot) => {
+                self.hits += 1;
+                slot
+            }
+            None => {
+                self.misses += 1;
+                self.insert(&k, &f())
+            }
+        };
+        self.value_mut(slot)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        let slot = self.find(k)?;
+        self.set_state(slot, REMOVED);
+        self.write_u64(LEN_OFFSET, (self.len() as u64).saturating_sub(1));
+        Some(*self.value(slot))
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        for slot in 0..self.capacity {
+            if self.state(slot) == OCCUPIED {
+                let key = self.key(slot);
+                if !f(&key, self.value_mut(slot)) {
+                    self.set_state(slot, REMOVED);
+                    self.write_u64(LEN_OFFSET, (self.len() as u64).saturating_sub(1));
+                }
+            }
+        }
+    }
+
+    fn cache_clear(&mut self) {
+        for slot in 0..self.capacity {
+            self.set_state(slot, EMPTY);
+        }
+        self.write_u64(LEN_OFFSET, 0);
+    }
+
+    fn cache_reset(&mut self) {
+        self.cache_clear();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        self.len()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = (0..self.capacity)
+            .filter(|&slot| self.state(slot) == OCCUPIED)
+            .map(|slot| (self.key(slot), *self.value(slot)))
+            .collect::<Vec<_>>();
+        snapshot.sort_by_key(|(k, _)| *k);
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.find(k).map(|slot| CacheEntry::new(self.value(slot)))
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory, removing any file left there by a previous run
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("cached-mmap-{}-{}.cache", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn basic_cache() {
+        let path = temp_path("basic_cache");
+        let mut c = MmapCache::<u64, f64>::open(&path, 10).unwrap();
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_set(1, 0.5), None);
+        assert_eq!(c.cache_get(&1), Some(&0.5));
+        assert_eq!(c.cache_set(1, 1.5), Some(0.5));
+        *c.cache_get_mut(&1).unwrap() += 1.0;
+        assert_eq!(c.cache_get_or_set_with(1, || 0.0), &2.5);
+        assert_eq!(c.cache_get_or_set_with(2, || 4.0), &4.0);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_hits(), Some(3));
+        assert_eq!(c.cache_misses(), Some(2));
+        assert_eq!(c.cache_capacity(), Some(10));
+        assert_eq!(c.cache_snapshot(), [(1, 2.5), (2, 4.0)]);
+
+        assert_eq!(c.cache_remove(&1), Some(2.5));
+        assert_eq!(c.cache_remove(&1), None);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(
+            c.cache_get_with_meta(&2).map(|entry| *entry.value),
+            Some(4.0)
+        );
+
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_get(&2), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn persists_across_opens() {
+        let path = temp_path("persists_across_opens");
+        {
+            let mut c = MmapCache::<u32, [u16; 3]>::open(&path, 100).unwrap();
+            for n in 0..50 {
+                c.cache_set(n, [n as u16; 3]);
+            }
+            c.cache_remove(&7);
+            c.flush().unwrap();
+        }
+        let mut c = MmapCache::<u32, [u16; 3]>::open(&path, 100).unwrap();
+        assert_eq!(c.cache_size(), 49);
+        assert_eq!(c.cache_get(&7), None);
+        assert_eq!(c.cache_get(&42), Some(&[42; 3]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_entries_when_full() {
+        let path = temp_path("replaces_entries_when_full");
+        let mut c = MmapCache::<u8, u64>::open(&path, 1).unwrap();
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get(&2), Some(&20));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reuses_removed_slots() {
+        let path = temp_path("reuses_removed_slots");
+        let mut c = MmapCache::<u8, u64>::open(&path, 4).unwrap();
+        for n in 0..4 {
+            c.cache_set(n, n as u64);
+        }
+        c.cache_remove(&2);
+        c.cache_set(9, 9);
+        assert_eq!(c.cache_size(), 4);
+        assert_eq!(c.cache_snapshot(), [(0, 0), (1, 1), (3, 3), (9, 9)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn locks_the_file() {
+        let path = temp_path("locks_the_file");
+        let c = MmapCache::<u64, u64>::open(&path, 10).unwrap();
+        assert!(matches!(
+            MmapCache::<u64, u64>::open(&path, 10),
+            Err(MmapCacheError::Locked)
+        ));
+        drop(c);
+        MmapCache::<u64, u64>::open(&path, 10).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tolerates_a_stale_len() {
+        let path = temp_path("tolerates_a_stale_len");
+        let mut c = MmapCache::<u64, u64>::open(&path, 10).unwrap();
+        c.cache_set(1, 10);
+        c.write_u64(LEN_OFFSET, 0);
+        assert_eq!(c.cache_remove(&1), Some(10));
+        assert_eq!(c.cache_size(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_incompatible_files() {
+        let path = temp_path("rejects_incompatible_files");
+        MmapCache::<u64, u64>::open(&path, 10).unwrap();
+        assert!(matches!(
+            MmapCache::<u64, u32>::open(&path, 10),
+            Err(MmapCacheError::Layout)
+        ));
+        assert!(matches!(
+            MmapCache::<u64, u64>::open(&path, 20),
+            Err(MmapCacheError::Layout)
+        ));
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&99u32.to_ne_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            MmapCache::<u64, u64>::open(&path, 10),
+            Err(MmapCacheError::Version {
+                found: 99,
+                expected: MMAP_FORMAT_VERSION
+            })
+        ));
+
+        std::fs::write(&path, b"not a cache").unwrap();
+        assert!(matches!(
+            MmapCache::<u64, u64>::open(&path, 10),
+            Err(MmapCacheError::NotACacheFile)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn aligns_values() {
+        let layout = Layout::of::<u8, u64>();
+        assert_eq!(layout.key_offset, 1);
+        assert_eq!(layout.value_offset, 8);
+        assert_eq!(layout.slot_size, 16);
+
+        let path = temp_path("aligns_values");
+        let mut c = MmapCache::<u8, u128>::open(&path, 3).unwrap();
+        for n in 0..3 {
+            c.cache_set(n, u128::MAX - n as u128);
+        }
+        for n in 0..3 {
+            assert_eq!(c.cache_get(&n), Some(&(u128::MAX - n as u128)));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
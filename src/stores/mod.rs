@@ -1,30 +1,69 @@
-use crate::Cached;
+use crate::{Cached, CachedRead};
 use std::cmp::Eq;
 #[cfg(feature = "async")]
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
+use std::time::Duration;
+
+use instant::Instant;
 
 #[cfg(feature = "async")]
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
 
+#[cfg(feature = "chaos")]
+mod chaos;
+mod clock;
+#[cfg(feature = "dashmap_store")]
+mod dashmap_cache;
+mod evict_after_set;
 mod expiring_value_cache;
+mod fallible;
+mod grouped;
+#[cfg(feature = "lru_store")]
+mod lru_adapter;
+#[cfg(feature = "mmap_store")]
+mod mmap;
+mod multi_level;
+mod null;
+mod partitioned;
+mod priority;
 #[cfg(feature = "redis_store")]
 mod redis;
 mod sized;
 mod timed;
 mod timed_sized;
 mod unbound;
+mod weak;
+mod windowed;
 
 #[cfg(feature = "redis_store")]
 pub use crate::stores::redis::{
     RedisCache, RedisCacheBuildError, RedisCacheBuilder, RedisCacheError,
 };
+#[cfg(feature = "chaos")]
+pub use chaos::{ChaosCache, ChaosLookup};
+pub use clock::ClockCache;
+#[cfg(feature = "dashmap_store")]
+pub use dashmap_cache::DashMapCache;
+pub use evict_after_set::EvictAfterSetCache;
 pub use expiring_value_cache::{CanExpire, ExpiringValueCache};
+pub use fallible::FallibleCache;
+pub use grouped::{invalidate_group, GroupedCache};
+#[cfg(feature = "lru_store")]
+pub use lru_adapter::LruCacheAdapter;
+#[cfg(feature = "mmap_store")]
+pub use mmap::{MmapCache, MmapCacheError, MMAP_FORMAT_VERSION};
+pub use multi_level::{MultiLevelCache, PromotionPolicy};
+pub use null::NullCache;
+pub use partitioned::{PartitionStats, PartitionedCache};
+pub use priority::PriorityCache;
 pub use sized::SizedCache;
 pub use timed::TimedCache;
 pub use timed_sized::TimedSizedCache;
 pub use unbound::UnboundCache;
+pub use weak::WeakCache;
+pub use windowed::WindowedStats;
 
 #[cfg(all(
     feature = "async",
@@ -33,7 +72,59 @@ pub use unbound::UnboundCache;
 ))]
 pub use crate::stores::redis::{AsyncRedisCache, AsyncRedisCacheBuilder};
 
-impl<K: Hash + Eq, V> Cached<K, V> for HashMap<K, V> {
+/// Return whether a value stored at `stamp` has outlived a lifespan of `seconds` at `now`.
+///
+/// Values live during the half-open interval `[stamp, stamp + seconds)`: a value expires once
+/// exactly `seconds` have elapsed, so a lifespan of `0` expires values as soon as they're stored.
+/// The timed stores and `#[once]` functions with a `time` share this check, so they agree on
+/// when a value expires.
+pub fn is_expired(stamp: Instant, seconds: u64, now: Instant) -> bool {
+    now.saturating_duration_since(stamp) >= Duration::from_secs(seconds)
+}
+
+/// A cached value along with its metadata, see [`Cached::cache_get_with_meta`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheEntry<'a, V> {
+    /// The cached value
+    pub value: &'a V,
+    /// When the value was stored, or last refreshed, for stores timestamping their values
+    pub inserted_at: Option<Instant>,
+    /// How long until the value expires, for stores expiring values by time
+    pub remaining_ttl: Option<Duration>,
+    /// How many times the value was retrieved since it was stored, for stores counting hits
+    /// per entry
+    pub hits: Option<u64>,
+}
+
+impl<'a, V> CacheEntry<'a, V> {
+    /// An entry of a store keeping no metadata
+    pub(crate) fn new(value: &'a V) -> CacheEntry<'a, V> {
+        CacheEntry {
+            value,
+            inserted_at: None,
+            remaining_ttl: None,
+            hits: None,
+        }
+    }
+}
+
+/// What [`Cached::cache_update`] did to the cached value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateResult {
+    /// A value was cached for a key without one
+    Inserted,
+    /// The cached value was replaced
+    Updated,
+    /// The cached value was removed
+    Removed,
+    /// No value was cached, and none was stored
+    Unchanged,
+}
+
+/// A plain `HashMap` is an unbounded cache without metrics or any locking, e.g. for a cache
+/// local to a function or passed to the code using it. Any hasher can be used, such as a faster
+/// non-cryptographic one for trusted keys.
+impl<K: Hash + Eq, V, S: BuildHasher> Cached<K, V> for HashMap<K, V, S> {
     fn cache_get(&mut self, k: &K) -> Option<&V> {
         self.get(k)
     }
@@ -46,25 +137,48 @@ impl<K: Hash + Eq, V> Cached<K, V> for HashMap<K, V> {
     fn cache_set(&mut self, k: K, v: V) -> Option<V> {
         self.insert(k, v)
     }
+    fn cache_extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.extend(entries);
+    }
     fn cache_remove(&mut self, k: &K) -> Option<V> {
         self.remove(k)
     }
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        self.retain(f);
+    }
     fn cache_clear(&mut self) {
         self.clear();
     }
     fn cache_reset(&mut self) {
-        *self = HashMap::new();
+        self.clear();
+        self.shrink_to_fit();
     }
     fn cache_size(&self) -> usize {
         self.len()
     }
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.get(k).map(CacheEntry::new)
+    }
 }
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for HashMap<K, V>
+impl<K, V, S> CachedAsync<K, V> for HashMap<K, V, S>
 where
     K: Hash + Eq + Clone + Send,
+    S: BuildHasher + Send,
 {
     async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
     where
@@ -109,4 +223,77 @@ mod tests {
         assert_eq!(c.cache_hits(), None);
         assert_eq!(c.cache_misses(), None);
     }
+
+    #[test]
+    fn update() {
+        let mut c: HashMap<&str, u32> = HashMap::new();
+        // insert if absent
+        let insert_if_absent = |current: Option<&u32>| match current {
+            Some(_) => None,
+            None => Some(1),
+        };
+        assert_eq!(
+            c.cache_update(&"a", insert_if_absent),
+            UpdateResult::Inserted
+        );
+        // modify
+        assert_eq!(
+            c.cache_update(&"a", |current| current.map(|n| n + 1)),
+            UpdateResult::Updated
+        );
+        assert_eq!(c.cache_get(&"a"), Some(&2));
+        assert_eq!(
+            c.cache_update(&"b", |current| current.map(|n| n + 1)),
+            UpdateResult::Unchanged
+        );
+        // conditional remove
+        let remove_if_odd = |current: Option<&u32>| current.filter(|n| *n % 2 == 0).copied();
+        assert_eq!(c.cache_update(&"a", remove_if_odd), UpdateResult::Updated);
+        c.cache_set("a", 3);
+        assert_eq!(c.cache_update(&"a", remove_if_odd), UpdateResult::Removed);
+        assert_eq!(c.cache_size(), 0);
+    }
+
+    #[test]
+    fn retain() {
+        let mut c: HashMap<u32, u32> = (1..=4).map(|n| (n, n)).collect();
+        c.cache_retain(|_, v| {
+            *v *= 10;
+            *v > 20
+        });
+        assert_eq!(c.cache_snapshot(), [(3, 30), (4, 40)]);
+    }
+
+    #[test]
+    fn expiry_boundary() {
+        let stamp = Instant::now();
+        let lifespan = Duration::from_secs(1);
+        assert!(!is_expired(stamp, 1, stamp));
+        assert!(!is_expired(
+            stamp,
+            1,
+            stamp + lifespan - Duration::from_nanos(1)
+        ));
+        assert!(is_expired(stamp, 1, stamp + lifespan));
+        // a lifespan of 0 expires values as soon as they're stored
+        assert!(is_expired(stamp, 0, stamp));
+        // a stamp from the future, e.g. from a clock going backwards, isn't expired
+        assert!(!is_expired(stamp + lifespan, 1, stamp));
+    }
+
+    #[test]
+    fn hashmap_with_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let mut c: HashMap<u32, u32, BuildHasherDefault<DefaultHasher>> = HashMap::default();
+        assert_eq!(c.cache_get_or_set_with(1, || 100), &100);
+        assert_eq!(c.cache_get_or_set_with(1, || 200), &100);
+        assert_eq!(c.cache_remove(&1), Some(100));
+        c.cache_extend(vec![(2, 200), (3, 300), (2, 201)]);
+        assert_eq!(c.cache_get(&2), Some(&201));
+        assert_eq!(c.cache_size(), 2);
+        c.cache_reset();
+        assert_eq!(c.cache_size(), 0);
+    }
 }
@@ -0,0 +1,411 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use super::{CacheEntry, Cached};
+
+/// When a [`MultiLevelCache`] copies a value found in a slower level into the faster ones
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PromotionPolicy {
+    /// Promote a value the first time it's found in a slower level
+    #[default]
+    Always,
+    /// Promote a value the second time it's found in a slower level, so values retrieved once
+    /// don't evict the values of the faster levels
+    OnSecondAccess,
+    /// Never promote values, they're only in the faster levels when they were set
+    Never,
+}
+
+/// Cache store made of three levels, from the fastest and smallest `L1` to the slowest and
+/// largest `L3`, e.g. a `SizedCache` in front of a `TimedSizedCache` in front of an `MmapCache`
+///
+/// Values are set in every level. A lookup consults the levels in order, and a value found in a
+/// slower level is copied into the faster ones according to the [`PromotionPolicy`].
+///
+/// Mutable access (`cache_get_mut`, `cache_get_or_set_with`) only keeps the value in the slowest
+/// level holding it, removing its copies from the faster levels so they can't disagree, and
+/// values computed by `cache_get_or_set_with` are only set in `L3`. Later lookups promote them.
+///
+/// ```rust
+/// use cached::stores::{MultiLevelCache, PromotionPolicy};
+/// use cached::{Cached, SizedCache, UnboundCache};
+///
+/// let mut cache = MultiLevelCache::new(
+///     SizedCache::with_size(1),
+///     SizedCache::with_size(10),
+///     UnboundCache::new(),
+///     PromotionPolicy::Always,
+/// );
+/// cache.cache_set(1, "a");
+/// // evicts 1 from the first level
+/// cache.cache_set(2, "b");
+/// assert_eq!(cache.levels().0.cache_get(&1), None);
+///
+/// // found in the second level, and promoted back to the first one
+/// assert_eq!(cache.cache_get(&1), Some(&"a"));
+/// assert_eq!(cache.levels().0.cache_get(&1), Some(&"a"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultiLevelCache<L1, L2, L3> {
+    l1: L1,
+    l2: L2,
+    l3: L3,
+    policy: PromotionPolicy,
+    // with `OnSecondAccess`, the hashes of the keys found once in a slower level
+    seen: HashSet<u64>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<L1, L2, L3> MultiLevelCache<L1, L2, L3> {
+    /// Creates a new `MultiLevelCache` from its levels, fastest first, promoting values
+    /// according to `policy`
+    pub fn new(l1: L1, l2: L2, l3: L3, policy: PromotionPolicy) -> MultiLevelCache<L1, L2, L3> {
+        MultiLevelCache {
+            l1,
+            l2,
+            l3,
+            policy,
+            seen: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return the promotion policy
+    pub fn policy(&self) -> PromotionPolicy {
+        self.policy
+    }
+
+    /// Return mutable references to the levels, fastest first
+    pub fn levels(&mut self) -> (&mut L1, &mut L2, &mut L3) {
+        (&mut self.l1, &mut self.l2, &mut self.l3)
+    }
+
+    /// Return the levels, fastest first
+    pub fn into_levels(self) -> (L1, L2, L3) {
+        (self.l1, self.l2, self.l3)
+    }
+}
+
+impl<L1, L2, L3> MultiLevelCache<L1, L2, L3> {
+    fn key_hash<K: Hash>(k: &K) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        k.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Return whether a value found in a slower level should be promoted
+    fn should_promote<K: Hash>(&mut self, k: &K) -> bool {
+        match self.policy {
+            PromotionPolicy::Always => true,
+            PromotionPolicy::Never => false,
+            PromotionPolicy::OnSecondAccess => {
+                let hash = Self::key_hash(k);
+                if self.seen.remove(&hash) {
+                    return true;
+                }
+                // keys evicted from the slower levels stay in `seen`, forget them all once they
+                // could outnumber the values, at worst delaying the promotion of a value
+                if self.seen.len() >= 1024 {
+                    self.seen.clear();
+                }
+                self.seen.insert(hash);
+                false
+            }
+        }
+    }
+
+    /// Return the level holding the value of `k` (1 to 3), promoting it into the faster levels
+    fn find<K, V>(&mut self, k: &K) -> Option<u8>
+    where
+        K: Hash + Eq + Clone,
+        V: Clone,
+        L1: Cached<K, V>,
+        L2: Cached<K, V>,
+        L3: Cached<K, V>,
+    {
+        if self.l1.cache_get(k).is_some() {
+            self.hits += 1;
+            return Some(1);
+        }
+        let (level, value) = match self.l2.cache_get(k) {
+            Some(value) => (2, value.clone()),
+            None => match self.l3.cache_get(k) {
+                Some(value) => (3, value.clone()),
+                None => {
+                    self.misses += 1;
+                    return None;
+                }
+            },
+        };
+        self.hits += 1;
+        if self.should_promote(k) {
+            if level == 3 {
+                self.l2.cache_set(k.clone(), value.clone());
+            }
+            self.l1.cache_set(k.clone(), value);
+        }
+        Some(level)
+    }
+
+    /// Return the slowest level holding the value of `k` (1 to 3), removing its copies from the
+    /// faster levels before it's mutated
+    fn find_slowest<K, V>(&mut self, k: &K) -> Option<u8>
+    where
+        L1: Cached<K, V>,
+        L2: Cached<K, V>,
+        L3: Cached<K, V>,
+    {
+        let level = if self.l3.cache_get(k).is_some() {
+            3
+        } else if self.l2.cache_get(k).is_some() {
+            2
+        } else if self.l1.cache_get(k).is_some() {
+            1
+        } else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        if level > 1 {
+            self.l1.cache_remove(k);
+        }
+        if level > 2 {
+            self.l2.cache_remove(k);
+        }
+        Some(level)
+    }
+}
+
+impl<K, V, L1, L2, L3> Cached<K, V> for MultiLevelCache<L1, L2, L3>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    L1: Cached<K, V>,
+    L2: Cached<K, V>,
+    L3: Cached<K, V>,
+{
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        match self.find(k)? {
+            1 => self.l1.cache_get(k),
+            2 => self.l2.cache_get(k),
+            _ => self.l3.cache_get(k),
+        }
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        match self.find_slowest(k)? {
+            1 => self.l1.cache_get_mut(k),
+            2 => self.l2.cache_get_mut(k),
+            _ => self.l3.cache_get_mut(k),
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        match self.find_slowest(&k) {
+            Some(1) => self.l1.cache_get_or_set_with(k, f),
+            Some(2) => self.l2.cache_get_or_set_with(k, f),
+            _ => self.l3.cache_get_or_set_with(k, f),
+        }
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        if self.policy == PromotionPolicy::OnSecondAccess {
+            self.seen.remove(&Self::key_hash(&k));
+        }
+        let previous3 = self.l3.cache_set(k.clone(), v.clone());
+        let previous2 = self.l2.cache_set(k.clone(), v.clone());
+        let previous1 = self.l1.cache_set(k, v);
+        previous1.or(previous2).or(previous3)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        if self.policy == PromotionPolicy::OnSecondAccess {
+            self.seen.remove(&Self::key_hash(k));
+        }
+        let previous1 = self.l1.cache_remove(k);
+        let previous2 = self.l2.cache_remove(k);
+        let previous3 = self.l3.cache_remove(k);
+        previous1.or(previous2).or(previous3)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        self.l1.cache_retain(&mut f);
+        self.l2.cache_retain(&mut f);
+        self.l3.cache_retain(&mut f);
+    }
+
+    fn cache_clear(&mut self) {
+        self.l1.cache_clear();
+        self.l2.cache_clear();
+        self.l3.cache_clear();
+        self.seen.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.l1.cache_reset();
+        self.l2.cache_reset();
+        self.l3.cache_reset();
+        self.seen.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.l1.cache_reset_metrics();
+        self.l2.cache_reset_metrics();
+        self.l3.cache_reset_metrics();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// The size of the largest level. The levels overlap, so it's a lower bound of the number
+    /// of cached values: see `cache_snapshot` for the values of every level.
+    fn cache_size(&self) -> usize {
+        self.l1
+            .cache_size()
+            .max(self.l2.cache_size())
+            .max(self.l3.cache_size())
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        // the values of the faster levels take precedence
+        let mut values = BTreeMap::new();
+        for (k, v) in self
+            .l3
+            .cache_snapshot()
+            .into_iter()
+            .chain(self.l2.cache_snapshot())
+            .chain(self.l1.cache_snapshot())
+        {
+            values.insert(k, v);
+        }
+        values.into_iter().collect()
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.l1
+            .cache_get_with_meta(k)
+            .or_else(|| self.l2.cache_get_with_meta(k))
+            .or_else(|| self.l3.cache_get_with_meta(k))
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use crate::{SizedCache, UnboundCache};
+
+    type Levels =
+        MultiLevelCache<SizedCache<u32, u32>, SizedCache<u32, u32>, UnboundCache<u32, u32>>;
+
+    fn levels(policy: PromotionPolicy) -> Levels {
+        MultiLevelCache::new(
+            SizedCache::with_size(1),
+            SizedCache::with_size(2),
+            UnboundCache::new(),
+            policy,
+        )
+    }
+
+    /// Set 1, 2 and 3, leaving 1 only in the third level
+    fn fill(c: &mut Levels) {
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        c.cache_set(3, 30);
+    }
+
+    #[test]
+    fn always_promotes() {
+        let mut c = levels(PromotionPolicy::Always);
+        fill(&mut c);
+        assert_eq!(c.levels().1.cache_get(&1), None);
+
+        assert_eq!(c.cache_get(&1), Some(&10));
+        let (l1, l2, _) = c.levels();
+        assert_eq!(l1.cache_get(&1), Some(&10));
+        assert_eq!(l2.cache_get(&1), Some(&10));
+        assert_eq!(c.cache_hits(), Some(1));
+
+        assert_eq!(c.cache_get(&4), None);
+        assert_eq!(c.cache_misses(), Some(1));
+    }
+
+    #[test]
+    fn promotes_on_second_access() {
+        let mut c = levels(PromotionPolicy::OnSecondAccess);
+        fill(&mut c);
+
+        assert_eq!(c.cache_get(&1), Some(&10));
+        assert_eq!(c.levels().0.cache_get(&1), None);
+        assert_eq!(c.cache_get(&1), Some(&10));
+        assert_eq!(c.levels().0.cache_get(&1), Some(&10));
+        assert_eq!(c.levels().1.cache_get(&1), Some(&10));
+    }
+
+    #[test]
+    fn never_promotes() {
+        let mut c = levels(PromotionPolicy::Never);
+        fill(&mut c);
+
+        for _ in 0..3 {
+            assert_eq!(c.cache_get(&1), Some(&10));
+        }
+        let (l1, l2, _) = c.levels();
+        assert_eq!(l1.cache_get(&1), None);
+        assert_eq!(l2.cache_get(&1), None);
+        assert_eq!(c.cache_hits(), Some(3));
+    }
+
+    #[test]
+    fn mutable_access_keeps_one_copy() {
+        let mut c = levels(PromotionPolicy::Never);
+        fill(&mut c);
+        *c.cache_get_mut(&3).unwrap() += 1;
+        assert_eq!(c.cache_get(&3), Some(&31));
+        let (l1, l2, l3) = c.levels();
+        assert_eq!(l1.cache_get(&3), None);
+        assert_eq!(l2.cache_get(&3), None);
+        assert_eq!(l3.cache_get(&3), Some(&31));
+
+        assert_eq!(c.cache_get_or_set_with(4, || 40), &40);
+        assert_eq!(c.levels().2.cache_get(&4), Some(&40));
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &10);
+    }
+
+    #[test]
+    fn remove_and_snapshot() {
+        let mut c = levels(PromotionPolicy::Always);
+        fill(&mut c);
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_snapshot(), vec![(1, 10), (2, 20), (3, 30)]);
+
+        assert_eq!(c.cache_remove(&3), Some(30));
+        assert_eq!(c.cache_remove(&1), Some(10));
+        assert_eq!(c.cache_get(&3), None);
+        assert_eq!(c.cache_snapshot(), vec![(2, 20)]);
+
+        c.cache_reset();
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_misses(), Some(0));
+    }
+}
@@ -0,0 +1,107 @@
+use std::marker::PhantomData;
+
+use crate::Cached;
+
+/// Cache store caching nothing, e.g. to run a cached function without its cache
+///
+/// Every lookup misses and values are dropped when set. `cache_get_or_set_with` always calls
+/// the function, keeping its value only until the next call.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct NullCache<K, V> {
+    value: Option<V>,
+    misses: u64,
+    _key: PhantomData<K>,
+}
+
+impl<K, V> NullCache<K, V> {
+    /// Creates a store caching nothing
+    pub fn new() -> NullCache<K, V> {
+        NullCache {
+            value: None,
+            misses: 0,
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for NullCache<K, V> {
+    fn default() -> Self {
+        NullCache::new()
+    }
+}
+
+impl<K, V> Cached<K, V> for NullCache<K, V> {
+    fn cache_get(&mut self, _k: &K) -> Option<&V> {
+        self.misses += 1;
+        None
+    }
+
+    fn cache_get_mut(&mut self, _k: &K) -> Option<&mut V> {
+        self.misses += 1;
+        None
+    }
+
+    fn cache_set(&mut self, _k: K, _v: V) -> Option<V> {
+        None
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, _k: K, f: F) -> &mut V {
+        self.misses += 1;
+        self.value.insert(f())
+    }
+
+    fn cache_remove(&mut self, _k: &K) -> Option<V> {
+        None
+    }
+
+    fn cache_clear(&mut self) {
+        self.value = None;
+    }
+
+    fn cache_reset(&mut self) {
+        self.value = None;
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        0
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_nothing() {
+        let mut c = NullCache::new();
+        assert_eq!(c.cache_set(1, 10), None);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &11);
+        assert_eq!(c.cache_get_or_set_with(1, || 12), &12);
+        assert_eq!(c.cache_remove(&1), None);
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(3));
+        c.cache_reset_metrics();
+        assert_eq!(c.cache_misses(), Some(0));
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::{CacheEntry, Cached, SizedCache};
+use crate::lru_list::LRUList;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// The size and metrics of a partition of a [`PartitionedCache`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PartitionStats {
+    /// The number of cached values in the partition
+    pub size: usize,
+    /// The number of times a value of the partition was retrieved
+    pub hits: u64,
+    /// The number of times a value of the partition was unable to be retrieved
+    pub misses: u64,
+}
+
+/// Cache store split into partitions, each a [`SizedCache`] with its own size limit
+///
+/// The partition of a key is read with a partition function, e.g. the tenant of a per-tenant
+/// lookup, so filling up a partition only evicts the least recently used values of that
+/// partition, never the values of the others. The number of partitions can be bounded too,
+/// dropping the least recently used partition (and all its values) when a new one is needed.
+///
+/// Note: This cache is in-memory only
+///
+/// ```rust
+/// use cached::{Cached, PartitionedCache};
+///
+/// // up to 2 values per tenant
+/// let mut cache = PartitionedCache::new(|(tenant, _): &(u32, u32)| *tenant, 2);
+/// cache.cache_set((1, 1), "a");
+/// cache.cache_set((1, 2), "b");
+/// cache.cache_set((2, 1), "c");
+/// // evicts (1, 1), the values of tenant 2 are kept
+/// cache.cache_set((1, 3), "d");
+/// assert_eq!(cache.cache_get(&(1, 1)), None);
+/// assert_eq!(cache.cache_get(&(2, 1)), Some(&"c"));
+/// assert_eq!(cache.partition_stats()[0].1.size, 1);
+/// ```
+#[derive(Clone)]
+pub struct PartitionedCache<P, K, V> {
+    partition: Arc<dyn Fn(&K) -> P + Send + Sync>,
+    // each partition, along with the index of its key in `order`
+    partitions: HashMap<P, (usize, SizedCache<K, V>)>,
+    order: LRUList<P>,
+    size: usize,
+    max_partitions: Option<usize>,
+    // the metrics of the dropped partitions, and the misses in partitions that don't exist
+    hits: u64,
+    misses: u64,
+}
+
+impl<P, K, V> fmt::Debug for PartitionedCache<P, K, V>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartitionedCache")
+            .field("partitions", &self.order)
+            .field("size", &self.size)
+            .field("max_partitions", &self.max_partitions)
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+impl<P: Hash + Eq + Clone, K: Hash + Eq + Clone, V> PartitionedCache<P, K, V> {
+    /// Creates a new `PartitionedCache` storing up to `size` values in each partition, the
+    /// partition of a key being returned by `partition`
+    ///
+    /// Panics if `size` is zero.
+    pub fn new<F>(partition: F, size: usize) -> PartitionedCache<P, K, V>
+    where
+        F: Fn(&K) -> P + Send + Sync + 'static,
+    {
+        if size == 0 {
+            panic!("`size` of `PartitionedCache` must be greater than zero.")
+        }
+        PartitionedCache {
+            partition: Arc::new(partition),
+            partitions: HashMap::new(),
+            order: LRUList::with_capacity(0),
+            size,
+            max_partitions: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Keep up to `max` partitions, dropping the least recently used partition when a value
+    /// of a new one is stored. Partitions are used by any lookup or insert of their values.
+    ///
+    /// Panics if `max` is zero.
+    pub fn with_max_partitions(mut self, max: usize) -> PartitionedCache<P, K, V> {
+        if max == 0 {
+            panic!("`max_partitions` of `PartitionedCache` must be greater than zero.")
+        }
+        self.max_partitions = Some(max);
+        self
+    }
+
+    /// Return the partition of `key`
+    pub fn partition_of(&self, key: &K) -> P {
+        (self.partition)(key)
+    }
+
+    /// Return the size and metrics of every partition, most recently used first
+    pub fn partition_stats(&self) -> Vec<(&P, PartitionStats)> {
+        self.order
+            .iter()
+            .map(|p| {
+                let cache = &self.partitions[p].1;
+                let stats = PartitionStats {
+                    size: cache.cache_size(),
+                    hits: cache.hits,
+                    misses: cache.misses,
+                };
+                (p, stats)
+            })
+            .collect()
+    }
+
+    /// Remove the partition `partition` and all its values, returning how many values it held
+    pub fn clear_partition(&mut self, partition: &P) -> usize {
+        match self.partitions.remove(partition) {
+            Some((index, cache)) => {
+                self.order.remove(index);
+                self.keep_metrics(&cache);
+                cache.cache_size()
+            }
+            None => 0,
+        }
+    }
+
+    /// Keep the metrics of a partition being dropped
+    fn keep_metrics(&mut self, cache: &SizedCache<K, V>) {
+        self.hits += cache.hits;
+        self.misses += cache.misses;
+    }
+
+    /// Return the cache of `partition` if it exists, making it the most recently used one
+    fn touch(&mut self, partition: &P) -> Option<&mut SizedCache<K, V>> {
+        let (index, cache) = self.partitions.get_mut(partition)?;
+        self.order.move_to_front(*index);
+        Some(cache)
+    }
+
+    /// Return the cache of `partition`, making it the most recently used one. A new partition
+    /// drops the least recently used one first when there are already `max_partitions`.
+    fn partition_mut(&mut self, partition: P) -> &mut SizedCache<K, V> {
+        if let Some((index, _)) = self.partitions.get(&partition) {
+            self.order.move_to_front(*index);
+        } else {
+            if Some(self.partitions.len()) == self.max_partitions {
+                let dropped = self.order.remove(self.order.back());
+                if let Some((_, cache)) = self.partitions.remove(&dropped) {
+                    self.keep_metrics(&cache);
+                }
+            }
+            let index = self.order.push_front(partition.clone());
+            let cache = SizedCache::with_size(self.size);
+            self.partitions.insert(partition.clone(), (index, cache));
+        }
+        &mut self
+            .partitions
+            .get_mut(&partition)
+            .expect("partition exists")
+            .1
+    }
+}
+
+impl<P, K, V> Cached<K, V> for PartitionedCache<P, K, V>
+where
+    P: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn cache_get(&mut self, k: &K) -> Option<&V> {
+        let partition = self.partition_of(k);
+        if !self.partitions.contains_key(&partition) {
+            self.misses += 1;
+            return None;
+        }
+        self.touch(&partition)?.cache_get(k)
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut V> {
+        let partition = self.partition_of(k);
+        if !self.partitions.contains_key(&partition) {
+            self.misses += 1;
+            return None;
+        }
+        self.touch(&partition)?.cache_get_mut(k)
+    }
+
+    fn cache_set(&mut self, k: K, v: V) -> Option<V> {
+        let partition = self.partition_of(&k);
+        self.partition_mut(partition).cache_set(k, v)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, k: K, f: F) -> &mut V {
+        let partition = self.partition_of(&k);
+        self.partition_mut(partition).cache_get_or_set_with(k, f)
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        let partition = self.partition_of(k);
+        self.partitions.get_mut(&partition)?.1.cache_remove(k)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F)
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        for (_, cache) in self.partitions.values_mut() {
+            cache.cache_retain(&mut f);
+        }
+    }
+
+    fn cache_clear(&mut self) {
+        for (_, cache) in self.partitions.values() {
+            self.hits += cache.hits;
+            self.misses += cache.misses;
+        }
+        self.partitions.clear();
+        self.order.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.cache_clear();
+        self.partitions = HashMap::new();
+        self.order = LRUList::with_capacity(0);
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        for (_, cache) in self.partitions.values_mut() {
+            cache.cache_reset_metrics();
+        }
+    }
+
+    fn cache_size(&self) -> usize {
+        self.partitions
+            .values()
+            .map(|(_, cache)| cache.cache_size())
+            .sum()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .partitions
+            .values()
+            .flat_map(|(_, cache)| cache.cache_snapshot())
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        let partition = self.partition_of(k);
+        self.partitions.get(&partition)?.1.cache_get_with_meta(k)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        let hits = self.partitions.values().map(|(_, cache)| cache.hits);
+        Some(self.hits + hits.sum::<u64>())
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        let misses = self.partitions.values().map(|(_, cache)| cache.misses);
+        Some(self.misses + misses.sum::<u64>())
+    }
+
+    /// The size of every partition times the number of partitions, `None` when the number of
+    /// partitions isn't bounded
+    fn cache_capacity(&self) -> Option<usize> {
+        self.max_partitions.map(|max| max * self.size)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<P, K, V> CachedAsync<K, V> for PartitionedCache<P, K, V>
+where
+    P: Hash + Eq + Clone + Send,
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        let partition = self.partition_of(&k);
+        self.partition_mut(partition).get_or_set_with(k, f).await
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        let partition = self.partition_of(&k);
+        self.partition_mut(partition)
+            .try_get_or_set_with(k, f)
+            .await
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    fn tenant_cache(size: usize) -> PartitionedCache<u32, (u32, u32), u32> {
+        PartitionedCache::new(|(tenant, _): &(u32, u32)| *tenant, size)
+    }
+
+    #[test]
+    fn partitions_evict_separately() {
+        let mut c = tenant_cache(2);
+        c.cache_set((1, 1), 11);
+        c.cache_set((2, 1), 21);
+        for id in 2..10 {
+            c.cache_set((1, id), id);
+        }
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_get(&(2, 1)), Some(&21));
+        assert_eq!(c.cache_get(&(1, 1)), None);
+        assert_eq!(c.cache_get(&(3, 1)), None);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+
+        let stats = c.partition_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats[0],
+            (
+                &1,
+                PartitionStats {
+                    size: 2,
+                    hits: 0,
+                    misses: 1
+                }
+            )
+        );
+        assert_eq!(c.cache_remove(&(1, 9)), Some(9));
+        assert_eq!(c.clear_partition(&1), 1);
+        assert_eq!(c.clear_partition(&1), 0);
+        assert_eq!(c.cache_size(), 1);
+        // the metrics of dropped partitions are kept
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+
+    #[test]
+    fn max_partitions() {
+        let mut c = tenant_cache(2).with_max_partitions(2);
+        assert_eq!(c.cache_capacity(), Some(4));
+        c.cache_set((1, 1), 11);
+        c.cache_set((2, 1), 21);
+        assert_eq!(c.cache_get(&(1, 1)), Some(&11));
+        // drops the least recently used partition
+        c.cache_set((3, 1), 31);
+        assert_eq!(c.cache_get(&(2, 1)), None);
+        assert_eq!(c.cache_get_or_set_with((1, 2), || 12), &12);
+        let partitions = c.partition_stats().into_iter().map(|(p, _)| *p);
+        assert_eq!(partitions.collect::<Vec<_>>(), [1, 3]);
+
+        c.cache_retain(|_, v| *v != 12);
+        assert_eq!(c.cache_snapshot(), [((1, 1), 11), ((3, 1), 31)]);
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+        assert!(c.partition_stats().is_empty());
+        assert_eq!(c.cache_hits(), Some(1));
+    }
+}
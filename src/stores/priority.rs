@@ -0,0 +1,361 @@
+use super::{CacheEntry, Cached};
+use std::cmp::Eq;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// Priority Cache
+///
+/// Stores up to a specified size, then evicts the entry with the lowest priority, rather than
+/// the oldest one like a [`SizedCache`](crate::SizedCache). Among entries of the same priority,
+/// the one set first is evicted first. A new entry is always stored, even when its priority is
+/// lower than the priorities of the entries it competes with.
+///
+/// The priority of an entry is computed from its key and value by the function given to
+/// [`PriorityCache::with_size`] when it's set with [`Cached::cache_set`], or given explicitly to
+/// [`PriorityCache::cache_set_with_priority`]. Changing a value through
+/// [`Cached::cache_get_mut`] keeps its priority.
+///
+/// ```rust
+/// use cached::proc_macro::cached;
+/// use cached::PriorityCache;
+///
+/// #[derive(Clone)]
+/// struct Page {
+///     body: String,
+///     // pages that are expensive to render are worth keeping
+///     render_cost: u32,
+/// }
+///
+/// #[cached(
+///     type = "PriorityCache<String, Page, u32>",
+///     create = "{ PriorityCache::with_size(100, |_, page: &Page| page.render_cost) }"
+/// )]
+/// fn render(path: String) -> Page {
+///     Page {
+///         render_cost: path.len() as u32,
+///         body: format!("<h1>{}</h1>", path),
+///     }
+/// }
+///
+/// assert_eq!(render("/about".to_string()).body, "<h1>/about</h1>");
+/// ```
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct PriorityCache<K, V, P> {
+    pub(super) store: HashMap<K, Slot<V, P>>,
+    // the keys by priority, then by the order they were set in
+    pub(super) order: BTreeMap<(P, u64), K>,
+    pub(super) priority: fn(&K, &V) -> P,
+    pub(super) next_seq: u64,
+    pub(super) capacity: usize,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Slot<V, P> {
+    value: V,
+    priority: P,
+    seq: u64,
+}
+
+impl<K: Hash + Eq + Clone, V, P: Ord + Clone> PriorityCache<K, V, P> {
+    /// Creates a new `PriorityCache` with a given size limit and pre-allocated backing data,
+    /// computing the priority of the entries set with [`Cached::cache_set`] with `priority`
+    pub fn with_size(size: usize, priority: fn(&K, &V) -> P) -> PriorityCache<K, V, P> {
+        if size == 0 {
+            panic!("`size` of `PriorityCache` must be greater than zero.")
+        }
+        PriorityCache {
+            store: HashMap::with_capacity(size),
+            order: BTreeMap::new(),
+            priority,
+            next_seq: 0,
+            capacity: size,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Insert a key, value pair with the given priority rather than the computed one, returning
+    /// the value it replaces. Evicts the entry with the lowest priority if the cache is full.
+    pub fn cache_set_with_priority(&mut self, key: K, val: V, priority: P) -> Option<V> {
+        let previous = self.remove_entry(&key);
+        if previous.is_none() && self.store.len() >= self.capacity {
+            self.evict();
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.order.insert((priority.clone(), seq), key.clone());
+        self.store.insert(
+            key,
+            Slot {
+                value: val,
+                priority,
+                seq,
+            },
+        );
+        previous
+    }
+
+    /// Return the priority of the entry of `key`, without counting a hit or miss
+    pub fn priority_of(&self, key: &K) -> Option<&P> {
+        self.store.get(key).map(|slot| &slot.priority)
+    }
+
+    /// Return an iterator of keys in the order they would be evicted in, lowest priority first
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.order.values()
+    }
+
+    fn set(&mut self, key: K, val: V) -> Option<V> {
+        let priority = (self.priority)(&key, &val);
+        self.cache_set_with_priority(key, val, priority)
+    }
+
+    /// Remove the entry with the lowest priority
+    fn evict(&mut self) {
+        let lowest = self.order.keys().next().cloned();
+        if let Some(lowest) = lowest {
+            if let Some(key) = self.order.remove(&lowest) {
+                self.store.remove(&key);
+            }
+        }
+    }
+
+    fn remove_entry(&mut self, key: &K) -> Option<V> {
+        let slot = self.store.remove(key)?;
+        self.order.remove(&(slot.priority, slot.seq));
+        Some(slot.value)
+    }
+
+    fn count(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, P: Ord + Clone> Cached<K, V> for PriorityCache<K, V, P> {
+    fn cache_get(&mut self, key: &K) -> Option<&V> {
+        let hit = self.store.contains_key(key);
+        self.count(hit);
+        self.store.get(key).map(|slot| &slot.value)
+    }
+
+    fn cache_get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let hit = self.store.contains_key(key);
+        self.count(hit);
+        self.store.get_mut(key).map(|slot| &mut slot.value)
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.set(key, val)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let hit = self.store.contains_key(&key);
+        self.count(hit);
+        if !hit {
+            self.set(key.clone(), f());
+        }
+        &mut self
+            .store
+            .get_mut(&key)
+            .expect("PriorityCache lost the entry it just set")
+            .value
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<V> {
+        self.remove_entry(k)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let order = &mut self.order;
+        self.store.retain(|key, slot| {
+            let keep = f(key, &mut slot.value);
+            if !keep {
+                order.remove(&(slot.priority.clone(), slot.seq));
+            }
+            keep
+        });
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.order.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.store = HashMap::with_capacity(self.capacity);
+        self.order.clear();
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+        self.hits = 0;
+    }
+
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .store
+            .iter()
+            .map(|(key, slot)| (key.clone(), slot.value.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.store.get(k).map(|slot| CacheEntry::new(&slot.value))
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+/// Inserts the entries in order, like [`Cached::cache_extend`]
+impl<K: Hash + Eq + Clone, V, P: Ord + Clone> Extend<(K, V)> for PriorityCache<K, V, P> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V, P> CachedAsync<K, V> for PriorityCache<K, V, P>
+where
+    K: Hash + Eq + Clone + Send,
+    P: Ord + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        let hit = self.store.contains_key(&k);
+        self.count(hit);
+        if !hit {
+            let val = f().await;
+            self.set(k.clone(), val);
+        }
+        &mut self
+            .store
+            .get_mut(&k)
+            .expect("PriorityCache lost the entry it just set")
+            .value
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        let hit = self.store.contains_key(&k);
+        self.count(hit);
+        if !hit {
+            let val = f().await?;
+            self.set(k.clone(), val);
+        }
+        Ok(&mut self
+            .store
+            .get_mut(&k)
+            .expect("PriorityCache lost the entry it just set")
+            .value)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_cache() {
+        // the value is its own priority
+        let mut c = PriorityCache::with_size(3, |_, v: &u32| *v);
+        assert!(c.cache_get(&1).is_none());
+        assert!(c.cache_set(1, 30).is_none());
+        assert!(c.cache_set(2, 10).is_none());
+        assert!(c.cache_set(3, 20).is_none());
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [2, 3, 1]);
+
+        // the lowest priority is evicted, even though 1 is the oldest
+        assert!(c.cache_set(4, 40).is_none());
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(c.cache_get(&1), Some(&30));
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [3, 1, 4]);
+
+        // setting a value again recomputes its priority
+        assert_eq!(c.cache_set(4, 5), Some(40));
+        assert_eq!(c.priority_of(&4), Some(&5));
+        assert!(c.cache_set(5, 50).is_none());
+        assert!(c.cache_get(&4).is_none());
+
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_capacity(), Some(3));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(3));
+    }
+
+    #[test]
+    fn explicit_priority_and_ties() {
+        let mut c = PriorityCache::with_size(2, |_, _: &&str| 0);
+        c.cache_set_with_priority(1, "keep", 10);
+        c.cache_set(2, "first");
+        c.cache_set(3, "second");
+        // 2 and 3 have the same priority, so 2, set first, was evicted
+        assert_eq!(c.cache_snapshot(), vec![(1, "keep"), (3, "second")]);
+
+        // changing a value keeps its priority
+        *c.cache_get_mut(&3).unwrap() = "changed";
+        assert_eq!(c.priority_of(&3), Some(&0));
+        assert_eq!(*c.cache_get_or_set_with(4, || "new"), "new");
+        assert_eq!(*c.cache_get_or_set_with(4, || "newer"), "new");
+        assert_eq!(c.cache_snapshot(), vec![(1, "keep"), (4, "new")]);
+    }
+
+    #[test]
+    fn remove_and_retain() {
+        let mut c = PriorityCache::with_size(3, |k: &u32, _: &u32| *k);
+        c.cache_extend([(1, 100), (2, 200), (3, 300)]);
+        assert_eq!(c.cache_remove(&2), Some(200));
+        assert_eq!(c.cache_remove(&2), None);
+        c.cache_retain(|k, v| {
+            *v += 1;
+            *k == 3
+        });
+        assert_eq!(c.cache_snapshot(), vec![(3, 301)]);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [3]);
+        assert_eq!(c.cache_get_with_meta(&3).map(|e| *e.value), Some(301));
+
+        c.cache_reset();
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.key_order().count(), 0);
+    }
+}
@@ -9,6 +9,8 @@ pub struct RedisCacheBuilder<K, V> {
     refresh: bool,
     namespace: String,
     prefix: String,
+    version: Option<String>,
+    key_encoder: Option<KeyEncoder<K>>,
     connection_string: Option<String>,
     pool_max_size: Option<u32>,
     pool_min_idle: Option<u32>,
@@ -20,6 +22,8 @@ pub struct RedisCacheBuilder<K, V> {
 
 const ENV_KEY: &str = "CACHED_REDIS_CONNECTION_STRING";
 const DEFAULT_NAMESPACE: &str = "cached-redis-store:";
+/// Number of keys requested per `SCAN` (and removed per `DEL`) when clearing a prefix
+const SCAN_BATCH_SIZE: usize = 500;
 
 use thiserror::Error;
 
@@ -48,6 +52,8 @@ where
             refresh: false,
             namespace: DEFAULT_NAMESPACE.to_string(),
             prefix: prefix.as_ref().to_string(),
+            version: None,
+            key_encoder: None,
             connection_string: None,
             pool_max_size: None,
             pool_min_idle: None,
@@ -88,6 +94,26 @@ where
         self
     }
 
+    /// Set the version of cached values. Used to generate keys formatted as:
+    /// `{namespace}{prefix}{version}:{key}`, so values cached under another
+    /// version are no longer reachable once the version is bumped. They're left
+    /// to expire, or can be removed with `RedisCache::clear_namespace`.
+    pub fn set_version<S: ToString>(mut self, version: S) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Set the function encoding the `{key}` part of cache keys. Defaults to the
+    /// `Display` output of the key. Encoding with
+    /// [`CacheKeyEncode`](crate::keys::CacheKeyEncode) instead keeps keys stable
+    /// across changes to that output:
+    /// `.set_key_encoder(<K as cached::keys::CacheKeyEncode>::encode)`.
+    /// Keys encoded otherwise aren't found once the encoder is changed.
+    pub fn set_key_encoder(mut self, encoder: fn(&K) -> Vec<u8>) -> Self {
+        self.key_encoder = Some(encoder);
+        self
+    }
+
     /// Set the connection string for redis
     pub fn set_connection_string(mut self, cs: &str) -> Self {
         self.connection_string = Some(cs.to_string());
@@ -171,6 +197,8 @@ where
             pool: self.create_pool()?,
             namespace: self.namespace,
             prefix: self.prefix,
+            version: self.version,
+            key_encoder: self.key_encoder,
             _phantom_k: self._phantom_k,
             _phantom_v: self._phantom_v,
         })
@@ -186,6 +214,8 @@ pub struct RedisCache<K, V> {
     pub(super) refresh: bool,
     pub(super) namespace: String,
     pub(super) prefix: String,
+    pub(super) version: Option<String>,
+    key_encoder: Option<KeyEncoder<K>>,
     connection_string: String,
     pool: r2d2::Pool<redis::Client>,
     _phantom_k: PhantomData<K>,
@@ -203,14 +233,83 @@ where
         RedisCacheBuilder::new(prefix, seconds)
     }
 
-    fn generate_key(&self, key: &K) -> String {
-        format!("{}{}{}", self.namespace, self.prefix, key)
+    fn generate_key(&self, key: &K) -> Vec<u8> {
+        generate_key(
+            &self.namespace,
+            &self.prefix,
+            self.version.as_deref(),
+            self.key_encoder,
+            key,
+        )
     }
 
     /// Return the redis connection string used
     pub fn connection_string(&self) -> String {
         self.connection_string.clone()
     }
+
+    /// Remove every value cached under this cache's `{namespace}{prefix}`, whatever
+    /// their version, returning the number of keys removed. Keys are found with
+    /// `SCAN` and removed in batches, so other clients aren't blocked while a
+    /// large prefix is cleared, but values set concurrently may be missed.
+    pub fn clear_namespace(&self) -> Result<u64, RedisCacheError> {
+        let mut conn = self.pool.get()?;
+        let pattern = scan_pattern(&self.namespace, &self.prefix);
+        let mut cursor = 0u64;
+        let mut removed = 0;
+        loop {
+            let (next, keys): (u64, Vec<Vec<u8>>) = redis::cmd("SCAN")
+                .cursor_arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_BATCH_SIZE)
+                .query(&mut *conn)?;
+            if !keys.is_empty() {
+                let n: u64 = redis::cmd("DEL").arg(keys).query(&mut *conn)?;
+                removed += n;
+            }
+            if next == 0 {
+                return Ok(removed);
+            }
+            cursor = next;
+        }
+    }
+}
+
+/// Encodes the `{key}` part of cache keys, see `RedisCacheBuilder::set_key_encoder`
+type KeyEncoder<K> = fn(&K) -> Vec<u8>;
+
+fn generate_key<K: Display>(
+    namespace: &str,
+    prefix: &str,
+    version: Option<&str>,
+    key_encoder: Option<KeyEncoder<K>>,
+    key: &K,
+) -> Vec<u8> {
+    let mut generated = match version {
+        Some(version) => format!("{}{}{}:", namespace, prefix, version),
+        None => format!("{}{}", namespace, prefix),
+    }
+    .into_bytes();
+    match key_encoder {
+        Some(encode) => generated.extend(encode(key)),
+        None => generated.extend(key.to_string().into_bytes()),
+    }
+    generated
+}
+
+/// `SCAN MATCH` pattern of every key under `{namespace}{prefix}`
+fn scan_pattern(namespace: &str, prefix: &str) -> String {
+    let mut pattern = String::new();
+    for c in namespace.chars().chain(prefix.chars()) {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
 }
 
 #[derive(Error, Debug)]
@@ -281,7 +380,7 @@ where
 
         let val = CachedRedisValue::new(val);
         pipe.get(key.clone());
-        pipe.set_ex::<String, String>(
+        pipe.set_ex::<Vec<u8>, String>(
             key,
             serde_json::to_string(&val)
                 .map_err(|e| RedisCacheError::CacheSerializationError { error: e })?,
@@ -310,7 +409,7 @@ where
         let key = self.generate_key(key);
 
         pipe.get(key.clone());
-        pipe.del::<String>(key).ignore();
+        pipe.del::<Vec<u8>>(key).ignore();
         let res: (Option<String>,) = pipe.query(&mut *conn)?;
         match res.0 {
             None => Ok(None),
@@ -356,6 +455,8 @@ mod async_redis {
         refresh: bool,
         namespace: String,
         prefix: String,
+        version: Option<String>,
+        key_encoder: Option<KeyEncoder<K>>,
         connection_string: Option<String>,
         _phantom_k: PhantomData<K>,
         _phantom_v: PhantomData<V>,
@@ -373,6 +474,8 @@ mod async_redis {
                 refresh: false,
                 namespace: DEFAULT_NAMESPACE.to_string(),
                 prefix: prefix.as_ref().to_string(),
+                version: None,
+                key_encoder: None,
                 connection_string: None,
                 _phantom_k: Default::default(),
                 _phantom_v: Default::default(),
@@ -409,6 +512,22 @@ mod async_redis {
             self
         }
 
+        /// Set the version of cached values. Used to generate keys formatted as:
+        /// `{namespace}{prefix}{version}:{key}`, so values cached under another
+        /// version are no longer reachable once the version is bumped. They're left
+        /// to expire, or can be removed with `AsyncRedisCache::clear_namespace`.
+        pub fn set_version<S: ToString>(mut self, version: S) -> Self {
+            self.version = Some(version.to_string());
+            self
+        }
+
+        /// Set the function encoding the `{key}` part of cache keys, see
+        /// [`RedisCacheBuilder::set_key_encoder`]
+        pub fn set_key_encoder(mut self, encoder: fn(&K) -> Vec<u8>) -> Self {
+            self.key_encoder = Some(encoder);
+            self
+        }
+
         /// Set the connection string for redis
         pub fn set_connection_string(mut self, cs: &str) -> Self {
             self.connection_string = Some(cs.to_string());
@@ -445,6 +564,8 @@ mod async_redis {
                 multiplexed_connection: self.create_multiplexed_connection().await?,
                 namespace: self.namespace,
                 prefix: self.prefix,
+                version: self.version,
+                key_encoder: self.key_encoder,
                 _phantom_k: self._phantom_k,
                 _phantom_v: self._phantom_v,
             })
@@ -460,6 +581,8 @@ mod async_redis {
         pub(super) refresh: bool,
         pub(super) namespace: String,
         pub(super) prefix: String,
+        pub(super) version: Option<String>,
+        key_encoder: Option<KeyEncoder<K>>,
         connection_string: String,
         multiplexed_connection: redis::aio::MultiplexedConnection,
         _phantom_k: PhantomData<K>,
@@ -477,14 +600,49 @@ mod async_redis {
             AsyncRedisCacheBuilder::new(prefix, seconds)
         }
 
-        fn generate_key(&self, key: &K) -> String {
-            format!("{}{}{}", self.namespace, self.prefix, key)
+        fn generate_key(&self, key: &K) -> Vec<u8> {
+            generate_key(
+                &self.namespace,
+                &self.prefix,
+                self.version.as_deref(),
+                self.key_encoder,
+                key,
+            )
         }
 
         /// Return the redis connection string used
         pub fn connection_string(&self) -> String {
             self.connection_string.clone()
         }
+
+        /// Remove every value cached under this cache's `{namespace}{prefix}`, whatever
+        /// their version, returning the number of keys removed. Keys are found with
+        /// `SCAN` and removed in batches, so other clients aren't blocked while a
+        /// large prefix is cleared, but values set concurrently may be missed.
+        pub async fn clear_namespace(&self) -> Result<u64, RedisCacheError> {
+            let mut conn = self.multiplexed_connection.clone();
+            let pattern = scan_pattern(&self.namespace, &self.prefix);
+            let mut cursor = 0u64;
+            let mut removed = 0;
+            loop {
+                let (next, keys): (u64, Vec<Vec<u8>>) = redis::cmd("SCAN")
+                    .cursor_arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(SCAN_BATCH_SIZE)
+                    .query_async(&mut conn)
+                    .await?;
+                if !keys.is_empty() {
+                    let n: u64 = redis::cmd("DEL").arg(keys).query_async(&mut conn).await?;
+                    removed += n;
+                }
+                if next == 0 {
+                    return Ok(removed);
+                }
+                cursor = next;
+            }
+        }
     }
 
     #[async_trait]
@@ -528,7 +686,7 @@ mod async_redis {
 
             let val = CachedRedisValue::new(val);
             pipe.get(key.clone());
-            pipe.set_ex::<String, String>(
+            pipe.set_ex::<Vec<u8>, String>(
                 key,
                 serde_json::to_string(&val)
                     .map_err(|e| RedisCacheError::CacheSerializationError { error: e })?,
@@ -558,7 +716,7 @@ mod async_redis {
             let key = self.generate_key(key);
 
             pipe.get(key.clone());
-            pipe.del::<String>(key).ignore();
+            pipe.del::<Vec<u8>>(key).ignore();
             let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
             match res.0 {
                 None => Ok(None),
@@ -705,4 +863,83 @@ mod tests {
 
         assert_eq!(100, c.cache_remove(&1).unwrap().unwrap());
     }
+
+    #[test]
+    fn version() {
+        let prefix = format!("{}:redis-cache-test-version:", now_millis());
+        let v1: RedisCache<u32, u32> = RedisCache::new(&prefix, 3600)
+            .set_version(1)
+            .build()
+            .unwrap();
+        let v2: RedisCache<u32, u32> = RedisCache::new(&prefix, 3600)
+            .set_version(2)
+            .build()
+            .unwrap();
+
+        assert!(v1.cache_set(1, 100).unwrap().is_none());
+        assert!(v2.cache_get(&1).unwrap().is_none());
+        assert!(v2.cache_set(1, 200).unwrap().is_none());
+        assert_eq!(v1.cache_get(&1).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn clear_namespace() {
+        let now = now_millis();
+        let c: RedisCache<u32, u32> =
+            RedisCache::new(format!("{}:redis-cache-test-clear[*]:", now), 3600)
+                .set_version(1)
+                .build()
+                .unwrap();
+        let other: RedisCache<u32, u32> =
+            RedisCache::new(format!("{}:redis-cache-test-clear-other:", now), 3600)
+                .build()
+                .unwrap();
+        for i in 0..1200 {
+            c.cache_set(i, i).unwrap();
+        }
+        other.cache_set(1, 1).unwrap();
+
+        assert_eq!(c.clear_namespace().unwrap(), 1200);
+        assert!(c.cache_get(&1).unwrap().is_none());
+        assert_eq!(other.cache_get(&1).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn clear_namespace_encoded_keys() {
+        let c: RedisCache<u32, u32> = RedisCache::new(
+            format!("{}:redis-cache-test-clear-encoded:", now_millis()),
+            3600,
+        )
+        .set_key_encoder(<u32 as crate::keys::CacheKeyEncode>::encode)
+        .build()
+        .unwrap();
+        // `200` is encoded as `[200, 0, 0, 0]`, which isn't UTF-8
+        c.cache_set(200, 1).unwrap();
+        c.cache_set(1, 2).unwrap();
+
+        assert_eq!(c.clear_namespace().unwrap(), 2);
+        assert!(c.cache_get(&200).unwrap().is_none());
+    }
+
+    #[test]
+    fn scan_pattern_escapes_globs() {
+        assert_eq!(scan_pattern("ns:", "fn:"), "ns:fn:*");
+        assert_eq!(scan_pattern("ns:", r"f[*]?\"), r"ns:f\[\*\]\?\\*");
+    }
+
+    #[test]
+    fn generate_key_encodes_keys() {
+        // keys keep their `Display` format by default
+        assert_eq!(generate_key("ns:", "fn:", None, None, &7u16), b"ns:fn:7");
+        assert_eq!(
+            generate_key("ns:", "fn:", Some("2"), None, &7u16),
+            b"ns:fn:2:7"
+        );
+
+        let encode: KeyEncoder<u16> = <u16 as crate::keys::CacheKeyEncode>::encode;
+        assert_eq!(
+            generate_key("ns:", "fn:", None, Some(encode), &7u16),
+            b"ns:fn:\x07\x00"
+        );
+    }
 }
@@ -1,10 +1,13 @@
-use super::Cached;
+use super::windowed::{WindowedCounter, WindowedStats};
+use super::{CacheEntry, Cached};
 use crate::lru_list::LRUList;
 use hashbrown::raw::RawTable;
 use std::cmp::Eq;
 use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
 use std::fmt;
 use std::hash::{BuildHasher, Hash, Hasher};
+use std::time::Duration;
 
 #[cfg(feature = "async")]
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
@@ -22,8 +25,13 @@ pub struct SizedCache<K, V> {
     pub(super) hash_builder: RandomState,
     pub(super) order: LRUList<(K, V)>,
     pub(super) capacity: usize,
+    // once full, entries are evicted until the cache holds `low_watermark - 1` entries
+    pub(super) low_watermark: usize,
     pub(super) hits: u64,
     pub(super) misses: u64,
+    pub(super) window: WindowedCounter,
+    // keys exempt from eviction, see `TimedSizedCache::pin`
+    pub(super) pinned: HashSet<K>,
 }
 
 impl<K, V> fmt::Debug for SizedCache<K, V>
@@ -35,6 +43,7 @@ where
         f.debug_struct("SizedCache")
             .field("order", &self.order)
             .field("capacity", &self.capacity)
+            .field("low_watermark", &self.low_watermark)
             .field("hits", &self.hits)
             .field("misses", &self.misses)
             .finish()
@@ -81,11 +90,32 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
             hash_builder: RandomState::new(),
             order: LRUList::<(K, V)>::with_capacity(size),
             capacity: size,
+            low_watermark: size,
             hits: 0,
             misses: 0,
+            window: WindowedCounter::default(),
+            pinned: HashSet::new(),
         }
     }
 
+    /// Creates a new `SizedCache` growing up to `high` entries, then evicting the least recently
+    /// used entries in bulk until it holds `low` entries, counting the inserted one.
+    ///
+    /// A `SizedCache` created `with_size(high)` evicts a single entry on every insert once full.
+    /// Evicting in bulk instead runs the eviction once every `high - low + 1` inserts, trading
+    /// memory and hit rate (up to `high - low` fewer cached entries right after an eviction) for
+    /// less frequent bookkeeping under insert-heavy load.
+    ///
+    /// Panics if `low` is zero or greater than `high`.
+    pub fn with_size_and_watermark(high: usize, low: usize) -> SizedCache<K, V> {
+        if low == 0 || low > high {
+            panic!("the low watermark of `SizedCache` must be between 1 and its `size`.")
+        }
+        let mut cache = Self::with_size(high);
+        cache.low_watermark = low;
+        cache
+    }
+
     /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data
     pub fn try_with_size(size: usize) -> std::io::Result<SizedCache<K, V>> {
         if size == 0 {
@@ -111,11 +141,60 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
             hash_builder: RandomState::new(),
             order: LRUList::<(K, V)>::with_capacity(size),
             capacity: size,
+            low_watermark: size,
             hits: 0,
             misses: 0,
+            window: WindowedCounter::default(),
+            pinned: HashSet::new(),
         })
     }
 
+    /// Creates a new `SizedCache` with a given size limit, warmed up with the most recently used
+    /// entries of `other` that fit, e.g. to resize a cache without starting cold.
+    ///
+    /// The entries keep their order, the pinned ones stay pinned, and the metrics start from zero.
+    pub fn from_cache(other: &SizedCache<K, V>, size: usize) -> SizedCache<K, V>
+    where
+        V: Clone,
+    {
+        Self::from_entries(other, size, |_, v| Some(v.clone()))
+    }
+
+    /// Creates a new `SizedCache` of `size` holding the most recently used entries of `other`
+    /// that fit, converted with `convert`. Unpinned entries it returns `None` for are skipped,
+    /// pinned ones are kept as they are.
+    pub(super) fn from_entries<F: FnMut(&K, &V) -> Option<V>>(
+        other: &SizedCache<K, V>,
+        size: usize,
+        mut convert: F,
+    ) -> SizedCache<K, V>
+    where
+        V: Clone,
+    {
+        let entries = other
+            .iter_order()
+            .filter_map(|(k, v)| match convert(k, v) {
+                Some(v) => Some((k, v)),
+                None if other.is_pinned(k) => Some((k, v.clone())),
+                None => None,
+            })
+            .take(size)
+            .collect::<Vec<_>>();
+        let mut cache = Self::with_size(size);
+        for (k, v) in entries.into_iter().rev() {
+            cache.cache_set(k.clone(), v);
+        }
+        let pinned = other
+            .key_order()
+            .filter(|k| other.is_pinned(k))
+            .cloned()
+            .collect::<Vec<_>>();
+        for k in pinned {
+            cache.pin_if(&k, |_| true);
+        }
+        cache
+    }
+
     pub(super) fn iter_order(&self) -> impl Iterator<Item = &(K, V)> {
         self.order.iter()
     }
@@ -172,28 +251,63 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
 
     fn check_capacity(&mut self) {
         if self.store.len() >= self.capacity {
-            // store has reached capacity, evict the oldest item.
-            // store capacity cannot be zero, so there must be content in `self.order`.
-            let index = self.order.back();
-            let key = &self.order.get(index).0;
-            let hash = self.hash(key);
-
-            let order = &self.order;
-            let erased = self.store.erase_entry(hash, |&i| *key == order.get(i).0);
-            assert!(erased, "SizedCache::cache_set failed evicting cache key");
-            self.order.remove(index);
+            // store has reached capacity, evict the oldest items that aren't pinned, down to
+            // the low watermark. store capacity cannot be zero, so there must be content in
+            // `self.order`, and at most `capacity - 1` keys can be pinned.
+            let mut index = self.order.back();
+            loop {
+                while self.pinned.contains(&self.order.get(index).0) {
+                    index = self.order.prev(index);
+                }
+                let prev = self.order.prev(index);
+                let key = &self.order.get(index).0;
+                let hash = self.hash(key);
+
+                let order = &self.order;
+                let erased = self.store.erase_entry(hash, |&i| *key == order.get(i).0);
+                assert!(erased, "SizedCache::cache_set failed evicting cache key");
+                self.order.remove(index);
+
+                let len = self.store.len();
+                if len < self.low_watermark || len == self.pinned.len() {
+                    break;
+                }
+                index = prev;
+            }
         }
     }
 
+    /// Return the cached value of `key`, without updating its position or the metrics
+    pub(super) fn peek(&self, key: &K) -> Option<&V> {
+        self.get_index(self.hash(key), key)
+            .map(|index| &self.order.get(index).1)
+    }
+
+    /// Return the cached value of `key` mutably, without updating its position or the metrics
+    pub(super) fn peek_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_index(self.hash(key), key)
+            .map(move |index| &mut self.order.get_mut(index).1)
+    }
+
+    /// Return the cached value of `key` along with how many times it was retrieved since it
+    /// was stored, without updating its position or the metrics
+    pub(super) fn peek_with_hits(&self, key: &K) -> Option<(&V, u64)> {
+        self.get_index(self.hash(key), key)
+            .map(|index| (&self.order.get(index).1, self.order.hits(index)))
+    }
+
     pub(super) fn get_if<F: FnOnce(&V) -> bool>(&mut self, key: &K, is_valid: F) -> Option<&V> {
         if let Some(index) = self.get_index(self.hash(key), key) {
             if is_valid(&self.order.get(index).1) {
                 self.order.move_to_front(index);
                 self.hits += 1;
+                self.window.hit();
+                self.order.count_hit(index);
                 return Some(&self.order.get(index).1);
             }
         }
         self.misses += 1;
+        self.window.miss();
         None
     }
 
@@ -206,10 +320,13 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
             if is_valid(&self.order.get(index).1) {
                 self.order.move_to_front(index);
                 self.hits += 1;
+                self.window.hit();
+                self.order.count_hit(index);
                 return Some(&mut self.order.get_mut(index).1);
             }
         }
         self.misses += 1;
+        self.window.miss();
         None
     }
 
@@ -229,6 +346,8 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
             self.hits += 1;
+            self.window.hit();
+            self.order.count_hit(index);
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
@@ -241,6 +360,7 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         } else {
             self.check_capacity();
             self.misses += 1;
+            self.window.miss();
             let index = self.order.push_front((key, f()));
             self.insert_index(hash, index);
             (false, false, &mut self.order.get_mut(index).1)
@@ -258,6 +378,8 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
             self.hits += 1;
+            self.window.hit();
+            self.order.count_hit(index);
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
@@ -270,12 +392,55 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         } else {
             self.check_capacity();
             self.misses += 1;
+            self.window.miss();
             let index = self.order.push_front((key, f()?));
             self.insert_index(hash, index);
             Ok((false, false, &mut self.order.get_mut(index).1))
         }
     }
 
+    /// Exempt `key` from eviction if it's cached and `is_valid` returns `true` for its value.
+    /// Pinning fails when it would leave no key to evict, i.e. `capacity - 1` keys
+    /// can be pinned at most.
+    pub(super) fn pin_if<F: FnOnce(&V) -> bool>(&mut self, key: &K, is_valid: F) -> bool {
+        if self.pinned.contains(key) {
+            return true;
+        }
+        let valid = match self.get_index(self.hash(key), key) {
+            Some(index) => is_valid(&self.order.get(index).1),
+            None => false,
+        };
+        if valid && self.pinned.len() + 1 < self.capacity {
+            self.pinned.insert(key.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn unpin(&mut self, key: &K) -> bool {
+        self.pinned.remove(key)
+    }
+
+    /// Keep the entries for which `keep` returns true, given whether their key is pinned
+    pub(super) fn retain_entries<F: FnMut(&K, &mut V, bool) -> bool>(&mut self, mut keep: F) {
+        let mut remove_keys = Vec::new();
+        for index in self.order.indices() {
+            let (k, v) = self.order.get_mut(index);
+            let pinned = !self.pinned.is_empty() && self.pinned.contains(k);
+            if !keep(k, v, pinned) {
+                remove_keys.push(k.clone());
+            }
+        }
+        for k in remove_keys {
+            self.cache_remove(&k);
+        }
+    }
+
+    pub(super) fn is_pinned(&self, key: &K) -> bool {
+        !self.pinned.is_empty() && self.pinned.contains(key)
+    }
+
     /// Returns a reference to the cache's `order`
     pub fn get_order(&self) -> &LRUList<(K, V)> {
         &self.order
@@ -290,6 +455,15 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
             self.cache_remove(&k);
         }
     }
+    /// Keep rolling hit and miss counts over the last `buckets` intervals of `bucket_width`,
+    /// read with [`Cached::cache_stats_windowed`], e.g. to alert on the hit rate of the last
+    /// few minutes. Each lookup only bumps the count of the current interval.
+    ///
+    /// Panics if `bucket_width` or `buckets` is zero.
+    pub fn with_windowed_stats(mut self, bucket_width: Duration, buckets: usize) -> Self {
+        self.window.enable(bucket_width, buckets);
+        self
+    }
 }
 
 #[cfg(feature = "async")]
@@ -319,6 +493,8 @@ where
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
             self.hits += 1;
+            self.window.hit();
+            self.order.count_hit(index);
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
@@ -331,6 +507,7 @@ where
         } else {
             self.check_capacity();
             self.misses += 1;
+            self.window.miss();
             let index = self.order.push_front((key, f().await));
             self.insert_index(hash, index);
             (false, false, &mut self.order.get_mut(index).1)
@@ -353,6 +530,8 @@ where
         let index = self.get_index(hash, &key);
         if let Some(index) = index {
             self.hits += 1;
+            self.window.hit();
+            self.order.count_hit(index);
             let replace_existing = {
                 let v = &self.order.get(index).1;
                 !is_valid(v)
@@ -365,6 +544,7 @@ where
         } else {
             self.check_capacity();
             self.misses += 1;
+            self.window.miss();
             let index = self.order.push_front((key, f().await?));
             self.insert_index(hash, index);
             Ok((false, false, &mut self.order.get_mut(index).1))
@@ -404,15 +584,20 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
         if let Some(index) = self.remove_index(hash, k) {
             // need to remove the key in the order list
             let (_key, value) = self.order.remove(index);
+            self.pinned.remove(k);
             Some(value)
         } else {
             None
         }
     }
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        self.retain_entries(|k, v, _| f(k, v));
+    }
     fn cache_clear(&mut self) {
         // clear both the store and the order list
         self.store.clear();
         self.order.clear();
+        self.pinned.clear();
     }
     fn cache_reset(&mut self) {
         // SizedCache uses cache_clear because capacity is fixed.
@@ -421,21 +606,55 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
     fn cache_reset_metrics(&mut self) {
         self.misses = 0;
         self.hits = 0;
+        self.window.reset();
     }
     fn cache_size(&self) -> usize {
         self.store.len()
     }
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .iter_order()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.peek_with_hits(k).map(|(value, hits)| CacheEntry {
+            hits: Some(hits),
+            ..CacheEntry::new(value)
+        })
+    }
+
     fn cache_hits(&self) -> Option<u64> {
         Some(self.hits)
     }
     fn cache_misses(&self) -> Option<u64> {
         Some(self.misses)
     }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.window.stats(window)
+    }
     fn cache_capacity(&self) -> Option<usize> {
         Some(self.capacity)
     }
 }
 
+/// Inserts the entries in order, like [`Cached::cache_extend`]. Once the cache is full, each
+/// new key evicts the least recently used entry, so extending it with more distinct keys than
+/// its size keeps the last ones.
+impl<K: Hash + Eq + Clone, V> Extend<(K, V)> for SizedCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
+    }
+}
+
 #[cfg(feature = "async")]
 #[async_trait]
 impl<K, V> CachedAsync<K, V> for SizedCache<K, V>
@@ -623,6 +842,58 @@ mod tests {
         assert_eq!(0, c.cache_size());
     }
 
+    #[test]
+    fn retain() {
+        let mut c = SizedCache::with_size(5);
+        for n in 1..=5 {
+            c.cache_set(n, n * 100);
+        }
+        c.cache_retain(|k, v| {
+            *v += 1;
+            k % 2 == 1
+        });
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [5, 3, 1]);
+        assert_eq!(
+            c.value_order().cloned().collect::<Vec<_>>(),
+            [501, 301, 101]
+        );
+        assert_eq!(c.cache_get(&2), None);
+        // freed slots are reused
+        c.cache_set(6, 600);
+        c.cache_set(7, 700);
+        assert_eq!(c.cache_size(), 5);
+    }
+
+    #[test]
+    fn from_cache() {
+        let mut c = SizedCache::with_size(5);
+        for n in 1..=5 {
+            c.cache_set(n, n * 100);
+        }
+        c.cache_get(&2);
+        assert!(c.pin_if(&5, |_| true));
+
+        // downsizing keeps the most recently used entries, in order
+        let mut smaller = SizedCache::from_cache(&c, 3);
+        assert_eq!(smaller.key_order().cloned().collect::<Vec<_>>(), [2, 5, 4]);
+        assert_eq!(
+            smaller.value_order().cloned().collect::<Vec<_>>(),
+            [200, 500, 400]
+        );
+        assert!(smaller.is_pinned(&5));
+        assert_eq!(smaller.cache_hits(), Some(0));
+        assert_eq!(smaller.cache_capacity(), Some(3));
+        smaller.cache_set(6, 600);
+        assert_eq!(smaller.key_order().cloned().collect::<Vec<_>>(), [6, 2, 5]);
+
+        let larger = SizedCache::from_cache(&c, 10);
+        assert_eq!(
+            larger.key_order().cloned().collect::<Vec<_>>(),
+            c.key_order().cloned().collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn sized_cache_get_mut() {
         let mut c = SizedCache::with_size(5);
@@ -741,4 +1012,89 @@ mod tests {
             .await;
         assert_eq!(res.unwrap(), &1);
     }
+
+    #[test]
+    fn snapshot() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(3, 30);
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        assert_eq!(c.cache_snapshot(), [(1, 10), (2, 20)]);
+    }
+
+    #[test]
+    fn watermark() {
+        let mut c = SizedCache::with_size_and_watermark(4, 2);
+        c.cache_extend((1..=4).map(|n| (n, n * 10)));
+        assert_eq!(c.cache_size(), 4);
+        // the three least recently used entries are evicted to make room for the new one
+        c.cache_get(&1);
+        c.cache_set(5, 50);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [5, 1]);
+        c.cache_set(6, 60);
+        c.cache_set(7, 70);
+        assert_eq!(c.cache_size(), 4);
+        c.cache_set(8, 80);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [8, 7]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn watermark_above_size() {
+        SizedCache::<u32, u32>::with_size_and_watermark(2, 3);
+    }
+
+    #[test]
+    fn extend() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_extend(vec![(3, 30), (1, 10), (2, 20)]);
+        assert_eq!(c.cache_snapshot(), [(1, 10), (2, 20)]);
+
+        // the last distinct keys are kept, in order
+        c.extend((4..=8).map(|n| (n, n * 10)));
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [8, 7]);
+    }
+
+    #[test]
+    fn windowed_stats() {
+        let mut c: SizedCache<u32, u32> = SizedCache::with_size(2);
+        c.cache_get(&1);
+        assert_eq!(
+            c.cache_stats_windowed(Duration::from_secs(60)),
+            WindowedStats::default()
+        );
+
+        let mut c = SizedCache::with_size(2).with_windowed_stats(Duration::from_secs(60), 5);
+        c.cache_get(&1);
+        c.cache_set(1, 10);
+        c.cache_get(&1);
+        c.cache_get_or_set_with(1, || 11);
+        let stats = c.cache_stats_windowed(Duration::from_secs(300));
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!(stats.window, Duration::from_secs(300));
+
+        c.cache_reset_metrics();
+        assert_eq!(c.cache_stats_windowed(Duration::from_secs(60)).hits, 0);
+    }
+
+    #[test]
+    fn get_with_meta() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        c.cache_get(&1);
+        c.cache_get_mut(&1);
+        c.cache_get_or_set_with(1, || 11);
+        c.cache_get(&3);
+        let entry = c.cache_get_with_meta(&1).unwrap();
+        assert_eq!((entry.value, entry.hits), (&10, Some(3)));
+        assert_eq!((entry.inserted_at, entry.remaining_ttl), (None, None));
+        assert_eq!(c.cache_get_with_meta(&2).unwrap().hits, Some(0));
+        assert!(c.cache_get_with_meta(&3).is_none());
+
+        // replacing a value resets its hits, and peeking doesn't count
+        c.cache_set(1, 11);
+        assert_eq!(c.cache_get_with_meta(&1).unwrap().hits, Some(0));
+        assert_eq!(c.cache_hits(), Some(3));
+    }
 }
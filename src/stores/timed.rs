@@ -1,14 +1,16 @@
 use std::cmp::Eq;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::Hash;
+use std::time::Duration;
 
 use instant::Instant;
 
 #[cfg(feature = "async")]
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
 
-use super::Cached;
+use super::windowed::{WindowedCounter, WindowedStats};
+use super::{is_expired, CacheEntry, Cached};
 
 /// Enum used for defining the status of time-cached values
 #[derive(Debug)]
@@ -18,6 +20,216 @@ pub(super) enum Status {
     Expired,
 }
 
+/// The timestamp making an entry of a cache with a `lifespan` expire `ttl` after `now`.
+/// A TTL can only shorten the lifespan, so longer TTLs are capped to it.
+pub(super) fn stamp_with_ttl(now: Instant, lifespan: u64, ttl: Duration) -> Instant {
+    let shortened_by = Duration::from_secs(lifespan).saturating_sub(ttl);
+    now.checked_sub(shortened_by).unwrap_or(now)
+}
+
+/// The timestamp carrying over an entry `stamp`ed in a cache with a `from` lifespan to a cache
+/// with a `to` lifespan: the entry expires when it would have, or earlier if `to` is shorter
+/// than its remaining TTL, but never later.
+/// `None` if the entry expired, or if its new timestamp can't be represented.
+pub(super) fn restamp(stamp: Instant, from: u64, to: u64, now: Instant) -> Option<Instant> {
+    let remaining = Duration::from_secs(from).saturating_sub(now.saturating_duration_since(stamp));
+    if remaining.is_zero() {
+        return None;
+    }
+    now.checked_sub(Duration::from_secs(to).saturating_sub(remaining))
+}
+
+type CloneKey<K> = fn(&K) -> K;
+
+/// When the entries of a timed cache were last inserted or retrieved, to expire the ones that
+/// weren't for its time-to-idle even if their lifespan isn't over
+#[derive(Clone, Debug)]
+pub(super) struct Idle<K> {
+    // the time-to-idle and how to clone the keys to track: the `Cached` impls don't require
+    // `K: Clone`, so it's only required when setting a time-to-idle
+    tti: Option<(u64, CloneKey<K>)>,
+    accessed: HashMap<K, Instant>,
+}
+
+impl<K: Hash + Eq> Idle<K> {
+    /// No time-to-idle, entries only expire with the lifespan
+    pub(super) fn none() -> Idle<K> {
+        Idle {
+            tti: None,
+            accessed: HashMap::new(),
+        }
+    }
+
+    /// Entries expire when they weren't accessed for `seconds`
+    pub(super) fn with_tti(seconds: u64) -> Idle<K>
+    where
+        K: Clone,
+    {
+        Idle {
+            tti: Some((seconds, K::clone)),
+            accessed: HashMap::new(),
+        }
+    }
+
+    pub(super) fn seconds(&self) -> Option<u64> {
+        self.tti.map(|(seconds, _)| seconds)
+    }
+
+    /// Record that `key` was inserted or retrieved at `now`
+    pub(super) fn touch(&mut self, key: &K, now: Instant) {
+        if let Some((_, clone_key)) = self.tti {
+            match self.accessed.get_mut(key) {
+                Some(accessed) => *accessed = now,
+                None => {
+                    self.accessed.insert(clone_key(key), now);
+                }
+            }
+        }
+    }
+
+    /// Whether `key` wasn't accessed for the time-to-idle at `now`
+    pub(super) fn is_idle(&self, key: &K, now: Instant) -> bool {
+        match (self.tti, self.accessed.get(key)) {
+            (Some((seconds, _)), Some(accessed)) => is_expired(*accessed, seconds, now),
+            _ => false,
+        }
+    }
+
+    /// How long `key` can go without being accessed from `now` before it's idle,
+    /// `None` without a time-to-idle
+    pub(super) fn remaining(&self, key: &K, now: Instant) -> Option<Duration> {
+        let (seconds, _) = self.tti?;
+        let accessed = self.accessed.get(key)?;
+        Some(Duration::from_secs(seconds).saturating_sub(now.saturating_duration_since(*accessed)))
+    }
+
+    pub(super) fn forget(&mut self, key: &K) {
+        self.accessed.remove(key);
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.accessed.clear();
+    }
+
+    /// Only keep tracking the keys `f` returns `true` for
+    pub(super) fn retain<F: FnMut(&K) -> bool>(&mut self, mut f: F) {
+        self.accessed.retain(|k, _| f(k));
+    }
+
+    pub(super) fn tracked(&self) -> usize {
+        self.accessed.len()
+    }
+}
+
+/// The keys of a timed cache in the order they were stamped, to remove a few of the expired
+/// entries on each write instead of keeping them until they're looked up or flushed.
+///
+/// Keys stamped before the ones already tracked, e.g. with a shorter TTL, are tracked ahead of
+/// them: the cleanup stops at the first live entry, so it would never reach them otherwise.
+#[derive(Clone, Debug)]
+pub(super) struct Cleanup<K> {
+    // how many entries to examine per write and how to clone the keys to track, like `Idle`
+    budget: Option<(usize, CloneKey<K>)>,
+    queue: VecDeque<(Instant, K)>,
+}
+
+impl<K> Cleanup<K> {
+    /// No cleanup, expired entries are only removed when looked up or flushed
+    pub(super) fn none() -> Cleanup<K> {
+        Cleanup {
+            budget: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Examine up to `budget` entries on each write, no cleanup for a zero `budget`
+    pub(super) fn with_budget(budget: usize) -> Cleanup<K>
+    where
+        K: Clone,
+    {
+        Cleanup {
+            budget: Some((budget, K::clone as CloneKey<K>)).filter(|_| budget > 0),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// The same budget, tracking no keys yet
+    pub(super) fn fresh(&self) -> Cleanup<K> {
+        Cleanup {
+            budget: self.budget,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub(super) fn budget(&self) -> usize {
+        self.budget.map_or(0, |(budget, _)| budget)
+    }
+
+    /// Record that `key` was newly stored, or stored with an earlier stamp, at `stamp`
+    pub(super) fn track(&mut self, key: &K, stamp: Instant) {
+        if let Some((_, clone_key)) = self.budget {
+            self.insert(stamp, clone_key(key));
+        }
+    }
+
+    // ahead of the keys stamped later, usually by pushing to the back
+    fn insert(&mut self, stamp: Instant, key: K) {
+        let index = self.queue.partition_point(|(tracked, _)| *tracked <= stamp);
+        self.queue.insert(index, (stamp, key));
+    }
+
+    /// The least recently stamped key, which may since have been removed or restamped
+    pub(super) fn pop(&mut self) -> Option<(Instant, K)> {
+        self.queue.pop_front()
+    }
+
+    /// Put back a key popped from the queue, still stamped at `stamp`
+    pub(super) fn put_back(&mut self, stamp: Instant, key: K) {
+        self.queue.push_front((stamp, key));
+    }
+
+    /// Track a popped key again, restamped at `stamp`
+    pub(super) fn requeue(&mut self, stamp: Instant, key: K) {
+        self.queue.push_back((stamp, key));
+    }
+
+    /// Whether enough of the tracked keys were removed since to rebuild the queue from the
+    /// `len` entries of the store
+    pub(super) fn is_bloated(&self, len: usize) -> bool {
+        self.queue.len() > 2 * len + self.budget()
+    }
+
+    /// Track the `entries` of the store afresh, in the order they were stamped
+    pub(super) fn rebuild<'a, I>(&mut self, entries: I)
+    where
+        K: 'a,
+        I: IntoIterator<Item = (&'a K, Instant)>,
+    {
+        if let Some((_, clone_key)) = self.budget {
+            let mut queue = entries
+                .into_iter()
+                .map(|(k, stamp)| (stamp, clone_key(k)))
+                .collect::<Vec<_>>();
+            queue.sort_by_key(|(stamp, _)| *stamp);
+            self.queue = queue.into();
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    #[cfg(test)]
+    pub(super) fn tracked(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// The shortest of the remaining `ttl` of an entry and of the time it can stay `idle`
+pub(super) fn remaining_until_expiry(ttl: Duration, idle: Option<Duration>) -> Duration {
+    idle.map_or(ttl, |idle| ttl.min(idle))
+}
+
 /// Cache store bound by time
 ///
 /// Values are timestamped when inserted and are
@@ -30,8 +242,12 @@ pub struct TimedCache<K, V> {
     pub(super) seconds: u64,
     pub(super) hits: u64,
     pub(super) misses: u64,
+    pub(super) window: WindowedCounter,
     pub(super) initial_capacity: Option<usize>,
     pub(super) refresh: bool,
+    pub(super) idle: Idle<K>,
+    pub(super) cleanup: Cleanup<K>,
+    pub(super) pinned: HashSet<K>,
 }
 
 impl<K: Hash + Eq, V> TimedCache<K, V> {
@@ -43,29 +259,133 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
     /// Creates a new `TimedCache` with a specified lifespan and
     /// cache-store with the specified pre-allocated capacity
     pub fn with_lifespan_and_capacity(seconds: u64, size: usize) -> TimedCache<K, V> {
+        Self::with_lifespan_and_refresh_and_capacity(seconds, false, size)
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan which
+    /// refreshes the ttl when the entry is retrieved: with `refresh`, every hit restarts the
+    /// lifespan of the entry, otherwise entries expire `seconds` after they were set
+    pub fn with_lifespan_and_refresh(seconds: u64, refresh: bool) -> TimedCache<K, V> {
         TimedCache {
-            store: Self::new_store(Some(size)),
+            store: Self::new_store(None),
             seconds,
             hits: 0,
             misses: 0,
-            initial_capacity: Some(size),
-            refresh: false,
+            window: WindowedCounter::default(),
+            initial_capacity: None,
+            refresh,
+            idle: Idle::none(),
+            cleanup: Cleanup::none(),
+            pinned: HashSet::new(),
         }
     }
 
-    /// Creates a new `TimedCache` with a specified lifespan which
-    /// refreshes the ttl when the entry is retrieved
-    pub fn with_lifespan_and_refresh(seconds: u64, refresh: bool) -> TimedCache<K, V> {
+    /// Creates a new `TimedCache` with a specified lifespan and time-to-idle: an entry expires
+    /// `seconds` after being inserted, or `tti_seconds` after it was last inserted or retrieved,
+    /// whichever comes first, e.g. to keep hot entries for at most a lifespan while dropping
+    /// the ones nobody asks for early.
+    ///
+    /// Tracking the last accesses keeps a clone of each key.
+    pub fn with_lifespan_and_tti(seconds: u64, tti_seconds: u64) -> TimedCache<K, V>
+    where
+        K: Clone,
+    {
         TimedCache {
-            store: Self::new_store(None),
+            idle: Idle::with_tti(tti_seconds),
+            ..Self::with_lifespan(seconds)
+        }
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan, warmed up with the unexpired
+    /// entries of `other`, e.g. to change the lifespan of a cache without starting cold.
+    ///
+    /// The entries keep their remaining TTL, cut down to `seconds` when it's longer: a new
+    /// lifespan never extends the life of an entry. The pinned entries stay pinned, the cache
+    /// refreshes lifetimes and expires idle entries like `other` does, and its metrics start
+    /// from zero.
+    pub fn from_cache_with_lifespan(other: &TimedCache<K, V>, seconds: u64) -> TimedCache<K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let now = Instant::now();
+        let mut cache = TimedCache {
+            store: Self::new_store(other.initial_capacity),
             seconds,
             hits: 0,
             misses: 0,
-            initial_capacity: None,
+            window: WindowedCounter::default(),
+            initial_capacity: other.initial_capacity,
+            refresh: other.refresh,
+            idle: other.idle.clone(),
+            cleanup: other.cleanup.fresh(),
+            pinned: other.pinned.clone(),
+        };
+        for (k, (stamp, v)) in &other.store {
+            if other.pinned.contains(k) {
+                cache.store.insert(k.clone(), (*stamp, v.clone()));
+                continue;
+            }
+            if other.idle.is_idle(k, now) {
+                continue;
+            }
+            if let Some(stamp) = restamp(*stamp, other.seconds, seconds, now) {
+                cache.store.insert(k.clone(), (stamp, v.clone()));
+            }
+        }
+        let store = &cache.store;
+        cache.idle.retain(|k| store.contains_key(k));
+        cache
+            .cleanup
+            .rebuild(store.iter().map(|(k, (stamp, _))| (k, *stamp)));
+        cache
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan which refreshes the ttl when
+    /// the entry is retrieved if `refresh` is set, and a cache-store with the specified
+    /// pre-allocated capacity
+    pub fn with_lifespan_and_refresh_and_capacity(
+        seconds: u64,
+        refresh: bool,
+        size: usize,
+    ) -> TimedCache<K, V> {
+        TimedCache {
+            store: Self::new_store(Some(size)),
+            seconds,
+            hits: 0,
+            misses: 0,
+            window: WindowedCounter::default(),
+            initial_capacity: Some(size),
             refresh,
+            idle: Idle::none(),
+            cleanup: Cleanup::none(),
+            pinned: HashSet::new(),
         }
     }
 
+    /// Remove up to `budget` expired entries on each write, e.g. so a cache whose keys churn
+    /// doesn't keep growing with entries nobody looks up again without calling
+    /// [`TimedCache::flush`].
+    ///
+    /// The entries are examined from the least recently stamped one, stopping at the first
+    /// one still alive, so each write takes at most `budget` extra steps. Tracking the order
+    /// keeps a clone of each key. A zero `budget` disables the cleanup.
+    pub fn with_cleanup_budget(mut self, budget: usize) -> Self
+    where
+        K: Clone,
+    {
+        self.cleanup = Cleanup::with_budget(budget);
+        let store = &self.store;
+        self.cleanup
+            .rebuild(store.iter().map(|(k, (stamp, _))| (k, *stamp)));
+        self
+    }
+
+    /// Returns the time-to-idle of the cache, see [`TimedCache::with_lifespan_and_tti`]
+    pub fn tti(&self) -> Option<u64> {
+        self.idle.seconds()
+    }
+
     /// Returns if the lifetime is refreshed when the value is retrieved
     pub fn refresh(&self) -> bool {
         self.refresh
@@ -85,142 +405,315 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
         &self.store
     }
 
+    /// Pin a cached `key`, exempting it from expiry until it's unpinned or removed, e.g. for a
+    /// default fallback value kept next to short-lived entries. A `TimedCache` is unbounded, so
+    /// pinned entries are never evicted either, see [`TimedSizedCache::pin`](crate::TimedSizedCache::pin)
+    /// for a bounded cache.
+    ///
+    /// Returns `false` when `key` isn't cached (or has expired).
+    pub fn pin(&mut self, key: &K) -> bool
+    where
+        K: Clone,
+    {
+        match self.status(key, Instant::now()) {
+            Status::Found => {
+                self.pinned.insert(key.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Unpin `key`, returning whether it was pinned. Its value then expires based on when it
+    /// was stored, so a value pinned for longer than the lifespan expires right away.
+    /// Likewise, with a time-to-idle, it expires based on when it was last accessed.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        self.pinned.remove(key)
+    }
+
+    /// Return an iterator of the pinned keys, in no particular order
+    pub fn pinned_keys(&self) -> impl Iterator<Item = &K> {
+        self.pinned.iter()
+    }
+
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
-        let seconds = self.seconds;
-        self.store
-            .retain(|_, (instant, _)| instant.elapsed().as_secs() < seconds);
+        let (seconds, now) = (self.seconds, Instant::now());
+        let (idle, pinned) = (&self.idle, &self.pinned);
+        self.store.retain(|k, (instant, _)| {
+            pinned.contains(k) || (!is_expired(*instant, seconds, now) && !idle.is_idle(k, now))
+        });
+        let store = &self.store;
+        self.idle.retain(|k| store.contains_key(k));
+        self.cleanup
+            .rebuild(store.iter().map(|(k, (stamp, _))| (k, *stamp)));
     }
-}
 
-impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
-    fn cache_get(&mut self, key: &K) -> Option<&V> {
-        let status = {
-            let mut val = self.store.get_mut(key);
-            if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        *instant = Instant::now();
-                    }
-                    Status::Found
-                } else {
-                    Status::Expired
+    /// Examine up to the cleanup budget of the least recently stamped entries, removing the
+    /// expired ones
+    fn clean_up(&mut self, now: Instant) {
+        if self.cleanup.is_bloated(self.store.len()) {
+            let store = &self.store;
+            self.cleanup
+                .rebuild(store.iter().map(|(k, (stamp, _))| (k, *stamp)));
+        }
+        for _ in 0..self.cleanup.budget() {
+            let (stamp, key) = match self.cleanup.pop() {
+                Some(tracked) => tracked,
+                None => break,
+            };
+            match self.store.get(&key).map(|(instant, _)| *instant) {
+                // removed since it was tracked
+                None => {}
+                Some(instant) if instant != stamp || self.pinned.contains(&key) => {
+                    self.cleanup.requeue(instant, key)
+                }
+                Some(instant) if self.is_entry_expired(&key, instant, now) => {
+                    self.store.remove(&key);
+                    self.idle.forget(&key);
+                }
+                Some(_) => {
+                    self.cleanup.put_back(stamp, key);
+                    break;
                 }
-            } else {
-                Status::NotFound
             }
-        };
-        match status {
+        }
+    }
+
+    /// Whether the entry of `key` stamped at `instant` expired at `now`, either a lifespan
+    /// after its stamp or when it wasn't accessed for the time-to-idle, unless it's pinned
+    fn is_entry_expired(&self, key: &K, instant: Instant, now: Instant) -> bool {
+        !self.pinned.contains(key)
+            && (is_expired(instant, self.seconds, now) || self.idle.is_idle(key, now))
+    }
+
+    fn status(&self, key: &K, now: Instant) -> Status {
+        match self.store.get(key) {
+            Some((instant, _)) if self.is_entry_expired(key, *instant, now) => Status::Expired,
+            Some(_) => Status::Found,
+            None => Status::NotFound,
+        }
+    }
+
+    /// Look `key` up at `now`, counting a hit or a miss and removing the entry if it expired
+    fn lookup(&mut self, key: &K, now: Instant) -> Option<&mut (Instant, V)> {
+        match self.status(key, now) {
             Status::NotFound => {
                 self.misses += 1;
+                self.window.miss();
                 None
             }
             Status::Found => {
                 self.hits += 1;
-                self.store.get(key).map(|stamped| &stamped.1)
+                self.window.hit();
+                self.idle.touch(key, now);
+                let stamped = self.store.get_mut(key)?;
+                if self.refresh {
+                    stamped.0 = now;
+                }
+                Some(stamped)
             }
             Status::Expired => {
                 self.misses += 1;
+                self.window.miss();
                 self.store.remove(key).unwrap();
+                self.idle.forget(key);
                 None
             }
         }
     }
 
-    fn cache_get_mut(&mut self, key: &K) -> Option<&mut V> {
-        let status = {
-            let mut val = self.store.get_mut(key);
-            if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        *instant = Instant::now();
-                    }
-                    Status::Found
-                } else {
-                    Status::Expired
-                }
-            } else {
-                Status::NotFound
-            }
-        };
-        match status {
-            Status::NotFound => {
-                self.misses += 1;
-                None
-            }
-            Status::Found => {
-                self.hits += 1;
-                self.store.get_mut(key).map(|stamped| &mut stamped.1)
+    fn set_at(&mut self, key: K, val: V, now: Instant) -> Option<V> {
+        let live = matches!(self.status(&key, now), Status::Found);
+        self.idle.touch(&key, now);
+        if !self.store.contains_key(&key) {
+            self.cleanup.track(&key, now);
+        }
+        let old = self.store.insert(key, (now, val));
+        self.clean_up(now);
+        old.filter(|_| live).map(|(_, v)| v)
+    }
+
+    /// Insert many `(key, value, ttl)` entries at once, each expiring `ttl` after being
+    /// inserted instead of after the cache lifespan, e.g. to warm the cache from a source
+    /// where each item carries its own freshness. Returns the number of distinct keys of
+    /// the batch that were stored.
+    ///
+    /// A TTL can only shorten the lifespan (longer TTLs are capped to it), and, like the
+    /// lifespan, is checked with a precision of one second. Entries with a zero TTL are
+    /// skipped. Retrieving an entry of a cache that refreshes lifetimes extends it to the
+    /// full lifespan.
+    pub fn cache_set_many_with_ttl<I>(&mut self, entries: I) -> usize
+    where
+        K: Clone,
+        I: IntoIterator<Item = (K, V, Duration)>,
+    {
+        let now = Instant::now();
+        let mut stored = HashSet::new();
+        for (key, value, ttl) in entries {
+            if ttl.is_zero() {
+                continue;
             }
-            Status::Expired => {
-                self.misses += 1;
-                self.store.remove(key).unwrap();
-                None
+            let stamp = stamp_with_ttl(now, self.seconds, ttl);
+            stored.insert(key.clone());
+            self.idle.touch(&key, now);
+            // an entry restamped earlier is tracked again so the cleanup reaches it in time
+            if self.store.get(&key).is_none_or(|(old, _)| stamp < *old) {
+                self.cleanup.track(&key, stamp);
             }
+            self.store.insert(key, (stamp, value));
         }
+        self.clean_up(now);
+        stored.len()
+    }
+    /// Keep rolling hit and miss counts over the last `buckets` intervals of `bucket_width`,
+    /// read with [`Cached::cache_stats_windowed`], e.g. to alert on the hit rate of the last
+    /// few minutes. Each lookup only bumps the count of the current interval.
+    ///
+    /// Panics if `bucket_width` or `buckets` is zero.
+    pub fn with_windowed_stats(mut self, bucket_width: Duration, buckets: usize) -> Self {
+        self.window.enable(bucket_width, buckets);
+        self
+    }
+}
+
+impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
+    fn cache_get(&mut self, key: &K) -> Option<&V> {
+        self.lookup(key, Instant::now()).map(|stamped| &stamped.1)
+    }
+
+    fn cache_get_with_expiry(&mut self, key: &K) -> Option<(&V, Option<Duration>)> {
+        let now = Instant::now();
+        let instant = self.lookup(key, now)?.0;
+        let expires_in = if self.pinned.contains(key) {
+            None
+        } else {
+            let lifespan = Duration::from_secs(self.seconds);
+            let ttl = lifespan.saturating_sub(now.saturating_duration_since(instant));
+            Some(remaining_until_expiry(ttl, self.idle.remaining(key, now)))
+        };
+        self.store.get(key).map(|(_, v)| (v, expires_in))
+    }
+
+    fn cache_get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.lookup(key, Instant::now())
+            .map(|stamped| &mut stamped.1)
     }
 
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let now = Instant::now();
+        // an expired entry is removed by the lookup, so the entry is only occupied on hits
+        self.lookup(&key, now);
+        self.clean_up(now);
         match self.store.entry(key) {
-            Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
-                    }
-                    self.hits += 1;
-                } else {
-                    self.misses += 1;
-                    let val = f();
-                    occupied.insert((Instant::now(), val));
-                }
-                &mut occupied.into_mut().1
-            }
+            Entry::Occupied(occupied) => &mut occupied.into_mut().1,
             Entry::Vacant(vacant) => {
-                self.misses += 1;
                 let val = f();
-                &mut vacant.insert((Instant::now(), val)).1
+                self.idle.touch(vacant.key(), now);
+                self.cleanup.track(vacant.key(), now);
+                &mut vacant.insert((now, val)).1
             }
         }
     }
 
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = (Instant::now(), val);
-        self.store.insert(key, stamped).and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
-                Some(v)
-            } else {
-                None
-            }
-        })
+        self.set_at(key, val, Instant::now())
     }
     fn cache_remove(&mut self, k: &K) -> Option<V> {
-        self.store.remove(k).and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
-                Some(v)
-            } else {
-                None
-            }
-        })
+        let live = matches!(self.status(k, Instant::now()), Status::Found);
+        self.idle.forget(k);
+        self.pinned.remove(k);
+        self.store.remove(k).filter(|_| live).map(|(_, v)| v)
+    }
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let (seconds, now) = (self.seconds, Instant::now());
+        let (idle, pinned) = (&self.idle, &self.pinned);
+        self.store.retain(|k, (instant, v)| {
+            (pinned.contains(k) || (!is_expired(*instant, seconds, now) && !idle.is_idle(k, now)))
+                && f(k, v)
+        });
+        let store = &self.store;
+        self.idle.retain(|k| store.contains_key(k));
+        self.pinned.retain(|k| store.contains_key(k));
+        self.cleanup
+            .rebuild(store.iter().map(|(k, (stamp, _))| (k, *stamp)));
     }
     fn cache_clear(&mut self) {
         self.store.clear();
+        self.idle.clear();
+        self.cleanup.clear();
+        self.pinned.clear();
     }
     fn cache_reset_metrics(&mut self) {
         self.misses = 0;
         self.hits = 0;
+        self.window.reset();
     }
     fn cache_reset(&mut self) {
         self.store = Self::new_store(self.initial_capacity);
+        self.idle.clear();
+        self.cleanup.clear();
+        self.pinned = HashSet::new();
     }
     fn cache_size(&self) -> usize {
         self.store.len()
     }
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        // most recently stored first, i.e. the longest remaining lifespan first
+        let now = Instant::now();
+        let mut snapshot = self
+            .store
+            .iter()
+            .filter(|(k, (instant, _))| !self.is_entry_expired(k, *instant, now))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(ka, (ia, _)), (kb, (ib, _))| ib.cmp(ia).then_with(|| ka.cmp(kb)));
+        snapshot
+            .into_iter()
+            .map(|(k, (_, v))| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn cache_get_stale(&self, k: &K) -> Option<&V> {
+        self.store.get(k).map(|(_, value)| value)
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        let (instant, value) = self.store.get(k)?;
+        let now = Instant::now();
+        let lifespan = Duration::from_secs(self.seconds);
+        let elapsed = now.saturating_duration_since(*instant);
+        let remaining_ttl = if self.pinned.contains(k) {
+            None
+        } else if elapsed < lifespan && !self.idle.is_idle(k, now) {
+            Some(remaining_until_expiry(
+                lifespan - elapsed,
+                self.idle.remaining(k, now),
+            ))
+        } else {
+            return None;
+        };
+        Some(CacheEntry {
+            value,
+            inserted_at: Some(*instant),
+            remaining_ttl,
+            hits: None,
+        })
+    }
+
     fn cache_hits(&self) -> Option<u64> {
         Some(self.hits)
     }
     fn cache_misses(&self) -> Option<u64> {
         Some(self.misses)
     }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.window.stats(window)
+    }
     fn cache_lifespan(&self) -> Option<u64> {
         Some(self.seconds)
     }
@@ -232,6 +725,14 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
     }
 }
 
+/// Inserts the entries in order, like [`Cached::cache_extend`], each expiring a lifespan after
+/// being inserted
+impl<K: Hash + Eq, V> Extend<(K, V)> for TimedCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
+    }
+}
+
 #[cfg(feature = "async")]
 #[async_trait]
 impl<K, V> CachedAsync<K, V> for TimedCache<K, V>
@@ -244,22 +745,16 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = V> + Send,
     {
+        let now = Instant::now();
+        self.lookup(&k, now);
+        self.clean_up(now);
         match self.store.entry(k) {
-            Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
-                    }
-                    self.hits += 1;
-                } else {
-                    self.misses += 1;
-                    occupied.insert((Instant::now(), f().await));
-                }
-                &mut occupied.into_mut().1
-            }
+            Entry::Occupied(occupied) => &mut occupied.into_mut().1,
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                &mut vacant.insert((Instant::now(), f().await)).1
+                let val = f().await;
+                self.idle.touch(vacant.key(), now);
+                self.cleanup.track(vacant.key(), now);
+                &mut vacant.insert((now, val)).1
             }
         }
     }
@@ -270,22 +765,16 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = Result<V, E>> + Send,
     {
+        let now = Instant::now();
+        self.lookup(&k, now);
+        self.clean_up(now);
         let v = match self.store.entry(k) {
-            Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
-                    }
-                    self.hits += 1;
-                } else {
-                    self.misses += 1;
-                    occupied.insert((Instant::now(), f().await?));
-                }
-                &mut occupied.into_mut().1
-            }
+            Entry::Occupied(occupied) => &mut occupied.into_mut().1,
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                &mut vacant.insert((Instant::now(), f().await?)).1
+                let val = f().await?;
+                self.idle.touch(vacant.key(), now);
+                self.cleanup.track(vacant.key(), now);
+                &mut vacant.insert((now, val)).1
             }
         };
 
@@ -358,6 +847,27 @@ mod tests {
         assert_eq!(c.cache_get(&2), None);
     }
 
+    #[test]
+    fn refresh_slides_the_lifespan() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let mut c = TimedCache::with_lifespan_and_refresh(2, true);
+        c.set_at(1, 100, start);
+        // every hit restamps the entry, so reads within the lifespan keep it alive
+        assert_eq!(c.lookup(&1, at(1500)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(3000)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(4500)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(6500)).map(|e| e.1), None);
+
+        let mut c = TimedCache::with_lifespan_and_refresh(2, false);
+        c.set_at(1, 100, start);
+        // without refresh, the lifespan counts from insertion however often it's read
+        assert_eq!(c.lookup(&1, at(1500)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(1999)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(2000)).map(|e| e.1), None);
+    }
+
     #[test]
     fn clear() {
         let mut c = TimedCache::with_lifespan(3600);
@@ -394,6 +904,17 @@ mod tests {
         assert!(init_capacity <= c.store.capacity());
     }
 
+    #[test]
+    fn with_lifespan_and_refresh_and_capacity() {
+        let mut c = TimedCache::with_lifespan_and_refresh_and_capacity(100, true, 64);
+        assert!(c.refresh());
+        assert!(64 <= c.store.capacity());
+        assert_eq!(c.cache_set(1, 100), None);
+
+        c.cache_reset();
+        assert!(64 <= c.store.capacity());
+    }
+
     #[test]
     fn remove() {
         let mut c = TimedCache::with_lifespan(3600);
@@ -505,4 +1026,277 @@ mod tests {
 
         assert_eq!(c.cache_misses(), Some(7));
     }
+
+    #[test]
+    fn snapshot() {
+        let mut c = TimedCache::with_lifespan(10);
+        let now = Instant::now();
+        c.store.insert(1, (now - Duration::from_secs(5), 10));
+        c.store.insert(2, (now - Duration::from_secs(20), 20));
+        c.store.insert(3, (now - Duration::from_secs(1), 30));
+        c.cache_set(4, 40);
+        assert_eq!(c.cache_snapshot(), [(4, 40), (3, 30), (1, 10)]);
+    }
+
+    #[test]
+    fn set_many_with_ttl() {
+        let now = Instant::now();
+        assert_eq!(
+            stamp_with_ttl(now, 10, Duration::from_secs(4)),
+            now - Duration::from_secs(6)
+        );
+        assert_eq!(stamp_with_ttl(now, 10, Duration::from_secs(60)), now);
+
+        let mut c = TimedCache::with_lifespan(3);
+        let stored = c.cache_set_many_with_ttl(vec![
+            (1, 10, Duration::from_secs(1)),
+            (2, 20, Duration::from_secs(60)),
+            (3, 30, Duration::from_secs(0)),
+            (1, 11, Duration::from_secs(1)),
+        ]);
+        assert_eq!(stored, 2);
+        assert_eq!(c.cache_get(&1), Some(&11));
+        assert_eq!(c.cache_get(&3), None);
+
+        sleep(Duration::new(1, 0));
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get(&2), Some(&20));
+    }
+
+    #[test]
+    fn cleanup_budget_with_mixed_ttls() {
+        let mut c = TimedCache::with_lifespan(10).with_cleanup_budget(1);
+        c.cache_set_many_with_ttl(vec![
+            (1, 10, Duration::from_secs(10)),
+            (2, 20, Duration::from_secs(1)),
+            (3, 30, Duration::from_secs(10)),
+        ]);
+        c.cache_set_many_with_ttl(vec![(3, 31, Duration::from_secs(1))]);
+        assert_eq!(c.cache_size(), 3);
+
+        // the short TTLs are cleaned up first, even though the long one was inserted before
+        sleep(Duration::new(1, 0));
+        c.cache_set(4, 40);
+        assert_eq!(c.cache_size(), 3);
+        c.cache_set(5, 50);
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_get(&1), Some(&10));
+    }
+
+    #[test]
+    fn expiry_boundary() {
+        let mut c = TimedCache::with_lifespan(10);
+        let now = Instant::now();
+        // values expire once exactly their lifespan has elapsed
+        c.store.insert(1, (now - Duration::from_secs(10), 10));
+        c.store.insert(2, (now - Duration::from_millis(9_900), 20));
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get(&2), Some(&20));
+
+        let mut c = TimedCache::with_lifespan(0);
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &11);
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+
+    #[test]
+    fn get_with_meta() {
+        let mut c = TimedCache::with_lifespan(10);
+        let now = Instant::now();
+        c.store.insert(1, (now - Duration::from_secs(4), 10));
+        c.store.insert(2, (now - Duration::from_secs(20), 20));
+        let entry = c.cache_get_with_meta(&1).unwrap();
+        assert_eq!(entry.value, &10);
+        assert_eq!(entry.inserted_at, Some(now - Duration::from_secs(4)));
+        let remaining = entry.remaining_ttl.unwrap();
+        assert!(remaining <= Duration::from_secs(6) && remaining > Duration::from_secs(5));
+        assert!(c.cache_get_with_meta(&2).is_none());
+        assert!(c.cache_get_with_meta(&3).is_none());
+        // peeking isn't a lookup
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+    }
+
+    #[test]
+    fn get_stale() {
+        let mut c = TimedCache::with_lifespan(10);
+        let now = Instant::now();
+        c.store.insert(1, (now - Duration::from_secs(4), 10));
+        c.store.insert(2, (now - Duration::from_secs(20), 20));
+        assert_eq!(c.cache_get_stale(&1), Some(&10));
+        assert_eq!(c.cache_get_stale(&2), Some(&20));
+        // until a lookup removes it
+        assert!(c.cache_get(&2).is_none());
+        assert!(c.cache_get_stale(&2).is_none());
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(1));
+    }
+
+    #[test]
+    fn from_cache_with_lifespan() {
+        let mut c = TimedCache::with_lifespan(100);
+        c.cache_set(1, 100);
+        c.cache_set_many_with_ttl([(2, 200, Duration::from_secs(2))]);
+
+        // a longer lifespan doesn't extend the remaining TTL
+        let mut longer = TimedCache::from_cache_with_lifespan(&c, 1000);
+        let (_, ttl) = longer.cache_get_with_expiry(&1).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(100));
+        let (_, ttl) = longer.cache_get_with_expiry(&2).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(2));
+
+        // a shorter one cuts it down
+        let mut shorter = TimedCache::from_cache_with_lifespan(&c, 1);
+        let (_, ttl) = shorter.cache_get_with_expiry(&1).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(1));
+        assert_eq!(shorter.cache_hits(), Some(1));
+        c.set_refresh(true);
+        assert!(TimedCache::from_cache_with_lifespan(&c, 1).refresh());
+
+        sleep(Duration::new(2, 0));
+        let copy = TimedCache::from_cache_with_lifespan(&c, 100);
+        assert_eq!(copy.cache_size(), 1);
+        assert_eq!(longer.cache_get(&2), None);
+    }
+
+    #[test]
+    fn tti() {
+        // a lifespan of 10s and a time-to-idle of 4s, looked up with a manual clock
+        let mut c = TimedCache::with_lifespan_and_tti(10, 4);
+        assert_eq!(c.tti(), Some(4));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        for key in 1..=3 {
+            c.set_at(key, key * 100, start);
+        }
+
+        // accessed frequently, before the lifespan is over: never idle
+        for secs in [3, 6, 9] {
+            assert_eq!(c.lookup(&1, at(secs)).map(|(_, v)| *v), Some(100));
+        }
+        // accessed frequently, after the lifespan is over: expired all the same
+        assert!(c.lookup(&1, at(10)).is_none());
+
+        // idle before the lifespan is over
+        assert!(c.lookup(&2, at(5)).is_none());
+
+        // idle after the lifespan is over
+        assert!(c.lookup(&3, at(11)).is_none());
+
+        assert_eq!(c.cache_hits(), Some(3));
+        assert_eq!(c.cache_misses(), Some(3));
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.idle.tracked(), 0);
+    }
+
+    #[test]
+    fn tti_longer_than_lifespan() {
+        let mut c = TimedCache::with_lifespan_and_tti(4, 10);
+        let start = Instant::now();
+        c.set_at(1, 100, start);
+        assert!(c.lookup(&1, start + Duration::from_secs(3)).is_some());
+        assert!(c.lookup(&1, start + Duration::from_secs(4)).is_none());
+
+        // overwriting an idle entry doesn't return it
+        let mut c = TimedCache::with_lifespan_and_tti(10, 2);
+        c.set_at(1, 100, start);
+        assert_eq!(c.set_at(1, 200, start + Duration::from_secs(1)), Some(100));
+        assert_eq!(c.set_at(1, 300, start + Duration::from_secs(3)), None);
+    }
+
+    #[test]
+    fn tti_expiry() {
+        let mut c = TimedCache::with_lifespan_and_tti(100, 2);
+        assert_eq!(*c.cache_get_or_set_with(1, || 100), 100);
+        let (_, ttl) = c.cache_get_with_expiry(&1).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(2));
+        assert!(c.cache_get_with_meta(&1).is_some());
+
+        sleep(Duration::new(2, 0));
+        assert!(c.cache_get_with_meta(&1).is_none());
+        c.flush();
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.idle.tracked(), 0);
+    }
+
+    #[test]
+    fn cleanup_budget_under_churn() {
+        let mut c = TimedCache::with_lifespan(10).with_cleanup_budget(2);
+        let start = Instant::now();
+        let mut largest = 0;
+        for i in 0..10_000u64 {
+            let now = start + Duration::from_millis(100 * i);
+            c.set_at(i, i, now);
+            // some keys are written again, or removed and written again, while alive
+            if i % 3 == 0 && i >= 30 {
+                c.set_at(i - 30, i, now);
+            }
+            if i % 7 == 0 && i >= 50 {
+                c.cache_remove(&(i - 50));
+                c.set_at(i - 50, i, now);
+            }
+            largest = largest.max(c.cache_size());
+        }
+        // ~100 keys are written per lifespan, plus the ones written again
+        assert!(largest <= 200, "{}", largest);
+        assert!(c.cleanup.tracked() <= 2 * c.cache_size() + 2);
+
+        // without a budget, the expired entries stay until flushed
+        let mut c = TimedCache::with_lifespan(10);
+        for i in 0..1_000u64 {
+            c.set_at(i, i, start + Duration::from_millis(100 * i));
+        }
+        assert_eq!(c.cache_size(), 1_000);
+        assert_eq!(c.cleanup.tracked(), 0);
+    }
+
+    #[test]
+    fn cleanup_budget_keeps_live_entries() {
+        let mut c = TimedCache::with_lifespan_and_refresh(2, true).with_cleanup_budget(4);
+        let start = Instant::now();
+        c.set_at(1, 100, start);
+        c.set_at(2, 200, start);
+        // refreshed, so it outlives the entry stored with it
+        assert!(c.lookup(&1, start + Duration::from_secs(1)).is_some());
+        c.set_at(3, 300, start + Duration::from_secs(2));
+        assert_eq!(c.cache_size(), 2);
+        assert!(c.store.contains_key(&1));
+        // a refreshed entry is examined again after the ones stored before it was refreshed
+        c.set_at(4, 400, start + Duration::from_secs(3));
+        assert_eq!(c.cache_size(), 3);
+        c.set_at(5, 500, start + Duration::from_secs(4));
+        assert_eq!(c.cache_size(), 2);
+        assert!(!c.store.contains_key(&1));
+
+        c.cache_clear();
+        assert_eq!(c.cleanup.tracked(), 0);
+    }
+
+    #[test]
+    fn pinned_entries_never_expire() {
+        let mut c = TimedCache::with_lifespan(2).with_cleanup_budget(4);
+        let start = Instant::now();
+        c.set_at(1, 100, start);
+        c.set_at(2, 200, start);
+        assert!(c.pin(&1));
+        assert!(!c.pin(&3));
+        assert_eq!(c.cache_get_with_expiry(&1), Some((&100, None)));
+
+        // a pinned entry doesn't hold back the sweep of the entries stored after it
+        c.set_at(3, 300, start + Duration::from_secs(3));
+        assert_eq!(c.cache_size(), 2);
+        assert!(c.store.contains_key(&1));
+        c.flush();
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get_with_meta(&1).unwrap().remaining_ttl, None);
+
+        // once unpinned, it expires based on when it was stored
+        assert!(c.unpin(&1));
+        assert!(!c.unpin(&1));
+        assert!(c.lookup(&1, start + Duration::from_secs(3)).is_none());
+        assert!(c.pin(&3));
+        assert_eq!(c.cache_remove(&3), Some(300));
+        assert_eq!(c.pinned_keys().count(), 0);
+    }
 }
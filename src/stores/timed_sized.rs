@@ -1,21 +1,31 @@
 use std::cmp::Eq;
+use std::collections::HashSet;
 use std::hash::Hash;
+use std::time::Duration;
 
 use instant::Instant;
 
 #[cfg(feature = "async")]
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
 
-use crate::stores::timed::Status;
+use crate::stores::timed::{
+    remaining_until_expiry, restamp, stamp_with_ttl, Cleanup, Idle, Status,
+};
 
-use super::{Cached, SizedCache};
+use super::windowed::{WindowedCounter, WindowedStats};
+use super::{is_expired, CacheEntry, Cached, SizedCache};
 
 /// Timed LRU Cache
 ///
 /// Stores a limited number of values,
 /// evicting expired and least-used entries.
 /// Time expiration is determined based on entry insertion time..
-/// The TTL of an entry is not updated when retrieved.
+/// The TTL of an entry is not updated when retrieved, unless the cache is
+/// created with `refresh`. Retrieving an entry always makes it the most
+/// recently used one, last in line for eviction.
+/// Entries can be [pinned](TimedSizedCache::pin) to exempt them from expiry and eviction.
+/// With a [time-to-idle](TimedSizedCache::with_size_and_lifespan_and_tti), entries also
+/// expire when they weren't retrieved for it.
 ///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
@@ -25,7 +35,10 @@ pub struct TimedSizedCache<K, V> {
     pub(super) seconds: u64,
     pub(super) hits: u64,
     pub(super) misses: u64,
+    pub(super) window: WindowedCounter,
     pub(super) refresh: bool,
+    pub(super) idle: Idle<K>,
+    pub(super) cleanup: Cleanup<K>,
 }
 
 impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
@@ -35,7 +48,9 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
     }
 
     /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data.
-    /// Also set if the ttl should be refreshed on retrieving
+    /// Also set if the ttl should be refreshed on retrieving: when `true`, every hit restarts the
+    /// lifespan of the entry, otherwise entries expire `seconds` after they were set however often
+    /// they're retrieved
     pub fn with_size_and_lifespan_and_refresh(
         size: usize,
         seconds: u64,
@@ -50,7 +65,26 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
             seconds,
             hits: 0,
             misses: 0,
+            window: WindowedCounter::default(),
             refresh,
+            idle: Idle::none(),
+            cleanup: Cleanup::none(),
+        }
+    }
+
+    /// Creates a new `TimedSizedCache` with a given size limit, lifespan and time-to-idle: an
+    /// entry expires `seconds` after being inserted, or `tti_seconds` after it was last
+    /// inserted or retrieved, whichever comes first (see
+    /// [`TimedCache::with_lifespan_and_tti`](crate::TimedCache::with_lifespan_and_tti)).
+    /// Pinned entries don't expire either way.
+    pub fn with_size_and_lifespan_and_tti(
+        size: usize,
+        seconds: u64,
+        tti_seconds: u64,
+    ) -> TimedSizedCache<K, V> {
+        TimedSizedCache {
+            idle: Idle::with_tti(tti_seconds),
+            ..Self::with_size_and_lifespan(size, seconds)
         }
     }
 
@@ -68,15 +102,159 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
             seconds,
             hits: 0,
             misses: 0,
+            window: WindowedCounter::default(),
             refresh: false,
+            idle: Idle::none(),
+            cleanup: Cleanup::none(),
         })
     }
 
+    /// Creates a new `TimedSizedCache` with a given size limit and lifespan, warmed up with the
+    /// most recently used unexpired entries of `other` that fit, e.g. to resize a cache or
+    /// change its lifespan without starting cold.
+    ///
+    /// The entries keep their order and remaining TTL, cut down to `seconds` when it's longer:
+    /// a new lifespan never extends the life of an entry. The pinned entries stay pinned, the
+    /// cache refreshes lifetimes and expires idle entries like `other` does, and its metrics
+    /// start from zero.
+    pub fn from_cache_with_size_and_lifespan(
+        other: &TimedSizedCache<K, V>,
+        size: usize,
+        seconds: u64,
+    ) -> TimedSizedCache<K, V>
+    where
+        V: Clone,
+    {
+        let mut cache = Self::with_size_and_lifespan_and_refresh(size, seconds, other.refresh);
+        let now = Instant::now();
+        cache.store = SizedCache::from_entries(&other.store, size, |k, (stamp, v)| {
+            if other.idle.is_idle(k, now) {
+                return None;
+            }
+            restamp(*stamp, other.seconds, seconds, now).map(|stamp| (stamp, v.clone()))
+        });
+        cache.idle = other.idle.clone();
+        cache.prune_idle(0);
+        cache.cleanup = other.cleanup.fresh();
+        cache.rebuild_cleanup();
+        cache
+    }
+
+    /// Remove up to `budget` expired entries on each write, without waiting for them to be
+    /// evicted or calling [`TimedSizedCache::flush`] (see
+    /// [`TimedCache::with_cleanup_budget`](crate::TimedCache::with_cleanup_budget)).
+    /// Pinned entries are skipped. A zero `budget` disables the cleanup.
+    pub fn with_cleanup_budget(mut self, budget: usize) -> Self {
+        self.cleanup = Cleanup::with_budget(budget);
+        self.rebuild_cleanup();
+        self
+    }
+
+    fn rebuild_cleanup(&mut self) {
+        let store = &self.store;
+        self.cleanup
+            .rebuild(store.iter_order().map(|(k, (stamp, _))| (k, *stamp)));
+    }
+
+    /// Examine up to the cleanup budget of the least recently stamped entries, removing the
+    /// expired ones
+    fn clean_up(&mut self, now: Instant) {
+        // the store also evicts entries without telling
+        if self.cleanup.is_bloated(self.store.cache_size()) {
+            self.rebuild_cleanup();
+        }
+        for _ in 0..self.cleanup.budget() {
+            let (stamp, key) = match self.cleanup.pop() {
+                Some(tracked) => tracked,
+                None => break,
+            };
+            match self.store.peek(&key).map(|(instant, _)| *instant) {
+                // removed or evicted since it was tracked
+                None => {}
+                Some(instant) if instant != stamp || self.store.is_pinned(&key) => {
+                    self.cleanup.requeue(instant, key)
+                }
+                Some(instant) if self.is_entry_expired(&key, instant, now) => {
+                    self.store.cache_remove(&key);
+                    self.idle.forget(&key);
+                }
+                Some(_) => {
+                    self.cleanup.put_back(stamp, key);
+                    break;
+                }
+            }
+        }
+    }
+
     fn iter_order(&self) -> impl Iterator<Item = &(K, (Instant, V))> {
-        let max_seconds = self.seconds;
+        let now = Instant::now();
         self.store
             .iter_order()
-            .filter(move |(_k, stamped)| stamped.0.elapsed().as_secs() < max_seconds)
+            .filter(move |(k, stamped)| !self.is_entry_expired(k, stamped.0, now))
+    }
+
+    /// Whether the entry of `key` stamped at `instant` expired at `now`, either a lifespan
+    /// after its stamp or when it wasn't accessed for the time-to-idle, unless it's pinned
+    fn is_entry_expired(&self, key: &K, instant: Instant, now: Instant) -> bool {
+        !self.store.is_pinned(key)
+            && (is_expired(instant, self.seconds, now) || self.idle.is_idle(key, now))
+    }
+
+    fn status(&self, key: &K, now: Instant) -> Status {
+        match self.store.peek(key) {
+            Some((instant, _)) if self.is_entry_expired(key, *instant, now) => Status::Expired,
+            Some(_) => Status::Found,
+            None => Status::NotFound,
+        }
+    }
+
+    /// Look `key` up at `now`, counting a hit or a miss and removing the entry if it expired
+    fn lookup(&mut self, key: &K, now: Instant) -> Option<&mut (Instant, V)> {
+        match self.status(key, now) {
+            Status::NotFound => {
+                self.misses += 1;
+                self.window.miss();
+                None
+            }
+            Status::Found => {
+                self.hits += 1;
+                self.window.hit();
+                self.idle.touch(key, now);
+                let stamped = self.store.cache_get_mut(key)?;
+                if self.refresh {
+                    stamped.0 = now;
+                }
+                Some(stamped)
+            }
+            Status::Expired => {
+                self.misses += 1;
+                self.window.miss();
+                self.store.cache_remove(key);
+                self.idle.forget(key);
+                None
+            }
+        }
+    }
+
+    fn set_at(&mut self, key: K, val: V, now: Instant) -> Option<V> {
+        let live = matches!(self.status(&key, now), Status::Found);
+        self.idle.touch(&key, now);
+        if self.store.peek(&key).is_none() {
+            self.cleanup.track(&key, now);
+        }
+        let old = self.store.cache_set(key, (now, val));
+        self.prune_idle(2 * self.size);
+        self.clean_up(now);
+        old.filter(|_| live).map(|(_, v)| v)
+    }
+
+    /// The store evicts entries without telling, so once more than `max` keys are tracked for
+    /// the time-to-idle, stop tracking the evicted ones
+    fn prune_idle(&mut self, max: usize) {
+        if self.idle.tracked() > max {
+            let store = &self.store;
+            self.idle.retain(|k| store.peek(k).is_some());
+        }
     }
 
     /// Return an iterator of keys in the current order from most
@@ -93,6 +271,12 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         self.iter_order().map(|(_k, v)| v)
     }
 
+    /// Returns the time-to-idle of the cache, see
+    /// [`TimedSizedCache::with_size_and_lifespan_and_tti`]
+    pub fn tti(&self) -> Option<u64> {
+        self.idle.seconds()
+    }
+
     /// Returns if the lifetime is refreshed when the value is retrieved
     pub fn refresh(&self) -> bool {
         self.refresh
@@ -110,120 +294,156 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
 
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
-        let seconds = self.seconds;
+        let now = Instant::now();
+        let expired = self
+            .store
+            .iter_order()
+            .filter(|(k, (instant, _))| self.is_entry_expired(k, *instant, now))
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+        for k in expired {
+            self.store.cache_remove(&k);
+        }
+        self.prune_idle(0);
+        self.rebuild_cleanup();
+    }
+
+    /// Pin a cached `key`, exempting it from expiry and eviction until it's unpinned or
+    /// removed. Pinned entries still count against the cache size, and at most `size - 1`
+    /// entries can be pinned so there's always room for new entries.
+    ///
+    /// Returns `false` when `key` isn't cached (or has expired), or the pin limit is reached.
+    pub fn pin(&mut self, key: &K) -> bool {
+        let (max_seconds, now) = (self.seconds, Instant::now());
+        let idle = self.idle.is_idle(key, now);
+        self.store.pin_if(key, |stamped| {
+            !idle && !is_expired(stamped.0, max_seconds, now)
+        })
+    }
+
+    /// Unpin `key`, returning whether it was pinned. Its value then expires based on
+    /// when it was stored, so a value pinned for longer than the lifespan expires right away.
+    /// Likewise, with a time-to-idle, it expires based on when it was last accessed.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        self.store.unpin(key)
+    }
+
+    /// Insert many `(key, value, ttl)` entries at once, each expiring `ttl` after being
+    /// inserted instead of after the cache lifespan (see [`TimedCache::cache_set_many_with_ttl`](crate::TimedCache::cache_set_many_with_ttl)).
+    ///
+    /// Entries are inserted in order, so a batch larger than the cache size evicts its own
+    /// earlier entries. Returns the number of distinct keys of the batch still cached
+    /// after the batch.
+    pub fn cache_set_many_with_ttl<I>(&mut self, entries: I) -> usize
+    where
+        I: IntoIterator<Item = (K, V, Duration)>,
+    {
+        let now = Instant::now();
+        let mut batch = HashSet::new();
+        for (key, value, ttl) in entries {
+            if ttl.is_zero() {
+                continue;
+            }
+            let stamp = stamp_with_ttl(now, self.seconds, ttl);
+            batch.insert(key.clone());
+            self.idle.touch(&key, now);
+            // an entry restamped earlier is tracked again so the cleanup reaches it in time
+            if self.store.peek(&key).is_none_or(|(old, _)| stamp < *old) {
+                self.cleanup.track(&key, stamp);
+            }
+            self.store.cache_set(key, (stamp, value));
+        }
+        self.prune_idle(2 * self.size);
+        self.clean_up(now);
+        batch
+            .iter()
+            .filter(|key| self.store.peek(key).is_some())
+            .count()
+    }
+
+    /// Return an iterator of pinned keys in the current order from most
+    /// to least recently used.
+    pub fn pinned_keys(&self) -> impl Iterator<Item = &K> {
         self.store
-            .retain(|_, (instant, _)| instant.elapsed().as_secs() < seconds);
+            .iter_order()
+            .map(|(k, _v)| k)
+            .filter(move |k| self.store.is_pinned(k))
+    }
+    /// Keep rolling hit and miss counts over the last `buckets` intervals of `bucket_width`,
+    /// read with [`Cached::cache_stats_windowed`], e.g. to alert on the hit rate of the last
+    /// few minutes. Each lookup only bumps the count of the current interval.
+    ///
+    /// Panics if `bucket_width` or `buckets` is zero.
+    pub fn with_windowed_stats(mut self, bucket_width: Duration, buckets: usize) -> Self {
+        self.window.enable(bucket_width, buckets);
+        self
     }
 }
 
 impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     fn cache_get(&mut self, key: &K) -> Option<&V> {
-        let status = {
-            let mut val = self.store.get_mut_if(key, |_| true);
-            if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        *instant = Instant::now();
-                    }
-                    Status::Found
-                } else {
-                    Status::Expired
-                }
-            } else {
-                Status::NotFound
-            }
+        self.lookup(key, Instant::now()).map(|stamped| &stamped.1)
+    }
+
+    fn cache_get_with_expiry(&mut self, key: &K) -> Option<(&V, Option<Duration>)> {
+        let now = Instant::now();
+        let instant = self.lookup(key, now)?.0;
+        let expires_in = if self.store.is_pinned(key) {
+            None
+        } else {
+            let lifespan = Duration::from_secs(self.seconds);
+            let ttl = lifespan.saturating_sub(now.saturating_duration_since(instant));
+            Some(remaining_until_expiry(ttl, self.idle.remaining(key, now)))
         };
-        match status {
-            Status::NotFound => {
-                self.misses += 1;
-                None
-            }
-            Status::Found => {
-                self.hits += 1;
-                self.store.cache_get(key).map(|stamped| &stamped.1)
-            }
-            Status::Expired => {
-                self.misses += 1;
-                self.store.cache_remove(key);
-                None
-            }
-        }
+        self.store.peek(key).map(|(_, v)| (v, expires_in))
     }
 
     fn cache_get_mut(&mut self, key: &K) -> std::option::Option<&mut V> {
-        let status = {
-            let mut val = self.store.get_mut_if(key, |_| true);
-            if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
-                    if self.refresh {
-                        *instant = Instant::now();
-                    }
-                    Status::Found
-                } else {
-                    Status::Expired
-                }
-            } else {
-                Status::NotFound
-            }
-        };
-        match status {
-            Status::NotFound => {
-                self.misses += 1;
-                None
-            }
-            Status::Found => {
-                self.hits += 1;
-                self.store.cache_get_mut(key).map(|stamped| &mut stamped.1)
-            }
-            Status::Expired => {
-                self.misses += 1;
-                self.store.cache_remove(key);
-                None
-            }
-        }
+        self.lookup(key, Instant::now())
+            .map(|stamped| &mut stamped.1)
     }
 
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
-        let setter = || (Instant::now(), f());
-        let max_seconds = self.seconds;
-        let (was_present, was_valid, stamped) =
-            self.store.get_or_set_with_if(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
-            });
-        if was_present && was_valid {
-            if self.refresh {
-                stamped.0 = Instant::now();
-            }
-            self.hits += 1;
-        } else {
-            self.misses += 1;
+        let now = Instant::now();
+        // an expired entry is removed by the lookup, so the entry is only present on hits
+        if self.lookup(&key, now).is_some() {
+            let stamped = self
+                .store
+                .peek_mut(&key)
+                .expect("looked up entry is present");
+            return &mut stamped.1;
         }
+        self.idle.touch(&key, now);
+        self.prune_idle(2 * self.size);
+        self.cleanup.track(&key, now);
+        self.clean_up(now);
+        let (_, _, stamped) = self.store.get_or_set_with_if(key, || (now, f()), |_| true);
         &mut stamped.1
     }
 
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = self.store.cache_set(key, (Instant::now(), val));
-        stamped.and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
-                Some(v)
-            } else {
-                None
-            }
-        })
+        self.set_at(key, val, Instant::now())
     }
 
     fn cache_remove(&mut self, k: &K) -> Option<V> {
+        let live = matches!(self.status(k, Instant::now()), Status::Found);
+        self.idle.forget(k);
         let stamped = self.store.cache_remove(k);
-        stamped.and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
-                Some(v)
-            } else {
-                None
-            }
-        })
+        stamped.filter(|_| live).map(|(_, v)| v)
+    }
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let (seconds, now) = (self.seconds, Instant::now());
+        let idle = &self.idle;
+        self.store.retain_entries(|k, (instant, v), pinned| {
+            (pinned || (!is_expired(*instant, seconds, now) && !idle.is_idle(k, now))) && f(k, v)
+        });
+        self.prune_idle(0);
+        self.rebuild_cleanup();
     }
     fn cache_clear(&mut self) {
         self.store.cache_clear();
+        self.idle.clear();
+        self.cleanup.clear();
     }
     fn cache_reset(&mut self) {
         self.cache_clear();
@@ -231,16 +451,62 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     fn cache_reset_metrics(&mut self) {
         self.misses = 0;
         self.hits = 0;
+        self.window.reset();
     }
     fn cache_size(&self) -> usize {
         self.store.cache_size()
     }
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        // most recently stored first, i.e. the longest remaining lifespan first
+        let mut snapshot = self.iter_order().collect::<Vec<_>>();
+        snapshot.sort_by(|(ka, (ia, _)), (kb, (ib, _))| ib.cmp(ia).then_with(|| ka.cmp(kb)));
+        snapshot
+            .into_iter()
+            .map(|(k, (_, v))| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn cache_get_stale(&self, k: &K) -> Option<&V> {
+        self.store.peek(k).map(|(_, value)| value)
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        let ((instant, value), hits) = self.store.peek_with_hits(k)?;
+        let now = Instant::now();
+        let lifespan = Duration::from_secs(self.seconds);
+        let elapsed = now.saturating_duration_since(*instant);
+        let remaining_ttl = if self.store.is_pinned(k) {
+            None
+        } else if elapsed < lifespan && !self.idle.is_idle(k, now) {
+            Some(remaining_until_expiry(
+                lifespan - elapsed,
+                self.idle.remaining(k, now),
+            ))
+        } else {
+            return None;
+        };
+        Some(CacheEntry {
+            value,
+            inserted_at: Some(*instant),
+            remaining_ttl,
+            hits: Some(hits),
+        })
+    }
+
     fn cache_hits(&self) -> Option<u64> {
         Some(self.hits)
     }
     fn cache_misses(&self) -> Option<u64> {
         Some(self.misses)
     }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.window.stats(window)
+    }
     fn cache_capacity(&self) -> Option<usize> {
         Some(self.size)
     }
@@ -254,6 +520,16 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     }
 }
 
+/// Inserts the entries in order, like [`Cached::cache_extend`], each expiring a lifespan after
+/// being inserted. Once the cache is full, each new key evicts the least recently used entry
+/// (pinned entries aren't evicted), so extending it with more distinct keys than its size keeps
+/// the last ones.
+impl<K: Hash + Eq + Clone, V> Extend<(K, V)> for TimedSizedCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
+    }
+}
+
 #[cfg(feature = "async")]
 #[async_trait]
 impl<K, V> CachedAsync<K, V> for TimedSizedCache<K, V>
@@ -266,22 +542,18 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = V> + Send,
     {
-        let setter = || async { (Instant::now(), f().await) };
-        let max_seconds = self.seconds;
-        let (was_present, was_valid, stamped) = self
+        let now = Instant::now();
+        if self.lookup(&key, now).is_none() {
+            self.idle.touch(&key, now);
+            self.prune_idle(2 * self.size);
+            self.cleanup.track(&key, now);
+            self.clean_up(now);
+        }
+        let setter = || async { (now, f().await) };
+        let (_, _, stamped) = self
             .store
-            .get_or_set_with_if_async(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
-            })
+            .get_or_set_with_if_async(key, setter, |_| true)
             .await;
-        if was_present && was_valid {
-            if self.refresh {
-                stamped.0 = Instant::now();
-            }
-            self.hits += 1;
-        } else {
-            self.misses += 1;
-        }
         &mut stamped.1
     }
 
@@ -291,24 +563,26 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = Result<V, E>> + Send,
     {
+        let now = Instant::now();
+        // only track the key once its value is computed, a failure doesn't cache anything
+        let untracked = match self.lookup(&key, now) {
+            Some(_) => None,
+            None => Some(key.clone()),
+        };
+        if untracked.is_some() {
+            self.clean_up(now);
+        }
         let setter = || async {
             let new_val = f().await?;
-            Ok((Instant::now(), new_val))
+            Ok((now, new_val))
         };
-        let max_seconds = self.seconds;
-        let (was_present, was_valid, stamped) = self
+        let (_, _, stamped) = self
             .store
-            .try_get_or_set_with_if_async(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
-            })
+            .try_get_or_set_with_if_async(key, setter, |_| true)
             .await?;
-        if was_present && was_valid {
-            if self.refresh {
-                stamped.0 = Instant::now();
-            }
-            self.hits += 1;
-        } else {
-            self.misses += 1;
+        if let Some(key) = untracked {
+            self.idle.touch(&key, now);
+            self.cleanup.track(&key, now);
         }
         Ok(&mut stamped.1)
     }
@@ -426,6 +700,41 @@ mod tests {
         assert_eq!(c.cache_get(&2), None);
     }
 
+    #[test]
+    fn refreshed_entries_are_evicted_last() {
+        let mut c = TimedSizedCache::with_size_and_lifespan_and_refresh(2, 60, true);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        c.cache_set(3, 300);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [3, 1]);
+
+        assert_eq!(c.cache_get_mut(&1), Some(&mut 100));
+        c.cache_set(4, 400);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [4, 1]);
+
+        assert_eq!(c.cache_get_or_set_with(1, || 101), &100);
+        c.cache_set(5, 500);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [5, 1]);
+    }
+
+    #[test]
+    fn refresh_slides_the_lifespan() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let mut c = TimedSizedCache::with_size_and_lifespan_and_refresh(2, 2, true);
+        c.set_at(1, 100, start);
+        assert_eq!(c.lookup(&1, at(1500)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(3000)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(6500)).map(|e| e.1), None);
+
+        let mut c = TimedSizedCache::with_size_and_lifespan_and_refresh(2, 2, false);
+        c.set_at(1, 100, start);
+        assert_eq!(c.lookup(&1, at(1500)).map(|e| e.1), Some(100));
+        assert_eq!(c.lookup(&1, at(2000)).map(|e| e.1), None);
+    }
+
     #[test]
     fn try_new() {
         let c: std::io::Result<TimedSizedCache<i32, i32>> =
@@ -479,6 +788,59 @@ mod tests {
         assert_eq!(0, c.cache_size());
     }
 
+    #[test]
+    fn retain() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 1);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        assert!(c.pin(&1));
+        sleep(Duration::new(1, 0));
+        c.cache_set(3, 300);
+        c.cache_set(4, 400);
+
+        let mut seen = Vec::new();
+        c.cache_retain(|k, v| {
+            seen.push(*k);
+            *v += 1;
+            *k != 4
+        });
+        // the expired value isn't passed to the predicate, the pinned one is
+        seen.sort_unstable();
+        assert_eq!(seen, [1, 3, 4]);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_get(&1), Some(&101));
+        assert_eq!(c.cache_get(&3), Some(&301));
+        assert_eq!(c.pinned_keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn from_cache_with_size_and_lifespan() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(4, 100);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+        c.cache_set_many_with_ttl([(3, 300, Duration::from_secs(2))]);
+        c.cache_set(4, 400);
+        assert!(c.pin(&1));
+
+        let mut resized = TimedSizedCache::from_cache_with_size_and_lifespan(&c, 3, 1000);
+        assert_eq!(resized.key_order().cloned().collect::<Vec<_>>(), [4, 3, 2]);
+        assert_eq!(resized.pinned_keys().count(), 0);
+        // a longer lifespan doesn't extend the remaining TTL
+        let (_, ttl) = resized.cache_get_with_expiry(&3).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(2));
+        let (_, ttl) = resized.cache_get_with_expiry(&2).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(100));
+
+        // a shorter one cuts it down
+        let mut shortened = TimedSizedCache::from_cache_with_size_and_lifespan(&c, 4, 1);
+        assert_eq!(shortened.pinned_keys().collect::<Vec<_>>(), [&1]);
+        let (_, ttl) = shortened.cache_get_with_expiry(&4).unwrap();
+        assert!(ttl.unwrap() <= Duration::from_secs(1));
+        sleep(Duration::new(2, 0));
+        assert_eq!(shortened.key_order().cloned().collect::<Vec<_>>(), [1]);
+        assert_eq!(c.key_order().count(), 3);
+    }
+
     #[test]
     fn remove_expired() {
         let mut c = TimedSizedCache::with_size_and_lifespan(3, 1);
@@ -672,4 +1034,204 @@ mod tests {
             .await;
         assert_eq!(res.unwrap(), &5);
     }
+
+    #[test]
+    fn snapshot() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 10);
+        let now = Instant::now();
+        c.store.cache_set(1, (now - Duration::from_secs(5), 10));
+        c.store.cache_set(2, (now - Duration::from_secs(20), 20));
+        c.store.cache_set(3, (now - Duration::from_secs(1), 30));
+        c.cache_set(4, 40);
+        assert_eq!(c.cache_snapshot(), [(4, 40), (3, 30), (1, 10)]);
+    }
+
+    #[test]
+    fn expiry_boundary() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 10);
+        let now = Instant::now();
+        // values expire once exactly their lifespan has elapsed
+        c.store.cache_set(1, (now - Duration::from_secs(10), 10));
+        c.store
+            .cache_set(2, (now - Duration::from_millis(9_900), 20));
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get(&2), Some(&20));
+
+        let mut c = TimedSizedCache::with_size_and_lifespan(5, 0);
+        c.cache_set(1, 10);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get_or_set_with(1, || 11), &11);
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+
+    #[test]
+    fn pin_survives_expiry() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 10);
+        let expired = Instant::now() - Duration::from_secs(20);
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        assert!(c.pin(&1));
+        c.store.cache_get_mut(&1).unwrap().0 = expired;
+        c.store.cache_get_mut(&2).unwrap().0 = expired;
+
+        assert!(!c.pin(&2), "expired keys can't be pinned");
+        assert!(!c.pin(&3), "missing keys can't be pinned");
+        assert_eq!(c.cache_get(&1), Some(&10));
+        assert_eq!(c.cache_get(&2), None);
+        c.flush();
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1]);
+
+        assert!(c.unpin(&1));
+        assert!(!c.unpin(&1));
+        assert_eq!(c.cache_get(&1), None);
+    }
+
+    #[test]
+    fn pin_under_capacity_pressure() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 10);
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        c.cache_set(3, 30);
+        assert!(c.pin(&1));
+        assert!(c.pin(&2));
+        assert!(!c.pin(&3), "at most `size - 1` keys can be pinned");
+
+        // 1 and 2 are the least recently used, but pinned
+        for n in 4..10 {
+            c.cache_set(n, n * 10);
+        }
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [9, 2, 1]);
+        assert_eq!(c.pinned_keys().copied().collect::<Vec<_>>(), [2, 1]);
+
+        c.cache_remove(&2);
+        assert_eq!(c.pinned_keys().copied().collect::<Vec<_>>(), [1]);
+        c.cache_set(10, 100);
+        c.cache_set(11, 110);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [11, 10, 1]);
+    }
+
+    #[test]
+    fn set_many_with_ttl() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 3);
+        c.cache_set(1, 10);
+        assert!(c.pin(&1));
+        let stored = c.cache_set_many_with_ttl(vec![
+            (2, 20, Duration::from_secs(1)),
+            (3, 30, Duration::from_secs(2)),
+            (4, 40, Duration::from_secs(0)),
+            (5, 50, Duration::from_secs(2)),
+        ]);
+        // 2 was evicted by 5, the pinned 1 stays
+        assert_eq!(stored, 2);
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [5, 3, 1]);
+
+        sleep(Duration::new(1, 0));
+        assert_eq!(c.cache_get(&3), Some(&30));
+        assert_eq!(c.cache_get(&1), Some(&10));
+    }
+
+    #[test]
+    fn cleanup_budget_with_mixed_ttls() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(10, 10).with_cleanup_budget(1);
+        c.cache_set_many_with_ttl(vec![
+            (1, 10, Duration::from_secs(10)),
+            (2, 20, Duration::from_secs(1)),
+        ]);
+
+        // the short TTL is cleaned up first, even though the long one was inserted before
+        sleep(Duration::new(1, 0));
+        c.cache_set(3, 30);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_get(&1), Some(&10));
+    }
+
+    #[test]
+    fn get_with_meta() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(3, 10);
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        assert!(c.pin(&2));
+        let entry = c.cache_get_with_meta(&1).unwrap();
+        assert_eq!(entry.value, &10);
+        assert!(entry.inserted_at.is_some());
+        assert!(entry.remaining_ttl.unwrap() > Duration::from_secs(9));
+        assert_eq!(c.cache_get_with_meta(&2).unwrap().remaining_ttl, None);
+        // peeking doesn't change the eviction order
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(c.cache_hits(), Some(0));
+
+        assert_eq!(entry.hits, Some(0));
+        c.cache_get(&1);
+        c.cache_get_or_set_with(1, || 11);
+        assert_eq!(c.cache_get_with_meta(&1).unwrap().hits, Some(2));
+        c.cache_set(1, 11);
+        assert_eq!(c.cache_get_with_meta(&1).unwrap().hits, Some(0));
+    }
+
+    #[test]
+    fn tti() {
+        // a lifespan of 10s and a time-to-idle of 4s, looked up with a manual clock
+        let mut c = TimedSizedCache::with_size_and_lifespan_and_tti(5, 10, 4);
+        assert_eq!(c.tti(), Some(4));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        for key in 1..=4 {
+            c.set_at(key, key * 100, start);
+        }
+        assert!(c.pin(&4));
+
+        // accessed frequently, before the lifespan is over: never idle
+        for secs in [3, 6, 9] {
+            assert_eq!(c.lookup(&1, at(secs)).map(|(_, v)| *v), Some(100));
+        }
+        // accessed frequently, after the lifespan is over: expired all the same
+        assert!(c.lookup(&1, at(10)).is_none());
+
+        // idle before the lifespan is over
+        assert!(c.lookup(&2, at(5)).is_none());
+
+        // idle after the lifespan is over
+        assert!(c.lookup(&3, at(11)).is_none());
+
+        // pinned entries don't expire either way
+        assert!(c.lookup(&4, at(11)).is_some());
+
+        assert_eq!(c.cache_hits(), Some(4));
+        assert_eq!(c.cache_misses(), Some(3));
+        assert_eq!(c.key_order().cloned().collect::<Vec<_>>(), [4]);
+        assert_eq!(c.idle.tracked(), 1);
+    }
+
+    #[test]
+    fn tti_evicted() {
+        let mut c = TimedSizedCache::with_size_and_lifespan_and_tti(2, 100, 100);
+        for key in 0..10 {
+            c.cache_set(key, key);
+        }
+        // evicted keys stop being tracked
+        assert!(c.idle.tracked() <= 4);
+
+        c.cache_set(10, 10);
+        let copy = TimedSizedCache::from_cache_with_size_and_lifespan(&c, 1, 100);
+        assert_eq!(copy.tti(), Some(100));
+        assert_eq!(copy.idle.tracked(), 1);
+    }
+
+    #[test]
+    fn cleanup_budget_under_churn() {
+        let mut c = TimedSizedCache::with_size_and_lifespan(10_000, 10).with_cleanup_budget(2);
+        c.cache_set(0, 0);
+        assert!(c.pin(&0));
+        let start = Instant::now();
+        let mut largest = 0;
+        for i in 1..10_000u64 {
+            c.set_at(i, i, start + Duration::from_millis(100 * i));
+            largest = largest.max(c.cache_size());
+        }
+        // ~100 keys are written per lifespan, and the pinned one stays
+        assert!(largest <= 102, "{}", largest);
+        assert!(c.store.peek(&0).is_some());
+        assert!(c.cleanup.tracked() <= 2 * c.cache_size() + 2);
+    }
 }
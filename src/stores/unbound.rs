@@ -1,7 +1,10 @@
-use super::Cached;
+use super::windowed::{WindowedCounter, WindowedStats};
+use super::{CacheEntry, Cached, CachedRead};
 use std::cmp::Eq;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use std::collections::hash_map::Entry;
 
@@ -16,11 +19,70 @@ use {super::CachedAsync, async_trait::async_trait, futures::Future};
 #[derive(Clone, Debug)]
 pub struct UnboundCache<K, V> {
     pub(super) store: HashMap<K, V>,
-    pub(super) hits: u64,
-    pub(super) misses: u64,
+    pub(super) hits: Counter,
+    pub(super) misses: Counter,
+    pub(super) window: WindowedCounter,
     pub(super) initial_capacity: Option<usize>,
+    pub(super) growth: GrowthWarning,
 }
 
+/// A lifetime hit or miss count, bumped through a shared reference by
+/// [`CachedRead::cache_read`]
+#[derive(Debug, Default)]
+pub(super) struct Counter(AtomicU64);
+
+impl Clone for Counter {
+    fn clone(&self) -> Self {
+        Counter(AtomicU64::new(self.get()))
+    }
+}
+
+impl Counter {
+    fn bump(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn reset(&mut self) {
+        *self.0.get_mut() = 0;
+    }
+}
+
+/// Warns once when an `UnboundCache` grows past a number of entries
+#[derive(Clone, Debug, Default)]
+pub(super) struct GrowthWarning {
+    threshold: Option<(String, usize)>,
+    warned: bool,
+}
+
+impl GrowthWarning {
+    fn check(&mut self, len: usize) {
+        match &self.threshold {
+            Some((name, threshold)) if !self.warned && len > *threshold => {
+                self.warned = true;
+                warn_growth(name, len, *threshold);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+fn warn_growth(name: &str, len: usize, threshold: usize) {
+    log::warn!(
+        "unbound cache `{}` has grown to {} entries, past its warning threshold of {}",
+        name,
+        len,
+        threshold
+    );
+}
+
+#[cfg(not(feature = "log"))]
+fn warn_growth(_name: &str, _len: usize, _threshold: usize) {}
+
 impl<K, V> PartialEq for UnboundCache<K, V>
 where
     K: Eq + Hash,
@@ -44,9 +106,11 @@ impl<K: Hash + Eq, V> UnboundCache<K, V> {
     pub fn new() -> UnboundCache<K, V> {
         UnboundCache {
             store: Self::new_store(None),
-            hits: 0,
-            misses: 0,
+            hits: Counter::default(),
+            misses: Counter::default(),
+            window: WindowedCounter::default(),
             initial_capacity: None,
+            growth: GrowthWarning::default(),
         }
     }
 
@@ -54,9 +118,30 @@ impl<K: Hash + Eq, V> UnboundCache<K, V> {
     pub fn with_capacity(size: usize) -> UnboundCache<K, V> {
         UnboundCache {
             store: Self::new_store(Some(size)),
-            hits: 0,
-            misses: 0,
+            hits: Counter::default(),
+            misses: Counter::default(),
+            window: WindowedCounter::default(),
             initial_capacity: Some(size),
+            growth: GrowthWarning::default(),
+        }
+    }
+
+    /// Creates an empty `UnboundCache` that logs a warning naming the cache the first
+    /// time it grows past `threshold` entries (and again after being cleared), so runaway
+    /// growth is noticed before it runs the process out of memory. The cache stays unbounded.
+    ///
+    /// Warnings are logged with the [`log`](https://docs.rs/log) crate when the `log` feature is enabled.
+    pub fn with_warn_threshold<S: AsRef<str>>(name: S, threshold: usize) -> UnboundCache<K, V> {
+        UnboundCache {
+            store: Self::new_store(None),
+            hits: Counter::default(),
+            misses: Counter::default(),
+            window: WindowedCounter::default(),
+            initial_capacity: None,
+            growth: GrowthWarning {
+                threshold: Some((name.as_ref().to_string(), threshold)),
+                warned: false,
+            },
         }
     }
 
@@ -68,17 +153,28 @@ impl<K: Hash + Eq, V> UnboundCache<K, V> {
     pub fn get_store(&self) -> &HashMap<K, V> {
         &self.store
     }
+    /// Keep rolling hit and miss counts over the last `buckets` intervals of `bucket_width`,
+    /// read with [`Cached::cache_stats_windowed`], e.g. to alert on the hit rate of the last
+    /// few minutes. Each lookup only bumps the count of the current interval.
+    ///
+    /// Panics if `bucket_width` or `buckets` is zero.
+    pub fn with_windowed_stats(mut self, bucket_width: Duration, buckets: usize) -> Self {
+        self.window.enable(bucket_width, buckets);
+        self
+    }
 }
 
 impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
     fn cache_get(&mut self, key: &K) -> Option<&V> {
         match self.store.get(key) {
             Some(v) => {
-                self.hits += 1;
+                self.hits.bump();
+                self.window.hit();
                 Some(v)
             }
             None => {
-                self.misses += 1;
+                self.misses.bump();
+                self.window.miss();
                 None
             }
         }
@@ -86,27 +182,35 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
     fn cache_get_mut(&mut self, key: &K) -> std::option::Option<&mut V> {
         match self.store.get_mut(key) {
             Some(v) => {
-                self.hits += 1;
+                self.hits.bump();
+                self.window.hit();
                 Some(v)
             }
             None => {
-                self.misses += 1;
+                self.misses.bump();
+                self.window.miss();
                 None
             }
         }
     }
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        self.store.insert(key, val)
+        let old = self.store.insert(key, val);
+        self.growth.check(self.store.len());
+        old
     }
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let len = self.store.len();
         match self.store.entry(key) {
             Entry::Occupied(occupied) => {
-                self.hits += 1;
+                self.hits.bump();
+                self.window.hit();
                 occupied.into_mut()
             }
 
             Entry::Vacant(vacant) => {
-                self.misses += 1;
+                self.misses.bump();
+                self.window.miss();
+                self.growth.check(len + 1);
                 vacant.insert(f())
             }
         }
@@ -114,24 +218,78 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
     fn cache_remove(&mut self, k: &K) -> Option<V> {
         self.store.remove(k)
     }
+    fn cache_retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        self.store.retain(f);
+    }
     fn cache_clear(&mut self) {
         self.store.clear();
+        self.growth.warned = false;
     }
     fn cache_reset(&mut self) {
         self.store = Self::new_store(self.initial_capacity);
+        self.growth.warned = false;
     }
     fn cache_reset_metrics(&mut self) {
-        self.misses = 0;
-        self.hits = 0;
+        self.misses.reset();
+        self.hits.reset();
+        self.window.reset();
     }
     fn cache_size(&self) -> usize {
         self.store.len()
     }
+    fn cache_snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone,
+    {
+        let mut snapshot = self
+            .store
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, V>> {
+        self.store.get(k).map(CacheEntry::new)
+    }
+
     fn cache_hits(&self) -> Option<u64> {
-        Some(self.hits)
+        Some(self.hits.get())
     }
     fn cache_misses(&self) -> Option<u64> {
-        Some(self.misses)
+        Some(self.misses.get())
+    }
+
+    fn cache_stats_windowed(&self, window: Duration) -> WindowedStats {
+        self.window.stats(window)
+    }
+}
+
+/// Lookups don't change the store, so they're served through a shared reference, counting
+/// hits and misses atomically
+impl<K: Hash + Eq, V> CachedRead<K, V> for UnboundCache<K, V> {
+    fn cache_read(&self, key: &K) -> Option<&V> {
+        match self.store.get(key) {
+            Some(v) => {
+                self.hits.bump();
+                self.window.hit();
+                Some(v)
+            }
+            None => {
+                self.misses.bump();
+                self.window.miss();
+                None
+            }
+        }
+    }
+}
+
+/// Inserts the entries in order, like [`Cached::cache_extend`]
+impl<K: Hash + Eq, V> Extend<(K, V)> for UnboundCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        self.cache_extend(entries);
     }
 }
 
@@ -147,14 +305,18 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = V> + Send,
     {
+        let len = self.store.len();
         match self.store.entry(key) {
             Entry::Occupied(occupied) => {
-                self.hits += 1;
+                self.hits.bump();
+                self.window.hit();
                 occupied.into_mut()
             }
 
             Entry::Vacant(vacant) => {
-                self.misses += 1;
+                self.misses.bump();
+                self.window.miss();
+                self.growth.check(len + 1);
                 vacant.insert(f().await)
             }
         }
@@ -166,15 +328,20 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = Result<V, E>> + Send,
     {
+        let len = self.store.len();
         let v = match self.store.entry(key) {
             Entry::Occupied(occupied) => {
-                self.hits += 1;
+                self.hits.bump();
+                self.window.hit();
                 occupied.into_mut()
             }
 
             Entry::Vacant(vacant) => {
-                self.misses += 1;
-                vacant.insert(f().await?)
+                self.misses.bump();
+                self.window.miss();
+                let v = vacant.insert(f().await?);
+                self.growth.check(len + 1);
+                v
             }
         };
         Ok(v)
@@ -201,6 +368,25 @@ mod tests {
         assert_eq!(1, misses);
     }
 
+    #[test]
+    fn shared_reads() {
+        let mut c = UnboundCache::new().with_windowed_stats(Duration::from_secs(60), 2);
+        c.cache_set(1, 100);
+        let shared = &c;
+        assert_eq!(shared.cache_read(&1), Some(&100));
+        assert_eq!(shared.cache_read(&2), None);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(1));
+        let stats = c.cache_stats_windowed(Duration::from_secs(60));
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+
+        // the counts are copied along with the store
+        let copy = c.clone();
+        c.cache_reset_metrics();
+        assert_eq!(copy.cache_hits(), Some(1));
+        assert_eq!(c.cache_hits(), Some(0));
+    }
+
     #[test]
     fn clear() {
         let mut c = UnboundCache::new();
@@ -333,4 +519,35 @@ mod tests {
 
         assert_eq!(c.cache_misses(), Some(6));
     }
+
+    #[test]
+    fn snapshot() {
+        let mut c = UnboundCache::new();
+        assert!(c.cache_snapshot().is_empty());
+        c.cache_set(3, 30);
+        c.cache_set(1, 10);
+        c.cache_set(2, 20);
+        assert_eq!(c.cache_snapshot(), [(1, 10), (2, 20), (3, 30)]);
+    }
+
+    #[test]
+    fn warn_threshold() {
+        let mut c = UnboundCache::with_warn_threshold("WARN", 2);
+        c.cache_set(1, 1);
+        c.cache_set(2, 2);
+        assert!(!c.growth.warned);
+        c.cache_get_or_set_with(3, || 3);
+        assert!(c.growth.warned);
+        c.cache_clear();
+        assert!(!c.growth.warned);
+    }
+
+    #[test]
+    fn extend() {
+        let mut c = UnboundCache::new();
+        c.extend((1..=3).map(|n| (n, n * 10)));
+        c.extend(vec![(1, 100)]);
+        assert_eq!(c.cache_snapshot(), [(1, 100), (2, 20), (3, 30)]);
+        assert_eq!(c.cache_misses(), Some(0));
+    }
 }
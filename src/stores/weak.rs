@@ -0,0 +1,244 @@
+use super::{CacheEntry, Cached};
+use std::cmp::Eq;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+
+/// Number of entries the cache may hold before it first drops the entries of dropped values
+const MIN_PURGE_LEN: usize = 16;
+
+/// Cache holding weak references to its values
+///
+/// Values are stored as [`Weak<V>`] and live only as long as something else holds an
+/// [`Arc<V>`] to them: the cache never keeps a value alive by itself. Once every `Arc` of a
+/// value is dropped, its entry counts as a miss and is removed. Entries of dropped values are
+/// also removed in bulk as the cache grows, so they don't accumulate.
+///
+/// The [`Cached`] methods take and return the `Weak` references, [`WeakCache::get`] and
+/// [`WeakCache::insert`] work with `Arc`s.
+///
+/// ```rust
+/// use cached::stores::WeakCache;
+/// use std::sync::Arc;
+///
+/// let mut cache = WeakCache::new();
+/// let config = Arc::new("config".to_string());
+/// cache.insert(1, &config);
+/// assert_eq!(cache.get(&1).as_deref(), Some(&"config".to_string()));
+///
+/// drop(config);
+/// assert_eq!(cache.get(&1), None);
+/// ```
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct WeakCache<K, V> {
+    store: HashMap<K, Weak<V>>,
+    hits: u64,
+    misses: u64,
+    // dropped values are purged once the store holds this many entries
+    purge_len: usize,
+}
+
+impl<K: Hash + Eq, V> WeakCache<K, V> {
+    /// Creates an empty `WeakCache`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> WeakCache<K, V> {
+        WeakCache {
+            store: HashMap::new(),
+            hits: 0,
+            misses: 0,
+            purge_len: MIN_PURGE_LEN,
+        }
+    }
+
+    /// Attempt to retrieve a cached value that is still alive
+    pub fn get(&mut self, k: &K) -> Option<Arc<V>> {
+        self.cache_get(k).and_then(Weak::upgrade)
+    }
+
+    /// Cache a weak reference to `v`, returning the previous value if it's still alive
+    pub fn insert(&mut self, k: K, v: &Arc<V>) -> Option<Arc<V>> {
+        self.cache_set(k, Arc::downgrade(v))
+            .and_then(|old| old.upgrade())
+    }
+
+    /// Remove the entries of the values that were dropped
+    pub fn purge(&mut self) {
+        self.store.retain(|_, v| v.strong_count() > 0);
+    }
+
+    /// Purge the dropped values once the store doubled in size since the last purge, keeping
+    /// the number of entries of dropped values proportional to the number of live ones
+    fn purge_if_grown(&mut self) {
+        if self.store.len() >= self.purge_len {
+            self.purge();
+            self.purge_len = MIN_PURGE_LEN.max(self.store.len() * 2);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Cached<K, Weak<V>> for WeakCache<K, V> {
+    fn cache_get(&mut self, k: &K) -> Option<&Weak<V>> {
+        self.cache_get_mut(k).map(|v| &*v)
+    }
+
+    fn cache_get_mut(&mut self, k: &K) -> Option<&mut Weak<V>> {
+        let alive = matches!(self.store.get(k), Some(v) if v.strong_count() > 0);
+        if alive {
+            self.hits += 1;
+            self.store.get_mut(k)
+        } else {
+            self.misses += 1;
+            self.store.remove(k);
+            None
+        }
+    }
+
+    fn cache_set(&mut self, k: K, v: Weak<V>) -> Option<Weak<V>> {
+        let old = self.store.insert(k, v);
+        self.purge_if_grown();
+        old
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> Weak<V>>(&mut self, k: K, f: F) -> &mut Weak<V> {
+        self.purge_if_grown();
+        match self.store.entry(k) {
+            Entry::Occupied(mut occupied) => {
+                if occupied.get().strong_count() > 0 {
+                    self.hits += 1;
+                } else {
+                    self.misses += 1;
+                    occupied.insert(f());
+                }
+                occupied.into_mut()
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                vacant.insert(f())
+            }
+        }
+    }
+
+    fn cache_remove(&mut self, k: &K) -> Option<Weak<V>> {
+        self.store.remove(k).filter(|v| v.strong_count() > 0)
+    }
+
+    fn cache_retain<F: FnMut(&K, &mut Weak<V>) -> bool>(&mut self, mut f: F) {
+        self.store.retain(|k, v| v.strong_count() > 0 && f(k, v));
+    }
+
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.purge_len = MIN_PURGE_LEN;
+    }
+
+    fn cache_reset(&mut self) {
+        self.store = HashMap::new();
+        self.purge_len = MIN_PURGE_LEN;
+    }
+
+    fn cache_reset_metrics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Return the number of values still alive
+    fn cache_size(&self) -> usize {
+        self.store.values().filter(|v| v.strong_count() > 0).count()
+    }
+
+    fn cache_snapshot(&self) -> Vec<(K, Weak<V>)>
+    where
+        K: Clone + Ord,
+    {
+        let mut snapshot = self
+            .store
+            .iter()
+            .filter(|(_, v)| v.strong_count() > 0)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    fn cache_get_with_meta(&self, k: &K) -> Option<CacheEntry<'_, Weak<V>>> {
+        self.store
+            .get(k)
+            .filter(|v| v.strong_count() > 0)
+            .map(CacheEntry::new)
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_values_miss() {
+        let mut c = WeakCache::new();
+        let one = Arc::new(1);
+        let two = Arc::new(2);
+        assert_eq!(c.insert("one", &one), None);
+        c.insert("two", &two);
+        assert_eq!(c.get(&"one"), Some(one.clone()));
+        assert_eq!(c.cache_size(), 2);
+
+        drop(two);
+        assert_eq!(c.cache_size(), 1);
+        assert!(c.cache_get(&"two").is_none());
+        assert_eq!(c.store.len(), 1);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(1));
+
+        // the cache doesn't keep values alive
+        let weak = Arc::downgrade(&one);
+        drop(one);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(c.get(&"one"), None);
+    }
+
+    #[test]
+    fn get_or_set_replaces_dropped_values() {
+        let mut c = WeakCache::new();
+        let first = Arc::new("first");
+        c.insert(1, &first);
+        drop(first);
+
+        let second = Arc::new("second");
+        let weak = c.cache_get_or_set_with(1, || Arc::downgrade(&second));
+        assert_eq!(weak.upgrade(), Some(second.clone()));
+        assert_eq!(c.cache_misses(), Some(1));
+        c.cache_get_or_set_with(1, || unreachable!());
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_remove(&1).and_then(|v| v.upgrade()), Some(second));
+    }
+
+    #[test]
+    fn purges_dropped_values() {
+        let mut c = WeakCache::new();
+        for n in 0..100 {
+            // each value is dropped right away
+            c.insert(n, &Arc::new(n));
+        }
+        assert!(c.store.len() < MIN_PURGE_LEN);
+        assert_eq!(c.cache_size(), 0);
+
+        let kept = (0..100).map(Arc::new).collect::<Vec<_>>();
+        for (n, v) in kept.iter().enumerate() {
+            c.insert(n, v);
+        }
+        assert_eq!(c.cache_size(), 100);
+        assert_eq!(c.cache_snapshot().len(), 100);
+    }
+}
@@ -0,0 +1,271 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use instant::Instant;
+
+/// Hits and misses of a cache over a recent window of time, see
+/// [`Cached::cache_stats_windowed`](crate::Cached::cache_stats_windowed)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WindowedStats {
+    /// Number of times a cached value was retrieved during the window
+    pub hits: u64,
+    /// Number of times a value wasn't cached (or had expired) during the window
+    pub misses: u64,
+    /// The window the counts cover: the requested window rounded up to whole buckets and
+    /// capped to the history kept, or zero when windowed stats aren't enabled
+    pub window: Duration,
+}
+
+impl WindowedStats {
+    /// Return the ratio of hits to lookups during the window, or `None` without lookups
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / lookups as f64)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    // number of `width`s since the start of the ring when this bucket was last used
+    index: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Clone for Bucket {
+    fn clone(&self) -> Self {
+        Bucket {
+            index: AtomicU64::new(self.index.load(Ordering::Relaxed)),
+            hits: AtomicU64::new(self.hits.load(Ordering::Relaxed)),
+            misses: AtomicU64::new(self.misses.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Ring buffer of per-interval hit and miss counts. Recording only bumps the atomic count of
+/// the current bucket, reusing the slot of an outdated bucket; windows are summed when read.
+///
+/// Nothing is locked, so counts are recorded through a shared reference, e.g. by stores serving
+/// reads through [`CachedRead`](crate::CachedRead). The counts recorded while a slot moves on to
+/// a new bucket may be lost, or by a late thread still on the outdated bucket.
+#[derive(Clone, Debug)]
+struct Ring {
+    start: Instant,
+    width: Duration,
+    buckets: Vec<Bucket>,
+}
+
+impl Ring {
+    fn new(start: Instant, width: Duration, buckets: usize) -> Ring {
+        if width.is_zero() || buckets == 0 {
+            panic!("windowed stats need a non-zero bucket width and number of buckets.")
+        }
+        Ring {
+            start,
+            width,
+            buckets: (0..buckets).map(|_| Bucket::default()).collect(),
+        }
+    }
+
+    fn index(&self, now: Instant) -> u64 {
+        (now.saturating_duration_since(self.start).as_nanos() / self.width.as_nanos()) as u64
+    }
+
+    /// The bucket of `now`, taking over the slot of an outdated bucket, or `None` when the slot
+    /// already moved on to a newer bucket
+    fn bucket(&self, now: Instant) -> Option<&Bucket> {
+        let index = self.index(now);
+        let bucket = &self.buckets[(index % self.buckets.len() as u64) as usize];
+        let mut current = bucket.index.load(Ordering::Acquire);
+        while current < index {
+            match bucket.index.compare_exchange_weak(
+                current,
+                index,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    bucket.hits.store(0, Ordering::Relaxed);
+                    bucket.misses.store(0, Ordering::Relaxed);
+                    return Some(bucket);
+                }
+                Err(index) => current = index,
+            }
+        }
+        if current == index {
+            Some(bucket)
+        } else {
+            None
+        }
+    }
+
+    fn record(&self, now: Instant, hit: bool) {
+        if let Some(bucket) = self.bucket(now) {
+            let count = if hit { &bucket.hits } else { &bucket.misses };
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn stats(&self, window: Duration, now: Instant) -> WindowedStats {
+        let current = self.index(now);
+        let covered = window.as_nanos().div_ceil(self.width.as_nanos());
+        let covered = covered.max(1).min(self.buckets.len() as u128) as u64;
+        let oldest = current.saturating_sub(covered - 1);
+        let mut stats = WindowedStats {
+            window: self.width * covered as u32,
+            ..WindowedStats::default()
+        };
+        for bucket in &self.buckets {
+            if (oldest..=current).contains(&bucket.index.load(Ordering::Acquire)) {
+                stats.hits += bucket.hits.load(Ordering::Relaxed);
+                stats.misses += bucket.misses.load(Ordering::Relaxed);
+            }
+        }
+        stats
+    }
+}
+
+/// Opt-in rolling window of cache hits and misses, kept by the stores next to their
+/// lifetime counters. Does nothing until enabled.
+#[derive(Clone, Debug, Default)]
+pub(super) struct WindowedCounter {
+    ring: Option<Ring>,
+}
+
+impl WindowedCounter {
+    pub(super) fn enable(&mut self, bucket_width: Duration, buckets: usize) {
+        self.ring = Some(Ring::new(Instant::now(), bucket_width, buckets));
+    }
+
+    pub(super) fn hit(&self) {
+        if let Some(ring) = &self.ring {
+            ring.record(Instant::now(), true);
+        }
+    }
+
+    pub(super) fn miss(&self) {
+        if let Some(ring) = &self.ring {
+            ring.record(Instant::now(), false);
+        }
+    }
+
+    pub(super) fn stats(&self, window: Duration) -> WindowedStats {
+        self.ring
+            .as_ref()
+            .map(|ring| ring.stats(window, Instant::now()))
+            .unwrap_or_default()
+    }
+
+    pub(super) fn reset(&mut self) {
+        if let Some(ring) = &mut self.ring {
+            *ring = Ring::new(Instant::now(), ring.width, ring.buckets.len());
+        }
+    }
+}
+
+#[cfg(test)]
+/// Windowed stats tests
+mod tests {
+    use super::*;
+
+    /// A ring with a manual clock: `at(secs)` is `secs` seconds after the ring started
+    fn ring(buckets: usize) -> (Ring, impl Fn(u64) -> Instant) {
+        let start = Instant::now();
+        let ring = Ring::new(start, Duration::from_secs(60), buckets);
+        (ring, move |secs| start + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn counts_within_window() {
+        let (ring, at) = ring(5);
+        ring.record(at(0), true);
+        ring.record(at(30), false);
+        ring.record(at(70), true);
+        ring.record(at(130), true);
+
+        let last_minute = ring.stats(Duration::from_secs(60), at(130));
+        assert_eq!((last_minute.hits, last_minute.misses), (1, 0));
+        assert_eq!(last_minute.window, Duration::from_secs(60));
+
+        let last_two = ring.stats(Duration::from_secs(90), at(130));
+        assert_eq!((last_two.hits, last_two.misses), (2, 0));
+        assert_eq!(last_two.window, Duration::from_secs(120));
+
+        let all = ring.stats(Duration::from_secs(3600), at(130));
+        assert_eq!((all.hits, all.misses), (3, 1));
+        assert_eq!(all.window, Duration::from_secs(300));
+        assert_eq!(all.hit_rate(), Some(0.75));
+    }
+
+    #[test]
+    fn buckets_rotate() {
+        let (ring, at) = ring(3);
+        ring.record(at(0), true);
+        ring.record(at(60), false);
+        ring.record(at(120), true);
+        // reuses the slot of the first bucket
+        ring.record(at(180), true);
+        let stats = ring.stats(Duration::from_secs(180), at(180));
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+
+        // buckets left behind are outdated instead of cleared
+        let stats = ring.stats(Duration::from_secs(180), at(300));
+        assert_eq!((stats.hits, stats.misses), (1, 0));
+        let stats = ring.stats(Duration::from_secs(180), at(1000));
+        assert_eq!(stats.hit_rate(), None);
+    }
+
+    #[test]
+    fn late_counts_dropped() {
+        let (ring, at) = ring(2);
+        ring.record(at(120), true);
+        // a caller still in the first minute finds its slot taken over by the third one
+        ring.record(at(0), true);
+        let stats = ring.stats(Duration::from_secs(180), at(120));
+        assert_eq!((stats.hits, stats.misses), (1, 0));
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let mut counter = WindowedCounter::default();
+        counter.enable(Duration::from_secs(3600), 2);
+        let counter = std::sync::Arc::new(counter);
+        let handles = (0..4)
+            .map(|_| {
+                let counter = std::sync::Arc::clone(&counter);
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        counter.hit();
+                        counter.miss();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let stats = counter.stats(Duration::from_secs(60));
+        assert_eq!((stats.hits, stats.misses), (400, 400));
+    }
+
+    #[test]
+    fn disabled() {
+        let mut counter = WindowedCounter::default();
+        counter.hit();
+        assert_eq!(
+            counter.stats(Duration::from_secs(60)),
+            WindowedStats::default()
+        );
+        counter.enable(Duration::from_secs(60), 2);
+        counter.hit();
+        counter.miss();
+        let stats = counter.stats(Duration::from_secs(60));
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        counter.reset();
+        assert_eq!(counter.stats(Duration::from_secs(60)).hit_rate(), None);
+    }
+}
@@ -0,0 +1,91 @@
+/*!
+Test support for code calling `#[cached]` functions
+
+With the `testing` feature, `#[cached]` functions get a `{fn}_cache_scope()` function returning a
+[`CacheScope`]: until it's dropped, the function uses a fresh store of its own on the current
+thread instead of its static, so tests running in parallel don't see each other's values.
+
+```rust,no_run
+use cached::proc_macro::cached;
+use cached::Cached;
+
+#[cached(size = 100)]
+fn price(item: u32) -> u32 {
+    item * 10
+}
+
+#[test]
+fn uses_its_own_prices() {
+    let scope = price_cache_scope();
+    scope.lock().unwrap().cache_set(1, 42);
+    assert_eq!(price(1), 42);
+}
+# pub fn main() { }
+```
+*/
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::thread::LocalKey;
+
+/// The stores swapped in by the scopes of a cached function on a thread, innermost last
+pub type Scopes<M> = RefCell<Vec<&'static M>>;
+
+/// A store swapped in for the static of a `#[cached]` function on the current thread until the
+/// scope is dropped, see the [module docs](self). Scopes nest: the innermost one is used.
+///
+/// It derefs to the store, e.g. to prime or inspect it: `scope.lock().unwrap()`, or
+/// `scope.lock().await` for async functions.
+///
+/// The store is leaked: the cached functions borrow it for `'static` and may still hold on to it
+/// (e.g. in a pending future) when the scope is dropped. Scopes are meant for tests, where it's
+/// one store per scope.
+///
+/// Async functions look the scope up on the thread polling them, so scope them in tests running
+/// their futures on the test thread, e.g. `#[tokio::test]` with its default current-thread runtime.
+pub struct CacheScope<M: 'static> {
+    scopes: &'static LocalKey<Scopes<M>>,
+    store: &'static M,
+    // the scope must be exited on the thread that entered it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<M: 'static> CacheScope<M> {
+    /// Swap in `store` for the cached function of `scopes` on the current thread
+    pub fn enter(scopes: &'static LocalKey<Scopes<M>>, store: M) -> CacheScope<M> {
+        let store: &'static M = Box::leak(Box::new(store));
+        scopes.with(|scopes| scopes.borrow_mut().push(store));
+        CacheScope {
+            scopes,
+            store,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<M: 'static> Deref for CacheScope<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        self.store
+    }
+}
+
+impl<M: 'static> Drop for CacheScope<M> {
+    fn drop(&mut self) {
+        // scopes are usually dropped innermost first, but don't rely on it
+        let store = self.store;
+        self.scopes.with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+            if let Some(index) = scopes.iter().rposition(|s| std::ptr::eq(*s, store)) {
+                scopes.remove(index);
+            }
+        });
+    }
+}
+
+/// The store a cached function uses on the current thread: the one of its innermost scope,
+/// or its `global` static outside of scopes
+pub fn current_store<M>(scopes: &'static LocalKey<Scopes<M>>, global: &'static M) -> &'static M {
+    scopes.with(|scopes| scopes.borrow().last().copied().unwrap_or(global))
+}
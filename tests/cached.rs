@@ -530,6 +530,29 @@ fn test_cached_return_flag() {
     }
 }
 
+#[cached(with_cached_flag = true)]
+fn cached_return_flag_ordered(n: i32) -> cached::Return<i32> {
+    cached::Return::new(n)
+}
+
+#[test]
+fn test_cached_return_flag_ordered() {
+    let computed = cached_return_flag_ordered(2);
+    let cached = cached_return_flag_ordered(2);
+    assert!(!computed.was_cached && cached.was_cached);
+    // compared by value, whether it was cached or not
+    assert!(cached == computed);
+    assert_eq!(cached.cmp(&computed), std::cmp::Ordering::Equal);
+    let mut returned = vec![
+        cached_return_flag_ordered(3),
+        cached,
+        cached_return_flag_ordered(1),
+    ];
+    returned.sort();
+    assert_eq!(returned.iter().map(|r| **r).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(*returned.into_iter().max().unwrap(), 3);
+}
+
 #[cached(result = true, with_cached_flag = true)]
 fn cached_return_flag_result(n: i32) -> Result<cached::Return<i32>, ()> {
     if n == 10 {
@@ -558,6 +581,25 @@ fn test_cached_return_flag_result() {
     }
 }
 
+#[derive(Clone, Debug)]
+struct FlagError;
+
+// the `Return` wrapper is found through a fully qualified `Result`
+#[cached(result = true, with_cached_flag = true)]
+fn cached_return_flag_qualified(n: i32) -> std::result::Result<::cached::Return<i32>, FlagError> {
+    if n < 0 {
+        return Err(FlagError);
+    }
+    Ok(cached::Return::new(n))
+}
+
+#[test]
+fn test_cached_return_flag_qualified() {
+    assert!(!cached_return_flag_qualified(1).unwrap().was_cached);
+    assert!(cached_return_flag_qualified(1).unwrap().was_cached);
+    assert!(matches!(cached_return_flag_qualified(-1), Err(FlagError)));
+}
+
 #[cached(option = true, with_cached_flag = true)]
 fn cached_return_flag_option(n: i32) -> Option<cached::Return<i32>> {
     if n == 10 {
@@ -1240,6 +1282,46 @@ mod redis_tests {
         assert!(cached_redis_cached_flag(6).is_err());
     }
 
+    #[io_cached(
+        redis = true,
+        time = 60,
+        version = 2,
+        with_cached_flag = true,
+        map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+    )]
+    fn cached_redis_version(n: u32) -> Result<cached::Return<u32>, TestError> {
+        Ok(cached::Return::new(n))
+    }
+
+    #[test]
+    fn test_cached_redis_version() {
+        CACHED_REDIS_VERSION.clear_namespace().unwrap();
+        assert!(!cached_redis_version(1).unwrap().was_cached);
+        assert!(cached_redis_version(1).unwrap().was_cached);
+        assert_eq!(CACHED_REDIS_VERSION.clear_namespace().unwrap(), 1);
+        assert!(!cached_redis_version(1).unwrap().was_cached);
+    }
+
+    #[io_cached(
+        redis = true,
+        time = 60,
+        key_encoder = "<u32 as cached::keys::CacheKeyEncode>::encode",
+        with_cached_flag = true,
+        map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+    )]
+    fn cached_redis_key_encoder(n: u32) -> Result<cached::Return<u32>, TestError> {
+        Ok(cached::Return::new(n))
+    }
+
+    #[test]
+    fn test_cached_redis_key_encoder() {
+        CACHED_REDIS_KEY_ENCODER.clear_namespace().unwrap();
+        assert!(!cached_redis_key_encoder(1).unwrap().was_cached);
+        assert!(cached_redis_key_encoder(1).unwrap().was_cached);
+        assert!(!cached_redis_key_encoder(2).unwrap().was_cached);
+        assert_eq!(CACHED_REDIS_KEY_ENCODER.clear_namespace().unwrap(), 2);
+    }
+
     #[io_cached(
         map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##,
         type = "cached::RedisCache<u32, u32>",
@@ -1438,3 +1520,2336 @@ fn test_expiring_value_unexpired_article_returned_with_hit() {
         assert_eq!(cache.cache_misses(), Some(1));
     }
 }
+
+#[cached::proc_macro::cached_macro_test]
+fn run_with_isolated_cache() -> (Option<u64>, Option<u64>) {
+    #[cached]
+    fn isolated_double(n: u32) -> u32 {
+        n * 2
+    }
+
+    assert_eq!(isolated_double(2), 4);
+    assert_eq!(isolated_double(2), 4);
+    let cache = ISOLATED_DOUBLE.lock().unwrap();
+    (cache.cache_hits(), cache.cache_misses())
+}
+
+#[test]
+fn test_cached_macro_test_isolates_cache() {
+    assert_eq!(run_with_isolated_cache(), (Some(1), Some(1)));
+    // the second run starts from a cold cache again
+    assert_eq!(run_with_isolated_cache(), (Some(1), Some(1)));
+}
+
+#[cached::proc_macro::cached_macro_test]
+#[test]
+fn test_cached_macro_test_on_test_fn() {
+    #[cached(name = "MACRO_TEST_NAMED")]
+    fn macro_test_named(n: u32) -> u32 {
+        n + 1
+    }
+
+    assert_eq!(macro_test_named(1), 2);
+    assert_eq!(MACRO_TEST_NAMED.lock().unwrap().cache_size(), 1);
+}
+
+#[cached::proc_macro::cached_macro_test]
+fn run_with_isolated_rwlock_cache() -> usize {
+    #[cached(lock = "rwlock")]
+    fn isolated_triple(n: u32) -> u32 {
+        n * 3
+    }
+
+    assert_eq!(isolated_triple(2), 6);
+    let size = ISOLATED_TRIPLE.read().unwrap().cache_size();
+    size
+}
+
+#[test]
+fn test_cached_macro_test_rwlock() {
+    assert_eq!(run_with_isolated_rwlock_cache(), 1);
+    assert_eq!(run_with_isolated_rwlock_cache(), 1);
+}
+
+#[cfg(feature = "async")]
+#[cached::proc_macro::cached_macro_test]
+#[tokio::test]
+async fn test_cached_macro_test_async() {
+    #[cached]
+    async fn isolated_async(n: u32) -> u32 {
+        n + 2
+    }
+
+    assert_eq!(isolated_async(1).await, 3);
+    assert_eq!(ISOLATED_ASYNC.lock().await.cache_size(), 1);
+}
+
+#[cfg(feature = "dashmap_store")]
+#[cached::proc_macro::cached_macro_test]
+fn run_with_isolated_unlocked_cache() -> usize {
+    #[cached(
+        lock = "none",
+        type = "cached::stores::DashMapCache<u32, u32>",
+        create = "{ cached::stores::DashMapCache::new() }"
+    )]
+    fn isolated_unlocked(n: u32) -> u32 {
+        n * 4
+    }
+
+    assert_eq!(isolated_unlocked(1), 4);
+    assert_eq!(isolated_unlocked(2), 8);
+    cached::ConcurrentCached::cache_size(&*ISOLATED_UNLOCKED)
+}
+
+#[cfg(feature = "dashmap_store")]
+#[test]
+fn test_cached_macro_test_unlocked() {
+    assert_eq!(run_with_isolated_unlocked_cache(), 2);
+    assert_eq!(run_with_isolated_unlocked_cache(), 2);
+}
+
+static SCHEMA_VERSION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+fn schema_version() -> u64 {
+    SCHEMA_VERSION.load(std::sync::atomic::Ordering::SeqCst)
+}
+
+#[cached(size = 10, version_fn = "schema_version")]
+fn versioned_lookup(n: u32) -> u32 {
+    n * 10
+}
+
+#[test]
+fn test_version_fn() {
+    assert_eq!(versioned_lookup(1), 10);
+    assert_eq!(versioned_lookup(1), 10);
+    {
+        let cache = VERSIONED_LOOKUP.lock().unwrap();
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(1));
+    }
+
+    SCHEMA_VERSION.store(2, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(versioned_lookup(1), 10);
+    {
+        let cache = VERSIONED_LOOKUP.lock().unwrap();
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(2));
+        assert_eq!(
+            cache.key_order().cloned().collect::<Vec<_>>(),
+            [("2".to_string(), 1), ("1".to_string(), 1)]
+        );
+    }
+}
+
+#[cached(key = "String", convert = r#"{ x.to_string() }"#)]
+fn generic_display<T: std::fmt::Display>(x: T) -> String {
+    format!("<{}>", x)
+}
+
+#[test]
+fn test_generic_display() {
+    assert_eq!(generic_display(1), "<1>");
+    assert_eq!(generic_display("1"), "<1>");
+    let cache = GENERIC_DISPLAY.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.cache_misses(), Some(1));
+}
+
+#[cached(size = 2, key = "usize", convert = r#"{ items.len() }"#)]
+fn generic_where_len<I>(items: Vec<I>) -> usize
+where
+    I: Clone + Into<usize>,
+{
+    items.into_iter().map(Into::into).sum()
+}
+
+#[test]
+fn test_generic_where_len() {
+    assert_eq!(generic_where_len::<u8>(vec![1, 2]), 3);
+    assert_eq!(generic_where_len(vec![5u16, 5u16]), 3);
+}
+
+#[once]
+fn generic_once<T: std::fmt::Display>(x: T) -> String {
+    x.to_string()
+}
+
+#[test]
+fn test_generic_once() {
+    assert_eq!(generic_once(1), "1");
+    assert_eq!(generic_once("2"), "1");
+}
+
+static CONFIG_SIZE_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn config_cache_size() -> usize {
+    CONFIG_SIZE_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    2
+}
+
+fn config_cache_time() -> u64 {
+    60
+}
+
+#[cached(size_fn = "config_cache_size")]
+fn config_sized(n: u32) -> u32 {
+    n
+}
+
+#[cached(size_fn = "config_cache_size", time_fn = "config_cache_time")]
+fn config_timed_sized(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_config_sized() {
+    for n in 0..5 {
+        config_sized(n);
+    }
+    {
+        let cache = CONFIG_SIZED.lock().unwrap();
+        assert_eq!(cache.cache_capacity(), Some(2));
+        assert_eq!(cache.cache_size(), 2);
+    }
+    config_timed_sized(1);
+    config_timed_sized(1);
+    {
+        let cache = CONFIG_TIMED_SIZED.lock().unwrap();
+        assert_eq!(cache.cache_capacity(), Some(2));
+        assert_eq!(cache.cache_lifespan(), Some(60));
+    }
+    // the size function is only called when each cache is created
+    assert_eq!(
+        CONFIG_SIZE_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+}
+
+#[cached(result = true)]
+fn try_prime_result(n: u32) -> Result<u32, String> {
+    if n == 1 || n == 3 {
+        Err(format!("{} is odd", n))
+    } else {
+        Ok(n)
+    }
+}
+
+#[cached(result = true)]
+fn try_prime_io_result(n: u32) -> std::io::Result<u32> {
+    Ok(n)
+}
+
+#[cached(option = true)]
+fn try_prime_option(n: u32) -> Option<u32> {
+    if n > 0 {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_try_prime_cache() {
+    let failed = (0..4)
+        .filter(|n| try_prime_result_try_prime_cache(*n).is_err())
+        .collect::<Vec<_>>();
+    assert_eq!(failed, [1, 3]);
+    assert_eq!(TRY_PRIME_RESULT.lock().unwrap().cache_size(), 2);
+
+    assert!(try_prime_io_result_try_prime_cache(1).unwrap());
+    assert_eq!(TRY_PRIME_IO_RESULT.lock().unwrap().cache_size(), 1);
+
+    assert!(!try_prime_option_try_prime_cache(0));
+    assert!(try_prime_option_try_prime_cache(1));
+    assert_eq!(TRY_PRIME_OPTION.lock().unwrap().cache_size(), 1);
+}
+
+#[cfg(feature = "async")]
+#[cached(result = true)]
+async fn try_prime_result_async(n: u32) -> Result<u32, ()> {
+    if n == 0 {
+        Err(())
+    } else {
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_try_prime_cache_async() {
+    assert_eq!(try_prime_result_async_try_prime_cache(0).await, Err(()));
+    assert_eq!(try_prime_result_async_try_prime_cache(1).await, Ok(true));
+    assert_eq!(TRY_PRIME_RESULT_ASYNC.lock().await.cache_size(), 1);
+}
+
+#[cfg(feature = "async")]
+#[once(block_on = true)]
+async fn once_block_on(n: u32) -> u32 {
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    n
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_once_block_on_from_sync() {
+    assert_eq!(once_block_on_blocking(1), 1);
+    assert_eq!(once_block_on_blocking(2), 1);
+}
+
+static COLD_KEYS: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(Vec::new());
+
+#[cached(on_miss = r#"{ |n: &u32| COLD_KEYS.lock().unwrap().push(*n) }"#)]
+fn on_miss_sync(n: u32) -> u32 {
+    assert!(ON_MISS_SYNC.try_lock().is_ok());
+    n
+}
+
+#[test]
+fn test_on_miss() {
+    on_miss_sync(1);
+    on_miss_sync(2);
+    on_miss_sync(1);
+    assert_eq!(*COLD_KEYS.lock().unwrap(), [1, 2]);
+}
+
+#[cfg(feature = "async")]
+static ASYNC_COLD_KEYS: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(Vec::new());
+
+#[cfg(feature = "async")]
+#[cached(
+    sync_writes = true,
+    on_miss = r#"{ |n: &u32| ASYNC_COLD_KEYS.lock().unwrap().push(*n) }"#
+)]
+async fn on_miss_async(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_on_miss_async() {
+    on_miss_async(1).await;
+    on_miss_async(1).await;
+    on_miss_async(2).await;
+    assert_eq!(*ASYNC_COLD_KEYS.lock().unwrap(), [1, 2]);
+}
+
+static WAITED_COLD_KEYS: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(Vec::new());
+
+#[cached(
+    sync_writes = true,
+    on_miss = r#"{ |n: &u32| WAITED_COLD_KEYS.lock().unwrap().push(*n) }"#
+)]
+fn on_miss_sync_writes(n: u32) -> u32 {
+    sleep(Duration::from_millis(100));
+    n
+}
+
+#[test]
+fn test_on_miss_sync_writes() {
+    // the callers waiting for the lock find the value cached, only the first one computes it
+    let callers: Vec<_> = (0..4)
+        .map(|_| std::thread::spawn(|| on_miss_sync_writes(1)))
+        .collect();
+    for caller in callers {
+        assert_eq!(caller.join().unwrap(), 1);
+    }
+    assert_eq!(*WAITED_COLD_KEYS.lock().unwrap(), [1]);
+}
+
+static PLACEHOLDER_COLD_KEYS: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(Vec::new());
+
+#[cached(
+    sync_writes = true,
+    placeholder = "{ 0 }",
+    on_miss = r#"{ |n: &u32| PLACEHOLDER_COLD_KEYS.lock().unwrap().push(*n) }"#
+)]
+fn on_miss_placeholder(n: u32) -> u32 {
+    sleep(Duration::from_millis(300));
+    n
+}
+
+#[test]
+fn test_on_miss_placeholder() {
+    let first = std::thread::spawn(|| on_miss_placeholder(1));
+    sleep(Duration::from_millis(100));
+    assert_eq!(on_miss_placeholder(1), 0);
+    assert_eq!(first.join().unwrap(), 1);
+    assert_eq!(*PLACEHOLDER_COLD_KEYS.lock().unwrap(), [1]);
+}
+
+#[cached(warn_at = 2)]
+fn warn_at_unbound(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_warn_at() {
+    for n in 0..4 {
+        warn_at_unbound(n);
+    }
+    assert_eq!(WARN_AT_UNBOUND.lock().unwrap().cache_size(), 4);
+}
+
+#[cached(size = 2, time = 1)]
+fn pinned_timed_sized(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cache_pin() {
+    assert!(!pinned_timed_sized_cache_pin(1));
+    pinned_timed_sized(1);
+    assert!(pinned_timed_sized_cache_pin(1));
+    pinned_timed_sized(2);
+    pinned_timed_sized(3);
+    sleep(Duration::from_secs(2));
+    {
+        let cache = PINNED_TIMED_SIZED.lock().unwrap();
+        assert_eq!(cache.key_order().copied().collect::<Vec<_>>(), [1]);
+    }
+    assert!(pinned_timed_sized_cache_unpin(1));
+    assert!(PINNED_TIMED_SIZED
+        .lock()
+        .unwrap()
+        .key_order()
+        .next()
+        .is_none());
+}
+
+#[cfg(feature = "async")]
+#[cached(size = 2, time = 10)]
+async fn pinned_timed_sized_async(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cache_pin_async() {
+    pinned_timed_sized_async(1).await;
+    assert!(pinned_timed_sized_async_cache_pin(1).await);
+    pinned_timed_sized_async(2).await;
+    pinned_timed_sized_async(3).await;
+    let cache = PINNED_TIMED_SIZED_ASYNC.lock().await;
+    assert_eq!(cache.pinned_keys().copied().collect::<Vec<_>>(), [1]);
+    assert_eq!(cache.key_order().copied().collect::<Vec<_>>(), [3, 1]);
+}
+
+#[cached(
+    key = "cached::keys::BytesKey",
+    convert = r#"{ cached::keys::BytesKey::from(&prices[..]) }"#
+)]
+fn total_price(prices: Vec<f64>) -> f64 {
+    prices.iter().sum()
+}
+
+#[test]
+fn test_bytes_key() {
+    assert_eq!(total_price(vec![1.0, 2.5]), 3.5);
+    assert_eq!(total_price(vec![1.0, 2.5]), 3.5);
+    assert_eq!(total_price(vec![2.5, 1.0]), 3.5);
+    let cache = TOTAL_PRICE.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.cache_misses(), Some(2));
+}
+
+static RUN_ONCE_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[once]
+fn run_once_init() {
+    RUN_ONCE_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+#[test]
+fn test_once_unit_guard() {
+    assert!(!run_once_init_has_run());
+    run_once_init();
+    run_once_init();
+    assert!(run_once_init_has_run());
+    assert_eq!(RUN_ONCE_CALLS.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+static RUN_ONCE_TIMED_CALLS: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+#[once(time = 10, sync_writes = true)]
+fn run_once_timed() -> () {
+    RUN_ONCE_TIMED_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+#[test]
+fn test_once_unit_guard_time() {
+    run_once_timed();
+    run_once_timed();
+    assert!(run_once_timed_has_run());
+    assert_eq!(
+        RUN_ONCE_TIMED_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+
+    *RUN_ONCE_TIMED.write().unwrap() =
+        Some(cached::instant::Instant::now() - Duration::from_secs(11));
+    assert!(!run_once_timed_has_run());
+    run_once_timed();
+    assert!(run_once_timed_has_run());
+    assert_eq!(
+        RUN_ONCE_TIMED_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+}
+
+#[cfg(feature = "async")]
+static RUN_ONCE_ASYNC_CALLS: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(feature = "async")]
+#[once]
+async fn run_once_async() {
+    RUN_ONCE_ASYNC_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_unit_guard_async() {
+    assert!(!run_once_async_has_run().await);
+    run_once_async().await;
+    run_once_async().await;
+    assert!(run_once_async_has_run().await);
+    assert_eq!(
+        RUN_ONCE_ASYNC_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+}
+
+#[cached(size = 10, trace_caller = true)]
+fn traced(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_trace_caller() {
+    assert_eq!(traced(1), 2);
+    let line = line!() - 1;
+    assert_eq!(traced(1), 2);
+    let (location, value) = traced_cache_get_with_location(1).unwrap();
+    assert_eq!(value, 2);
+    assert_eq!(location.file(), file!());
+    assert_eq!(location.line(), line);
+    assert!(traced_cache_get_with_location(2).is_none());
+
+    traced_prime_cache(1);
+    let (location, _) = traced_cache_get_with_location(1).unwrap();
+    assert_eq!(location.line(), line!() - 2);
+}
+
+#[cached(result = true, trace_caller = true)]
+fn traced_result(n: u32) -> Result<u32, String> {
+    Ok(n)
+}
+
+#[test]
+fn test_trace_caller_result() {
+    assert_eq!(traced_result(1), Ok(1));
+    let line = line!() - 1;
+    assert_eq!(traced_result(1), Ok(1));
+    assert_eq!(
+        traced_result_cache_get_with_location(1).map(|(location, value)| (location.line(), value)),
+        Some((line, 1))
+    );
+}
+
+#[cached(key_transform = r#"{ |k| k.to_lowercase() }"#)]
+fn key_transform_lowercase(name: String) -> String {
+    name
+}
+
+#[cached(
+    key = "(String, u32)",
+    convert = r#"{ (name.to_string(), n) }"#,
+    key_transform = r#"{ |(name, n): (String, u32)| (name.trim().to_string(), n) }"#
+)]
+fn key_transform_convert(name: &str, n: u32) -> String {
+    format!("{}{}", name, n)
+}
+
+#[test]
+fn test_key_transform() {
+    assert_eq!(key_transform_lowercase("Ab".to_string()), "Ab");
+    assert_eq!(key_transform_lowercase("AB".to_string()), "Ab");
+    assert_eq!(key_transform_lowercase("ab".to_string()), "Ab");
+    {
+        let cache = KEY_TRANSFORM_LOWERCASE.lock().unwrap();
+        assert_eq!(cache.cache_size(), 1);
+        assert_eq!(cache.cache_misses(), Some(1));
+    }
+
+    assert_eq!(key_transform_convert(" a ", 1), " a 1");
+    assert_eq!(key_transform_convert("a", 1), " a 1");
+    assert_eq!(key_transform_convert("a", 2), "a2");
+    {
+        let cache = KEY_TRANSFORM_CONVERT.lock().unwrap();
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(2));
+    }
+}
+
+#[cached(size = 10, error_on_miss = true)]
+fn error_on_miss_sync(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_error_on_miss() {
+    assert_eq!(error_on_miss_sync(1), Err(cached::CacheMiss));
+    assert_eq!(error_on_miss_sync_prime_cache(1), 2);
+    assert_eq!(error_on_miss_sync(1), Ok(2));
+    assert_eq!(error_on_miss_sync(2), Err(cached::CacheMiss));
+    let cache = ERROR_ON_MISS_SYNC.lock().unwrap();
+    assert_eq!(cache.cache_size(), 1);
+}
+
+#[cached(error_on_miss = true)]
+async fn error_on_miss_async(n: u32) -> u32 {
+    n * 2
+}
+
+#[tokio::test]
+async fn test_error_on_miss_async() {
+    assert_eq!(error_on_miss_async(1).await, Err(cached::CacheMiss));
+    assert_eq!(error_on_miss_async_prime_cache(1).await, 2);
+    assert_eq!(error_on_miss_async(1).await, Ok(2));
+}
+
+#[cached(time = 60, with_cached_flag = true)]
+fn cached_return_expires_in(n: i32) -> cached::Return<i32> {
+    cached::Return::new(n)
+}
+
+#[once(time = 60, option = true, with_cached_flag = true)]
+fn once_return_expires_in() -> Option<cached::Return<i32>> {
+    Some(cached::Return::new(1))
+}
+
+#[cached(time = 60, with_cached_flag = true)]
+async fn async_cached_return_expires_in(n: i32) -> cached::Return<i32> {
+    cached::Return::new(n)
+}
+
+#[tokio::test]
+async fn test_return_expires_in() {
+    let r = cached_return_expires_in(1);
+    assert_eq!(r.expires_in, None);
+    let r = cached_return_expires_in(1);
+    let expires_in = r.expires_in.unwrap();
+    assert!(expires_in <= Duration::from_secs(60) && expires_in > Duration::from_secs(50));
+
+    assert_eq!(cached_return_flag(100).expires_in, None);
+    assert_eq!(cached_return_flag(100).expires_in, None);
+
+    assert_eq!(once_return_expires_in().unwrap().expires_in, None);
+    let expires_in = once_return_expires_in().unwrap().expires_in.unwrap();
+    assert!(expires_in <= Duration::from_secs(60) && expires_in > Duration::from_secs(50));
+
+    assert_eq!(async_cached_return_expires_in(1).await.expires_in, None);
+    assert!(async_cached_return_expires_in(1).await.expires_in.is_some());
+}
+
+#[cached(with_cached_flag = true)]
+fn set_enabled_cached(n: i32) -> cached::Return<i32> {
+    cached::Return::new(n)
+}
+
+#[cached(with_cached_flag = true)]
+fn set_enabled_other(n: i32) -> cached::Return<i32> {
+    cached::Return::new(n)
+}
+
+#[once(with_cached_flag = true)]
+fn set_enabled_once() -> cached::Return<i32> {
+    cached::Return::new(1)
+}
+
+#[cached(with_cached_flag = true)]
+async fn set_enabled_async(n: i32) -> cached::Return<i32> {
+    cached::Return::new(n)
+}
+
+#[tokio::test]
+async fn test_set_enabled() {
+    assert!(!set_enabled_cached(1).was_cached);
+    assert!(!set_enabled_other(1).was_cached);
+    assert!(!set_enabled_once().was_cached);
+    assert!(!set_enabled_async(1).await.was_cached);
+
+    assert!(set_enabled_cached_set_enabled(false));
+    assert!(set_enabled_once_set_enabled(false));
+    assert!(!set_enabled_once_set_enabled(false));
+    assert!(set_enabled_async_set_enabled(false));
+    assert!(!set_enabled_cached(1).was_cached);
+    assert!(!set_enabled_cached(2).was_cached);
+    assert!(!set_enabled_cached(2).was_cached);
+    assert!(!set_enabled_once().was_cached);
+    assert!(!set_enabled_async(1).await.was_cached);
+    // other functions keep their cache
+    assert!(set_enabled_other(1).was_cached);
+    {
+        let cache = SET_ENABLED_CACHED.lock().unwrap();
+        assert_eq!(cache.cache_size(), 1);
+        assert_eq!(cache.cache_hits(), Some(0));
+    }
+
+    assert!(!set_enabled_cached_set_enabled(true));
+    set_enabled_once_set_enabled(true);
+    set_enabled_async_set_enabled(true);
+    assert!(set_enabled_cached(1).was_cached);
+    assert!(!set_enabled_cached(2).was_cached);
+    assert!(set_enabled_once().was_cached);
+    assert!(set_enabled_async(1).await.was_cached);
+}
+
+#[cached(
+    type = "SizedCache<String, usize>",
+    create = "{ SizedCache::with_size(2) }",
+    convert = r#"{ format!("{}{}", a, b) }"#
+)]
+fn config_attributes(a: &str, b: &str) -> usize {
+    a.len() + b.len()
+}
+
+struct KeyedConfig;
+
+impl cached::CacheConfig<(&str, &str)> for KeyedConfig {
+    type Key = String;
+    type Value = usize;
+    type Cache = SizedCache<String, usize>;
+
+    fn create() -> Self::Cache {
+        SizedCache::with_size(2)
+    }
+
+    fn key((a, b): (&str, &str)) -> String {
+        format!("{}{}", a, b)
+    }
+}
+
+#[cached(config = "KeyedConfig")]
+fn config_typed(a: &str, b: &str) -> usize {
+    a.len() + b.len()
+}
+
+#[test]
+fn test_config_matches_attributes() {
+    for (a, b) in [("a", "b"), ("ab", ""), ("c", "d"), ("a", "b"), ("e", "f")] {
+        assert_eq!(config_attributes(a, b), config_typed(a, b));
+    }
+    let attributes = CONFIG_ATTRIBUTES.lock().unwrap();
+    let typed = CONFIG_TYPED.lock().unwrap();
+    assert_eq!(
+        attributes.key_order().collect::<Vec<_>>(),
+        typed.key_order().collect::<Vec<_>>()
+    );
+    assert_eq!(attributes.cache_hits(), typed.cache_hits());
+    assert_eq!(attributes.cache_misses(), typed.cache_misses());
+    // ("ab", "") has the same key as ("a", "b")
+    assert_eq!(typed.cache_hits(), Some(2));
+}
+
+struct PositiveConfig;
+
+impl cached::CacheConfig<i32> for PositiveConfig {
+    type Key = i32;
+    type Value = i32;
+    type Cache = UnboundCache<i32, i32>;
+
+    fn create() -> Self::Cache {
+        UnboundCache::new()
+    }
+
+    fn key(n: i32) -> i32 {
+        n
+    }
+
+    fn should_cache(value: &i32) -> bool {
+        *value > 0
+    }
+}
+
+#[cached(config = "PositiveConfig", result = true)]
+fn config_should_cache(n: i32) -> Result<i32, ()> {
+    if n == 0 {
+        Err(())
+    } else {
+        Ok(n)
+    }
+}
+
+#[cached(config = "PositiveConfig")]
+async fn config_should_cache_async(n: i32) -> i32 {
+    n
+}
+
+#[tokio::test]
+async fn test_config_should_cache() {
+    assert_eq!(config_should_cache(1), Ok(1));
+    assert_eq!(config_should_cache(-1), Ok(-1));
+    assert_eq!(config_should_cache(0), Err(()));
+    assert_eq!(CONFIG_SHOULD_CACHE.lock().unwrap().cache_size(), 1);
+
+    assert_eq!(config_should_cache_async(1).await, 1);
+    assert_eq!(config_should_cache_async(-1).await, -1);
+    assert_eq!(CONFIG_SHOULD_CACHE_ASYNC.lock().await.cache_size(), 1);
+}
+
+const SIZE_EXPR_BASE: usize = 2;
+
+#[cached(size = "SIZE_EXPR_BASE * 2")]
+fn size_expr(n: u32) -> u32 {
+    n
+}
+
+#[cached(size = "SIZE_EXPR_BASE", time = 60)]
+fn size_expr_timed(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_size_expr() {
+    for n in 0..10 {
+        size_expr(n);
+        size_expr_timed(n);
+    }
+    assert_eq!(SIZE_EXPR.lock().unwrap().cache_capacity(), Some(4));
+    assert_eq!(SIZE_EXPR.lock().unwrap().cache_size(), 4);
+    assert_eq!(SIZE_EXPR_TIMED.lock().unwrap().cache_capacity(), Some(2));
+}
+
+#[deny(unused_mut, unused_variables)]
+#[allow(clippy::toplevel_ref_arg)]
+mod binding_patterns {
+    use cached::proc_macro::{cached, once};
+
+    #[derive(Clone)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[cached]
+    fn ref_arg(ref a: u32) -> u32 {
+        *a + 1
+    }
+
+    #[cached]
+    fn ref_mut_arg(ref mut a: u32) -> u32 {
+        *a += 1;
+        *a
+    }
+
+    #[cached]
+    fn mut_in_tuple((mut a, b): (u32, u32)) -> u32 {
+        a += b;
+        a
+    }
+
+    #[cached]
+    fn at_binding(whole @ (a, _): (u32, u32)) -> u32 {
+        whole.0 + whole.1 + a
+    }
+
+    #[cached(key = "i32", convert = "{ x + y }")]
+    fn struct_pattern(Point { x, y }: Point, &scale: &i32) -> i32 {
+        x * y * scale
+    }
+
+    #[once]
+    fn once_patterns(ref mut a: u32, (mut b, c): (u32, u32)) -> u32 {
+        *a += 1;
+        b += c;
+        *a + b
+    }
+
+    #[once]
+    fn once_at_binding(whole @ (a, _): (u32, u32)) -> u32 {
+        whole.0 + whole.1 + a
+    }
+
+    #[test]
+    fn test_binding_patterns() {
+        assert_eq!(ref_arg(1), 2);
+        assert_eq!(ref_mut_arg(1), 2);
+        assert_eq!(ref_mut_arg(1), 2);
+        assert_eq!(mut_in_tuple((1, 2)), 3);
+        assert_eq!(mut_in_tuple((1, 2)), 3);
+        assert_eq!(at_binding((1, 2)), 4);
+        assert_eq!(struct_pattern(Point { x: 2, y: 3 }, &1), 6);
+        // cached by `x + y`
+        assert_eq!(struct_pattern(Point { x: 3, y: 2 }, &2), 6);
+        assert_eq!(once_patterns(1, (2, 3)), 7);
+        assert_eq!(once_patterns(5, (5, 5)), 7);
+        assert_eq!(once_at_binding((1, 2)), 4);
+    }
+}
+
+#[cached(prime_batched = true)]
+fn batch_primed(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_prime_batched() {
+    assert_eq!(batch_primed_prime_cache(1), 2);
+    assert_eq!(batch_primed_prime_cache(2), 4);
+    // buffered values aren't visible until flushed
+    assert_eq!(BATCH_PRIMED.lock().unwrap().cache_size(), 0);
+    assert_eq!(batch_primed_prime_flush(), 2);
+    assert_eq!(batch_primed_prime_flush(), 0);
+    {
+        let cache = BATCH_PRIMED.lock().unwrap();
+        assert_eq!(cache.cache_size(), 2);
+        assert_eq!(cache.cache_misses(), Some(0));
+    }
+    assert_eq!(batch_primed(2), 4);
+    assert_eq!(BATCH_PRIMED.lock().unwrap().cache_hits(), Some(1));
+}
+
+#[cached(option = true, prime_batched = true, prime_batch_size = 2)]
+async fn async_batch_primed(n: u32) -> Option<u32> {
+    if n == 0 {
+        None
+    } else {
+        Some(n * 2)
+    }
+}
+
+#[tokio::test]
+async fn test_async_prime_batch_size() {
+    assert_eq!(async_batch_primed_prime_cache(1).await, Some(2));
+    // `None`s aren't buffered
+    assert_eq!(async_batch_primed_prime_cache(0).await, None);
+    assert_eq!(ASYNC_BATCH_PRIMED.lock().await.cache_size(), 0);
+    // flushed once the batch is full
+    assert_eq!(async_batch_primed_prime_cache(2).await, Some(4));
+    assert_eq!(ASYNC_BATCH_PRIMED.lock().await.cache_size(), 2);
+    assert_eq!(async_batch_primed_prime_cache(3).await, Some(6));
+    assert_eq!(async_batch_primed_prime_flush().await, 1);
+    assert_eq!(ASYNC_BATCH_PRIMED.lock().await.cache_size(), 3);
+}
+
+#[cached(result_option = true)]
+fn find_user(id: u32) -> Option<Result<String, String>> {
+    match id {
+        0 => None,
+        1 => Some(Err("unavailable".to_string())),
+        _ => Some(Ok(format!("user {}", id))),
+    }
+}
+
+#[test]
+fn test_result_option() {
+    assert_eq!(find_user(0), None);
+    assert_eq!(find_user(1), Some(Err("unavailable".to_string())));
+    assert_eq!(find_user(2), Some(Ok("user 2".to_string())));
+    assert_eq!(find_user(2), Some(Ok("user 2".to_string())));
+    {
+        let cache = FIND_USER.lock().unwrap();
+        assert_eq!(cache.cache_size(), 1);
+        assert_eq!(cache.cache_hits(), Some(1));
+    }
+    assert_eq!(find_user_try_prime_cache(0), Ok(false));
+    assert_eq!(find_user_try_prime_cache(1), Err("unavailable".to_string()));
+    assert_eq!(find_user_try_prime_cache(3), Ok(true));
+    assert_eq!(FIND_USER.lock().unwrap().cache_size(), 2);
+}
+
+#[cached(result_option = true, with_cached_flag = true)]
+async fn async_find_user(id: u32) -> Option<Result<cached::Return<u32>, ()>> {
+    match id {
+        0 => None,
+        _ => Some(Ok(cached::Return::new(id))),
+    }
+}
+
+#[tokio::test]
+async fn test_async_result_option() {
+    assert!(async_find_user(0).await.is_none());
+    assert!(!async_find_user(1).await.unwrap().unwrap().was_cached);
+    assert!(async_find_user(1).await.unwrap().unwrap().was_cached);
+    assert!(async_find_user(0).await.is_none());
+}
+
+#[cached(
+    type = "SizedCache<u32, u32>",
+    create_try = r#"{ Err::<SizedCache<u32, u32>, _>("unable to connect") }"#,
+    on_create_error = "null"
+)]
+fn double_without_cache(n: u32) -> u32 {
+    n * 2
+}
+
+#[cached(
+    type = "SizedCache<u32, u32>",
+    create_try = r#"{ Ok::<_, String>(SizedCache::with_size(10)) }"#,
+    on_create_error = "null"
+)]
+fn double_with_cache(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_create_try_null() {
+    assert_eq!(double_without_cache(1), 2);
+    assert_eq!(double_without_cache(1), 2);
+    {
+        let cache = DOUBLE_WITHOUT_CACHE.lock().unwrap();
+        assert!(cache.is_null());
+        assert_eq!(cache.cache_size(), 0);
+        assert_eq!(cache.cache_misses(), Some(2));
+    }
+
+    assert_eq!(double_with_cache(1), 2);
+    assert_eq!(double_with_cache(1), 2);
+    let cache = DOUBLE_WITH_CACHE.lock().unwrap();
+    assert!(!cache.is_null());
+    assert_eq!(cache.cache_hits(), Some(1));
+}
+
+#[cached(
+    type = "SizedCache<u32, u32>",
+    create_try = r#"{ Err::<SizedCache<u32, u32>, _>("unable to connect") }"#
+)]
+fn double_or_panic(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+#[should_panic(expected = "unable to create cache `DOUBLE_OR_PANIC`: \"unable to connect\"")]
+fn test_create_try_panic() {
+    double_or_panic(1);
+}
+
+#[cached(invalidation_group = "users")]
+fn user_name(id: u32) -> String {
+    format!("user {}", id)
+}
+
+#[cached(size = 10, time = 60, invalidation_group = "users")]
+async fn user_email(id: u32) -> String {
+    format!("user{}@example.com", id)
+}
+
+#[tokio::test]
+async fn test_invalidation_group() {
+    user_name(1);
+    user_email(1).await;
+    assert!(user_email_cache_pin(1).await);
+    assert_eq!(USER_NAME.lock().unwrap().cache_size(), 1);
+    assert_eq!(USER_EMAIL.lock().await.cache_size(), 1);
+
+    cached::invalidate_group("users");
+    assert_eq!(USER_NAME.lock().unwrap().cache_size(), 0);
+    assert_eq!(USER_EMAIL.lock().await.cache_size(), 0);
+    user_name(1);
+    // pinned entries are invalidated too
+    user_email(1).await;
+    assert_eq!(USER_NAME.lock().unwrap().cache_misses(), Some(2));
+    assert_eq!(USER_EMAIL.lock().await.cache_misses(), Some(2));
+}
+
+#[cached(key = "String", convert = r#"{ name.to_lowercase() }"#)]
+fn greeting(name: &str) -> String {
+    format!("Hello, {}", name)
+}
+
+#[cached]
+async fn async_add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[tokio::test]
+async fn test_key_fn() {
+    assert_eq!(greeting_key("Ferris"), "ferris");
+    assert_eq!(greeting("Ferris"), "Hello, Ferris");
+    assert_eq!(
+        GREETING
+            .lock()
+            .unwrap()
+            .cache_remove(&greeting_key("FERRIS")),
+        Some("Hello, Ferris".to_string())
+    );
+
+    assert_eq!(async_add_key(1, 2), (1, 2));
+    assert_eq!(async_add(1, 2).await, 3);
+    assert_eq!(
+        ASYNC_ADD.lock().await.cache_get(&async_add_key(1, 2)),
+        Some(&3)
+    );
+}
+
+#[once(time = 0)]
+fn once_expired_immediately() -> u32 {
+    ONCE_EXPIRED_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+}
+
+static ONCE_EXPIRED_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cached(time = 0)]
+fn cached_expired_immediately(n: u32) -> u32 {
+    CACHED_EXPIRED_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + n
+}
+
+static CACHED_EXPIRED_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[test]
+fn test_time_zero_always_expired() {
+    assert_eq!(once_expired_immediately(), 1);
+    assert_eq!(once_expired_immediately(), 2);
+    assert_eq!(cached_expired_immediately(1), 1);
+    assert_eq!(cached_expired_immediately(1), 2);
+}
+
+#[cached(size = 4, low_watermark = 2)]
+fn watermarked(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_low_watermark() {
+    for n in 0..4 {
+        watermarked(n);
+    }
+    assert_eq!(WATERMARKED.lock().unwrap().cache_size(), 4);
+    watermarked(4);
+    assert_eq!(WATERMARKED.lock().unwrap().cache_size(), 2);
+}
+
+#[cached(key = "u64", convert = r#"{ u64::from(a) * 1000 + u64::from(b) }"#)]
+fn pair_score(a: u32, b: u32) -> u64 {
+    u64::from(a + b)
+}
+
+#[cached]
+async fn async_square(n: u32) -> u32 {
+    n * n
+}
+
+#[cached(
+    type = "UnboundCache<String, usize>",
+    create = "{ UnboundCache::new() }",
+    convert = r#"{ s.to_string() }"#
+)]
+fn string_len(s: &str) -> usize {
+    s.len()
+}
+
+#[tokio::test]
+async fn test_cache_remove() {
+    pair_score(1, 2);
+    pair_score(3, 4);
+    assert_eq!(pair_score_cache_remove(1, 2), Some(3));
+    assert_eq!(pair_score_cache_remove(1, 2), None);
+    assert_eq!(pair_score_cache_remove_key(&3004), Some(7));
+    assert_eq!(PAIR_SCORE.lock().unwrap().cache_size(), 0);
+
+    async_square(3).await;
+    assert_eq!(async_square_cache_remove_key(&3).await, Some(9));
+    async_square(3).await;
+    assert_eq!(async_square_cache_remove(3).await, Some(9));
+
+    string_len("abc");
+    assert_eq!(string_len_cache_remove("abc"), Some(3));
+}
+
+#[cached(size = 10)]
+fn pair_sum(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[cached]
+async fn async_cube(n: u32) -> u32 {
+    n * n * n
+}
+
+#[tokio::test]
+async fn test_cache_contains_key() {
+    assert!(!pair_sum_cache_contains_key(1, 2));
+    pair_sum(1, 2);
+    assert!(pair_sum_cache_contains_key(1, 2));
+    assert!(!pair_sum_cache_contains_key(2, 1));
+
+    assert!(!async_cube_cache_contains_key(2).await);
+    async_cube(2).await;
+    assert!(async_cube_cache_contains_key(2).await);
+}
+
+#[once(time = 0, result = true, reset_on_err = true)]
+fn once_reset_on_err() -> Result<u32, String> {
+    match ONCE_RESET_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+        0 => Ok(1),
+        _ => Err("unavailable".to_string()),
+    }
+}
+
+static ONCE_RESET_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[test]
+fn test_once_reset_on_err() {
+    assert_eq!(once_reset_on_err(), Ok(1));
+    assert!(ONCE_RESET_ON_ERR.read().unwrap().is_some());
+    // the value expired, the refresh fails and drops it
+    assert!(once_reset_on_err().is_err());
+    assert!(ONCE_RESET_ON_ERR.read().unwrap().is_none());
+}
+
+#[cfg(feature = "mmap_store")]
+mod mmap_store {
+    use cached::proc_macro::cached;
+    use cached::{Cached, MmapCache};
+
+    fn cache_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("cached-test-triangle-{}.cache", std::process::id()))
+    }
+
+    #[cached(
+        type = "MmapCache<u64, u64>",
+        create = r#"{ MmapCache::open(cache_path(), 100).expect("error opening the cache file") }"#
+    )]
+    fn triangle(n: u64) -> u64 {
+        (1..=n).sum()
+    }
+
+    #[test]
+    fn test_mmap_cache() {
+        let _ = std::fs::remove_file(cache_path());
+        assert_eq!(triangle(10), 55);
+        assert_eq!(triangle(10), 55);
+        let closed_path = cache_path().with_extension("closed");
+        {
+            let mut cache = TRIANGLE.lock().unwrap();
+            assert_eq!(cache.cache_hits(), Some(1));
+            cache.flush().unwrap();
+            // the file stays locked while it's open, close it as an exiting process would
+            *cache = MmapCache::open(&closed_path, 100).unwrap();
+        }
+
+        // a new process opening the file finds the cached values
+        let mut reopened = MmapCache::<u64, u64>::open(cache_path(), 100).unwrap();
+        assert_eq!(reopened.cache_get(&10), Some(&55));
+        std::fs::remove_file(cache_path()).unwrap();
+        std::fs::remove_file(closed_path).unwrap();
+    }
+}
+
+#[cfg(feature = "arcswap")]
+mod arcswap_once {
+    use cached::proc_macro::once;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    static SHARED_CONFIG_CALLS: AtomicU32 = AtomicU32::new(0);
+
+    #[once(lock = "arcswap", sync_writes = true)]
+    fn shared_config() -> String {
+        SHARED_CONFIG_CALLS.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(50));
+        "config".to_string()
+    }
+
+    #[test]
+    fn test_arcswap_first_call_race() {
+        let threads = (0..8)
+            .map(|_| std::thread::spawn(shared_config))
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), "config");
+        }
+        assert_eq!(SHARED_CONFIG_CALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            SHARED_CONFIG.load().as_deref().map(String::as_str),
+            Some("config")
+        );
+    }
+
+    static ASYNC_SHARED_CONFIG_CALLS: AtomicU32 = AtomicU32::new(0);
+
+    #[once(lock = "arcswap", sync_writes = true)]
+    async fn async_shared_config() -> u32 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        ASYNC_SHARED_CONFIG_CALLS.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_async_arcswap_first_call_race() {
+        let tasks = (0..8)
+            .map(|_| tokio::spawn(async_shared_config()))
+            .collect::<Vec<_>>();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), 1);
+        }
+        assert_eq!(ASYNC_SHARED_CONFIG_CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    static TOKEN_CALLS: AtomicU32 = AtomicU32::new(0);
+
+    #[once(lock = "arcswap", time = 0, result = true, reset_on_err = true)]
+    fn token() -> Result<u32, String> {
+        match TOKEN_CALLS.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(1),
+            _ => Err("unavailable".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_arcswap_expiry_and_errors() {
+        assert_eq!(token(), Ok(1));
+        assert!(TOKEN.load().is_some());
+        assert!(token().is_err());
+        assert!(TOKEN.load().is_none());
+        assert_eq!(token_prime_cache(), Err("unavailable".to_string()));
+    }
+}
+
+#[cached(
+    key = "String",
+    convert = r#"{ name.to_lowercase() }"#,
+    with_key = true
+)]
+fn salutation(name: &str) -> String {
+    format!("hello {}", name)
+}
+
+#[cached(result = true, with_key = true)]
+fn parse_port(port: String) -> Result<u16, std::num::ParseIntError> {
+    port.parse()
+}
+
+#[cached(option = true, size = 10, with_key = true)]
+async fn async_half(n: u32) -> Option<u32> {
+    match n % 2 {
+        0 => Some(n / 2),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn test_with_key() {
+    assert_eq!(
+        salutation_with_key("Ferris"),
+        ("ferris".to_string(), "hello Ferris".to_string())
+    );
+    assert_eq!(
+        parse_port_with_key("8080".to_string()),
+        Ok(("8080".to_string(), 8080))
+    );
+    assert!(parse_port_with_key("http".to_string()).is_err());
+    assert_eq!(async_half_with_key(4).await, Some((4, 2)));
+    assert_eq!(async_half_with_key(3).await, None);
+    // the key is the one the value is cached by
+    assert_eq!(
+        ASYNC_HALF.lock().await.cache_get(&async_half_key(4)),
+        Some(&2)
+    );
+}
+
+static INIT_RUNTIME_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cached(at_most_once)]
+fn init_runtime(threads: usize) -> String {
+    INIT_RUNTIME_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    format!("runtime with {} threads", threads)
+}
+
+#[test]
+fn test_at_most_once() {
+    let handles = (0..8)
+        .map(|_| std::thread::spawn(|| init_runtime(4)))
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), "runtime with 4 threads");
+    }
+    assert_eq!(
+        INIT_RUNTIME_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+    assert_eq!(INIT_RUNTIME.get().map(|(key, _)| *key), Some(4));
+
+    // it never runs again, not even for other arguments
+    let other_args = std::panic::catch_unwind(|| init_runtime(2));
+    assert!(other_args.is_err());
+    assert_eq!(
+        INIT_RUNTIME_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+}
+
+static CONNECT_ONCE_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cached(at_most_once, result = true)]
+async fn connect_once() -> Result<u32, String> {
+    match CONNECT_ONCE_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+        0 => Err("unavailable".to_string()),
+        n => Ok(n as u32),
+    }
+}
+
+#[tokio::test]
+async fn test_at_most_once_result() {
+    // errors aren't kept, the function runs until it succeeds once
+    assert!(connect_once().await.is_err());
+    assert_eq!(connect_once().await, Ok(1));
+    assert_eq!(connect_once().await, Ok(1));
+    assert_eq!(
+        CONNECT_ONCE_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+}
+
+// the body of a cached function calls the functions of the module, even one named `inner`
+fn inner(n: u32) -> u32 {
+    n + 1
+}
+
+#[cached(prime_name = "prime_lookup")]
+fn lookup(n: u32) -> u32 {
+    inner(n) * 2
+}
+
+// the default name of the cache-priming function of `lookup`
+fn lookup_prime_cache() -> &'static str {
+    "unrelated"
+}
+
+#[test]
+fn test_generated_names_avoid_user_items() {
+    assert_eq!(lookup(1), 4);
+    assert_eq!(prime_lookup(2), 6);
+    assert_eq!(lookup(2), 6);
+    assert_eq!(LOOKUP.lock().unwrap().cache_hits(), Some(1));
+    assert_eq!(lookup_prime_cache(), "unrelated");
+}
+
+static PLACEHOLDER_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cached(sync_writes = true, placeholder = r#"{ "loading".to_string() }"#)]
+fn slow_page(id: u32) -> String {
+    PLACEHOLDER_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    sleep(Duration::from_millis(300));
+    format!("page {}", id)
+}
+
+#[test]
+fn test_placeholder() {
+    let first = std::thread::spawn(|| slow_page(1));
+    sleep(Duration::from_millis(100));
+    // the value of 1 is being computed
+    assert_eq!(slow_page(1), "loading");
+    assert_eq!(first.join().unwrap(), "page 1");
+    assert_eq!(slow_page(1), "page 1");
+    assert_eq!(
+        PLACEHOLDER_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+    assert_eq!(SLOW_PAGE.lock().unwrap().cache_size(), 1);
+}
+
+#[cached(sync_writes = true, placeholder = "{ Ok(0) }", result = true)]
+async fn slow_count(id: u32) -> Result<u32, String> {
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    Ok(id * 10)
+}
+
+#[tokio::test]
+async fn test_placeholder_async() {
+    let first = tokio::spawn(slow_count(1));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(slow_count(1).await, Ok(0));
+    assert_eq!(first.await.unwrap(), Ok(10));
+    assert_eq!(slow_count(1).await, Ok(10));
+    assert_eq!(SLOW_COUNT.lock().await.cache_hits(), Some(1));
+}
+
+#[cached(size = 10, time = 100, time_to_idle = 1)]
+fn cached_timed_idle(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_timed_idle() {
+    assert_eq!(CACHED_TIMED_IDLE.lock().unwrap().tti(), Some(1));
+    cached_timed_idle(1);
+    cached_timed_idle(1);
+    sleep(Duration::from_secs(1));
+    cached_timed_idle(1);
+    {
+        let cache = CACHED_TIMED_IDLE.lock().unwrap();
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(2));
+    }
+}
+
+static CACHED_BEHIND_FEATURE_CALLS: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+#[cached(feature = "redis_store")]
+fn cached_behind_feature(n: u32) -> u32 {
+    CACHED_BEHIND_FEATURE_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    n
+}
+
+#[test]
+fn test_cached_behind_feature() {
+    cached_behind_feature(1);
+    cached_behind_feature(1);
+    let (calls, cached) = if cfg!(feature = "redis_store") {
+        (1, 1)
+    } else {
+        (2, 0)
+    };
+    assert_eq!(
+        CACHED_BEHIND_FEATURE_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        calls
+    );
+    assert_eq!(CACHED_BEHIND_FEATURE.lock().unwrap().cache_size(), cached);
+}
+
+/// A key type of its own, so the keys of `cached_newtype_key` can't be mixed up with other `u32`s
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct AccountId(u32);
+
+#[cached(size = 10, key = "AccountId", convert = r#"{ AccountId(id) }"#)]
+fn cached_newtype_key(id: u32) -> u32 {
+    id * 2
+}
+
+#[test]
+fn test_cached_newtype_key() {
+    assert_eq!(cached_newtype_key(1), 2);
+    assert_eq!(cached_newtype_key(1), 2);
+    let cache = CACHED_NEWTYPE_KEY.lock().unwrap();
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.key_order().collect::<Vec<_>>(), [&AccountId(1)]);
+}
+
+#[cached(sync_writes = true, cold_start_timeout = 1)]
+fn cached_cold_start_timeout(n: u64) -> u64 {
+    sleep(Duration::from_secs(n));
+    n
+}
+
+#[test]
+fn test_cached_cold_start_timeout() {
+    let slow = std::thread::spawn(|| cached_cold_start_timeout(3));
+    sleep(Duration::from_millis(500));
+    // the slow call holds the lock for longer than the timeout
+    assert_eq!(cached_cold_start_timeout(0), Err(cached::ColdStartTimeout));
+    assert_eq!(slow.join().unwrap(), Ok(3));
+    assert_eq!(cached_cold_start_timeout(3), Ok(3));
+    assert_eq!(cached_cold_start_timeout(0), Ok(0));
+}
+
+#[cached(sync_writes = true, cold_start_timeout = 1)]
+async fn cached_cold_start_timeout_async(n: u64) -> u64 {
+    tokio::time::sleep(Duration::from_secs(n)).await;
+    n
+}
+
+#[tokio::test]
+async fn test_cached_cold_start_timeout_async() {
+    let slow = tokio::spawn(cached_cold_start_timeout_async(3));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(
+        cached_cold_start_timeout_async(0).await,
+        Err(cached::ColdStartTimeout)
+    );
+    assert_eq!(slow.await.unwrap(), Ok(3));
+    assert_eq!(cached_cold_start_timeout_async(3).await, Ok(3));
+    assert_eq!(cached_cold_start_timeout_async(0).await, Ok(0));
+}
+
+#[test]
+fn test_cached_map() {
+    let mut cache = cached_map! {
+        "one" => 1,
+        "two" => 2,
+        "one" => 11,
+    };
+    assert_eq!(cache.cache_size(), 2);
+    assert_eq!(cache.cache_get(&"one"), Some(&11));
+    assert_eq!(cache.cache_get(&"two"), Some(&2));
+
+    let mut empty: UnboundCache<String, u32> = cached_map! {};
+    assert!(empty.cache_get(&"one".to_string()).is_none());
+}
+
+mod cache_visibility {
+    use cached::proc_macro::cached;
+    use cached::Cached;
+
+    pub mod inner {
+        use cached::proc_macro::cached;
+
+        #[cached]
+        pub(super) fn cached_pub_super(n: u32) -> u32 {
+            n + 1
+        }
+
+        #[cached]
+        pub(in crate::cache_visibility) fn cached_pub_in_path(n: u32) -> u32 {
+            n + 2
+        }
+
+        // the cache is visible to the parent module, unlike the function
+        #[cached(cache_visibility = "pub(in crate::cache_visibility)")]
+        fn cached_private_fn(n: u32) -> u32 {
+            n + 3
+        }
+
+        pub(super) fn call_cached_private_fn(n: u32) -> u32 {
+            cached_private_fn(n)
+        }
+    }
+
+    // the function is public, its cache stays private to this module
+    #[cached(cache_visibility = "")]
+    pub fn cached_private_cache(n: u32) -> u32 {
+        n + 4
+    }
+
+    #[test]
+    fn test_cache_visibility() {
+        assert_eq!(inner::cached_pub_super(1), 2);
+        assert_eq!(inner::CACHED_PUB_SUPER.lock().unwrap().cache_size(), 1);
+        assert_eq!(inner::cached_pub_in_path(1), 3);
+        assert_eq!(inner::CACHED_PUB_IN_PATH.lock().unwrap().cache_size(), 1);
+        assert_eq!(inner::call_cached_private_fn(1), 4);
+        assert_eq!(inner::CACHED_PRIVATE_FN.lock().unwrap().cache_size(), 1);
+        assert_eq!(cached_private_cache(1), 5);
+        assert_eq!(CACHED_PRIVATE_CACHE.lock().unwrap().cache_size(), 1);
+    }
+}
+
+static COMPUTE_TIMEOUT_DELAY_MS: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(0);
+static COMPUTE_TIMEOUT_CALLS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[derive(Debug, PartialEq)]
+enum ComputeError {
+    Timeout,
+}
+
+impl From<cached::ComputeTimeout> for ComputeError {
+    fn from(_: cached::ComputeTimeout) -> Self {
+        ComputeError::Timeout
+    }
+}
+
+/// Returns `n` and how many times the function ran before
+async fn compute_after_delay(n: u64) -> (u64, u64) {
+    let delay = COMPUTE_TIMEOUT_DELAY_MS.load(std::sync::atomic::Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(delay)).await;
+    let calls = COMPUTE_TIMEOUT_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    (n, calls)
+}
+
+#[cached(time = 1, result = true, compute_timeout_ms = 100)]
+async fn cached_compute_timeout(n: u64) -> Result<(u64, u64), ComputeError> {
+    Ok(compute_after_delay(n).await)
+}
+
+#[cached(time = 1, option = true, sync_writes = true, compute_timeout_ms = 100)]
+async fn cached_compute_timeout_option(n: u64) -> Option<(u64, u64)> {
+    Some(compute_after_delay(n).await)
+}
+
+#[tokio::test]
+#[serial(compute_timeout)]
+async fn test_cached_compute_timeout() {
+    COMPUTE_TIMEOUT_DELAY_MS.store(0, std::sync::atomic::Ordering::SeqCst);
+    let first = cached_compute_timeout(1).await.unwrap();
+
+    COMPUTE_TIMEOUT_DELAY_MS.store(500, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(cached_compute_timeout(2).await, Err(ComputeError::Timeout));
+    assert_eq!(cached_compute_timeout(1).await, Ok(first));
+
+    // the expired value is returned while computing a fresh one times out
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(cached_compute_timeout(1).await, Ok(first));
+
+    // the timed out computation didn't keep anything locked, the next call computes again
+    COMPUTE_TIMEOUT_DELAY_MS.store(0, std::sync::atomic::Ordering::SeqCst);
+    assert_ne!(cached_compute_timeout(1).await, Ok(first));
+    assert_eq!(cached_compute_timeout(2).await.unwrap().0, 2);
+}
+
+#[tokio::test]
+#[serial(compute_timeout)]
+async fn test_cached_compute_timeout_option() {
+    COMPUTE_TIMEOUT_DELAY_MS.store(500, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(cached_compute_timeout_option(1).await, None);
+
+    COMPUTE_TIMEOUT_DELAY_MS.store(0, std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(cached_compute_timeout_option(1).await.unwrap().0, 1);
+}
+
+#[cached(size = 10, evict_after_set = 1)]
+fn evicted_after_set(n: u32) -> u32 {
+    n
+}
+
+#[cached(evict_after_set = 1)]
+async fn evicted_after_set_async(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_evict_after_set() {
+    evicted_after_set(1);
+    evicted_after_set(1);
+    assert_eq!(EVICTED_AFTER_SET.lock().unwrap().cache_hits(), Some(1));
+    // removed by the background thread, without calling the function again
+    sleep(Duration::from_millis(1500));
+    assert_eq!(EVICTED_AFTER_SET.lock().unwrap().cache_size(), 0);
+    evicted_after_set(1);
+    assert_eq!(EVICTED_AFTER_SET.lock().unwrap().cache_misses(), Some(2));
+}
+
+#[tokio::test]
+async fn test_evict_after_set_async() {
+    evicted_after_set_async(1).await;
+    assert_eq!(EVICTED_AFTER_SET_ASYNC.lock().await.cache_size(), 1);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(EVICTED_AFTER_SET_ASYNC.lock().await.cache_size(), 0);
+}
+
+// the cache is shared by every `N`, so the key includes it
+#[cached(key = "(usize, Vec<u8>)", convert = r#"{ (N, data.to_vec()) }"#)]
+fn render_block<const N: usize>(data: [u8; N]) -> String {
+    format!("{:?}", data)
+}
+
+#[test]
+fn test_const_generic() {
+    assert_eq!(render_block([1, 2]), "[1, 2]");
+    assert_eq!(render_block([1, 2, 3]), "[1, 2, 3]");
+    assert_eq!(render_block([1, 2]), "[1, 2]");
+    let cache = RENDER_BLOCK.lock().unwrap();
+    assert_eq!(cache.cache_size(), 2);
+    assert_eq!(cache.cache_hits(), Some(1));
+}
+
+static NORMALIZED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn normalize(name: &str) -> String {
+    NORMALIZED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    name.trim().to_lowercase()
+}
+
+// the name is normalized once, for both the key and the body
+#[cached(
+    key = "String",
+    convert_prepared = r#"{ let name = normalize(name); (name.clone(), name) }"#,
+    prepared_arg = "name: String"
+)]
+fn greet_normalized(name: &str) -> String {
+    format!("hello {}", name)
+}
+
+#[cached(
+    key = "String",
+    convert_prepared = r#"{ let name = name.trim().to_lowercase(); (name.clone(), name) }"#,
+    prepared_arg = "name: String",
+    sync_writes = true
+)]
+async fn greet_normalized_async(name: &str) -> String {
+    format!("hello {}", name)
+}
+
+#[test]
+fn test_convert_prepared() {
+    assert_eq!(greet_normalized(" Ferris "), "hello ferris");
+    assert_eq!(NORMALIZED.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert_eq!(greet_normalized("FERRIS"), "hello ferris");
+    assert_eq!(GREET_NORMALIZED.lock().unwrap().cache_hits(), Some(1));
+    assert_eq!(greet_normalized_key(" Ferris"), "ferris");
+    greet_normalized_prime_cache("Crab");
+    assert_eq!(GREET_NORMALIZED.lock().unwrap().cache_size(), 2);
+}
+
+#[tokio::test]
+async fn test_convert_prepared_async() {
+    assert_eq!(greet_normalized_async(" Ferris ").await, "hello ferris");
+    assert_eq!(greet_normalized_async("FERRIS").await, "hello ferris");
+    assert_eq!(GREET_NORMALIZED_ASYNC.lock().await.cache_hits(), Some(1));
+}
+
+static INFLIGHT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+static MAX_INFLIGHT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cached(max_inflight = 2)]
+fn limited_inflight(n: u32) -> u32 {
+    use std::sync::atomic::Ordering;
+    let running = INFLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+    MAX_INFLIGHT.fetch_max(running, Ordering::SeqCst);
+    sleep(Duration::from_millis(50));
+    INFLIGHT.fetch_sub(1, Ordering::SeqCst);
+    n * 2
+}
+
+#[test]
+fn test_max_inflight() {
+    let threads = (0..6)
+        .map(|n| std::thread::spawn(move || limited_inflight(n)))
+        .collect::<Vec<_>>();
+    for (n, thread) in threads.into_iter().enumerate() {
+        assert_eq!(thread.join().unwrap(), n as u32 * 2);
+    }
+    assert_eq!(MAX_INFLIGHT.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert_eq!(LIMITED_INFLIGHT.lock().unwrap().cache_size(), 6);
+}
+
+#[cached(
+    type = "cached::PartitionedCache<u32, (u32, u32), String>",
+    create = "{ cached::PartitionedCache::new(|(tenant, _): &(u32, u32)| *tenant, 3) }",
+    convert = "{ (tenant, id) }"
+)]
+fn tenant_lookup(tenant: u32, id: u32) -> String {
+    format!("{}/{}", tenant, id)
+}
+
+#[test]
+fn test_partitioned_fairness() {
+    // many cold tenants, each looking up a couple of ids
+    for tenant in 1..=20 {
+        tenant_lookup(tenant, 1);
+        tenant_lookup(tenant, 2);
+    }
+    // one hot tenant looking up many ids
+    for id in 0..1000 {
+        tenant_lookup(0, id);
+    }
+    let mut cache = TENANT_LOOKUP.lock().unwrap();
+    assert_eq!(cache.cache_size(), 20 * 2 + 3);
+    for tenant in 1..=20 {
+        assert_eq!(
+            cache.cache_get(&(tenant, 1)),
+            Some(&format!("{}/1", tenant))
+        );
+        assert!(cache.cache_get(&(tenant, 2)).is_some());
+    }
+    let hot = cache.partition_stats().into_iter().find(|(p, _)| **p == 0);
+    assert_eq!(hot.unwrap().1.size, 3);
+}
+
+#[cfg(feature = "async")]
+static BRIDGED_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(feature = "async")]
+#[cached(size = 10)]
+fn bridged(n: u32) -> u32 {
+    BRIDGED_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    n * 2
+}
+
+#[cfg(feature = "async")]
+async fn bridged_async(n: u32) -> u32 {
+    cached::async_sync::get_or_set_bridged(&BRIDGED, n, || async move {
+        BRIDGED_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        n * 2
+    })
+    .await
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_sync_and_async_share_a_cache() {
+    assert_eq!(bridged(1), 2);
+    assert_eq!(bridged_async(1).await, 2);
+    assert_eq!(bridged_async(2).await, 4);
+    assert_eq!(bridged(2), 4);
+    assert_eq!(BRIDGED_CALLS.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert_eq!(BRIDGED.lock().unwrap().cache_size(), 2);
+}
+
+static FLAKY_INIT_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+static FLAKY_INIT_UP: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[once(result = true, retry_backoff_ms = 100, retry_backoff_max_ms = 400)]
+fn flaky_init() -> Result<u32, String> {
+    FLAKY_INIT_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    if FLAKY_INIT_UP.load(std::sync::atomic::Ordering::SeqCst) {
+        Ok(1)
+    } else {
+        Err("unavailable".to_string())
+    }
+}
+
+#[test]
+fn test_once_retry_backoff() {
+    let calls = || FLAKY_INIT_CALLS.load(std::sync::atomic::Ordering::SeqCst);
+    // callers get the error back without retrying during the backoff
+    for _ in 0..3 {
+        assert_eq!(flaky_init(), Err("unavailable".to_string()));
+    }
+    assert_eq!(calls(), 1);
+
+    // retried once the backoff elapsed, failing again doubles it to 200ms
+    sleep(Duration::from_millis(120));
+    assert!(flaky_init().is_err());
+    assert!(flaky_init().is_err());
+    assert_eq!(calls(), 2);
+    sleep(Duration::from_millis(120));
+    assert!(flaky_init().is_err());
+    assert_eq!(calls(), 2);
+
+    FLAKY_INIT_UP.store(true, std::sync::atomic::Ordering::SeqCst);
+    sleep(Duration::from_millis(120));
+    assert_eq!(flaky_init(), Ok(1));
+    assert_eq!(flaky_init(), Ok(1));
+    assert_eq!(calls(), 3);
+}
+
+#[cfg(feature = "async")]
+static FLAKY_ASYNC_INIT_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[once(result = true, sync_writes = true, retry_backoff_ms = 60000)]
+async fn flaky_async_init() -> Result<u32, String> {
+    FLAKY_ASYNC_INIT_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Err("unavailable".to_string())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_once_retry_backoff_async() {
+    for _ in 0..5 {
+        assert_eq!(flaky_async_init().await, Err("unavailable".to_string()));
+    }
+    assert_eq!(
+        FLAKY_ASYNC_INIT_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+}
+
+/// Shared state that shouldn't be part of the key, and isn't `Hash`
+#[derive(Clone)]
+struct Shared {
+    calls: std::sync::Arc<std::sync::atomic::AtomicU32>,
+}
+
+/// An extractor-like newtype, unwrapped into the key
+struct Id(u32);
+
+impl std::ops::Deref for Id {
+    type Target = u32;
+
+    fn deref(&self) -> &u32 {
+        &self.0
+    }
+}
+
+#[cached(ignore_types = "Shared")]
+fn ignoring_shared(shared: Shared, n: u32) -> u32 {
+    shared
+        .calls
+        .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    n * 2
+}
+
+#[cached(extractor = true, ignore_types = "Shared")]
+fn extracting_id(shared: &Shared, Id(id): Id) -> u32 {
+    shared
+        .calls
+        .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    id * 2
+}
+
+#[test]
+fn test_ignore_types_and_extractor() {
+    let shared = Shared {
+        calls: Default::default(),
+    };
+    assert_eq!(ignoring_shared(shared.clone(), 1), 2);
+    assert_eq!(ignoring_shared(shared.clone(), 1), 2);
+    assert_eq!(IGNORING_SHARED.lock().unwrap().cache_get(&1), Some(&2));
+
+    assert_eq!(extracting_id(&shared, Id(2)), 4);
+    assert_eq!(extracting_id(&shared, Id(2)), 4);
+    assert_eq!(EXTRACTING_ID.lock().unwrap().cache_get(&2), Some(&4));
+    assert_eq!(shared.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+/// `Hash` but neither `Eq` nor `Clone`
+#[derive(Hash)]
+struct Shape {
+    points: Vec<(i32, i32)>,
+}
+
+static SHAPE_SIZE_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cached(hash_only_key = true)]
+fn shape_size(shape: &Shape, scale: u32) -> usize {
+    SHAPE_SIZE_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    shape.points.len() * scale as usize
+}
+
+/// A hasher hashing every value to 0, so every key collides
+#[derive(Default)]
+struct CollidingHasher;
+
+impl std::hash::Hasher for CollidingHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {}
+}
+
+#[cached(hash_only_key = true, key_hasher = "CollidingHasher")]
+fn colliding(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_hash_only_key() {
+    let shape = Shape {
+        points: vec![(0, 0), (1, 1)],
+    };
+    assert_eq!(shape_size(&shape, 2), 4);
+    assert_eq!(shape_size(&shape, 2), 4);
+    assert_eq!(shape_size(&shape, 3), 6);
+    assert_eq!(
+        SHAPE_SIZE_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        2
+    );
+    assert_eq!(SHAPE_SIZE.lock().unwrap().cache_size(), 2);
+
+    // a collision returns the value cached for other arguments
+    assert_eq!(colliding(1), 1);
+    assert_eq!(colliding(2), 1);
+}
+
+/// Resolve a short name into its canonical id, e.g. with a lookup in a remote service
+async fn resolve_user_id(name: &&str) -> u64 {
+    tokio::task::yield_now().await;
+    match *name {
+        "ferris" | "Ferris" => 1,
+        _ => 0,
+    }
+}
+
+static GREET_USER_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cached(key = "u64", key_fn_async = "resolve_user_id")]
+async fn greet_user(name: &str) -> String {
+    GREET_USER_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    format!("Hello, {}", name)
+}
+
+#[tokio::test]
+async fn test_key_fn_async() {
+    assert_eq!(greet_user("ferris").await, "Hello, ferris");
+    // resolves to the same id, so the cached greeting is returned
+    assert_eq!(greet_user("Ferris").await, "Hello, ferris");
+    assert_eq!(
+        GREET_USER_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+    assert_eq!(greet_user_key("Ferris").await, 1);
+    assert!(greet_user_cache_contains_key("ferris").await);
+}
+
+static LIST_USERS_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cached]
+fn list_users() -> Vec<String> {
+    LIST_USERS_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    vec!["ferris".to_string()]
+}
+
+#[cached(result = true, invalidate_sibling = "list_users")]
+fn create_user(name: String) -> Result<String, ()> {
+    if name.is_empty() {
+        return Err(());
+    }
+    Ok(name)
+}
+
+#[test]
+fn test_invalidate_sibling() {
+    let calls = || LIST_USERS_CALLS.load(std::sync::atomic::Ordering::SeqCst);
+    list_users();
+    list_users();
+    assert_eq!(calls(), 1);
+
+    // caching a new user empties the cache of the list
+    assert_eq!(create_user("corro".to_string()), Ok("corro".to_string()));
+    list_users();
+    assert_eq!(calls(), 2);
+
+    // a cached or uncached (error) result leaves it alone
+    assert_eq!(create_user("corro".to_string()), Ok("corro".to_string()));
+    assert_eq!(create_user(String::new()), Err(()));
+    list_users();
+    assert_eq!(calls(), 2);
+
+    create_user_prime_cache("ferris".to_string()).unwrap();
+    list_users();
+    assert_eq!(calls(), 3);
+}
+
+#[cfg(feature = "async")]
+static LIST_ORDERS_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached]
+async fn list_orders() -> usize {
+    LIST_ORDERS_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) as usize
+}
+
+#[cfg(feature = "async")]
+#[cached(invalidate_sibling = "list_orders")]
+async fn create_order(id: u32) -> u32 {
+    id
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_invalidate_sibling_async() {
+    assert_eq!(list_orders().await, 0);
+    assert_eq!(list_orders().await, 0);
+    create_order(1).await;
+    assert_eq!(list_orders().await, 1);
+    create_order(1).await;
+    assert_eq!(list_orders().await, 1);
+
+    list_orders_cache_flush().await;
+    assert_eq!(list_orders().await, 2);
+}
+
+/// `Send` but not `Sync`: the cache's `Mutex` is `Sync` regardless
+#[cached]
+fn counter_cell(start: u32) -> std::cell::Cell<u32> {
+    std::cell::Cell::new(start)
+}
+
+#[test]
+fn test_send_not_sync_value() {
+    let counter = counter_cell(1);
+    counter.set(counter.get() + 1);
+    assert_eq!(counter.get(), 2);
+    assert_eq!(counter_cell(1).get(), 1);
+}
+
+#[cached(inline = true)]
+#[must_use]
+fn hot_square(n: u64) -> u64 {
+    n * n
+}
+
+#[test]
+fn test_inline() {
+    let sum: u64 = (0..1000).map(|i| hot_square(i % 10)).sum();
+    assert_eq!(sum, 100 * (0..10).map(|i| i * i).sum::<u64>());
+    assert_eq!(HOT_SQUARE.lock().unwrap().cache_size(), 10);
+}
+
+#[cached(name = "DIMENSIONS", size = 10)]
+fn width_of(name: String) -> u32 {
+    name.len() as u32
+}
+
+#[cached(name = "DIMENSIONS", size = 10, shared = true)]
+fn height_of(name: String) -> u32 {
+    name.len() as u32 * 2
+}
+
+#[test]
+fn test_shared() {
+    assert_eq!(width_of("box".to_string()), 3);
+    assert_eq!(height_of("door".to_string()), 8);
+    {
+        let cache = DIMENSIONS.lock().unwrap();
+        assert_eq!(cache.cache_size(), 2);
+        assert_eq!(cache.cache_misses(), Some(2));
+    }
+    // the keys are shared too, so `height_of` finds the value cached by `width_of`
+    assert_eq!(height_of("box".to_string()), 3);
+    assert_eq!(DIMENSIONS.lock().unwrap().cache_hits(), Some(1));
+
+    // flushing either function empties the shared store
+    height_of_cache_flush();
+    assert_eq!(DIMENSIONS.lock().unwrap().cache_size(), 0);
+    assert_eq!(height_of("box".to_string()), 6);
+}
+
+#[cached(lock = "rwlock")]
+fn read_mostly(n: u64) -> u64 {
+    n * 3
+}
+
+#[test]
+fn test_rwlock() {
+    assert_eq!(read_mostly(1), 3);
+    let handles = (0..4)
+        .map(|_| std::thread::spawn(|| (0..100).map(|_| read_mostly(1)).sum::<u64>()))
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 300);
+    }
+    {
+        let cache = READ_MOSTLY.read().unwrap();
+        assert_eq!(cache.cache_hits(), Some(400));
+        assert_eq!(cache.cache_misses(), Some(1));
+    }
+    read_mostly_cache_flush();
+    assert_eq!(READ_MOSTLY.read().unwrap().cache_size(), 0);
+}
+
+#[cfg(feature = "dashmap_store")]
+#[cached(
+    lock = "none",
+    type = "cached::stores::DashMapCache<u64, u64>",
+    create = "{ cached::stores::DashMapCache::new() }",
+    result = true
+)]
+fn unlocked_read(n: u64) -> Result<u64, String> {
+    if n == 0 {
+        return Err("zero".to_string());
+    }
+    Ok(n * 5)
+}
+
+#[cfg(feature = "dashmap_store")]
+#[test]
+fn test_unlocked() {
+    assert_eq!(unlocked_read(1), Ok(5));
+    let handles = (0..4)
+        .map(|_| std::thread::spawn(|| (0..100).map(|_| unlocked_read(1).unwrap()).sum::<u64>()))
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 500);
+    }
+    assert!(unlocked_read(0).is_err());
+    assert_eq!(UNLOCKED_READ.cache_hits(), 400);
+    assert_eq!(UNLOCKED_READ.cache_misses(), 2);
+    assert_eq!(UNLOCKED_READ.cache_size(), 1);
+
+    // priming recomputes and stores the value even though it's cached
+    assert_eq!(unlocked_read_prime_cache(2), Ok(10));
+    assert_eq!(UNLOCKED_READ.cache_misses(), 2);
+    assert_eq!(UNLOCKED_READ.cache_size(), 2);
+}
+
+#[cfg(feature = "async")]
+#[cached(size = 10)]
+fn boxed_lookup(id: u32) -> std::pin::Pin<Box<dyn std::future::Future<Output = String> + Send>> {
+    Box::pin(async move {
+        tokio::task::yield_now().await;
+        format!("user-{}", id)
+    })
+}
+
+#[cfg(feature = "async")]
+#[cached(key = "String", convert = r#"{ name.to_string() }"#)]
+fn boxed_len<'a>(
+    name: &'a str,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = usize> + Send + 'a>> {
+    Box::pin(async move { name.len() })
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_boxed_future() {
+    // the function still returns a boxed future, e.g. to implement a trait method returning one
+    let lookup: std::pin::Pin<Box<dyn std::future::Future<Output = String> + Send>> =
+        boxed_lookup(1);
+    assert_eq!(lookup.await, "user-1");
+    assert_eq!(boxed_lookup(1).await, "user-1");
+    {
+        let cache = BOXED_LOOKUP.lock().await;
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(1));
+    }
+
+    let name = String::from("borrowed");
+    assert_eq!(boxed_len(&name).await, 8);
+    assert_eq!(boxed_len("borrowed").await, 8);
+    assert_eq!(BOXED_LEN.lock().await.cache_hits(), Some(1));
+}
+
+/// A custom store implementing the required `Cached` methods and `cache_snapshot`, relying on
+/// the default `cache_retain`
+struct PairStore {
+    pairs: Vec<(u32, u32)>,
+}
+
+impl Cached<u32, u32> for PairStore {
+    fn cache_get(&mut self, k: &u32) -> Option<&u32> {
+        self.pairs.iter().find(|(key, _)| key == k).map(|(_, v)| v)
+    }
+
+    fn cache_get_mut(&mut self, k: &u32) -> Option<&mut u32> {
+        self.pairs
+            .iter_mut()
+            .find(|(key, _)| key == k)
+            .map(|(_, v)| v)
+    }
+
+    fn cache_set(&mut self, k: u32, v: u32) -> Option<u32> {
+        let previous = self.cache_remove(&k);
+        self.pairs.push((k, v));
+        previous
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> u32>(&mut self, k: u32, f: F) -> &mut u32 {
+        let index = match self.pairs.iter().position(|(key, _)| *key == k) {
+            Some(index) => index,
+            None => {
+                self.pairs.push((k, f()));
+                self.pairs.len() - 1
+            }
+        };
+        &mut self.pairs[index].1
+    }
+
+    fn cache_remove(&mut self, k: &u32) -> Option<u32> {
+        let index = self.pairs.iter().position(|(key, _)| key == k)?;
+        Some(self.pairs.remove(index).1)
+    }
+
+    fn cache_snapshot(&self) -> Vec<(u32, u32)> {
+        let mut pairs = self.pairs.clone();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    fn cache_clear(&mut self) {
+        self.pairs.clear();
+    }
+
+    fn cache_reset(&mut self) {
+        self.pairs = Vec::new();
+    }
+
+    fn cache_size(&self) -> usize {
+        self.pairs.len()
+    }
+}
+
+#[cached(type = "PairStore", create = "{ PairStore { pairs: Vec::new() } }")]
+fn tripled(n: u32) -> u32 {
+    n * 3
+}
+
+#[test]
+fn test_custom_store_retain() {
+    for n in 0..4 {
+        assert_eq!(tripled(n), n * 3);
+    }
+    let mut cache = TRIPLED.lock().unwrap();
+    cache.cache_retain(|k, v| {
+        *v += 1;
+        k % 2 == 0
+    });
+    // the default removes the rejected values, without keeping the changes to the others
+    assert_eq!(cache.cache_snapshot(), [(0, 0), (2, 6)]);
+}
@@ -0,0 +1,95 @@
+/*!
+Tests of the global kill switch, in their own binary since disabling caching affects every
+macro-defined function of the process.
+*/
+
+use cached::proc_macro::{cached, once};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached]
+fn double(n: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    n * 2
+}
+
+#[cached(result = true)]
+fn checked_double(n: u32) -> Result<u32, ()> {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    Ok(n * 2)
+}
+
+#[cached(error_on_miss = true)]
+fn cached_only(n: u32) -> u32 {
+    n * 2
+}
+
+#[cached]
+async fn async_double(n: u32) -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    n * 2
+}
+
+#[once]
+fn once_value() -> u32 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    1
+}
+
+static RUNS: AtomicU32 = AtomicU32::new(0);
+
+#[once]
+fn run_once() {
+    RUNS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Return how many times the function bodies were called by `f`
+fn calls(f: impl FnOnce()) -> u32 {
+    let before = CALLS.load(Ordering::SeqCst);
+    f();
+    CALLS.load(Ordering::SeqCst) - before
+}
+
+#[test]
+fn test_set_caching_enabled() {
+    use cached::Cached;
+
+    assert!(cached::caching_enabled());
+    assert_eq!(calls(|| assert_eq!(double(1), 2)), 1);
+    assert_eq!(calls(|| assert_eq!(double(1), 2)), 0);
+    cached_only_prime_cache(1);
+    run_once();
+
+    cached::set_caching_enabled(false);
+    assert!(!cached::caching_enabled());
+    assert_eq!(calls(|| assert_eq!(double(1), 2)), 1);
+    assert_eq!(calls(|| assert_eq!(double(2), 4)), 1);
+    assert_eq!(calls(|| assert_eq!(double(2), 4)), 1);
+    assert_eq!(calls(|| assert_eq!(checked_double(1), Ok(2))), 1);
+    assert_eq!(calls(|| assert_eq!(checked_double(1), Ok(2))), 1);
+    assert_eq!(cached_only(1), Err(cached::CacheMiss));
+    let async_calls = calls(|| {
+        cached::async_sync::block_on(async {
+            assert_eq!(async_double(1).await, 2);
+            assert_eq!(async_double(1).await, 2);
+        })
+    });
+    assert_eq!(async_calls, 2);
+    assert_eq!(calls(|| assert_eq!(once_value(), 1)), 1);
+    assert_eq!(calls(|| assert_eq!(once_value(), 1)), 1);
+    // run-once guards don't cache a value and keep running once
+    run_once();
+    assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    // nothing was read or written while disabled
+    assert_eq!(DOUBLE.lock().unwrap().cache_size(), 1);
+    assert_eq!(DOUBLE.lock().unwrap().cache_hits(), Some(1));
+    assert_eq!(CHECKED_DOUBLE.lock().unwrap().cache_size(), 0);
+
+    cached::set_caching_enabled(true);
+    assert_eq!(calls(|| assert_eq!(double(1), 2)), 0);
+    assert_eq!(calls(|| assert_eq!(double(2), 4)), 1);
+    assert_eq!(cached_only(1), Ok(2));
+    assert_eq!(calls(|| assert_eq!(once_value(), 1)), 1);
+    assert_eq!(calls(|| assert_eq!(once_value(), 1)), 0);
+}
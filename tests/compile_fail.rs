@@ -0,0 +1,20 @@
+/*!
+Compile errors reported for misused macros, snapshotted in the `.stderr` file of each case.
+Regenerate them with `TRYBUILD=overwrite cargo test --test compile_fail`.
+*/
+
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/compile_fail/*.rs");
+    // rustc lists the stores implementing `Cached` next to these errors, and the feature-gated
+    // stores are part of that list, so these cases are only snapshotted with all of them enabled:
+    // `cargo test --features chaos,lru_store,mmap_store --test compile_fail`
+    if cfg!(all(
+        feature = "chaos",
+        feature = "lru_store",
+        feature = "mmap_store"
+    )) {
+        t.compile_fail("tests/compile_fail/all_stores/*.rs");
+    }
+}
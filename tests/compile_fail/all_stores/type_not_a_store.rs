@@ -0,0 +1,9 @@
+use cached::proc_macro::cached;
+use std::collections::BTreeMap;
+
+#[cached(type = "BTreeMap<u32, u32>", create = "{ BTreeMap::new() }")]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
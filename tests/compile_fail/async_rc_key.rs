@@ -0,0 +1,48 @@
+use cached::proc_macro::cached;
+use cached::Cached;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+// a store that's `Send` and `Sync` whatever its keys and values, so that only the
+// assertions of `#[cached]` report them
+struct NoStore<K, V>(PhantomData<fn() -> (K, V)>);
+
+impl<K, V> Cached<K, V> for NoStore<K, V> {
+    fn cache_get(&mut self, _k: &K) -> Option<&V> {
+        None
+    }
+    fn cache_get_mut(&mut self, _k: &K) -> Option<&mut V> {
+        None
+    }
+    fn cache_set(&mut self, _k: K, _v: V) -> Option<V> {
+        None
+    }
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, _k: K, _f: F) -> &mut V {
+        unimplemented!()
+    }
+    fn cache_remove(&mut self, _k: &K) -> Option<V> {
+        None
+    }
+    fn cache_clear(&mut self) {}
+    fn cache_reset(&mut self) {}
+    fn cache_size(&self) -> usize {
+        0
+    }
+}
+
+#[cached(type = "NoStore<Rc<u32>, u32>", create = "{ NoStore(PhantomData) }")]
+async fn rc_key(k: Rc<u32>) -> u32 {
+    *k
+}
+
+#[cached(
+    type = "NoStore<Rc<u32>, u32>",
+    create = "{ NoStore(PhantomData) }",
+    key = "Rc<u32>",
+    convert = "{ Rc::new(k) }"
+)]
+async fn rc_converted_key(k: u32) -> u32 {
+    k
+}
+
+fn main() {}
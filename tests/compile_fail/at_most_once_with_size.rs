@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(at_most_once, size = 10)]
+fn init(threads: usize) -> usize {
+    threads
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(size = 10, unbound)]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
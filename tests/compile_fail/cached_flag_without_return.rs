@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(with_cached_flag = true)]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use cached::proc_macro::cached;
+
+#[derive(Clone)]
+struct MyReturn<T>(T);
+
+#[cached(result = true, with_cached_flag = true)]
+fn lookup(n: u32) -> Result<MyReturn<u32>, String> {
+    Ok(MyReturn(n))
+}
+
+#[cached(result = true, with_cached_flag = true)]
+fn wrapped_result(n: u32) -> cached::Return<Result<u32, String>> {
+    cached::Return::new(Ok(n))
+}
+
+#[cached(option = true, with_cached_flag = true)]
+fn find(n: u32) -> Option<u32> {
+    Some(n)
+}
+
+fn main() {}
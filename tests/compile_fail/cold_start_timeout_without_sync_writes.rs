@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(cold_start_timeout = 5)]
+fn page(id: u32) -> String {
+    format!("page {}", id)
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(result = true, compute_timeout_ms = 100)]
+fn fetch(id: u32) -> Result<u32, String> {
+    Ok(id)
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use cached::proc_macro::cached;
+
+struct Config;
+
+#[cached(config = "Config", size = 10)]
+fn lookup(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
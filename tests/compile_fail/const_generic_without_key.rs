@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached]
+fn render<const N: usize>(data: [u8; N]) -> String {
+    format!("{:?}", data)
+}
+
+fn main() {}
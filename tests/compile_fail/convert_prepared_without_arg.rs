@@ -0,0 +1,11 @@
+use cached::proc_macro::cached;
+
+#[cached(
+    key = "String",
+    convert_prepared = r#"{ let name = name.to_lowercase(); (name.clone(), name) }"#
+)]
+fn greet(name: &str) -> String {
+    format!("hello {}", name)
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(result = true, error_on_miss = true)]
+fn lookup(n: u32) -> Result<u32, String> {
+    Ok(n)
+}
+
+fn main() {}
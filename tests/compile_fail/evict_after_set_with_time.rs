@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(size = 10, time = 60, evict_after_set = 30)]
+fn lookup(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
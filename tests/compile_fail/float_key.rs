@@ -0,0 +1,13 @@
+use cached::proc_macro::cached;
+
+#[cached]
+fn price(rate: f64) -> f64 {
+    rate * 100.0
+}
+
+#[cached]
+fn total(prices: Vec<f32>) -> f32 {
+    prices.iter().sum()
+}
+
+fn main() {}
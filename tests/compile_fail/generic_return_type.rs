@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(key = "usize", convert = r#"{ N }"#)]
+fn zeroes<const N: usize>() -> [u8; N] {
+    [0; N]
+}
+
+fn main() {}
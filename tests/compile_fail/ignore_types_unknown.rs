@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(ignore_types = "State")]
+fn get_user(id: u64) -> u64 {
+    id
+}
+
+fn main() {}
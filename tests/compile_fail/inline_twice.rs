@@ -0,0 +1,9 @@
+use cached::proc_macro::cached;
+
+#[cached(inline = true)]
+#[inline(always)]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use cached::proc_macro::cached;
+
+async fn double_key(n: &u32) -> u32 {
+    n * 2
+}
+
+#[cached(key = "u32", key_fn_async = "double_key")]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(key_hasher = "std::collections::hash_map::DefaultHasher")]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(key_lifetime = "'a")]
+fn greet(name: String) -> String {
+    format!("Hello, {}", name)
+}
+
+fn main() {}
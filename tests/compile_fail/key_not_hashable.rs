@@ -0,0 +1,19 @@
+use cached::proc_macro::cached;
+
+#[derive(PartialEq, Eq)]
+struct UserId(u32);
+
+#[cached(key = "UserId", convert = r#"{ UserId(id) }"#)]
+fn user_name(id: u32) -> String {
+    format!("user {}", id)
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct OrderId(u32);
+
+#[cached(size = 10, key = "OrderId", convert = r#"{ OrderId(id) }"#)]
+fn order_total(id: u32) -> u64 {
+    id as u64
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(key = "String")]
+fn greet(name: String) -> String {
+    format!("Hello, {}", name)
+}
+
+fn main() {}
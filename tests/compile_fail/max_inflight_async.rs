@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(max_inflight = 4)]
+async fn fetch(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use cached::proc_macro::once;
+
+#[once(lock = "arcswap")]
+fn init_logger() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::once;
+
+#[once(result = true, option = true)]
+fn load() -> Option<u32> {
+    Some(1)
+}
+
+fn main() {}
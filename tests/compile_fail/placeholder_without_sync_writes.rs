@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(placeholder = r#"{ String::new() }"#)]
+fn page(id: u32) -> String {
+    format!("page {}", id)
+}
+
+fn main() {}
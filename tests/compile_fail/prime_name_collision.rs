@@ -0,0 +1,14 @@
+use cached::proc_macro::cached;
+
+// `lookup_prime_cache` clashes with the cache-priming function of `lookup`, renamed with `prime_name`
+
+#[cached]
+fn lookup(n: u32) -> u32 {
+    n * 2
+}
+
+fn lookup_prime_cache(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use cached::proc_macro::cached;
+
+#[cached(prime_name = "lookup")]
+fn lookup(n: u32) -> u32 {
+    n * 2
+}
+
+#[cached(prime_name = "prime lookup")]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::once;
+
+#[once(time = 10, reset_on_err = true)]
+fn config() -> u32 {
+    1
+}
+
+fn main() {}
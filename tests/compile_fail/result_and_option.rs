@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(result = true, option = true)]
+fn lookup(n: u32) -> Result<u32, ()> {
+    Ok(n)
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::once;
+
+#[once(retry_backoff_ms = 1000)]
+fn config() -> u32 {
+    1
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use cached::proc_macro::cached;
+
+#[cached(size = 10, lock = "rwlock")]
+fn sized(n: u32) -> u32 {
+    n
+}
+
+#[cached(lock = "rwlock")]
+async fn asynchronous(n: u32) -> u32 {
+    n
+}
+
+#[cached(lock = "spinlock")]
+fn spinning(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use cached::proc_macro::cached;
+
+#[cached(name = "LENGTHS", size = 10)]
+fn length(s: String) -> usize {
+    s.len()
+}
+
+#[cached(name = "LENGTHS", shared = true)]
+fn char_count(s: String) -> usize {
+    s.chars().count()
+}
+
+#[cached(shared = true)]
+fn byte_count(s: String) -> usize {
+    s.len()
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(size = 10, time_to_idle = 5)]
+fn lookup(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(trace_caller = true)]
+async fn traced(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(type = "SizedCache<u32, u32>")]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
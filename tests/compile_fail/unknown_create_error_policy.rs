@@ -0,0 +1,12 @@
+use cached::proc_macro::cached;
+
+#[cached(
+    type = "cached::SizedCache<u32, u32>",
+    create_try = r#"{ Ok::<_, ()>(cached::SizedCache::with_size(10)) }"#,
+    on_create_error = "ignore"
+)]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use cached::proc_macro::cached;
+
+#[cached(lock = "none")]
+fn without_store(n: u32) -> u32 {
+    n
+}
+
+#[cached(
+    lock = "none",
+    type = "cached::UnboundCache<u32, u32>",
+    create = "{ cached::UnboundCache::new() }",
+    sync_writes = true
+)]
+fn synchronized(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
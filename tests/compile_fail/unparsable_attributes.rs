@@ -0,0 +1,23 @@
+use cached::proc_macro::cached;
+
+#[cached(key = "u32", convert = r#"{ n + }"#)]
+fn bad_convert(n: u32) -> u32 {
+    n
+}
+
+#[cached(key = "Vec<u32", convert = r#"{ vec![n] }"#)]
+fn bad_key(n: u32) -> u32 {
+    n
+}
+
+#[cached(type = "cached::SizedCache<u32 u32>", create = "{ cached::SizedCache::with_size(1) }")]
+fn bad_type(n: u32) -> u32 {
+    n
+}
+
+#[cached(type = "cached::SizedCache<u32, u32>", create = "{ cached::SizedCache::with_size(1)")]
+fn bad_create(n: u32) -> u32 {
+    n
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use cached::proc_macro::cached;
+
+#[cached(key = "usize", convert = "{ s.len() }")]
+fn first_word<'a>(s: &'a str) -> &'a str {
+    s.split(' ').next().unwrap_or("")
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use cached::proc_macro::cached;
+use std::rc::Rc;
+
+#[cached]
+fn rc_value(k: u32) -> Rc<u32> {
+    Rc::new(k)
+}
+
+fn main() {}
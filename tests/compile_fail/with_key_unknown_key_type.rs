@@ -0,0 +1,13 @@
+use cached::proc_macro::cached;
+
+#[cached(
+    type = "cached::UnboundCache<String, usize>",
+    create = "{ cached::UnboundCache::new() }",
+    convert = r#"{ s.to_string() }"#,
+    with_key = true
+)]
+fn len(s: &str) -> usize {
+    s.len()
+}
+
+fn main() {}
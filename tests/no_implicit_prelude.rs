@@ -0,0 +1,421 @@
+/*!
+Macro-defined functions in a crate without the implicit prelude, proving the generated code only
+uses fully qualified paths
+*/
+#![no_implicit_prelude]
+
+use ::cached::proc_macro::{cached, cached_macro_test, io_cached, once};
+use ::std::string::String;
+use ::std::sync::atomic::{AtomicU32, Ordering};
+use ::std::time::Duration;
+
+#[cached]
+fn plain(n: u32) -> u32 {
+    n * 2
+}
+
+#[cached(size = 2, result = true)]
+fn sized_result(n: u32) -> ::std::result::Result<u32, String> {
+    ::std::result::Result::Ok(n)
+}
+
+#[cached(time = 10, option = true, sync_writes = true)]
+fn timed_option(n: u32) -> ::std::option::Option<u32> {
+    ::std::option::Option::Some(n)
+}
+
+#[cached(
+    key = "String",
+    convert = r#"{ ::std::format!("{}", n) }"#,
+    with_cached_flag = true
+)]
+fn keyed_flag(n: u32) -> ::cached::Return<u32> {
+    ::cached::Return::new(n)
+}
+
+#[cached(
+    type = "::cached::SizedCache<u32, u32>",
+    create = "{ ::cached::SizedCache::with_size(2) }"
+)]
+fn typed(n: u32) -> u32 {
+    n
+}
+
+#[cached(size = 2, max_inflight = 1, evict_after_set = 10)]
+fn limited(n: u32) -> u32 {
+    n
+}
+
+#[cached(
+    size = 2,
+    time = 10,
+    time_to_idle = 5,
+    with_key = true,
+    trace_caller = true,
+    key_transform = r#"{ |k| k }"#,
+    on_miss = r#"{ |_| {} }"#
+)]
+fn traced(n: u32) -> u32 {
+    n
+}
+
+#[cached(
+    size = 2,
+    time = 10,
+    invalidation_group = "no_implicit_prelude",
+    prime_batched = true,
+    prime_batch_size = 2
+)]
+fn grouped(n: u32) -> u32 {
+    n
+}
+
+fn version() -> u32 {
+    1
+}
+
+#[cached(
+    result_option = true,
+    version_fn = "version",
+    sync_writes = true,
+    placeholder = r#"{ ::std::option::Option::None }"#
+)]
+fn versioned(n: u32) -> ::std::option::Option<::std::result::Result<u32, ()>> {
+    ::std::option::Option::Some(::std::result::Result::Ok(n))
+}
+
+#[cached(error_on_miss = true)]
+fn cache_only(n: u32) -> u32 {
+    n
+}
+
+#[cached(sync_writes = true, cold_start_timeout = 1, feature = "async")]
+fn cold(n: u32) -> u32 {
+    n
+}
+
+#[cached(
+    type = "::cached::UnboundCache<u32, u32>",
+    create_try = r#"{ ::std::result::Result::<_, ()>::Ok(::cached::UnboundCache::new()) }"#,
+    on_create_error = "null"
+)]
+fn fallible(n: u32) -> u32 {
+    n
+}
+
+#[cached(at_most_once = true, result = true)]
+fn init(n: u32) -> ::std::result::Result<u32, ()> {
+    ::std::result::Result::Ok(n)
+}
+
+struct Config;
+
+impl ::cached::CacheConfig<u32> for Config {
+    type Key = u32;
+    type Value = u32;
+    type Cache = ::cached::SizedCache<u32, u32>;
+
+    fn create() -> Self::Cache {
+        ::cached::SizedCache::with_size(2)
+    }
+
+    fn key(n: u32) -> u32 {
+        n
+    }
+}
+
+#[cached(config = "Config")]
+fn configured(n: u32) -> u32 {
+    n
+}
+
+#[cached(extractor = true, ignore_types = "Config")]
+fn extracted(_config: &Config, n: &u32) -> u32 {
+    *n
+}
+
+#[cached(hash_only_key = true)]
+fn hashed(s: &str) -> usize {
+    s.len()
+}
+
+#[cached(invalidate_sibling = "hashed")]
+fn invalidating(n: u32) -> u32 {
+    n
+}
+
+#[cached(inline = true)]
+fn inlined(n: u32) -> u32 {
+    n
+}
+
+#[cached(name = "INLINED", shared = true)]
+fn inlined_shared(n: u32) -> u32 {
+    n
+}
+
+#[cached(lock = "rwlock", with_cached_flag = true)]
+fn read_locked(n: u32) -> ::cached::Return<u32> {
+    ::cached::Return::new(n)
+}
+
+#[cfg(feature = "dashmap_store")]
+#[cached(
+    lock = "none",
+    type = "::cached::stores::DashMapCache<u32, u32>",
+    create = "{ ::cached::stores::DashMapCache::new() }",
+    option = true
+)]
+fn unlocked(n: u32) -> ::std::option::Option<u32> {
+    ::std::option::Option::Some(n)
+}
+
+#[once]
+fn run_once_guard() {}
+
+#[cfg(feature = "arcswap")]
+#[once(lock = "arcswap", sync_writes = true, time = 10)]
+fn once_arcswap() -> u32 {
+    1
+}
+
+#[cfg(feature = "async")]
+#[cached(time = 10, size = 2)]
+async fn async_plain(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[cached(result = true, sync_writes = true, compute_timeout_ms = 1000)]
+async fn async_timeout(n: u32) -> ::std::result::Result<u32, ::cached::ComputeTimeout> {
+    ::std::result::Result::Ok(n)
+}
+
+#[once(time = 10)]
+fn once_timed() -> u32 {
+    1
+}
+
+#[once(result = true, sync_writes = true, retry_backoff_ms = 10)]
+fn once_backoff() -> ::std::result::Result<u32, ()> {
+    ::std::result::Result::Err(())
+}
+
+#[cfg(feature = "async")]
+#[once(sync_writes = true, option = true, block_on = true)]
+async fn once_async() -> ::std::option::Option<u32> {
+    ::std::option::Option::Some(1)
+}
+
+#[cfg(feature = "async")]
+#[cached(error_on_miss = true)]
+async fn async_cache_only(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[cached(sync_writes = true, placeholder = r#"{ 0 }"#)]
+async fn async_placeholder(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[cached(sync_writes = true, cold_start_timeout = 1)]
+async fn async_cold(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+async fn async_key(n: &u32) -> u32 {
+    *n
+}
+
+#[cfg(feature = "async")]
+#[cached(key = "u32", key_fn_async = "async_key")]
+async fn async_keyed(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[cached(invalidate_sibling = "async_plain")]
+async fn async_invalidating(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[cached]
+fn async_boxed(
+    n: u32,
+) -> ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<Output = u32> + ::std::marker::Send>>
+{
+    ::std::boxed::Box::pin(async move { n })
+}
+
+/// An `IOCached` store counting its writes
+struct CountingStore(AtomicU32, ::std::collections::HashMap<u32, u32>);
+
+impl ::cached::IOCached<u32, u32> for CountingStore {
+    type Error = ();
+
+    fn cache_get(&self, k: &u32) -> ::std::result::Result<::std::option::Option<u32>, ()> {
+        ::std::result::Result::Ok(self.1.get(k).copied())
+    }
+
+    fn cache_set(&self, _: u32, v: u32) -> ::std::result::Result<::std::option::Option<u32>, ()> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        ::std::result::Result::Ok(::std::option::Option::Some(v))
+    }
+
+    fn cache_remove(&self, _: &u32) -> ::std::result::Result<::std::option::Option<u32>, ()> {
+        ::std::result::Result::Ok(::std::option::Option::None)
+    }
+
+    fn cache_set_refresh(&mut self, _: bool) -> bool {
+        false
+    }
+}
+
+#[io_cached(
+    map_error = r#"|e| e"#,
+    type = "CountingStore",
+    create = r#"{ CountingStore(AtomicU32::new(0), ::std::collections::HashMap::new()) }"#
+)]
+fn io(n: u32) -> ::std::result::Result<u32, ()> {
+    ::std::result::Result::Ok(n)
+}
+
+/// Items named like the paths the generated code uses, which it must not pick up
+mod shadowed {
+    #![allow(dead_code, non_camel_case_types)]
+
+    use ::cached::proc_macro::{cached, once};
+
+    mod cached {}
+    mod std {}
+    mod once_cell {}
+    struct Option;
+    struct Result;
+    struct Some;
+    struct Ok;
+    struct Mutex;
+    trait Cached {}
+
+    #[cached(size = 2)]
+    pub fn shadowed_sized(n: u32) -> u32 {
+        n
+    }
+
+    #[cfg(feature = "async")]
+    #[cached(time = 10, sync_writes = true)]
+    pub async fn shadowed_async(n: u32) -> u32 {
+        n
+    }
+
+    #[once]
+    pub fn shadowed_once() -> u32 {
+        1
+    }
+}
+
+#[test]
+fn no_implicit_prelude() {
+    use ::cached::Cached;
+
+    ::std::assert_eq!(plain(1), 2);
+    ::std::assert_eq!(plain(1), 2);
+    ::std::assert_eq!(
+        PLAIN.lock().unwrap().cache_hits(),
+        ::std::option::Option::Some(1)
+    );
+    ::std::assert_eq!(sized_result(1), ::std::result::Result::Ok(1));
+    ::std::assert_eq!(timed_option(1), ::std::option::Option::Some(1));
+    ::std::assert!(!keyed_flag(1).was_cached);
+    ::std::assert!(keyed_flag(1).was_cached);
+    ::std::assert_eq!(typed(1), 1);
+    ::std::assert_eq!(limited(1), 1);
+    ::std::assert_eq!(once_timed(), 1);
+    ::std::assert_eq!(once_backoff(), ::std::result::Result::Err(()));
+    ::std::assert_eq!(once_backoff(), ::std::result::Result::Err(()));
+    ::std::assert_eq!(io(1), ::std::result::Result::Ok(1));
+    ::std::assert_eq!(shadowed::shadowed_sized(1), 1);
+    ::std::assert_eq!(shadowed::shadowed_once(), 1);
+
+    ::std::assert_eq!(traced(1), 1);
+    ::std::assert_eq!(traced_with_key(1), (1, 1));
+    ::std::assert!(traced_cache_get_with_location(1).is_some());
+    ::std::assert!(traced_cache_pin(1));
+    ::std::assert_eq!(traced_cache_remove(1), ::std::option::Option::Some(1));
+    ::std::assert_eq!(grouped_prime_cache(1), 1);
+    ::std::assert_eq!(grouped_prime_flush(), 1);
+    ::cached::invalidate_group("no_implicit_prelude");
+    ::std::assert_eq!(grouped(1), 1);
+    ::std::assert_eq!(
+        versioned_try_prime_cache(1),
+        ::std::result::Result::Ok(true)
+    );
+    ::std::assert_eq!(
+        versioned(1),
+        ::std::option::Option::Some(::std::result::Result::Ok(1))
+    );
+    ::std::assert!(cache_only(1).is_err());
+    cache_only_prime_cache(1);
+    ::std::assert_eq!(cache_only(1), ::std::result::Result::Ok(1));
+    ::std::assert_eq!(cold(1), ::std::result::Result::Ok(1));
+    ::std::assert_eq!(fallible(1), 1);
+    ::std::assert_eq!(init(1), ::std::result::Result::Ok(1));
+    ::std::assert_eq!(configured(1), 1);
+    ::std::assert_eq!(configured(1), 1);
+    ::std::assert_eq!(extracted(&Config, &1), 1);
+    ::std::assert_eq!(hashed("ab"), 2);
+    ::std::assert_eq!(invalidating(1), 1);
+    ::std::assert_eq!(inlined(1), 1);
+    ::std::assert_eq!(inlined_shared(1), 1);
+    ::std::assert!(!read_locked(1).was_cached);
+    ::std::assert!(read_locked(1).was_cached);
+    #[cfg(feature = "dashmap_store")]
+    ::std::assert_eq!(unlocked(1), ::std::option::Option::Some(1));
+    run_once_guard();
+    ::std::assert!(run_once_guard_has_run());
+    #[cfg(feature = "arcswap")]
+    ::std::assert_eq!(once_arcswap(), 1);
+    let _ = Duration::from_secs(1);
+}
+
+#[cached_macro_test]
+#[test]
+fn no_implicit_prelude_macro_test() {
+    #[cached]
+    fn scoped(n: u32) -> u32 {
+        n * 2
+    }
+
+    ::std::assert_eq!(scoped(2), 4);
+    #[cfg(feature = "async")]
+    {
+        #[cached]
+        async fn scoped_async(n: u32) -> u32 {
+            n * 2
+        }
+        let runtime = ::tokio::runtime::Runtime::new().unwrap();
+        ::std::assert_eq!(runtime.block_on(scoped_async(2)), 4);
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn no_implicit_prelude_async() {
+    let runtime = ::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        ::std::assert_eq!(async_plain(1).await, 1);
+        ::std::assert_eq!(async_timeout(1).await, ::std::result::Result::Ok(1));
+        ::std::assert_eq!(once_async().await, ::std::option::Option::Some(1));
+        ::std::assert_eq!(shadowed::shadowed_async(1).await, 1);
+        ::std::assert!(async_cache_only(1).await.is_err());
+        ::std::assert_eq!(async_placeholder(1).await, 1);
+        ::std::assert_eq!(async_keyed(1).await, 1);
+        ::std::assert_eq!(async_invalidating(1).await, 1);
+        ::std::assert_eq!(async_boxed(1).await, 1);
+        ::std::assert_eq!(async_cold(1).await, ::std::result::Result::Ok(1));
+    });
+    ::std::assert_eq!(once_async_blocking(), ::std::option::Option::Some(1));
+}
@@ -0,0 +1,67 @@
+/*!
+Tests of the cache scopes of the `testing` feature
+*/
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::thread;
+
+use cached::proc_macro::cached;
+use cached::Cached;
+
+static SQUARE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[cached(size = 10)]
+fn square(n: u32) -> u32 {
+    SQUARE_CALLS.fetch_add(1, Ordering::SeqCst);
+    n * n
+}
+
+#[test]
+fn scopes_dont_interfere_across_threads() {
+    // every thread primes the same key with a value of its own, and only sees its own
+    let barrier = Barrier::new(4);
+    thread::scope(|s| {
+        for id in 0..4 {
+            let barrier = &barrier;
+            s.spawn(move || {
+                let scope = square_cache_scope();
+                scope.lock().unwrap().cache_set(2, 100 + id);
+                barrier.wait();
+                assert_eq!(square(2), 100 + id);
+                assert_eq!(square(3), 9);
+                assert_eq!(scope.lock().unwrap().cache_size(), 2);
+            });
+        }
+    });
+    // nothing leaked into the static
+    assert_eq!(SQUARE.lock().unwrap().cache_get(&2), None);
+}
+
+#[test]
+fn scopes_nest_and_restore() {
+    let outer = square_cache_scope();
+    outer.lock().unwrap().cache_set(5, 1);
+    {
+        let inner = square_cache_scope();
+        assert_eq!(square(5), 25);
+        assert_eq!(inner.lock().unwrap().cache_size(), 1);
+    }
+    assert_eq!(square(5), 1);
+    drop(outer);
+    assert_eq!(square(7), 49);
+    assert_eq!(SQUARE.lock().unwrap().cache_get(&7), Some(&49));
+}
+
+#[cached]
+async fn async_double(n: u32) -> u32 {
+    n * 2
+}
+
+#[tokio::test]
+async fn async_scope() {
+    let scope = async_double_cache_scope();
+    scope.lock().await.cache_set(4, 0);
+    assert_eq!(async_double(4).await, 0);
+    drop(scope);
+    assert_eq!(async_double(4).await, 8);
+}